@@ -0,0 +1,57 @@
+//`get_palette` reads 8-bit rgb and rgba images straight out of their buffers, and everything else a pixel at a time,
+//so the 16-bit image is there to compare against
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::{DynamicImage, RgbaImage};
+use pxls::{
+    cancellation::CancellationToken, get_palette, progress::NoProgress, DistanceAlgorithm,
+    PaletteSettings,
+};
+use std::hint::black_box;
+
+fn noise(width: u32, height: u32) -> RgbaImage {
+    //xorshift, so it's the same every run
+    let mut state = 0x2545_f491_u32;
+    RgbaImage::from_fn(width, height, |_, _| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let [r, g, b, _] = state.to_le_bytes();
+        //a few bits less, so chunks have colours in common like a photo would
+        image::Rgba([r & 0xf0, g & 0xf0, b & 0xf0, 255])
+    })
+}
+
+fn layouts(c: &mut Criterion) {
+    let rgba = noise(1000, 750);
+    let settings = PaletteSettings {
+        chunks_per_dimension: 25,
+        ..PaletteSettings::default()
+    };
+
+    let mut group = c.benchmark_group("get_palette");
+    for (name, image) in [
+        (
+            "rgb8",
+            DynamicImage::ImageRgba8(rgba.clone()).to_rgb8().into(),
+        ),
+        ("rgba8", DynamicImage::ImageRgba8(rgba.clone())),
+        ("rgb16", DynamicImage::ImageRgba8(rgba).to_rgb16().into()),
+    ] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &image, |b, image| {
+            b.iter(|| {
+                get_palette(
+                    black_box(image),
+                    settings.clone(),
+                    DistanceAlgorithm::Euclidean,
+                    &NoProgress,
+                    &CancellationToken::new(),
+                )
+                .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, layouts);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+//lots of small chunks means a progress report for every few pixels, which is where skipping them should show up
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::{DynamicImage, RgbImage};
+use pxls::{
+    cancellation::CancellationToken, get_palette, progress::NoProgress, progress::ProgressReporter,
+    DistanceAlgorithm, PaletteSettings,
+};
+use std::{hint::black_box, sync::mpsc::channel};
+
+fn gradient() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(800, 800, |x, y| {
+        image::Rgb([(x / 4) as u8, (y / 4) as u8, ((x + y) / 8) as u8])
+    }))
+}
+
+fn palette_with(image: &DynamicImage, progress: &impl ProgressReporter) {
+    let settings = PaletteSettings {
+        chunks_per_dimension: 200,
+        ..PaletteSettings::default()
+    };
+    get_palette(
+        black_box(image),
+        settings,
+        DistanceAlgorithm::Euclidean,
+        progress,
+        &CancellationToken::new(),
+    )
+    .unwrap();
+}
+
+fn reporters(c: &mut Criterion) {
+    let image = gradient();
+    let mut group = c.benchmark_group("progress");
+
+    group.bench_function(BenchmarkId::from_parameter("none"), |b| {
+        b.iter(|| palette_with(&image, &NoProgress));
+    });
+    group.bench_function(BenchmarkId::from_parameter("no sender"), |b| {
+        b.iter(|| palette_with(&image, &None::<&std::sync::mpsc::Sender<(u32, u32)>>));
+    });
+    group.bench_function(BenchmarkId::from_parameter("disconnected sender"), |b| {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        b.iter(|| palette_with(&image, &Some(&sender)));
+    });
+    group.bench_function(BenchmarkId::from_parameter("connected sender"), |b| {
+        let (sender, receiver) = channel();
+        b.iter(|| {
+            palette_with(&image, &Some(&sender));
+            receiver.try_iter().count()
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, reporters);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+fn main() {
+    //the C header for `ffi`, which only needs to be kept up to date when it's being built
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo always sets this");
+        let config = cbindgen::Config::from_root_or_default(&crate_dir);
+        cbindgen::generate_with_config(&crate_dir, config)
+            .expect("couldn't generate the header for src/ffi.rs")
+            .write_to_file(format!("{crate_dir}/include/pxls.h"));
+    }
+}
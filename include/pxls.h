@@ -0,0 +1,80 @@
+/* the C interface to pxls, see src/ffi.rs.
+ * build the library with `cargo rustc --lib --release --features capi --crate-type cdylib`.
+ *
+ * every image and palette is tightly-packed 8-bit RGBA. anything pxls hands back is owned by the caller until it goes
+ * back through the matching pxls_free_* with the same sizes, and must never be freed any other way. */
+
+#ifndef PXLS_H
+#define PXLS_H
+
+/* generated by build.rs - change src/ffi.rs rather than this */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum PxlsStatus {
+  PXLS_STATUS_OK = 0,
+  PXLS_STATUS_INVALID_SETTINGS = 1,
+  PXLS_STATUS_EMPTY_PALETTE = 2,
+  PXLS_STATUS_IMAGE_TOO_SMALL = 3,
+  PXLS_STATUS_CANCELLED = 4,
+  PXLS_STATUS_SCALE = 5,
+  PXLS_STATUS_ZERO_DIMENSION = 6,
+  PXLS_STATUS_NULL_POINTER = 7,
+  PXLS_STATUS_UNKNOWN_ALGORITHM = 8,
+  PXLS_STATUS_TOO_BIG = 9,
+} PxlsStatus;
+
+typedef enum PxlsDistance {
+  PXLS_DISTANCE_EUCLIDEAN = 0,
+  PXLS_DISTANCE_HSV_EUCLIDEAN = 1,
+  PXLS_DISTANCE_MANHATTAN = 2,
+  PXLS_DISTANCE_LUMINANCE = 3,
+  PXLS_DISTANCE_VALUE = 4,
+} PxlsDistance;
+
+typedef struct PxlsSettings {
+  uint32_t chunks_per_dimension;
+  uint32_t closeness_threshold;
+  uint32_t distance_algorithm;
+  uint32_t output_px_size;
+  uint32_t dithering_ratio;
+  uint32_t dithering_scale;
+  bool scale_output_to_original;
+} PxlsSettings;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+struct PxlsSettings pxls_default_settings(void);
+
+enum PxlsStatus pxls_get_palette(const uint8_t *rgba,
+                                 uint32_t width,
+                                 uint32_t height,
+                                 const struct PxlsSettings *settings,
+                                 uint8_t **out_colours,
+                                 size_t *out_len);
+
+enum PxlsStatus pxls_dither(const uint8_t *rgba,
+                            uint32_t width,
+                            uint32_t height,
+                            const uint8_t *colours,
+                            size_t colours_len,
+                            const struct PxlsSettings *settings,
+                            uint8_t **out_rgba,
+                            uint32_t *out_width,
+                            uint32_t *out_height);
+
+void pxls_free_palette(uint8_t *colours, size_t len);
+
+void pxls_free_image(uint8_t *rgba, uint32_t width, uint32_t height);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PXLS_H */
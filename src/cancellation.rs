@@ -0,0 +1,162 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+//cheap to clone, and every clone shares the same flag.
+//cancelling a token cancels all of its children, but a child being cancelled leaves its parent alone
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Arc<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+
+    #[must_use]
+    pub fn child(&self) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+}
+
+//for code that still has a plain stop flag - setting the flag cancels the token and vice versa
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(cancelled: Arc<AtomicBool>) -> Self {
+        Self {
+            cancelled,
+            parent: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dither_original_with_palette, get_palette, progress::MaxUpdates, DistanceAlgorithm,
+        OutputSettings, PaletteSettings, PxlsError,
+    };
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use std::cell::RefCell;
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([x as u8 * 4, y as u8 * 4, 0, 255])
+        }))
+    }
+
+    #[test]
+    fn cancelling_a_parent_cancels_its_children() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let grandchild = child.child();
+        let sibling = parent.child();
+        assert!(!grandchild.is_cancelled());
+
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+
+        parent.cancel();
+        assert!(sibling.is_cancelled());
+        //made after the parent was cancelled
+        assert!(parent.child().is_cancelled());
+    }
+
+    #[test]
+    fn clones_share_the_flag() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        let child = token.child();
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(child.is_cancelled());
+    }
+
+    #[test]
+    fn plain_flags_are_bridged_both_ways() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let token = CancellationToken::from(flag.clone());
+        let child = token.child();
+        flag.store(true, Ordering::Relaxed);
+        assert!(token.is_cancelled());
+        assert!(child.is_cancelled());
+
+        let flag = Arc::new(AtomicBool::new(false));
+        CancellationToken::from(flag.clone()).cancel();
+        assert!(flag.load(Ordering::Relaxed));
+    }
+
+    //both get cancelled from the progress callback, so it happens part way through the stage
+    #[test]
+    fn palettes_stop_at_the_next_chunk() {
+        let (stop, reports) = (CancellationToken::new(), RefCell::new(vec![]));
+        let result = get_palette(
+            &gradient(),
+            PaletteSettings {
+                chunks_per_dimension: 8,
+                ..PaletteSettings::default()
+            },
+            DistanceAlgorithm::Euclidean,
+            &MaxUpdates(
+                |_, done, _| {
+                    reports.borrow_mut().push(done);
+                    if done == 3 {
+                        stop.cancel();
+                    }
+                },
+                u32::MAX,
+            ),
+            &stop,
+        );
+        assert!(matches!(result, Err(PxlsError::Cancelled)));
+        //the chunks up to the cancel, and then where it got to
+        assert_eq!(*reports.borrow(), [1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn dithering_stops_at_the_next_chunk() {
+        let (stop, reports) = (CancellationToken::new(), RefCell::new(vec![]));
+        let result = dither_original_with_palette(
+            &gradient(),
+            [Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])],
+            DistanceAlgorithm::Euclidean,
+            OutputSettings {
+                output_px_size: 2,
+                ..OutputSettings::default()
+            },
+            &MaxUpdates(
+                |_, done, _| {
+                    reports.borrow_mut().push(done);
+                    if done == 5 {
+                        stop.cancel();
+                    }
+                },
+                u32::MAX,
+            ),
+            &stop,
+        );
+        assert!(matches!(result, Err(PxlsError::Cancelled)));
+        assert_eq!(*reports.borrow(), [1, 2, 3, 4, 5, 5]);
+    }
+}
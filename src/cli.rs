@@ -1,18 +1,28 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
 use dialoguer::{theme::ColorfulTheme, FuzzySelect, Input};
-use image::ImageReader;
+use image::{DynamicImage, ImageFormat, ImageReader, Rgba};
 use pxls::{
-    dither_original_with_palette, get_palette, DistanceAlgorithm, OutputSettings, PaletteSettings,
-    ALL_ALGOS,
+    analyze_image,
+    export::{check_writable, NOT_WRITABLE_MESSAGE},
+    palette_export::palette_to_inkscape_svg,
+    pipeline::{Pxls, RenderResult},
+    pixel_operations::{rgb_from_hex, rgb_to_hex},
+    preprocess::{adjust, apply_flip, apply_rotation, Adjustments, Flip, Rotation},
+    progress::Stage,
+    quantizer::{
+        available_quantizers, find_quantizer, quantizer_names, BuiltInQuantizer, Quantizer,
+    },
+    DistanceAlgorithm, DitherMode, DitheringMode, OutputSettings, PaletteAlgorithm,
+    PaletteSettings, PxlsError, Stats, ALL_ALGOS, ALL_DITHER_MODES, ALL_ERROR_DIFFUSION_DIRECTIONS,
+    DEFAULT_SUPERPIXEL_COMPACTNESS, MAX_POST_SHARPEN,
 };
 use std::{
     collections::HashMap,
     fs,
-    path::PathBuf,
-    sync::{atomic::AtomicBool, mpsc::channel, Arc},
+    path::{Path, PathBuf},
 };
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::too_many_lines)]
 pub fn cli_main(should_ask: bool) -> anyhow::Result<()> {
     let CliArgs {
         input,
@@ -21,42 +31,108 @@ pub fn cli_main(should_ask: bool) -> anyhow::Result<()> {
         closeness_threshold,
         output_px_size,
         algorithm,
-        dithering_factor,
+        dither_mode,
+        dithering_mode,
         dithering_scale,
+        adjustments,
+        exclude_colors,
+        exclude_threshold,
+        inkscape_svg,
+        post_sharpen,
+        palette_algorithm,
+        palette_method,
+        rotation,
+        flip,
+        analyze,
+        verbosity,
     } = CliArgs::parse(should_ask)?;
+    init_tracing(verbosity);
 
-    let should_stop = Arc::new(AtomicBool::new(false));
+    let palette_settings = PaletteSettings {
+        chunks_per_dimension,
+        closeness_threshold,
+        exclude_colors,
+        exclude_threshold,
+        extra_colors: vec![],
+    }
+    .validated(algorithm)?;
+    let output_settings = OutputSettings {
+        output_px_size,
+        dither_mode,
+        dithering_mode,
+        dithering_scale,
+        scale_output_to_original: true, //TODO: consider making this an option...
+        post_sharpen,
+    }
+    .validated()?;
+
+    check_output_path(&output)?;
+    if let Some(inkscape_svg) = &inkscape_svg {
+        check_writable(inkscape_svg)
+            .with_context(|| format!("{}: {NOT_WRITABLE_MESSAGE}", inkscape_svg.display()))?;
+    }
 
     let image = ImageReader::open(input)?.decode()?;
     println!("Image read in");
 
+    if analyze {
+        print_analysis(&image);
+    }
+
+    let image = apply_flip(apply_rotation(image, rotation), flip);
+
+    let image = if adjustments.is_identity() {
+        image
+    } else {
+        println!("Applying adjustments");
+        adjust(&image, adjustments)
+    };
+
     println!("Generating palette");
-    let (tx, _rx) = channel();
-    let av_px_colours = get_palette(
-        &image,
-        PaletteSettings {
-            chunks_per_dimension,
-            closeness_threshold,
-        },
-        algorithm,
-        &tx,
-        should_stop.clone(),
-    );
-    println!("Palette generated with {} colours", av_px_colours.len());
-    println!("Converting image to palette & shrinking");
-    let output_img = dither_original_with_palette(
-        &image,
-        &av_px_colours,
-        algorithm,
-        OutputSettings {
-            output_px_size,
-            dithering_likelihood: dithering_factor,
-            dithering_scale,
-            scale_output_to_original: true, //TODO: consider making this an option...
-        },
-        &tx,
-        should_stop,
-    );
+    let pipeline = Pxls::new(&image)
+        .quantizer(quantizer_for(
+            palette_method.as_deref(),
+            palette_algorithm,
+            &palette_settings,
+            algorithm,
+        )?)
+        .palette_settings(palette_settings)
+        .output_settings(output_settings)
+        .algorithm(algorithm);
+    #[cfg(feature = "custom-palette-sort")]
+    let pipeline = pipeline.sort_palette(pxls::PaletteSortOrder::CustomKey(
+        crate::custom_sort::palette_sort_key,
+    ));
+    let mut dithering_started = false;
+    let RenderResult {
+        palette,
+        output: output_img,
+        palette_stats,
+        dithering_stats,
+        ..
+    } = pipeline
+        .on_progress(|stage, _, _| {
+            if stage == Stage::Dithering && !dithering_started {
+                dithering_started = true;
+                println!("Converting image to palette & shrinking");
+            }
+        })
+        .run()
+        .map_err(|e| match e {
+            //nothing else can empty the palette, since an empty image fails before it gets here
+            PxlsError::EmptyPalette => {
+                anyhow!("{e}, as every colour was excluded - try a lower --exclude-threshold")
+            }
+            e => anyhow::Error::new(e).context("Couldn't convert the image"),
+        })?;
+    println!("Palette generated with {} colours", palette.len());
+    if verbosity > 0 {
+        print_stats(palette_stats, dithering_stats);
+    }
+    if let Some(inkscape_svg) = inkscape_svg {
+        fs::write(&inkscape_svg, palette_to_inkscape_svg(&palette, None))?;
+        println!("Palette written to {}", inkscape_svg.display());
+    }
     //TODO: maybe the CLI should get fewer options when coming from env
     //TODO: opinionated defaults?
     println!("Output image generated");
@@ -66,6 +142,108 @@ pub fn cli_main(should_ask: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn print_stats(palette_stats: Option<Stats>, dithering_stats: Stats) {
+    for (stage, stats) in palette_stats
+        .map(|stats| ("Palette", stats))
+        .into_iter()
+        .chain([("Dithering", dithering_stats)])
+    {
+        let cache = stats
+            .cache_hit_rate
+            .map(|rate| format!(", {:.1}% cache hits", rate * 100.0))
+            .unwrap_or_default();
+        println!(
+            "{stage} took {:.2?}: {} chunks, {} pixels sampled{cache}, {} colours",
+            stats.duration, stats.chunks_processed, stats.pixels_sampled, stats.palette_size
+        );
+    }
+}
+
+//each histogram is squashed down into a line of block characters, so it fits in a terminal
+fn print_analysis(image: &DynamicImage) {
+    const HISTOGRAM_WIDTH: usize = 64;
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let analysis = analyze_image(image);
+    println!("Mean colour: {}", rgb_to_hex(analysis.mean_color));
+    println!("Median colour: {}", rgb_to_hex(analysis.median_color));
+    println!("Dominant colours:");
+    for (colour, percentage) in &analysis.dominant_colors {
+        println!("  {} {percentage:.2}%", rgb_to_hex(*colour));
+    }
+
+    for (name, histogram) in [
+        ("R", &analysis.r_histogram),
+        ("G", &analysis.g_histogram),
+        ("B", &analysis.b_histogram),
+    ] {
+        let buckets: Vec<u32> = histogram
+            .chunks(histogram.len() / HISTOGRAM_WIDTH)
+            .map(|bucket| bucket.iter().sum())
+            .collect();
+        let tallest = buckets.iter().copied().max().unwrap_or(0).max(1);
+        let line: String = buckets
+            .into_iter()
+            .map(|count| BLOCKS[(count as usize * (BLOCKS.len() - 1)).div_ceil(tallest as usize)])
+            .collect();
+        println!("{name} {line}");
+    }
+}
+
+//rendering can take a while, so it's worth knowing up front that the output can't be saved
+fn check_output_path(output: &Path) -> anyhow::Result<()> {
+    match ImageFormat::from_path(output) {
+        Ok(format) if format.writing_enabled() => {}
+        Ok(format) => bail!("{} can't be saved as {format:?}", output.display()),
+        Err(_) => bail!(
+            "{} doesn't have the extension of an image format that can be saved",
+            output.display()
+        ),
+    }
+
+    check_writable(output).with_context(|| format!("{}: {NOT_WRITABLE_MESSAGE}", output.display()))
+}
+
+//`--palette-method` takes over from `--superpixels` and `--histogram-peaks` when it's given
+fn quantizer_for(
+    palette_method: Option<&str>,
+    palette_algorithm: PaletteAlgorithm,
+    settings: &PaletteSettings,
+    algorithm: DistanceAlgorithm,
+) -> anyhow::Result<Box<dyn Quantizer>> {
+    let Some(name) = palette_method else {
+        return Ok(Box::new(BuiltInQuantizer {
+            algorithm: palette_algorithm,
+            settings: settings.clone(),
+            dist_algo: algorithm,
+        }));
+    };
+    find_quantizer(name, settings, algorithm)
+        .ok_or_else(|| anyhow!("no palette method called {name}"))
+}
+
+pub fn list_algorithms() {
+    for (index, algo) in ALL_ALGOS.iter().copied().enumerate() {
+        let threshold = if algo.squares_closeness_threshold() {
+            "squared before comparing"
+        } else {
+            "compared as-is"
+        };
+        println!(
+            "{index}: {} - {}\n   closeness threshold is {threshold}, and is useful up to {}",
+            algo.to_str(),
+            algo.description(),
+            algo.closeness_threshold_range().end()
+        );
+    }
+
+    println!("\npalette methods, for --palette-method:");
+    for quantizer in available_quantizers(&PaletteSettings::default(), DistanceAlgorithm::Euclidean)
+    {
+        println!("{}: {}", quantizer.name(), quantizer.description());
+    }
+}
+
 pub struct CliArgs {
     input: PathBuf,
     output: PathBuf,
@@ -73,8 +251,22 @@ pub struct CliArgs {
     closeness_threshold: u32,
     output_px_size: u32,
     algorithm: DistanceAlgorithm,
-    dithering_factor: u32,
+    dither_mode: DitherMode,
+    dithering_mode: DitheringMode,
     dithering_scale: u32,
+    adjustments: Adjustments,
+    exclude_colors: Vec<Rgba<u8>>,
+    exclude_threshold: u32,
+    inkscape_svg: Option<PathBuf>,
+    post_sharpen: f32,
+    palette_algorithm: PaletteAlgorithm,
+    //from `--palette-method`, in place of `palette_algorithm`
+    palette_method: Option<String>,
+    rotation: Rotation,
+    flip: Flip,
+    analyze: bool,
+    //how many `-v`s there were
+    verbosity: u8,
 }
 
 impl CliArgs {
@@ -87,7 +279,11 @@ impl CliArgs {
     }
 
     fn parse_env() -> Option<Self> {
-        let args: Vec<String> = std::env::args().skip(1).collect();
+        let mut args: Vec<String> = std::env::args().skip(1).collect();
+        if args.len() < 8 {
+            return None;
+        }
+        let flags = args.split_off(8);
 
         let Ok(
             [input, chunks_per_dimension, closeness_threshold, algorithm, output, output_px_size, dithering_factor, dithering_scale],
@@ -96,6 +292,23 @@ impl CliArgs {
             return None;
         };
 
+        let CliFlags {
+            adjustments,
+            exclude_colors,
+            exclude_threshold,
+            dithering_fraction,
+            dither_mode,
+            algorithm_index,
+            inkscape_svg,
+            post_sharpen,
+            palette_algorithm,
+            palette_method,
+            rotation,
+            flip,
+            analyze,
+            verbosity,
+        } = CliFlags::parse(flags)?;
+
         let input = PathBuf::from(input);
         if !input.exists() || !input.is_file() {
             eprintln!("[input_file] must be a file that exists");
@@ -110,7 +323,10 @@ impl CliArgs {
             eprintln!("[closeness_threshold] must be a valid u32");
             return None;
         };
-        let algorithm = {
+        //the index flag takes over from the positional name if present
+        let algorithm = if let Some(index) = algorithm_index {
+            ALL_ALGOS[index]
+        } else {
             let possibilities: HashMap<_, _> = ALL_ALGOS
                 .iter()
                 .copied()
@@ -133,6 +349,11 @@ impl CliArgs {
             eprintln!("[dithering_factor] must be a valid u32");
             return None;
         };
+        //the fraction flag takes over from the positional ratio if present
+        let dithering_mode = dithering_fraction.map_or(
+            DitheringMode::Ratio(dithering_factor),
+            DitheringMode::Fraction,
+        );
         let Ok(dithering_scale) = dithering_scale.parse() else {
             eprintln!("[dithering_scale] must be a valid u32");
             return None;
@@ -145,8 +366,20 @@ impl CliArgs {
             closeness_threshold,
             output_px_size,
             algorithm,
-            dithering_factor,
+            dither_mode,
+            dithering_mode,
             dithering_scale,
+            adjustments,
+            exclude_colors,
+            exclude_threshold,
+            inkscape_svg,
+            post_sharpen,
+            palette_algorithm,
+            palette_method,
+            rotation,
+            flip,
+            analyze,
+            verbosity,
         })
     }
 
@@ -160,7 +393,7 @@ impl CliArgs {
                 let entry = entry?;
                 if entry.metadata()?.is_file() {
                     let path = entry.path();
-                    current_dir_fns.push(format!("{path:?}"));
+                    current_dir_fns.push(path.display().to_string());
                     current_dir_files.push(path);
                 }
             }
@@ -200,6 +433,26 @@ impl CliArgs {
         let dithering_scale = Input::with_theme(&theme)
             .with_prompt("What should the dithering scale be for the output?")
             .interact()?;
+        let mut adjustments = Adjustments::default();
+        for (name, adjustment) in [
+            ("brightness", &mut adjustments.brightness),
+            ("contrast", &mut adjustments.contrast),
+            ("saturation", &mut adjustments.saturation),
+        ] {
+            *adjustment = Input::with_theme(&theme)
+                .with_prompt(format!(
+                    "What should the {name} adjustment be? (-100 to 100)"
+                ))
+                .default(0)
+                .validate_with(|value: &i32| {
+                    if (-100..=100).contains(value) {
+                        Ok(())
+                    } else {
+                        Err("must be between -100 and 100")
+                    }
+                })
+                .interact()?;
+        }
 
         Ok(Self {
             input,
@@ -208,8 +461,299 @@ impl CliArgs {
             closeness_threshold,
             output_px_size,
             algorithm,
-            dithering_factor,
+            dither_mode: DitherMode::Legacy,
+            dithering_mode: DitheringMode::Ratio(dithering_factor),
             dithering_scale,
+            adjustments,
+            exclude_colors: vec![],
+            exclude_threshold: PaletteSettings::default().exclude_threshold,
+            inkscape_svg: None,
+            post_sharpen: 0.0,
+            palette_algorithm: PaletteAlgorithm::Chunks,
+            palette_method: None,
+            rotation: Rotation::None,
+            flip: Flip::None,
+            analyze: false,
+            verbosity: 0,
         })
     }
 }
+
+struct CliFlags {
+    adjustments: Adjustments,
+    exclude_colors: Vec<Rgba<u8>>,
+    exclude_threshold: u32,
+    dithering_fraction: Option<f32>,
+    dither_mode: DitherMode,
+    algorithm_index: Option<usize>,
+    inkscape_svg: Option<PathBuf>,
+    post_sharpen: f32,
+    palette_algorithm: PaletteAlgorithm,
+    //from `--palette-method`, in place of `palette_algorithm`
+    palette_method: Option<String>,
+    rotation: Rotation,
+    flip: Flip,
+    analyze: bool,
+    //how many `-v`s there were
+    verbosity: u8,
+}
+
+impl CliFlags {
+    #[allow(clippy::too_many_lines)]
+    fn parse(flags: Vec<String>) -> Option<Self> {
+        let mut parsed = Self {
+            adjustments: Adjustments::default(),
+            exclude_colors: vec![],
+            exclude_threshold: PaletteSettings::default().exclude_threshold,
+            dithering_fraction: None,
+            dither_mode: OutputSettings::default().dither_mode,
+            algorithm_index: None,
+            inkscape_svg: None,
+            post_sharpen: 0.0,
+            palette_algorithm: PaletteAlgorithm::Chunks,
+            palette_method: None,
+            rotation: Rotation::None,
+            flip: Flip::None,
+            analyze: false,
+            verbosity: 0,
+        };
+
+        let parse_adjustment = |flag: &str, value: &str| {
+            let Ok(value) = value.parse::<i32>() else {
+                eprintln!("{flag} must be followed by a valid i32");
+                return None;
+            };
+            if !(-100..=100).contains(&value) {
+                eprintln!("{flag} must be between -100 and 100");
+                return None;
+            }
+            Some(value)
+        };
+
+        //only applied once we know the dither mode, so the flags can come in any order
+        let mut diffusion_direction = None;
+        let mut compactness = None;
+
+        let mut flags = flags.into_iter();
+        while let Some(flag) = flags.next() {
+            //the only flags that don't take a value
+            if flag == "--analyze" {
+                parsed.analyze = true;
+                continue;
+            }
+            if let Some(vs) = flag
+                .strip_prefix('-')
+                .filter(|vs| !vs.is_empty() && vs.bytes().all(|b| b == b'v'))
+            {
+                parsed.verbosity = parsed.verbosity.saturating_add(vs.len() as u8);
+                continue;
+            }
+
+            let Some(value) = flags.next() else {
+                eprintln!("{flag} must be followed by a value");
+                return None;
+            };
+
+            match flag.as_str() {
+                "--brightness" => parsed.adjustments.brightness = parse_adjustment(&flag, &value)?,
+                "--contrast" => parsed.adjustments.contrast = parse_adjustment(&flag, &value)?,
+                "--saturation" => parsed.adjustments.saturation = parse_adjustment(&flag, &value)?,
+                "--exclude-color" => {
+                    let Some(colour) = rgb_from_hex(&value) else {
+                        eprintln!("{flag} must be followed by a hex colour like #FF0000");
+                        return None;
+                    };
+                    parsed.exclude_colors.push(colour);
+                }
+                "--exclude-threshold" => {
+                    let Ok(value) = value.parse() else {
+                        eprintln!("{flag} must be followed by a valid u32");
+                        return None;
+                    };
+                    parsed.exclude_threshold = value;
+                }
+                "--dithering-fraction" => {
+                    let Ok(value) = value.parse::<f32>() else {
+                        eprintln!("{flag} must be followed by a valid f32");
+                        return None;
+                    };
+                    if !(0.0..=1.0).contains(&value) {
+                        eprintln!("{flag} must be between 0.0 and 1.0");
+                        return None;
+                    }
+                    parsed.dithering_fraction = Some(value);
+                }
+                "--dither-mode" => {
+                    let Some(mode) = ALL_DITHER_MODES.iter().copied().find(|mode| {
+                        mode.to_str().to_lowercase().replace(' ', "-") == value.to_lowercase()
+                    }) else {
+                        eprintln!("{flag} must be followed by one of none, legacy, floyd-steinberg, bayer, blue-noise or random");
+                        return None;
+                    };
+                    parsed.dither_mode = mode;
+                }
+                "--diffusion-direction" => {
+                    let Some(direction) =
+                        ALL_ERROR_DIFFUSION_DIRECTIONS
+                            .iter()
+                            .copied()
+                            .find(|direction| {
+                                direction.to_str().to_lowercase().replace(' ', "-")
+                                    == value.to_lowercase()
+                            })
+                    else {
+                        eprintln!("{flag} must be followed by one of left-to-right, right-to-left or serpentine");
+                        return None;
+                    };
+                    diffusion_direction = Some(direction);
+                }
+                "--algorithm-index" => {
+                    let Some(index) = value.parse().ok().filter(|index| *index < ALL_ALGOS.len())
+                    else {
+                        eprintln!(
+                            "{flag} must be followed by an index below {} - see pxls list-algorithms",
+                            ALL_ALGOS.len()
+                        );
+                        return None;
+                    };
+                    parsed.algorithm_index = Some(index);
+                }
+                "--sharpen" => {
+                    let Ok(value) = value.parse::<f32>() else {
+                        eprintln!("{flag} must be followed by a valid f32");
+                        return None;
+                    };
+                    if value.is_nan() || value < 0.0 {
+                        eprintln!("{flag} can't be negative");
+                        return None;
+                    }
+                    if value > MAX_POST_SHARPEN {
+                        eprintln!("{flag} is clamped to {MAX_POST_SHARPEN}, so {value} will be treated as {MAX_POST_SHARPEN}");
+                    }
+                    parsed.post_sharpen = value.min(MAX_POST_SHARPEN);
+                }
+                "--export-inkscape-svg" => parsed.inkscape_svg = Some(PathBuf::from(value)),
+                "--rotate" => {
+                    parsed.rotation = match value.as_str() {
+                        "0" => Rotation::None,
+                        "90" => Rotation::Cw90,
+                        "180" => Rotation::Cw180,
+                        "270" => Rotation::Cw270,
+                        _ => {
+                            eprintln!("{flag} must be followed by 0, 90, 180 or 270 (clockwise)");
+                            return None;
+                        }
+                    };
+                }
+                "--flip" => {
+                    parsed.flip = match value.to_lowercase().as_str() {
+                        "none" => Flip::None,
+                        "horizontal" => Flip::Horizontal,
+                        "vertical" => Flip::Vertical,
+                        "both" => Flip::Both,
+                        _ => {
+                            eprintln!("{flag} must be followed by one of none, horizontal, vertical or both");
+                            return None;
+                        }
+                    };
+                }
+                "--superpixels" => {
+                    let Some(target) = value.parse().ok().filter(|target| *target > 0) else {
+                        eprintln!("{flag} must be followed by a usize greater than 0");
+                        return None;
+                    };
+                    parsed.palette_algorithm = PaletteAlgorithm::Superpixel {
+                        target,
+                        compactness: DEFAULT_SUPERPIXEL_COMPACTNESS,
+                    };
+                }
+                "--histogram-peaks" => {
+                    let Some(quantization) = value.parse().ok().filter(|bin_width| *bin_width > 0)
+                    else {
+                        eprintln!("{flag} must be followed by a u8 greater than 0");
+                        return None;
+                    };
+                    parsed.palette_algorithm = PaletteAlgorithm::HistogramPeaks { quantization };
+                }
+                "--palette-method" => {
+                    let names = quantizer_names();
+                    let Some(name) = names.iter().find(|name| name.eq_ignore_ascii_case(&value))
+                    else {
+                        eprintln!(
+                            "{flag} must be followed by one of {} - see pxls list-algorithms",
+                            names.join(", ")
+                        );
+                        return None;
+                    };
+                    parsed.palette_method = Some((*name).to_string());
+                }
+                "--compactness" => {
+                    let Some(value) = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|value| value.is_finite() && *value >= 0.0)
+                    else {
+                        eprintln!("{flag} must be followed by a valid f32 that isn't negative");
+                        return None;
+                    };
+                    compactness = Some(value);
+                }
+                _ => {
+                    eprintln!("unknown flag: {flag}");
+                    return None;
+                }
+            }
+        }
+
+        if let Some(new_direction) = diffusion_direction {
+            let DitherMode::FloydSteinberg { direction } = &mut parsed.dither_mode else {
+                eprintln!("--diffusion-direction only applies to --dither-mode floyd-steinberg");
+                return None;
+            };
+            *direction = new_direction;
+        }
+
+        if let Some(new_compactness) = compactness {
+            let PaletteAlgorithm::Superpixel { compactness, .. } = &mut parsed.palette_algorithm
+            else {
+                eprintln!("--compactness only applies with --superpixels");
+                return None;
+            };
+            *compactness = new_compactness;
+        }
+
+        if parsed.palette_method.is_some() && parsed.palette_algorithm != PaletteAlgorithm::Chunks {
+            eprintln!("--palette-method can't be used with --superpixels or --histogram-peaks");
+            return None;
+        }
+
+        Some(parsed)
+    }
+}
+
+//`-v` prints how long each part of the pipeline took, and `-vv` also prints its progress
+fn init_tracing(verbosity: u8) {
+    if verbosity == 0 {
+        return;
+    }
+
+    #[cfg(feature = "tracing")]
+    {
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        let level = if verbosity == 1 {
+            tracing::Level::INFO
+        } else {
+            tracing::Level::DEBUG
+        };
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+    #[cfg(not(feature = "tracing"))]
+    eprintln!(
+        "pxls was built without the `tracing` feature, so -v only prints the stats at the end"
+    );
+}
@@ -0,0 +1,134 @@
+use crate::{
+    css_colors::CSS_NAMED_COLORS,
+    pixel_operations::{luminance, rgb_to_hsv},
+    DistanceAlgorithm,
+};
+use image::Rgba;
+
+//similar colours are compared by hue and brightness
+const CLUSTER_DISTANCE: DistanceAlgorithm = DistanceAlgorithm::HSVEuclidean;
+
+//a group of similar palette colours, from darkest to lightest
+#[derive(Clone, Debug)]
+pub struct PaletteCluster {
+    pub colours: Vec<Rgba<u8>>,
+    //the average of the colours, which is what clusters get compared by
+    pub mean: Rgba<u8>,
+    //the closest CSS colour to the mean, so the cluster has something to be called
+    pub name: &'static str,
+}
+
+//enough rows that each one is about as long as there are rows
+pub fn default_cluster_count(palette_len: usize) -> usize {
+    (palette_len as f64).sqrt().ceil() as usize
+}
+
+//agglomerative clustering - every colour starts on its own, and the closest two clusters keep getting merged.
+//clusters come out in order of hue
+pub fn cluster_palette(palette: &[Rgba<u8>], target_clusters: usize) -> Vec<PaletteCluster> {
+    struct Growing {
+        sum: [u64; 3],
+        colours: Vec<Rgba<u8>>,
+    }
+
+    impl Growing {
+        fn mean(&self) -> Rgba<u8> {
+            let len = self.colours.len() as u64;
+            let [r, g, b] = self.sum.map(|channel| (channel / len) as u8);
+            Rgba([r, g, b, u8::MAX])
+        }
+    }
+
+    let mut clusters: Vec<Option<Growing>> = palette
+        .iter()
+        .map(|colour| {
+            Some(Growing {
+                sum: [0, 1, 2].map(|i| u64::from(colour.0[i])),
+                colours: vec![*colour],
+            })
+        })
+        .collect();
+
+    //each cluster's closest other cluster, which only needs working out again when that one changes
+    let closest_to = |clusters: &[Option<Growing>], i: usize| {
+        let mean = clusters[i].as_ref()?.mean();
+        clusters
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .filter_map(|(j, other)| {
+                Some((j, CLUSTER_DISTANCE.distance(mean, other.as_ref()?.mean())))
+            })
+            .min_by_key(|(_, distance)| *distance)
+    };
+    let mut closest: Vec<Option<(usize, u32)>> = (0..clusters.len())
+        .map(|i| closest_to(&clusters, i))
+        .collect();
+
+    let mut remaining = clusters.len();
+    while remaining > target_clusters.max(1) {
+        let Some((into, (from, _))) = closest
+            .iter()
+            .enumerate()
+            .filter_map(|(i, closest)| closest.map(|closest| (i, closest)))
+            .min_by_key(|(_, (_, distance))| *distance)
+        else {
+            break;
+        };
+
+        let from_cluster = clusters[from].take().unwrap();
+        closest[from] = None;
+        let merged = clusters[into].as_mut().unwrap();
+        for (total, extra) in merged.sum.iter_mut().zip(from_cluster.sum) {
+            *total += extra;
+        }
+        merged.colours.extend(from_cluster.colours);
+        let mean = merged.mean();
+        remaining -= 1;
+
+        closest[into] = closest_to(&clusters, into);
+        for other in 0..clusters.len() {
+            let Some((other_closest, other_distance)) = closest[other] else {
+                continue;
+            };
+            if other == into {
+                continue;
+            }
+
+            if other_closest == into || other_closest == from {
+                closest[other] = closest_to(&clusters, other);
+            } else if let Some(other_cluster) = &clusters[other] {
+                let distance = CLUSTER_DISTANCE.distance(other_cluster.mean(), mean);
+                if distance < other_distance {
+                    closest[other] = Some((into, distance));
+                }
+            }
+        }
+    }
+
+    let mut clusters: Vec<_> = clusters
+        .into_iter()
+        .flatten()
+        .map(|cluster| {
+            let mean = cluster.mean();
+            let mut colours = cluster.colours;
+            colours.sort_by_key(|colour| luminance(*colour));
+            PaletteCluster {
+                colours,
+                mean,
+                name: closest_named_color(mean),
+            }
+        })
+        .collect();
+    clusters.sort_by_key(|cluster| rgb_to_hsv(cluster.mean));
+    clusters
+}
+
+fn closest_named_color(colour: Rgba<u8>) -> &'static str {
+    CSS_NAMED_COLORS
+        .iter()
+        .min_by_key(|(_, [r, g, b])| {
+            DistanceAlgorithm::Euclidean.distance(colour, Rgba([*r, *g, *b, u8::MAX]))
+        })
+        .map_or("", |(name, _)| name)
+}
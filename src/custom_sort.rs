@@ -0,0 +1,40 @@
+use image::Rgba;
+
+//the key the CLI sorts its palette by with the `custom-palette-sort` feature, smallest first.
+//this one goes from warm to cool by correlated colour temperature, in kelvin
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn palette_sort_key(colour: Rgba<u8>) -> u64 {
+    let [red, green, blue] = [0, 1, 2].map(|i| {
+        let channel = f64::from(colour.0[i]) / 255.0;
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    //linear sRGB to CIE XYZ, then into xy chromaticity
+    let [cie_x, cie_y, cie_z] = [
+        [0.4124, 0.3576, 0.1805],
+        [0.2126, 0.7152, 0.0722],
+        [0.0193, 0.1192, 0.9505],
+    ]
+    .map(|[from_red, from_green, from_blue]: [f64; 3]| {
+        from_red.mul_add(red, from_green.mul_add(green, from_blue * blue))
+    });
+    let total = cie_x + cie_y + cie_z;
+    //black doesn't have a temperature, so it goes first
+    if total <= f64::EPSILON {
+        return 0;
+    }
+    let (chromaticity_x, chromaticity_y) = (cie_x / total, cie_y / total);
+
+    //McCamy's approximation, https://en.wikipedia.org/wiki/Color_temperature#Approximation.
+    //it's only meant for colours near white, so very saturated ones can come out below 0
+    let n = (chromaticity_x - 0.3320) / (0.1858 - chromaticity_y);
+    let kelvin = 449.0f64.mul_add(
+        n.powi(3),
+        3525.0f64.mul_add(n.powi(2), 6823.3f64.mul_add(n, 5520.33)),
+    );
+    kelvin.max(0.0) as u64
+}
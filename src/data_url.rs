@@ -0,0 +1,35 @@
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{DynamicImage, ImageFormat};
+use std::io::Cursor;
+
+//accepts `data:image/<whatever>;base64,<data>` - the format comes from the bytes, not the mime type
+pub fn load_from_data_url(data_url: &str) -> anyhow::Result<DynamicImage> {
+    let Some((header, data)) = data_url.trim().split_once(',') else {
+        bail!("data URL is missing the ',' before the data");
+    };
+    let Some(mime) = header
+        .strip_prefix("data:")
+        .and_then(|header| header.strip_suffix(";base64"))
+    else {
+        bail!("data URL must start with `data:` and be base64-encoded");
+    };
+    if !mime.starts_with("image/") {
+        bail!("data URL must contain an image, but had a mime type of {mime:?}");
+    }
+
+    let bytes = STANDARD
+        .decode(data)
+        .map_err(|e| anyhow!("unable to decode base64: {e}"))?;
+    Ok(image::load_from_memory(&bytes)?)
+}
+
+pub fn image_to_data_url(image: &DynamicImage) -> anyhow::Result<String> {
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, ImageFormat::Png)?;
+
+    Ok(format!(
+        "data:image/png;base64,{}",
+        STANDARD.encode(bytes.into_inner())
+    ))
+}
@@ -0,0 +1,196 @@
+use image::{
+    codecs::{
+        gif::{GifEncoder, Repeat},
+        jpeg::JpegEncoder,
+    },
+    error::{
+        ImageFormatHint, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
+    },
+    Delay, DynamicImage, Frame, ImageError, ImageFormat, ImageResult,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub const EXPORT_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    //quality goes from 1 to 100
+    Jpeg { quality: u8 },
+    Gif,
+    Bmp,
+    WebP,
+}
+
+impl ExportFormat {
+    pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Some(match extension.to_lowercase().as_str() {
+            "png" => Self::Png,
+            "jpg" | "jpeg" => Self::Jpeg {
+                quality: Self::DEFAULT_JPEG_QUALITY,
+            },
+            "gif" => Self::Gif,
+            "bmp" => Self::Bmp,
+            "webp" => Self::WebP,
+            _ => return None,
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    //pngs get saved as APNGs, with the `png` feature
+    pub const fn supports_animation(self) -> bool {
+        matches!(self, Self::Gif) || (cfg!(feature = "png") && matches!(self, Self::Png))
+    }
+
+    const fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpeg { .. } => ImageFormat::Jpeg,
+            Self::Gif => ImageFormat::Gif,
+            Self::Bmp => ImageFormat::Bmp,
+            Self::WebP => ImageFormat::WebP,
+        }
+    }
+}
+
+//files without an extension get saved as pngs, rather than as an extension-less file
+pub fn with_default_extension(mut path: PathBuf) -> PathBuf {
+    if path.extension().is_none() {
+        path.set_extension("png");
+    }
+    path
+}
+
+pub const NOT_WRITABLE_MESSAGE: &str =
+    "Cannot write to this location — check directory permissions.";
+
+//so a bad destination is found out about before a long render rather than after.
+//anything that's already there gets opened without truncating, and only files that we made get removed
+pub fn check_writable(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        OpenOptions::new().write(true).open(path)?;
+        return Ok(());
+    }
+
+    File::create_new(path)?;
+    std::fs::remove_file(path)
+}
+
+pub fn export_image(image: &DynamicImage, path: &Path, format: ExportFormat) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        //jpegs don't have an alpha channel
+        ExportFormat::Jpeg { quality } => JpegEncoder::new_with_quality(&mut writer, quality)
+            .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8())),
+        //our outputs only use the palette colours, so as long as there are fewer than 256 the quantisation is lossless
+        ExportFormat::Gif => {
+            GifEncoder::new(&mut writer).encode_frame(Frame::new(image.to_rgba8()))
+        }
+        ExportFormat::Png => image.write_to(&mut writer, ImageFormat::Png),
+        ExportFormat::Bmp => image.write_to(&mut writer, ImageFormat::Bmp),
+        //the webp encoder is lossless-only, which is what we want for pixel art anyway
+        ExportFormat::WebP => {
+            DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut writer, ImageFormat::WebP)
+        }
+    }
+}
+
+//each frame is shown for its duration, and the whole thing loops forever.
+//frames are taken one at a time, so they don't all need to be in memory at once
+#[cfg_attr(not(feature = "png"), allow(unused_variables))]
+pub fn export_animation(
+    mut frames: impl ExactSizeIterator<Item = ImageResult<(DynamicImage, Duration)>>,
+    path: &Path,
+    format: ExportFormat,
+) -> ImageResult<()> {
+    if !format.supports_animation() {
+        return Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Exact(format.image_format()),
+                UnsupportedErrorKind::GenericFeature("animation".to_string()),
+            ),
+        ));
+    }
+
+    let frame_count = frames.len() as u32;
+    let Some(first) = frames.next() else {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic("an animation needs at least one frame".to_string()),
+        )));
+    };
+    let first = first?;
+    let (width, height) = (first.0.width(), first.0.height());
+    let frames = std::iter::once(Ok(first)).chain(frames);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        ExportFormat::Gif => write_gif(&mut writer, frames),
+        #[cfg(feature = "png")]
+        ExportFormat::Png => write_apng(&mut writer, (width, height), frame_count, frames),
+        _ => unreachable!("checked by supports_animation"),
+    }
+}
+
+fn write_gif(
+    writer: &mut BufWriter<File>,
+    frames: impl Iterator<Item = ImageResult<(DynamicImage, Duration)>>,
+) -> ImageResult<()> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(Repeat::Infinite)?;
+    for frame in frames {
+        let (frame, delay) = frame?;
+        encoder.encode_frame(Frame::from_parts(
+            frame.to_rgba8(),
+            0,
+            0,
+            Delay::from_saturating_duration(delay),
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "png")]
+fn write_apng(
+    writer: &mut BufWriter<File>,
+    (width, height): (u32, u32),
+    frame_count: u32,
+    frames: impl Iterator<Item = ImageResult<(DynamicImage, Duration)>>,
+) -> ImageResult<()> {
+    let png_error = |e: png::EncodingError| {
+        ImageError::Encoding(image::error::EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            e,
+        ))
+    };
+
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frame_count, 0).map_err(png_error)?;
+
+    let mut png_writer = encoder.write_header().map_err(png_error)?;
+    for frame in frames {
+        let (frame, delay) = frame?;
+        png_writer
+            .set_frame_delay(delay.as_millis().min(u128::from(u16::MAX)) as u16, 1000)
+            .map_err(png_error)?;
+        png_writer
+            .write_image_data(frame.to_rgba8().as_raw())
+            .map_err(png_error)?;
+    }
+    png_writer.finish().map_err(png_error)
+}
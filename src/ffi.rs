@@ -0,0 +1,531 @@
+//a C interface to palette generation and dithering, with the header in include/pxls.h, which build.rs regenerates
+//from this file. build it as a shared library with `cargo rustc --lib --release --features capi --crate-type cdylib`.
+//
+//every image and palette is tightly-packed 8-bit RGBA. anything the library hands back is owned by the caller until it
+//goes back through the matching `pxls_free_*` with the same sizes, and must never be freed any other way.
+//every pointer has to either be null (which gets `PXLS_NULL_POINTER` back) or point to as much as its sizes say
+#![allow(clippy::missing_safety_doc)]
+
+use crate::{
+    cancellation::CancellationToken, dither_into, get_palette_from_source, progress::NoProgress,
+    DistanceAlgorithm, DitheringMode, OutputSettings, PaletteSettings, PxlsError, ALL_ALGOS,
+};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use std::{ptr, slice};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PxlsSettings {
+    pub chunks_per_dimension: u32,
+    pub closeness_threshold: u32,
+    //an index into `ALL_ALGOS`, ie. one of the `PxlsDistance`s
+    pub distance_algorithm: u32,
+    pub output_px_size: u32,
+    //higher means less dithering
+    pub dithering_ratio: u32,
+    pub dithering_scale: u32,
+    pub scale_output_to_original: bool,
+}
+
+impl Default for PxlsSettings {
+    fn default() -> Self {
+        let palette = PaletteSettings::default();
+        let output = OutputSettings::default();
+        let DitheringMode::Ratio(dithering_ratio) = output.dithering_mode else {
+            unreachable!("the default dithering is a ratio")
+        };
+
+        Self {
+            chunks_per_dimension: palette.chunks_per_dimension,
+            closeness_threshold: palette.closeness_threshold,
+            distance_algorithm: 0,
+            output_px_size: output.output_px_size,
+            dithering_ratio,
+            dithering_scale: output.dithering_scale,
+            scale_output_to_original: output.scale_output_to_original,
+        }
+    }
+}
+
+impl PxlsSettings {
+    fn split(self) -> Result<(PaletteSettings, OutputSettings, DistanceAlgorithm), PxlsStatus> {
+        let algorithm = *ALL_ALGOS
+            .get(self.distance_algorithm as usize)
+            .ok_or(PxlsStatus::UnknownAlgorithm)?;
+        let palette = PaletteSettings {
+            chunks_per_dimension: self.chunks_per_dimension,
+            closeness_threshold: self.closeness_threshold,
+            ..PaletteSettings::default()
+        };
+        let output = OutputSettings {
+            output_px_size: self.output_px_size,
+            dithering_mode: DitheringMode::Ratio(self.dithering_ratio),
+            dithering_scale: self.dithering_scale,
+            scale_output_to_original: self.scale_output_to_original,
+            ..OutputSettings::default()
+        };
+        Ok((palette, output, algorithm))
+    }
+}
+
+//the values for `PxlsSettings.distance_algorithm`, in the same order as `ALL_ALGOS`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PxlsDistance {
+    Euclidean = 0,
+    HsvEuclidean = 1,
+    Manhattan = 2,
+    Luminance = 3,
+    Value = 4,
+}
+
+//the first few line up with `PxlsError`, the rest are for things that C can get wrong
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PxlsStatus {
+    Ok = 0,
+    InvalidSettings = 1,
+    EmptyPalette = 2,
+    ImageTooSmall = 3,
+    Cancelled = 4,
+    Scale = 5,
+    ZeroDimension = 6,
+    NullPointer = 7,
+    UnknownAlgorithm = 8,
+    //the sizes given don't fit in memory
+    TooBig = 9,
+}
+
+impl From<PxlsError> for PxlsStatus {
+    fn from(error: PxlsError) -> Self {
+        match error {
+            PxlsError::InvalidSettings(_) => Self::InvalidSettings,
+            PxlsError::EmptyPalette => Self::EmptyPalette,
+            PxlsError::ImageTooSmall { .. } => Self::ImageTooSmall,
+            PxlsError::Cancelled => Self::Cancelled,
+            PxlsError::Scale(_) => Self::Scale,
+            PxlsError::ZeroDimension => Self::ZeroDimension,
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pxls_default_settings() -> PxlsSettings {
+    PxlsSettings::default()
+}
+
+//a null `settings` means the defaults
+unsafe fn read_settings(settings: *const PxlsSettings) -> PxlsSettings {
+    settings.as_ref().copied().unwrap_or_default()
+}
+
+fn rgba_len(width: u32, height: u32) -> Result<usize, PxlsStatus> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(PxlsStatus::TooBig)
+}
+
+//borrows the caller's pixels rather than copying them
+unsafe fn borrow_image<'a>(
+    rgba: *const u8,
+    width: u32,
+    height: u32,
+) -> Result<ImageBuffer<Rgba<u8>, &'a [u8]>, PxlsStatus> {
+    if rgba.is_null() {
+        return Err(PxlsStatus::NullPointer);
+    }
+    let pixels = slice::from_raw_parts(rgba, rgba_len(width, height)?);
+    Ok(ImageBuffer::from_raw(width, height, pixels)
+        .expect("the length is worked out from the size"))
+}
+
+fn give_away(bytes: Vec<u8>) -> *mut u8 {
+    Box::into_raw(bytes.into_boxed_slice()).cast()
+}
+
+unsafe fn take_back(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, len)));
+    }
+}
+
+//on success, `out_colours` gets `out_len` colours to go back through `pxls_free_palette`
+#[no_mangle]
+pub unsafe extern "C" fn pxls_get_palette(
+    rgba: *const u8,
+    width: u32,
+    height: u32,
+    settings: *const PxlsSettings,
+    out_colours: *mut *mut u8,
+    out_len: *mut usize,
+) -> PxlsStatus {
+    if out_colours.is_null() || out_len.is_null() {
+        return PxlsStatus::NullPointer;
+    }
+
+    let result = (|| {
+        let image = borrow_image(rgba, width, height)?;
+        let (palette_settings, _, algorithm) = read_settings(settings).split()?;
+        Ok(get_palette_from_source(
+            &image,
+            palette_settings,
+            algorithm,
+            &NoProgress,
+            &CancellationToken::new(),
+        )?)
+    })();
+
+    match result {
+        Ok(palette) => {
+            let bytes: Vec<u8> = palette.iter().flat_map(|colour| colour.0).collect();
+            *out_len = palette.len();
+            *out_colours = give_away(bytes);
+            PxlsStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+//on success, `out_rgba` gets an `out_width` by `out_height` image to go back through `pxls_free_image`
+#[no_mangle]
+pub unsafe extern "C" fn pxls_dither(
+    rgba: *const u8,
+    width: u32,
+    height: u32,
+    colours: *const u8,
+    colours_len: usize,
+    settings: *const PxlsSettings,
+    out_rgba: *mut *mut u8,
+    out_width: *mut u32,
+    out_height: *mut u32,
+) -> PxlsStatus {
+    if out_rgba.is_null() || out_width.is_null() || out_height.is_null() {
+        return PxlsStatus::NullPointer;
+    }
+
+    let result = (|| {
+        let image = borrow_image(rgba, width, height)?;
+        if colours.is_null() {
+            return Err(PxlsStatus::NullPointer);
+        }
+        let colours_bytes = colours_len.checked_mul(4).ok_or(PxlsStatus::TooBig)?;
+        let palette: Vec<_> = slice::from_raw_parts(colours, colours_bytes)
+            .chunks_exact(4)
+            .map(|colour| Rgba([colour[0], colour[1], colour[2], colour[3]]))
+            .collect();
+        let (_, output_settings, algorithm) = read_settings(settings).split()?;
+
+        let mut output = RgbaImage::new(0, 0);
+        dither_into(
+            &image,
+            palette,
+            algorithm,
+            output_settings,
+            &mut output,
+            &NoProgress,
+            &CancellationToken::new(),
+        )?;
+        Ok(output)
+    })();
+
+    match result {
+        Ok(output) => {
+            *out_width = output.width();
+            *out_height = output.height();
+            *out_rgba = give_away(output.into_raw());
+            PxlsStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pxls_free_palette(colours: *mut u8, len: usize) {
+    take_back(colours, len * 4);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pxls_free_image(rgba: *mut u8, width: u32, height: u32) {
+    take_back(rgba, width as usize * height as usize * 4);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScaleError, ValidationError};
+    use std::ptr::{null, null_mut};
+
+    //red on the left and blue on the right
+    fn stripes(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                if i % width < width / 2 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 255]
+                }
+            })
+            .collect()
+    }
+
+    unsafe fn get_palette(
+        rgba: *const u8,
+        width: u32,
+        height: u32,
+        settings: *const PxlsSettings,
+    ) -> Result<Vec<u8>, PxlsStatus> {
+        let (mut colours, mut len) = (null_mut(), 0);
+        match pxls_get_palette(rgba, width, height, settings, &mut colours, &mut len) {
+            PxlsStatus::Ok => {
+                let copied = slice::from_raw_parts(colours, len * 4).to_vec();
+                pxls_free_palette(colours, len);
+                Ok(copied)
+            }
+            status => {
+                assert!(colours.is_null(), "nothing gets handed back on errors");
+                Err(status)
+            }
+        }
+    }
+
+    unsafe fn dither(
+        rgba: *const u8,
+        (width, height): (u32, u32),
+        colours: *const u8,
+        colours_len: usize,
+        settings: *const PxlsSettings,
+    ) -> Result<(Vec<u8>, u32, u32), PxlsStatus> {
+        let (mut out, mut out_width, mut out_height) = (null_mut(), 0, 0);
+        match pxls_dither(
+            rgba,
+            width,
+            height,
+            colours,
+            colours_len,
+            settings,
+            &mut out,
+            &mut out_width,
+            &mut out_height,
+        ) {
+            PxlsStatus::Ok => {
+                let copied =
+                    slice::from_raw_parts(out, (out_width * out_height * 4) as usize).to_vec();
+                pxls_free_image(out, out_width, out_height);
+                Ok((copied, out_width, out_height))
+            }
+            status => {
+                assert!(out.is_null(), "nothing gets handed back on errors");
+                Err(status)
+            }
+        }
+    }
+
+    #[test]
+    fn palettes_and_dithers_round_trip() {
+        let image = stripes(64, 64);
+        let settings = pxls_default_settings();
+        unsafe {
+            let palette = get_palette(image.as_ptr(), 64, 64, &settings).unwrap();
+            assert_eq!(palette.len(), 2 * 4);
+            assert!(palette.chunks_exact(4).any(|c| c == [255, 0, 0, 255]));
+            assert!(palette.chunks_exact(4).any(|c| c == [0, 0, 255, 255]));
+
+            let (output, width, height) =
+                dither(image.as_ptr(), (64, 64), palette.as_ptr(), 2, &settings).unwrap();
+            assert_eq!((width, height), (64, 64));
+            assert!(output
+                .chunks_exact(4)
+                .all(|px| palette.chunks_exact(4).any(|c| c == px)));
+        }
+    }
+
+    #[test]
+    fn null_settings_are_the_defaults() {
+        let image = stripes(64, 64);
+        unsafe {
+            assert_eq!(
+                get_palette(image.as_ptr(), 64, 64, null()),
+                get_palette(image.as_ptr(), 64, 64, &pxls_default_settings())
+            );
+        }
+    }
+
+    #[test]
+    fn null_pointers_are_rejected() {
+        let image = stripes(64, 64);
+        let colours = [0, 0, 0, 255];
+        let (mut out, mut len, mut out_width, mut out_height) = (null_mut(), 0, 0, 0);
+        unsafe {
+            assert_eq!(
+                get_palette(null(), 64, 64, null()),
+                Err(PxlsStatus::NullPointer)
+            );
+            for (out_colours, out_len) in [(null_mut(), &raw mut len), (&raw mut out, null_mut())] {
+                assert_eq!(
+                    pxls_get_palette(image.as_ptr(), 64, 64, null(), out_colours, out_len),
+                    PxlsStatus::NullPointer
+                );
+            }
+
+            assert_eq!(
+                dither(null(), (64, 64), colours.as_ptr(), 1, null()),
+                Err(PxlsStatus::NullPointer)
+            );
+            assert_eq!(
+                dither(image.as_ptr(), (64, 64), null(), 1, null()),
+                Err(PxlsStatus::NullPointer)
+            );
+            for (out_rgba, out_width, out_height) in [
+                (null_mut(), &raw mut out_width, &raw mut out_height),
+                (&raw mut out, null_mut(), &raw mut out_height),
+                (&raw mut out, &raw mut out_width, null_mut()),
+            ] {
+                assert_eq!(
+                    pxls_dither(
+                        image.as_ptr(),
+                        64,
+                        64,
+                        colours.as_ptr(),
+                        1,
+                        null(),
+                        out_rgba,
+                        out_width,
+                        out_height,
+                    ),
+                    PxlsStatus::NullPointer
+                );
+            }
+        }
+        assert!(out.is_null());
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        let image = stripes(64, 64);
+        let colours = [0, 0, 0, 255];
+        unsafe {
+            //too big to even work out how many bytes there would be, so nothing gets read
+            assert_eq!(
+                get_palette(image.as_ptr(), u32::MAX, u32::MAX, null()),
+                Err(PxlsStatus::TooBig)
+            );
+            assert_eq!(
+                dither(
+                    image.as_ptr(),
+                    (64, 64),
+                    colours.as_ptr(),
+                    usize::MAX,
+                    null()
+                ),
+                Err(PxlsStatus::TooBig)
+            );
+
+            assert_eq!(
+                get_palette(image.as_ptr(), 0, 64, null()),
+                Err(PxlsStatus::ZeroDimension)
+            );
+            assert_eq!(
+                dither(image.as_ptr(), (64, 0), colours.as_ptr(), 1, null()),
+                Err(PxlsStatus::ZeroDimension)
+            );
+            assert_eq!(
+                dither(image.as_ptr(), (64, 64), colours.as_ptr(), 0, null()),
+                Err(PxlsStatus::EmptyPalette)
+            );
+        }
+    }
+
+    #[test]
+    fn bad_settings_are_rejected() {
+        let image = stripes(64, 64);
+        let colours = [0, 0, 0, 255];
+        let settings = |change: fn(&mut PxlsSettings)| {
+            let mut settings = pxls_default_settings();
+            change(&mut settings);
+            settings
+        };
+        unsafe {
+            assert_eq!(
+                get_palette(
+                    image.as_ptr(),
+                    64,
+                    64,
+                    &settings(|s| s.distance_algorithm = ALL_ALGOS.len() as u32)
+                ),
+                Err(PxlsStatus::UnknownAlgorithm)
+            );
+            assert_eq!(
+                get_palette(
+                    image.as_ptr(),
+                    64,
+                    64,
+                    &settings(|s| s.chunks_per_dimension = 0)
+                ),
+                Err(PxlsStatus::InvalidSettings)
+            );
+            assert_eq!(
+                dither(
+                    image.as_ptr(),
+                    (64, 64),
+                    colours.as_ptr(),
+                    1,
+                    &settings(|s| s.output_px_size = 0)
+                ),
+                Err(PxlsStatus::InvalidSettings)
+            );
+        }
+    }
+
+    #[test]
+    fn freeing_null_does_nothing() {
+        unsafe {
+            pxls_free_palette(null_mut(), 0);
+            pxls_free_palette(null_mut(), 5);
+            pxls_free_image(null_mut(), 4, 4);
+        }
+    }
+
+    #[test]
+    fn distances_match_all_algos() {
+        for (distance, algorithm) in [
+            PxlsDistance::Euclidean,
+            PxlsDistance::HsvEuclidean,
+            PxlsDistance::Manhattan,
+            PxlsDistance::Luminance,
+            PxlsDistance::Value,
+        ]
+        .into_iter()
+        .zip(ALL_ALGOS)
+        {
+            let settings = PxlsSettings {
+                distance_algorithm: distance as u32,
+                ..pxls_default_settings()
+            };
+            assert_eq!(settings.split().unwrap().2, *algorithm);
+        }
+    }
+
+    #[test]
+    fn statuses_mirror_pxls_errors() {
+        for (error, status) in [
+            (
+                PxlsError::InvalidSettings(ValidationError::NoChunks),
+                PxlsStatus::InvalidSettings,
+            ),
+            (PxlsError::EmptyPalette, PxlsStatus::EmptyPalette),
+            (
+                PxlsError::ImageTooSmall { needed: 2, got: 1 },
+                PxlsStatus::ImageTooSmall,
+            ),
+            (PxlsError::Cancelled, PxlsStatus::Cancelled),
+            (
+                PxlsError::Scale(ScaleError::NoScaleFactor),
+                PxlsStatus::Scale,
+            ),
+            (PxlsError::ZeroDimension, PxlsStatus::ZeroDimension),
+        ] {
+            assert_eq!(PxlsStatus::from(error), status);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//GPU version of `dither_original_with_palette`, mainly for the web where the CPU path is painfully slow.
+//everything is async because WebGPU can't block on buffer reads.
+
+use crate::{
+    check_not_empty, dither_chunk_size, pixel_perfect_scale, DistanceAlgorithm, DitherMode,
+    DitheringMode, OutputSettings, PxlsError,
+};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use std::{
+    fmt::{Display, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use wgpu::util::DeviceExt;
+
+mod palette;
+pub use palette::GpuPaletteExtractor;
+
+const DITHER_SHADER: &str = include_str!("gpu/dither.wgsl");
+//has to match `INDEX_BITS` in the shader
+const MAX_PALETTE_LEN: usize = 1 << 12;
+
+#[derive(Debug)]
+pub enum GpuError {
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    BufferMap(wgpu::BufferAsyncError),
+    PaletteTooBig(usize),
+    EmptyPalette,
+    UnsupportedDitherMode(DitherMode),
+    //the same checks as the CPU path, made before anything goes near the GPU
+    Invalid(PxlsError),
+    Scaling(PxlsError),
+}
+
+impl Display for GpuError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoAdapter => write!(f, "unable to find a GPU adapter"),
+            Self::RequestDevice(e) => write!(f, "unable to get a GPU device: {e}"),
+            Self::BufferMap(e) => write!(f, "unable to read back from the GPU: {e}"),
+            Self::PaletteTooBig(len) => write!(
+                f,
+                "palette has {len} colours, but the GPU path only supports up to {MAX_PALETTE_LEN}"
+            ),
+            Self::EmptyPalette => write!(f, "palette is empty"),
+            Self::UnsupportedDitherMode(mode) => {
+                write!(f, "{mode} dithering isn't supported on the GPU")
+            }
+            Self::Invalid(e) => write!(f, "{e}"),
+            Self::Scaling(e) => write!(f, "unable to scale the output: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+const fn algorithm_index(algo: DistanceAlgorithm) -> u32 {
+    match algo {
+        DistanceAlgorithm::Euclidean => 0,
+        DistanceAlgorithm::HSVEuclidean => 1,
+        DistanceAlgorithm::Manhattan => 2,
+        DistanceAlgorithm::Luminance => 3,
+        DistanceAlgorithm::Value => 4,
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+pub async fn dither_original_with_palette_gpu(
+    input: &DynamicImage,
+    palette: &[Rgba<u8>],
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+) -> Result<DynamicImage, GpuError> {
+    let output_settings = output_settings
+        .validated()
+        .map_err(|e| GpuError::Invalid(e.into()))?;
+    if palette.is_empty() {
+        return Err(GpuError::EmptyPalette);
+    }
+    if palette.len() > MAX_PALETTE_LEN {
+        return Err(GpuError::PaletteTooBig(palette.len()));
+    }
+    if output_settings.dither_mode != DitherMode::Legacy {
+        return Err(GpuError::UnsupportedDitherMode(output_settings.dither_mode));
+    }
+    check_not_empty(input).map_err(GpuError::Invalid)?;
+    let output_px_size =
+        dither_chunk_size(input.dimensions(), output_settings).map_err(GpuError::Invalid)?;
+
+    let (width, height) = input.dimensions();
+    let (num_width_chunks, num_height_chunks) = (width / output_px_size, height / output_px_size);
+    let (output_w, output_h) = (
+        num_width_chunks * output_settings.dithering_scale,
+        num_height_chunks * output_settings.dithering_scale,
+    );
+
+    let (device, queue) = request_device("pxls-dither").await?;
+
+    let input_texture = upload_input(&device, &queue, input);
+
+    //the shader unpacks these as little-endian `u32`s, so red ends up in the lowest byte
+    let palette_bytes: Vec<u8> = palette
+        .iter()
+        .flat_map(|Rgba(channels)| *channels)
+        .collect();
+    let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("pxls-palette"),
+        contents: &palette_bytes,
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    //the mode gets a flag, and then the ratio or the bits of the fraction
+    let (dithering_mode, dithering_value) = match output_settings.dithering_mode {
+        DitheringMode::Ratio(ratio) => (0, ratio),
+        DitheringMode::Fraction(fraction) => (1, fraction.to_bits()),
+    };
+    let params: Vec<u8> = [
+        output_px_size,
+        output_settings.dithering_scale,
+        dithering_mode,
+        dithering_value,
+        algorithm_index(distance_algorithm),
+        palette.len() as u32,
+        0,
+        0,
+    ]
+    .into_iter()
+    .flat_map(u32::to_le_bytes)
+    .collect();
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("pxls-params"),
+        contents: &params,
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let output_size = wgpu::Extent3d {
+        width: output_w,
+        height: output_h,
+        depth_or_array_layers: 1,
+    };
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("pxls-output"),
+        size: output_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("pxls-dither-shader"),
+        source: wgpu::ShaderSource::Wgsl(DITHER_SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("pxls-dither-pipeline"),
+        layout: None,
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("pxls-dither-bind-group"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &input_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: palette_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(
+                    &output_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    //texture -> buffer copies need each row padded out
+    let unpadded_bytes_per_row = output_w * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("pxls-readback"),
+        size: padded_bytes_per_row as u64 * output_h as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(num_width_chunks, num_height_chunks, 1);
+    }
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture: &output_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &readback_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(output_h),
+            },
+        },
+        output_size,
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = readback_buffer.slice(..);
+    map_for_reading(&device, &slice).await?;
+
+    let mut rgb = Vec::with_capacity((output_w * output_h * 3) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks_exact(padded_bytes_per_row as usize) {
+            for px in row[..(unpadded_bytes_per_row as usize)].chunks_exact(4) {
+                rgb.extend_from_slice(&px[..3]);
+            }
+        }
+    }
+    readback_buffer.unmap();
+
+    let output = DynamicImage::ImageRgb8(
+        ImageBuffer::from_raw(output_w, output_h, rgb)
+            .expect("buffer is always the right size for the output"),
+    );
+    pixel_perfect_scale(output_settings, &output).map_err(GpuError::Scaling)
+}
+
+fn upload_input(device: &wgpu::Device, queue: &wgpu::Queue, input: &DynamicImage) -> wgpu::Texture {
+    let (width, height) = input.dimensions();
+    let input_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let input_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("pxls-input"),
+        size: input_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &input_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &input.to_rgba8(),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(width * 4),
+            rows_per_image: Some(height),
+        },
+        input_size,
+    );
+
+    input_texture
+}
+
+async fn request_device(label: &str) -> Result<(wgpu::Device, wgpu::Queue), GpuError> {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .ok_or(GpuError::NoAdapter)?;
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some(label),
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        )
+        .await
+        .map_err(GpuError::RequestDevice)
+}
+
+async fn map_for_reading(
+    device: &wgpu::Device,
+    slice: &wgpu::BufferSlice<'_>,
+) -> Result<(), GpuError> {
+    let mapped = MapFuture::default();
+    {
+        let state = mapped.0.clone();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let mut state = state.lock().unwrap();
+            state.0 = Some(result);
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        });
+    }
+    //on the web the browser drives this for us
+    #[cfg(not(target_arch = "wasm32"))]
+    device.poll(wgpu::Maintain::Wait);
+    mapped.await.map_err(GpuError::BufferMap)
+}
+
+type MapState = (Option<Result<(), wgpu::BufferAsyncError>>, Option<Waker>);
+
+#[derive(Default)]
+struct MapFuture(Arc<Mutex<MapState>>);
+
+impl Future for MapFuture {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        state.0.take().map_or_else(
+            || {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            },
+            Poll::Ready,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ValidationError;
+
+    //everything here fails before the first await, so it never needs a GPU or a real executor
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        let mut future = std::pin::pin!(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("got as far as the GPU"),
+        }
+    }
+
+    fn dither(
+        input: &DynamicImage,
+        output_settings: OutputSettings,
+    ) -> Result<DynamicImage, GpuError> {
+        ready(dither_original_with_palette_gpu(
+            input,
+            &[Rgba([0, 0, 0, 255])],
+            DistanceAlgorithm::Euclidean,
+            output_settings,
+        ))
+    }
+
+    #[test]
+    fn bad_px_sizes_are_rejected_before_shifting() {
+        let input = DynamicImage::new_rgb8(16, 16);
+        for (output_px_size, error) in [
+            (0, ValidationError::NoOutputPxSize),
+            (33, ValidationError::OutputPxSizeTooBig(33)),
+            (u32::MAX, ValidationError::OutputPxSizeTooBig(u32::MAX)),
+        ] {
+            let settings = OutputSettings {
+                output_px_size,
+                ..OutputSettings::default()
+            };
+            assert!(matches!(
+                dither(&input, settings),
+                Err(GpuError::Invalid(PxlsError::InvalidSettings(e))) if e == error
+            ));
+        }
+    }
+
+    #[test]
+    fn empty_and_small_images_are_rejected() {
+        assert!(matches!(
+            dither(&DynamicImage::new_rgb8(0, 16), OutputSettings::default()),
+            Err(GpuError::Invalid(PxlsError::ZeroDimension))
+        ));
+        assert!(matches!(
+            dither(&DynamicImage::new_rgb8(16, 2), OutputSettings::default()),
+            Err(GpuError::Invalid(PxlsError::ImageTooSmall { .. }))
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//this mirrors `dither_original_with_palette`, with one workgroup per chunk
+
+struct Params {
+    output_px_size: u32,
+    dithering_scale: u32,
+    dithering_mode: u32,
+    //either the ratio, or the bits of the fraction
+    dithering_value: u32,
+    distance_algorithm: u32,
+    palette_len: u32,
+}
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read> palette: array<u32>;
+@group(0) @binding(2) var output: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+const WORKGROUP_SIZE: u32 = 64u;
+//distances get packed into the top bits with the palette index in the bottom bits, so that one atomicMin can find both
+const INDEX_BITS: u32 = 12u;
+const INDEX_MASK: u32 = 4095u;
+const MAX_DISTANCE: u32 = 1048575u;
+const NOTHING: u32 = 4294967295u;
+
+const EUCLIDEAN: u32 = 0u;
+const HSV_EUCLIDEAN: u32 = 1u;
+const MANHATTAN: u32 = 2u;
+const LUMINANCE: u32 = 3u;
+const VALUE: u32 = 4u;
+
+const RATIO: u32 = 0u;
+
+var<workgroup> accum_r: atomic<u32>;
+var<workgroup> accum_g: atomic<u32>;
+var<workgroup> accum_b: atomic<u32>;
+var<workgroup> first_packed: atomic<u32>;
+var<workgroup> second_packed: atomic<u32>;
+
+fn unpack_colour(packed: u32) -> vec3<u32> {
+    return vec3<u32>(packed & 255u, (packed >> 8u) & 255u, (packed >> 16u) & 255u);
+}
+
+fn abs_diff(a: u32, b: u32) -> u32 {
+    return max(a, b) - min(a, b);
+}
+
+fn luminance(c: vec3<u32>) -> u32 {
+    return (c.r * c.r * 299u / 1000u) + (c.g * c.g * 587u / 1000u) + (c.b * c.b * 57u / 500u);
+}
+
+fn rgb_to_hsv(c: vec3<u32>) -> vec3<u32> {
+    let min_channel = min(min(c.r, c.g), c.b);
+    let max_channel = max(max(c.r, c.g), c.b);
+    let delta = f32(max_channel - min_channel);
+    let cf = vec3<f32>(c);
+
+    var hue = 0.0;
+    if min_channel == max_channel {
+        hue = 0.0;
+    } else if max_channel == c.r {
+        hue = (cf.g - cf.b) / delta;
+    } else if max_channel == c.g {
+        hue = 2.0 + (cf.b - cf.r) / delta;
+    } else {
+        hue = 4.0 + (cf.r - cf.g) / delta;
+    }
+
+    hue *= 60.0;
+    if hue < 0.0 {
+        hue += 360.0;
+    }
+
+    var saturation = 0u;
+    if max_channel != 0u {
+        saturation = u32(floor(delta / f32(max_channel) + 0.5));
+    }
+
+    return vec3<u32>(u32(floor(hue + 0.5)), saturation, max_channel);
+}
+
+fn colour_distance(a: vec3<u32>, b: vec3<u32>) -> u32 {
+    switch params.distance_algorithm {
+        case HSV_EUCLIDEAN: {
+            let a_hsv = rgb_to_hsv(a);
+            let b_hsv = rgb_to_hsv(b);
+            let delta_h = abs_diff(a_hsv.x, b_hsv.x);
+            let delta_s = abs_diff(a_hsv.y, b_hsv.y);
+            let delta_v = abs_diff(a_hsv.z, b_hsv.z);
+            return delta_h * delta_h + delta_s * delta_s + delta_v * delta_v;
+        }
+        case MANHATTAN: {
+            return abs_diff(a.r, b.r) + abs_diff(a.g, b.g) + abs_diff(a.b, b.b);
+        }
+        case LUMINANCE: {
+            return abs_diff(luminance(a), luminance(b));
+        }
+        case VALUE: {
+            return abs_diff(max(max(a.r, a.g), a.b), max(max(b.r, b.g), b.b));
+        }
+        default: {
+            let delta_r = abs_diff(a.r, b.r);
+            let delta_g = abs_diff(a.g, b.g);
+            let delta_b = abs_diff(a.b, b.b);
+            return delta_r * delta_r + delta_g * delta_g + delta_b * delta_b;
+        }
+    }
+}
+
+fn pack(distance: u32, index: u32) -> u32 {
+    return (min(distance, MAX_DISTANCE) << INDEX_BITS) | index;
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(workgroup_id) chunk: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    if local == 0u {
+        atomicStore(&first_packed, NOTHING);
+        atomicStore(&second_packed, NOTHING);
+    }
+    workgroupBarrier();
+
+    //first, every thread averages a strided slice of the chunk
+    let size = params.output_px_size;
+    let origin = chunk.xy * size;
+    let total = size * size;
+
+    var r = 0u;
+    var g = 0u;
+    var b = 0u;
+    for (var i = local; i < total; i += WORKGROUP_SIZE) {
+        let position = origin + vec2<u32>(i % size, i / size);
+        let px = vec3<u32>(round(textureLoad(input, vec2<i32>(position), 0).rgb * 255.0));
+        r += px.r;
+        g += px.g;
+        b += px.b;
+    }
+    atomicAdd(&accum_r, r);
+    atomicAdd(&accum_g, g);
+    atomicAdd(&accum_b, b);
+    workgroupBarrier();
+
+    let av_px = vec3<u32>(
+        atomicLoad(&accum_r) / total,
+        atomicLoad(&accum_g) / total,
+        atomicLoad(&accum_b) / total,
+    );
+
+    //then split the palette between the threads to find the nearest two
+    for (var i = local; i < params.palette_len; i += WORKGROUP_SIZE) {
+        atomicMin(&first_packed, pack(colour_distance(unpack_colour(palette[i]), av_px), i));
+    }
+    workgroupBarrier();
+
+    let first_index = atomicLoad(&first_packed) & INDEX_MASK;
+    for (var i = local; i < params.palette_len; i += WORKGROUP_SIZE) {
+        if i != first_index {
+            atomicMin(&second_packed, pack(colour_distance(unpack_colour(palette[i]), av_px), i));
+        }
+    }
+    workgroupBarrier();
+
+    if local != 0u {
+        return;
+    }
+
+    let first = atomicLoad(&first_packed);
+    let second = atomicLoad(&second_packed);
+
+    let first_colour = unpack_colour(palette[first_index]);
+    var second_colour = first_colour;
+    var second_distance = NOTHING;
+    if second != NOTHING {
+        second_colour = unpack_colour(palette[second & INDEX_MASK]);
+        second_distance = second >> INDEX_BITS;
+    }
+
+    let first_distance = first >> INDEX_BITS;
+    var should_dither = false;
+    if params.dithering_mode == RATIO {
+        should_dither = abs_diff(first_distance, second_distance) <= colour_distance(first_colour, second_colour) / params.dithering_value;
+    } else {
+        let fraction = bitcast<f32>(params.dithering_value);
+        var ratio = 1.0;
+        if second_distance != 0u {
+            ratio = f32(first_distance) / f32(second_distance);
+        }
+        should_dither = fraction > 0.0 && ratio >= 1.0 - fraction;
+    }
+
+    if !should_dither {
+        second_colour = first_colour;
+    }
+
+    let scale = params.dithering_scale;
+    for (var px_x = scale * chunk.x; px_x < scale * (chunk.x + 1u); px_x++) {
+        for (var px_y = scale * chunk.y; px_y < scale * (chunk.y + 1u); px_y++) {
+            var is_even_px = px_y % 2u == 0u;
+            if px_x % 2u == 0u {
+                is_even_px = !is_even_px;
+            }
+            is_even_px = is_even_px && scale > 1u;
+
+            var colour = second_colour;
+            if is_even_px {
+                colour = first_colour;
+            }
+
+            textureStore(output, vec2<i32>(vec2<u32>(px_x, px_y)), vec4<f32>(vec3<f32>(colour) / 255.0, 1.0));
+        }
+    }
+}
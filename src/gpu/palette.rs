@@ -0,0 +1,331 @@
+//GPU version of `get_palette`. the GPU only counts the colours in each chunk - which one gets picked depends on
+//everything picked before it, so that part stays on the CPU.
+
+use super::{map_for_reading, request_device, upload_input};
+use crate::{
+    check_not_empty, dedup_palette, get_closest_factor, DistanceAlgorithm, Palette,
+    PaletteSettings, PxlsError,
+};
+use image::{DynamicImage, GenericImageView, Rgba};
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+
+const PALETTE_SHADER: &str = include_str!("palette.wgsl");
+//has to match `SLOTS` in the shader
+const HISTOGRAM_SLOTS: usize = 1024;
+//a key and a 64-bit count (as low then high halves) for each slot, and then the count of transparent black and the
+//overflow flag in the same layout
+const SLOT_STRIDE: usize = 3;
+const CHUNK_STRIDE: usize = (HISTOGRAM_SLOTS + 1) * SLOT_STRIDE;
+//chunks get counted in batches, so the readback buffer doesn't get huge with lots of chunks
+const CHUNKS_PER_BATCH: u32 = 512;
+
+pub struct GpuPaletteExtractor {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuPaletteExtractor {
+    pub async fn new() -> anyhow::Result<Self> {
+        let (device, queue) = request_device("pxls-palette").await?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("pxls-palette-shader"),
+            source: wgpu::ShaderSource::Wgsl(PALETTE_SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("pxls-palette-pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    pub async fn get_palette(
+        &self,
+        image: &DynamicImage,
+        PaletteSettings {
+            chunks_per_dimension,
+            closeness_threshold,
+            exclude_colors,
+            exclude_threshold,
+            extra_colors,
+        }: PaletteSettings,
+        dist_algo: DistanceAlgorithm,
+    ) -> anyhow::Result<Palette> {
+        let (chunks_per_dimension, chunk_width, chunk_height) =
+            chunk_layout(image, chunks_per_dimension, closeness_threshold, dist_algo)?;
+        let num_chunks = chunks_per_dimension * chunks_per_dimension;
+
+        let closeness_threshold = dist_algo.standardise_closeness_threshold(closeness_threshold);
+        let exclude_threshold = dist_algo.standardise_closeness_threshold(exclude_threshold);
+
+        let input_texture = upload_input(&self.device, &self.queue, image);
+        let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut av_px_colours: Vec<Rgba<u8>> = Vec::with_capacity(num_chunks as usize);
+        for first_chunk in (0..num_chunks).step_by(CHUNKS_PER_BATCH as usize) {
+            let batch_len = CHUNKS_PER_BATCH.min(num_chunks - first_chunk);
+            let histograms = self
+                .count_batch(
+                    &input_view,
+                    [chunks_per_dimension, chunk_width, chunk_height, first_chunk],
+                    batch_len,
+                )
+                .await?;
+
+            for (offset, histogram) in histograms.chunks_exact(CHUNK_STRIDE).enumerate() {
+                let chunk = first_chunk + offset as u32;
+                //too many colours to fit in the GPU's table get counted on the CPU instead
+                let counts = read_histogram(histogram).unwrap_or_else(|| {
+                    count_chunk_on_cpu(
+                        image,
+                        (chunk / chunks_per_dimension) * chunk_width,
+                        (chunk % chunks_per_dimension) * chunk_height,
+                        chunk_width,
+                        chunk_height,
+                    )
+                });
+
+                let most_common = counts
+                    .into_iter()
+                    .filter(|(px, _)| {
+                        !exclude_colors
+                            .iter()
+                            .any(|excluded| dist_algo.distance(*px, *excluded) < exclude_threshold)
+                            && !av_px_colours.iter().any(|so_far| {
+                                dist_algo.distance(*px, *so_far) < closeness_threshold
+                            })
+                    })
+                    .max_by_key(|(_, count)| *count);
+                if let Some((most_common, _)) = most_common {
+                    av_px_colours.push(most_common);
+                }
+            }
+        }
+
+        av_px_colours.extend(extra_colors);
+
+        Ok(dedup_palette(av_px_colours).into())
+    }
+
+    async fn count_batch(
+        &self,
+        input_view: &wgpu::TextureView,
+        params: [u32; 4],
+        batch_len: u32,
+    ) -> anyhow::Result<Vec<u32>> {
+        let params: Vec<u8> = params.into_iter().flat_map(u32::to_le_bytes).collect();
+        let params_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("pxls-palette-params"),
+                contents: &params,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let histograms_size = (batch_len as usize * CHUNK_STRIDE * 4) as u64;
+        let histograms_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pxls-palette-histograms"),
+            size: histograms_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pxls-palette-readback"),
+            size: histograms_size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pxls-palette-bind-group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histograms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(batch_len, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&histograms_buffer, 0, &readback_buffer, 0, histograms_size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = readback_buffer.slice(..);
+        map_for_reading(&self.device, &slice).await?;
+        let histograms = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        readback_buffer.unmap();
+
+        Ok(histograms)
+    }
+}
+
+//the same checks as the CPU path, so that nothing gets divided by 0 or sent to the GPU as an empty chunk
+fn chunk_layout(
+    image: &DynamicImage,
+    chunks_per_dimension: u32,
+    closeness_threshold: u32,
+    dist_algo: DistanceAlgorithm,
+) -> Result<(u32, u32, u32), PxlsError> {
+    PaletteSettings {
+        chunks_per_dimension,
+        closeness_threshold,
+        ..PaletteSettings::default()
+    }
+    .validated(dist_algo)?;
+    check_not_empty(image)?;
+
+    let chunks_per_dimension =
+        get_closest_factor(chunks_per_dimension, image.width().min(image.height()));
+    Ok((
+        chunks_per_dimension,
+        image.width() / chunks_per_dimension,
+        image.height() / chunks_per_dimension,
+    ))
+}
+
+//the counts from one chunk of the shader's output, or `None` if the chunk had too many colours to count
+fn read_histogram(histogram: &[u32]) -> Option<HashMap<Rgba<u8>, u64>> {
+    let count = |slot: &[u32]| u64::from(slot[1]) | (u64::from(slot[2]) << 32);
+    let (slots, [empty_lo, empty_hi, overflowed]) = histogram.split_at(CHUNK_STRIDE - SLOT_STRIDE)
+    else {
+        unreachable!("histograms are always CHUNK_STRIDE long");
+    };
+    if *overflowed != 0 {
+        return None;
+    }
+
+    //the same colour can be in more than one slot
+    let mut counts: HashMap<_, u64> = HashMap::new();
+    for slot in slots.chunks_exact(SLOT_STRIDE) {
+        if count(slot) > 0 {
+            *counts.entry(Rgba(slot[0].to_le_bytes())).or_default() += count(slot);
+        }
+    }
+    let empty = count(&[0, *empty_lo, *empty_hi]);
+    if empty > 0 {
+        counts.insert(Rgba([0; 4]), empty);
+    }
+    Some(counts)
+}
+
+fn count_chunk_on_cpu(
+    image: &DynamicImage,
+    start_x: u32,
+    start_y: u32,
+    width: u32,
+    height: u32,
+) -> HashMap<Rgba<u8>, u64> {
+    let mut counts: HashMap<_, u64> = HashMap::new();
+    for px_x in start_x..(start_x + width) {
+        for px_y in start_y..(start_y + height) {
+            *counts.entry(image.get_pixel(px_x, px_y)).or_default() += 1;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ValidationError;
+
+    fn layout(width: u32, height: u32, chunks: u32) -> Result<(u32, u32, u32), PxlsError> {
+        chunk_layout(
+            &DynamicImage::new_rgb8(width, height),
+            chunks,
+            PaletteSettings::default().closeness_threshold,
+            DistanceAlgorithm::Euclidean,
+        )
+    }
+
+    fn histogram(slots: &[(u32, u64)], empty: u64, overflowed: bool) -> Vec<u32> {
+        let mut histogram = vec![0; CHUNK_STRIDE];
+        for (slot, (key, count)) in histogram.chunks_exact_mut(SLOT_STRIDE).zip(slots) {
+            slot.copy_from_slice(&[*key, *count as u32, (*count >> 32) as u32]);
+        }
+        histogram[CHUNK_STRIDE - 3..].copy_from_slice(&[
+            empty as u32,
+            (empty >> 32) as u32,
+            u32::from(overflowed),
+        ]);
+        histogram
+    }
+
+    #[test]
+    fn empty_images_and_zero_chunks_are_rejected() {
+        assert!(matches!(layout(0, 16, 4), Err(PxlsError::ZeroDimension)));
+        assert!(matches!(layout(16, 0, 4), Err(PxlsError::ZeroDimension)));
+        assert!(matches!(
+            layout(16, 16, 0),
+            Err(PxlsError::InvalidSettings(ValidationError::NoChunks))
+        ));
+    }
+
+    #[test]
+    fn chunks_are_never_empty() {
+        for (width, height) in [(1, 1), (1, 300), (7, 5), (64, 64), (1000, 3)] {
+            let (chunks, chunk_width, chunk_height) = layout(width, height, 32).unwrap();
+            assert!(chunks > 0 && chunk_width > 0 && chunk_height > 0);
+            assert!(chunks * chunk_width <= width && chunks * chunk_height <= height);
+        }
+    }
+
+    #[test]
+    fn counts_past_u32_max_are_kept() {
+        let red = u32::from_le_bytes([255, 0, 0, 255]);
+        let big = u64::from(u32::MAX) + 5;
+        let counts = read_histogram(&histogram(&[(red, big), (red, 3)], big, false)).unwrap();
+        assert_eq!(counts[&Rgba([255, 0, 0, 255])], big + 3);
+        assert_eq!(counts[&Rgba([0; 4])], big);
+    }
+
+    #[test]
+    fn overflowed_chunks_go_to_the_cpu() {
+        assert!(read_histogram(&histogram(&[(1, 1)], 0, true)).is_none());
+    }
+
+    #[test]
+    fn shader_is_valid() {
+        use wgpu::naga::{
+            front::wgsl,
+            valid::{Capabilities, ValidationFlags, Validator},
+        };
+
+        let module = wgsl::parse_str(PALETTE_SHADER).unwrap();
+        Validator::new(ValidationFlags::all(), Capabilities::empty())
+            .validate(&module)
+            .unwrap();
+    }
+}
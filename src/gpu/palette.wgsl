@@ -0,0 +1,142 @@
+//counts the colours in each chunk for `get_palette`, with one workgroup per chunk
+//picking the colour still happens on the CPU, since it depends on every chunk before it
+
+struct Params {
+    chunks_per_dimension: u32,
+    chunk_width: u32,
+    chunk_height: u32,
+    //which chunk the first workgroup of this dispatch is for
+    first_chunk: u32,
+}
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> histograms: array<u32>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+const WORKGROUP_SIZE: u32 = 64u;
+//has to match `HISTOGRAM_SLOTS` in gpu.rs
+const SLOTS: u32 = 1024u;
+const SLOT_MASK: u32 = 1023u;
+//a packed colour of 0 (transparent black) marks an empty slot, so those get counted separately
+const EMPTY: u32 = 0u;
+const EMPTY_SLOT: u32 = SLOTS;
+const COUNT_SLOTS: u32 = SLOTS + 1u;
+
+var<workgroup> keys: array<atomic<u32>, SLOTS>;
+//counts are split into low and high halves, since a big enough chunk can have more than `u32::MAX` of one colour.
+//the extra one on the end is for transparent black
+var<workgroup> counts_lo: array<atomic<u32>, COUNT_SLOTS>;
+var<workgroup> counts_hi: array<atomic<u32>, COUNT_SLOTS>;
+var<workgroup> overflowed: atomic<u32>;
+
+fn pack_colour(c: vec4<f32>) -> u32 {
+    let channels = vec4<u32>(round(c * 255.0));
+    return channels.r | (channels.g << 8u) | (channels.b << 16u) | (channels.a << 24u);
+}
+
+fn home_slot(key: u32) -> u32 {
+    //fibonacci hashing
+    return (key * 2654435761u) >> 22u;
+}
+
+//only uses exchanges, since compare-exchange isn't supported everywhere. a key that gets knocked out of its slot
+//carries on along the probe sequence, so the slots between a key's home and wherever it ends up are never empty.
+//the same key can end up in two slots, but the CPU adds those together anyway.
+fn insert(key: u32) {
+    var carried = key;
+    var slot = home_slot(key);
+    for (var probes = 0u; probes < SLOTS; probes += 1u) {
+        let old = atomicExchange(&keys[slot], carried);
+        if old == EMPTY || old == carried {
+            return;
+        }
+
+        carried = old;
+        slot = (slot + 1u) & SLOT_MASK;
+    }
+    atomicStore(&overflowed, 1u);
+}
+
+//exactly one of the adds that wraps the low half around sees `u32::MAX`, so carries never get lost
+fn add_one(slot: u32) {
+    if atomicAdd(&counts_lo[slot], 1u) == 0xffffffffu {
+        atomicAdd(&counts_hi[slot], 1u);
+    }
+}
+
+fn count(key: u32) {
+    var slot = home_slot(key);
+    for (var probes = 0u; probes < SLOTS; probes += 1u) {
+        let existing = atomicLoad(&keys[slot]);
+        if existing == key {
+            add_one(slot);
+            return;
+        }
+        if existing == EMPTY {
+            break;
+        }
+        slot = (slot + 1u) & SLOT_MASK;
+    }
+    atomicStore(&overflowed, 1u);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    for (var i = local_index; i < SLOTS; i += WORKGROUP_SIZE) {
+        atomicStore(&keys[i], EMPTY);
+    }
+    for (var i = local_index; i <= EMPTY_SLOT; i += WORKGROUP_SIZE) {
+        atomicStore(&counts_lo[i], 0u);
+        atomicStore(&counts_hi[i], 0u);
+    }
+    if local_index == 0u {
+        atomicStore(&overflowed, 0u);
+    }
+    workgroupBarrier();
+
+    //same order as the CPU: down each column of chunks, then across
+    let chunk = params.first_chunk + workgroup_id.x;
+    let chunk_x = chunk / params.chunks_per_dimension;
+    let chunk_y = chunk % params.chunks_per_dimension;
+    let origin = vec2<u32>(chunk_x * params.chunk_width, chunk_y * params.chunk_height);
+
+    //first work out which colours there are, and then go back and count them.
+    //goes a row at a time, since the width times the height of a chunk might not fit in a u32
+    for (var y = 0u; y < params.chunk_height; y += 1u) {
+        for (var x = local_index; x < params.chunk_width; x += WORKGROUP_SIZE) {
+            let key = pack_colour(textureLoad(input, origin + vec2<u32>(x, y), 0));
+            if key != EMPTY {
+                insert(key);
+            }
+        }
+    }
+    workgroupBarrier();
+
+    for (var y = 0u; y < params.chunk_height; y += 1u) {
+        for (var x = local_index; x < params.chunk_width; x += WORKGROUP_SIZE) {
+            let key = pack_colour(textureLoad(input, origin + vec2<u32>(x, y), 0));
+            if key == EMPTY {
+                add_one(EMPTY_SLOT);
+            } else {
+                count(key);
+            }
+        }
+    }
+    workgroupBarrier();
+
+    //each chunk gets (key, count low, count high) for every slot, then (empty low, empty high, overflowed)
+    let base = workgroup_id.x * (SLOTS + 1u) * 3u;
+    for (var i = local_index; i < SLOTS; i += WORKGROUP_SIZE) {
+        histograms[base + i * 3u] = atomicLoad(&keys[i]);
+        histograms[base + i * 3u + 1u] = atomicLoad(&counts_lo[i]);
+        histograms[base + i * 3u + 2u] = atomicLoad(&counts_hi[i]);
+    }
+    if local_index == 0u {
+        histograms[base + SLOTS * 3u] = atomicLoad(&counts_lo[EMPTY_SLOT]);
+        histograms[base + SLOTS * 3u + 1u] = atomicLoad(&counts_hi[EMPTY_SLOT]);
+        histograms[base + SLOTS * 3u + 2u] = atomicLoad(&overflowed);
+    }
+}
@@ -1,28 +1,70 @@
-use crate::gui::worker_thread::{start_worker_thread, ThreadRequest, ThreadResult};
+use crate::gui::{
+    autosave::{find_leftovers, AutosaveDir, AutosavedEntry, LeftoverSession},
+    debouncer::Debouncer,
+    history::{AnimationFrame, CompressedImage},
+    persistence::{Backing, PersistedState},
+    session::{Session, SessionEntry},
+    worker_thread::{
+        start_worker_thread, ExportEntry, InputTransform, RecoveredEntry, RenderJob, RequestSender,
+        ThreadRequest, ThreadResult,
+    },
+};
 use eframe::{CreationContext, Frame, NativeOptions, Storage};
 use egui::{
-    panel::TopBottomSide, pos2, vec2, Color32, ColorImage, Context, Grid, ProgressBar, Rect, Sense,
-    Slider, TextureHandle, TextureId, TextureOptions, Widget,
+    panel::TopBottomSide, popup_below_widget, pos2, vec2, Button, Color32, ColorImage, Context,
+    Grid, Key, PopupCloseBehavior, ProgressBar, Rect, Sense, Slider, Stroke, TextEdit,
+    TextureHandle, TextureId, TextureOptions, Vec2, Widget,
+};
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    DynamicImage, GenericImageView, ImageError, ImageFormat, Pixel, Rgba,
+};
+use pxls::{
+    cancellation::CancellationToken,
+    changed_pixels,
+    clustering::PaletteCluster,
+    css_colors::{named_color, named_colors_matching},
+    describe_settings_changes, downsample_for_sharing, downsampled_size,
+    export::{check_writable, export_animation, export_image, ExportFormat, NOT_WRITABLE_MESSAGE},
+    heatmap_colour,
+    loading::Recovery,
+    pixel_operations::{parse_hex_color, rgb_to_hex, simulate_cvd, Cvd, ALL_CVDS},
+    pixel_perfect_scale, pixel_perfect_scale_by, predicted_output_size,
+    preprocess::Adjustments,
+    quantizer::{available_quantizers, DEFAULT_QUANTIZER},
+    ramp::{generate_color_ramp, RampColorSpace, ALL_RAMP_COLOR_SPACES},
+    saved_settings::CurrentSettings,
+    source_chunk_colour, DistanceAlgorithm, DitherMode, DitheringMode, ImageAnalysis,
+    InputHistograms, OutputSettings, Palette, PaletteSettings, PxlsError, Stats, ALL_ALGOS,
+    ALL_DITHER_MODES, ALL_ERROR_DIFFUSION_DIRECTIONS, LARGE_OUTPUT_PIXELS, MAX_POST_SHARPEN,
+    SHARING_MAX_DIMENSION,
 };
-use image::{DynamicImage, GenericImageView, Pixel, Rgba};
-use pxls::{pixel_perfect_scale, DistanceAlgorithm, OutputSettings, PaletteSettings, ALL_ALGOS};
 use std::{
-    path::PathBuf,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::{channel, Receiver, Sender},
+        mpsc::{channel, Receiver},
         Arc,
     },
     thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
 };
 
+mod autosave;
+mod debouncer;
+mod history;
+mod persistence;
+mod session;
 mod worker_thread;
 
+const APP_NAME: &str = "Pxls";
+
 pub fn gui_main() {
     let native_options = NativeOptions::default();
 
     if let Err(e) = eframe::run_native(
-        "Pxls",
+        APP_NAME,
         native_options,
         Box::new(|cc| Ok(Box::new(PxlsApp::new(cc)))),
     ) {
@@ -33,69 +75,805 @@ pub fn gui_main() {
 enum RenderStage {
     Nothing,
     CreatingPalette {
+        //what a newer palette render would start from - `None` while transforming, since we don't have the result yet
+        input: Option<Arc<DynamicImage>>,
+        //the history entry that stays on screen until this is done, and gets gone back to if it's cancelled
+        superseded: Option<usize>,
         last_progress: (u32, u32),
         progress_rx: Receiver<(u32, u32)>,
     },
     CreatingOutput {
-        palette_used: Arc<[Rgba<u8>]>,
+        input: Arc<DynamicImage>,
+        adjusted: Arc<DynamicImage>,
+        palette_used: Arc<Palette>,
+        palette_settings: PaletteSettings,
+        palette_method: String,
+        adjustments: Adjustments,
+        superseded: Option<usize>,
         last_progress: (u32, u32),
         progress_rx: Receiver<(u32, u32)>,
     },
     DisplayingImage(usize),
+    ExportingAll {
+        //the history index to go back to once we're done
+        displaying: usize,
+        last_progress: (u32, u32),
+        progress_rx: Receiver<(u32, u32)>,
+    },
+    RestoringSession {
+        last_progress: (u32, u32),
+        progress_rx: Receiver<(u32, u32)>,
+    },
+    RenderingAnimation {
+        //the history index that the frames are for
+        displaying: usize,
+        last_progress: (u32, u32),
+        progress_rx: Receiver<(u32, u32)>,
+    },
+}
+
+impl RenderStage {
+    //the history entry that's on screen, even if it's about to be replaced
+    const fn shown_entry(&self) -> Option<usize> {
+        match self {
+            Self::DisplayingImage(index) => Some(*index),
+            Self::CreatingPalette { superseded, .. } | Self::CreatingOutput { superseded, .. } => {
+                *superseded
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
 struct RenderedImage {
     input: Arc<DynamicImage>,
-    palette: Arc<[Rgba<u8>]>,
+    adjusted: Arc<DynamicImage>,
+    palette: Arc<Palette>,
+    output: CompressedImage,
+    //only the entry being displayed keeps its decoded output and textures around
+    resident: Option<ResidentOutput>,
+    //how this entry was being looked at when we last moved away from it
+    view: Option<SavedView>,
+    //the heatmap texture and the distance that red means, made when first asked for
+    difference: Option<(TextureHandle, u32)>,
+    difference_requested: bool,
+    //SSIM against the input, worked out in the background after rendering
+    quality_metric: Option<f32>,
+    //the mean distance of each palette colour from the pixels closest to it, worst first, worked out alongside the SSIM
+    colour_error: Option<Vec<(Rgba<u8>, f32)>>,
+    //how many output pixels each palette colour got, in palette order
+    usage: Vec<u32>,
+    //every frame of an animated input, once they've been asked for
+    animation: Option<Animation>,
+    //recovered from an autosave without its input, so `input` is just the output scaled back up and mustn't be rendered from
+    stand_in_input: bool,
+    settings: (
+        PaletteSettings,
+        OutputSettings,
+        DistanceAlgorithm,
+        Adjustments,
+        String,
+    ),
+}
+
+//what an entry's palette has to match for its output to be reused
+#[derive(Copy, Clone)]
+enum SamePalette<'a> {
+    //made with these settings and palette method, so it would come out the same
+    Settings(&'a PaletteSettings, &'a str),
+    //already made, with a `Palette::content_hash` of this
+    Content(u64),
+}
+
+//the first entry in the history that already has the output these would render
+fn find_reusable_render(
+    history: &[RenderedImage],
+    input: &Arc<DynamicImage>,
+    adjustments: Adjustments,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    palette: SamePalette,
+) -> Option<usize> {
+    history.iter().position(|ri| {
+        let (palette_settings, output, distance, ri_adjustments, palette_method) = &ri.settings;
+        //hopefully short-circuiting should ensure that the input is compared last :)
+        distance_algorithm == *distance
+            && adjustments == *ri_adjustments
+            && output_settings.canonical() == output.canonical()
+            && match palette {
+                SamePalette::Settings(settings, method) => {
+                    settings == palette_settings && method == palette_method
+                }
+                SamePalette::Content(hash) => ri.palette.content_hash() == hash,
+            }
+            && ri.input == *input
+    })
+}
+
+#[derive(Clone)]
+struct Animation {
+    frames: Vec<AnimationFrame>,
+    //the previews, which like the output are only kept while the entry is displayed
+    textures: Vec<TextureHandle>,
+}
+
+impl Animation {
+    fn total_duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.delay).sum()
+    }
+
+    //which frame is showing this far through, and how long until the next one
+    fn frame_at(&self, position: Duration) -> (usize, Duration) {
+        let total = self.total_duration();
+        if total.is_zero() {
+            return (0, Duration::MAX);
+        }
+
+        let mut position = Duration::from_nanos((position.as_nanos() % total.as_nanos()) as u64);
+        for (i, frame) in self.frames.iter().enumerate() {
+            if position < frame.delay {
+                return (i, frame.delay.saturating_sub(position));
+            }
+            position = position.saturating_sub(frame.delay);
+        }
+        (self.frames.len() - 1, Duration::ZERO)
+    }
+}
+
+#[derive(Clone)]
+struct ResidentOutput {
     output: DynamicImage,
     handle: TextureHandle,
-    settings: (PaletteSettings, OutputSettings, DistanceAlgorithm),
+    //the output as seen with each colour vision deficiency that's been looked at, for display only
+    simulated: Vec<(Cvd, TextureHandle)>,
+}
+
+impl ResidentOutput {
+    fn texture(&self, simulating: Option<Cvd>) -> &TextureHandle {
+        simulating
+            .and_then(|simulating| {
+                self.simulated
+                    .iter()
+                    .find(|(cvd, _)| *cvd == simulating)
+                    .map(|(_, handle)| handle)
+            })
+            .unwrap_or(&self.handle)
+    }
+
+    fn simulate(&mut self, cvd: Cvd, ctx: &Context, texture_options: TextureOptions) {
+        if self.simulated.iter().any(|(existing, _)| *existing == cvd) {
+            return;
+        }
+
+        //outputs only have a few colours, so each one only gets worked out once
+        let mut cache = HashMap::new();
+        let mut simulated = self.output.to_rgba8();
+        for pixel in simulated.pixels_mut() {
+            *pixel = *cache
+                .entry(*pixel)
+                .or_insert_with(|| simulate_cvd(*pixel, cvd));
+        }
+
+        let handle = ctx.load_texture(
+            format!("my-img-{}", cvd.to_str()),
+            PhotoBeingEdited::color_image_from_dynamic_image(&DynamicImage::ImageRgba8(simulated)),
+            texture_options,
+        );
+        self.simulated.push((cvd, handle));
+    }
+}
+
+impl RenderedImage {
+    //colours that are noticeably worse than the rest, which are worth subdividing or replacing
+    fn high_error_colours(&self) -> Vec<Rgba<u8>> {
+        let Some(colour_error) = &self.colour_error else {
+            return vec![];
+        };
+        let used: Vec<f32> = colour_error
+            .iter()
+            .map(|(_, error)| *error)
+            .filter(|error| *error > 0.0)
+            .collect();
+        if used.len() < 2 {
+            return vec![];
+        }
+        let mean = used.iter().sum::<f32>() / used.len() as f32;
+
+        colour_error
+            .iter()
+            .filter(|(_, error)| *error > mean * HIGH_COLOUR_ERROR_RATIO)
+            .map(|(colour, _)| *colour)
+            .collect()
+    }
+
+    fn decoded_output(&self) -> Cow<'_, DynamicImage> {
+        self.resident.as_ref().map_or_else(
+            || {
+                Cow::Owned(
+                    self.output
+                        .decode()
+                        .expect("history outputs are always valid pngs"),
+                )
+            },
+            |resident| Cow::Borrowed(&resident.output),
+        )
+    }
+
+    fn make_resident(&mut self, ctx: &Context, texture_options: TextureOptions) {
+        if let Some(animation) = self
+            .animation
+            .as_mut()
+            .filter(|animation| animation.textures.is_empty())
+        {
+            animation.textures = animation
+                .frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| {
+                    let preview = frame
+                        .preview
+                        .decode()
+                        .expect("history outputs are always valid pngs");
+                    ctx.load_texture(
+                        format!("animation-frame-{i}"),
+                        PhotoBeingEdited::color_image_from_dynamic_image(&preview),
+                        texture_options,
+                    )
+                })
+                .collect();
+        }
+
+        if self.resident.is_some() {
+            return;
+        }
+
+        let output = self.decoded_output().into_owned();
+        let handle = ctx.load_texture(
+            "my-img",
+            PhotoBeingEdited::color_image_from_dynamic_image(&output),
+            texture_options,
+        );
+        self.resident = Some(ResidentOutput {
+            output,
+            handle,
+            simulated: vec![],
+        });
+    }
+
+    fn evict(&mut self) {
+        self.resident = None;
+        if let Some(animation) = &mut self.animation {
+            animation.textures.clear();
+        }
+        self.difference = None;
+        self.difference_requested = false;
+    }
+
+    fn memory_used(&self) -> usize {
+        self.output.len_bytes()
+            + self
+                .resident
+                .as_ref()
+                .map_or(0, |resident| resident.output.as_bytes().len())
+            + self.animation.as_ref().map_or(0, |animation| {
+                animation
+                    .frames
+                    .iter()
+                    .map(|frame| frame.output.len_bytes() + frame.preview.len_bytes())
+                    .sum()
+            })
+    }
+
+    //uses the settings this entry was rendered with, rather than wherever the sliders are now
+    fn image_to_save(&self) -> Result<DynamicImage, PxlsError> {
+        pixel_perfect_scale(self.settings.1, &self.decoded_output())
+    }
+
+    fn export_name(&self) -> String {
+        let (palette_settings, output_settings, distance_algorithm, _, _) = &self.settings;
+        format!(
+            "{}_cpd{}_ct{}_px{}_ds{}",
+            distance_algorithm.to_str().to_lowercase().replace(' ', "-"),
+            palette_settings.chunks_per_dimension,
+            palette_settings.closeness_threshold,
+            output_settings.output_px_size,
+            output_settings.dithering_scale,
+        )
+    }
 }
 
 struct RenderedPalette {
-    input: (Arc<[Rgba<u8>]>, Rect),
+    input: (Arc<Palette>, Rect, Option<Cvd>),
     dimensions: [usize; 2],
     handle: TextureHandle,
 }
 
 struct PhotoBeingEdited {
     stage: RenderStage,
+    //the newest render, anything older gets stopped and ignored
+    render_job: RenderJob,
     worker_handle: Option<JoinHandle<()>>,
-    last_start_save_dirs: (Option<PathBuf>, Option<PathBuf>),
-    worker_should_stop: Arc<AtomicBool>,
-    requests_tx: Sender<ThreadRequest>,
+    persisted: PersistedState,
+    worker_should_stop: CancellationToken,
+    requests_tx: RequestSender,
     results_rx: Receiver<ThreadResult>,
     texture_options: TextureOptions,
     image_history: Vec<RenderedImage>,
+    original_input: Option<Arc<DynamicImage>>,
+    //only set when the input came from a file, since that's all that a session can be restored from
+    input_file: Option<PathBuf>,
+    //which frame of `input_file` is being used, if it's animated
+    input_frame: usize,
+    //when `input_file` was last changed on disk as of loading it, and when that was last checked
+    input_modified: Option<SystemTime>,
+    input_checked_at: Option<Instant>,
+    //off by default, since a reload throws away the current palette
+    auto_reload_file: bool,
+    //set when the input was damaged, so might be missing bits
+    input_recovery: Option<Recovery>,
+    //an animated input that's waiting for a frame to be picked
+    picking_frame: Option<PickingFrame>,
+    //the last session, until we find out whether it should be restored
+    previous_session: Option<Session>,
+    //the settings of the entry that a restored session was displaying, for the app to pick up
+    restored_settings: Option<(
+        PaletteSettings,
+        OutputSettings,
+        DistanceAlgorithm,
+        Adjustments,
+        String,
+    )>,
+    toasts: Vec<Toast>,
+    pending_export: Option<PendingExport>,
+    //remembered for the session, so exporting a few in a row doesn't mean picking it every time
+    export_scale: ExportScale,
+    palette_clusters: Option<ClusteredPalette>,
+    //`None` when the stats window is closed
+    image_stats: Option<ImageStats>,
+    //for the adjusted input of the entry that was last displayed with the histogram section open
+    input_histograms: Option<(Arc<DynamicImage>, Option<Box<InputHistograms>>)>,
+    render_stats: RenderStats,
+    pixel_changes: Option<PixelChanges>,
+    //`None` when autosaving is off
+    autosave: Option<AutosaveDir>,
+    autosaved_count: usize,
+    //from runs that crashed, newest first, waiting to be offered back
+    leftover_sessions: Vec<LeftoverSession>,
+    //leftovers that have been recovered, which only get deleted once this run exits cleanly
+    recovered_dirs: Vec<PathBuf>,
+}
+
+//which output pixels changed since the previously shown entry
+struct PixelChanges {
+    //the entry on screen then the one before it, checked by palette too in case the history has changed
+    between: (usize, usize),
+    palettes: (Arc<Palette>, Arc<Palette>),
+    //the changed pixels in opaque magenta and the fraction of them, or `None` if the outputs aren't the same size
+    mask: Option<(TextureHandle, f32)>,
+}
+
+//what the worker thread did for the latest renders, for the status bar
+#[derive(Copy, Clone, Default)]
+struct RenderStats {
+    //not set when only the output was re-rendered
+    palette: Option<Stats>,
+    dither: Option<Stats>,
+}
+
+struct PickingFrame {
+    file: PathBuf,
+    thumbnails: Vec<TextureHandle>,
+    chosen: usize,
+}
+
+struct Toast {
+    message: String,
+    shown_at: Instant,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PaletteView {
+    Grid,
+    //similar colours grouped into rows, for palettes too big to make sense of as a grid
+    Clusters,
+}
+
+//the clusters of the palette being shown, only kept until a different palette is shown
+struct ClusteredPalette {
+    palette: Arc<Palette>,
+    //`None` until the worker thread is done with them
+    clusters: Option<Vec<PaletteCluster>>,
+    highlighted: Option<usize>,
+    //dims everything outside the highlighted cluster, for the history entry and cluster it was made for
+    overlay: Option<((usize, usize), TextureHandle)>,
+}
+
+//the stats of the displayed entry's input, which get worked out again when a different input is displayed
+struct ImageStats {
+    input: Arc<DynamicImage>,
+    //`None` until the worker thread is done with it
+    analysis: Option<Box<ImageAnalysis>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ExportScale {
+    AsPreviewed,
+    MatchOriginal,
+    Custom(u32),
+    //as previewed, but shrunk to fit within this many pixels each way
+    ForSharing(u32),
+}
+
+struct PendingExport {
+    file: PathBuf,
+    format: ExportFormat,
+    ri: RenderedImage,
+    error: Option<String>,
+    //has to be ticked before saving anything over `LARGE_OUTPUT_PIXELS`
+    confirmed_large: bool,
+}
+
+impl PendingExport {
+    fn original_factor(&self) -> u32 {
+        (self.ri.input.width() / self.ri.output.width()).max(1)
+    }
+
+    fn predicted_size(&self, scale: ExportScale) -> (u64, u64) {
+        let factor = match scale {
+            ExportScale::AsPreviewed => {
+                let (width, height) =
+                    predicted_output_size(self.ri.input.dimensions(), self.ri.settings.1);
+                return (u64::from(width), u64::from(height));
+            }
+            ExportScale::MatchOriginal => self.original_factor(),
+            ExportScale::Custom(factor) => factor,
+            ExportScale::ForSharing(max_dimension) => {
+                let previewed =
+                    predicted_output_size(self.ri.input.dimensions(), self.ri.settings.1);
+                let (width, height) = downsampled_size(previewed, max_dimension);
+                return (u64::from(width), u64::from(height));
+            }
+        };
+
+        let (width, height) = self.ri.output.dimensions();
+        (
+            u64::from(width) * u64::from(factor),
+            u64::from(height) * u64::from(factor),
+        )
+    }
+
+    fn scaled(&self, scale: ExportScale) -> Result<DynamicImage, PxlsError> {
+        match scale {
+            ExportScale::AsPreviewed => self.ri.image_to_save(),
+            ExportScale::ForSharing(max_dimension) => Ok(downsample_for_sharing(
+                &self.ri.image_to_save()?,
+                max_dimension,
+            )),
+            _ => self.scale_output(&self.ri.decoded_output(), scale),
+        }
+    }
+
+    //for anything that's the same size as the entry's output, like the frames of an animation
+    fn scale_output(
+        &self,
+        output: &DynamicImage,
+        scale: ExportScale,
+    ) -> Result<DynamicImage, PxlsError> {
+        match scale {
+            ExportScale::AsPreviewed => pixel_perfect_scale(self.ri.settings.1, output),
+            ExportScale::MatchOriginal => {
+                Ok(pixel_perfect_scale_by(output, self.original_factor())?)
+            }
+            ExportScale::Custom(factor) => Ok(pixel_perfect_scale_by(output, factor)?),
+            ExportScale::ForSharing(max_dimension) => Ok(downsample_for_sharing(
+                &pixel_perfect_scale(self.ri.settings.1, output)?,
+                max_dimension,
+            )),
+        }
+    }
+
+    fn export_animation(&self, animation: &Animation, scale: ExportScale) -> Result<(), String> {
+        if !self.format.supports_animation() {
+            return Err("Animations can only be saved as GIFs or PNGs".to_string());
+        }
+
+        let frames = animation.frames.iter().map(|frame| {
+            let output = frame.output.decode()?;
+            let scaled = self.scale_output(&output, scale).map_err(|e| {
+                ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                    e.to_string(),
+                )))
+            })?;
+            Ok((scaled, frame.delay))
+        });
+        export_animation(frames, &self.file, self.format).map_err(|e| e.to_string())
+    }
+}
+
+//bars for each of the histograms on top of each other, all to the same scale. returns where they were drawn
+fn histogram_plot(ui: &mut egui::Ui, size: Vec2, histograms: &[(&[u32; 256], Color32)]) -> Rect {
+    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let tallest = histograms
+        .iter()
+        .flat_map(|(histogram, _)| histogram.iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1) as f32;
+    let bar_width = rect.width() / 256.0;
+    for (histogram, fill) in histograms {
+        //see-through when they overlap, so the ones underneath still show
+        let fill = if histograms.len() > 1 {
+            fill.gamma_multiply(0.5)
+        } else {
+            *fill
+        };
+        for (value, count) in histogram.iter().enumerate() {
+            let height = rect.height() * *count as f32 / tallest;
+            let left = (value as f32).mul_add(bar_width, rect.left());
+            painter.rect_filled(
+                Rect::from_min_max(
+                    pos2(left, rect.bottom() - height),
+                    pos2(left + bar_width, rect.bottom()),
+                ),
+                0.0,
+                fill,
+            );
+        }
+    }
+
+    if let Some(hovered) = response.hover_pos() {
+        #[allow(clippy::cast_sign_loss)]
+        let value = (((hovered.x - rect.left()) / bar_width) as usize).min(255);
+        let counts: Vec<_> = histograms
+            .iter()
+            .map(|(histogram, _)| histogram[value].to_string())
+            .collect();
+        response.on_hover_text(format!("{value}: {} pixel(s)", counts.join(" / ")));
+    }
+
+    rect
+}
+
+//where each band of brightnesses that the closeness threshold keeps apart starts, in histogram buckets, across the
+//spread of the input. `None` for the algorithms that don't just compare one number
+fn threshold_markers(
+    algo: DistanceAlgorithm,
+    closeness_threshold: u32,
+    histogram: &[u32; 256],
+) -> Option<Vec<f32>> {
+    const MAX_MARKERS: usize = 256;
+
+    if !matches!(
+        algo,
+        DistanceAlgorithm::Luminance | DistanceAlgorithm::Value
+    ) {
+        return None;
+    }
+    let (Some(lowest), Some(highest)) = (
+        histogram.iter().position(|count| *count > 0),
+        histogram.iter().rposition(|count| *count > 0),
+    ) else {
+        return Some(vec![]);
+    };
+    let step = algo.standardise_closeness_threshold(closeness_threshold);
+    if step == 0 {
+        return Some(vec![]);
+    }
+
+    //luminance buckets are square roots, so the bands get narrower as it gets brighter
+    let (lowest, highest) = (lowest as u32, highest as u32);
+    let (start, end) = if algo == DistanceAlgorithm::Luminance {
+        (lowest * lowest, (highest + 1) * (highest + 1) - 1)
+    } else {
+        (lowest, highest)
+    };
+    let to_bucket = |distance: u32| {
+        if algo == DistanceAlgorithm::Luminance {
+            (distance as f32).sqrt()
+        } else {
+            distance as f32
+        }
+    };
+    Some(
+        (start..=end)
+            .step_by(step as usize)
+            .take(MAX_MARKERS)
+            .map(to_bucket)
+            .collect(),
+    )
+}
+
+//milliseconds for anything quick, otherwise seconds
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f32())
+    }
+}
+
+fn modified_time(file: &Path) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+struct RampDialog {
+    start: [u8; 3],
+    end: [u8; 3],
+    steps: usize,
+    color_space: RampColorSpace,
+}
+
+impl Default for RampDialog {
+    fn default() -> Self {
+        Self {
+            start: [0x1a, 0x4d, 0x1a],
+            end: [0xff, 0xf0, 0x80],
+            steps: 4,
+            color_space: RampColorSpace::Oklab,
+        }
+    }
+}
+
+impl RampDialog {
+    fn ramp(&self) -> Vec<Rgba<u8>> {
+        let [sr, sg, sb] = self.start;
+        let [er, eg, eb] = self.end;
+        generate_color_ramp(
+            Rgba([sr, sg, sb, u8::MAX]),
+            Rgba([er, eg, eb, u8::MAX]),
+            self.steps,
+            self.color_space,
+        )
+    }
 }
 
 struct PxlsApp {
     current: PhotoBeingEdited,
     //this is in the App rather than the PhotoBeingEdited because it's more of a UI element than anything else
     show_palette: Option<RenderedPalette>,
+    palette_view: PaletteView,
     distance_algorithm: DistanceAlgorithm,
     palette_settings: PaletteSettings,
+    //the name of the quantizer to make the palette with
+    palette_method: String,
     output_settings: OutputSettings,
+    adjustments: Adjustments,
+    right_clicked_colour: Option<Rgba<u8>>,
+    ramp_dialog: Option<RampDialog>,
+    custom_color_input: String,
     needs_to_refresh_palette: bool,
     needs_to_refresh_output: bool,
     auto_update: bool,
+    //auto-update waits for the settings to stop changing, so dragging a slider doesn't queue up a render per tick
+    auto_update_debouncer: Debouncer,
+    last_seen_settings: Option<(
+        PaletteSettings,
+        OutputSettings,
+        DistanceAlgorithm,
+        Adjustments,
+        String,
+    )>,
+    view: View,
+    last_displayed_image_index: Option<usize>,
+    //what was on screen before that, for highlighting what's changed since
+    previously_displayed_index: Option<usize>,
+    //the history entry shown next to the current one, if we're comparing
+    pinned_entry: Option<usize>,
+    playback: Playback,
+    //picking colours off the input to add to the palette, instead of looking at the output
+    eyedropper: Option<Eyedropper>,
+}
+
+#[derive(Default)]
+struct Eyedropper {
+    //only outputs normally get textures, so the input gets one while it's being picked from
+    input: Option<(Arc<DynamicImage>, TextureHandle)>,
+    //waiting to be confirmed
+    sampled: Option<Rgba<u8>>,
+}
+
+struct Playback {
+    playing: bool,
+    //how far through the animation we are, as of `last_update`
+    position: Duration,
+    last_update: Instant,
+}
+
+impl Playback {
+    fn advance(&mut self) {
+        let now = Instant::now();
+        if self.playing {
+            self.position += now.duration_since(self.last_update);
+        }
+        self.last_update = now;
+    }
+
+    fn toggle(&mut self) {
+        self.advance();
+        self.playing = !self.playing;
+    }
+}
+
+struct View {
+    //screen points per output pixel
+    zoom: f32,
+    //offset of the image centre from the panel centre
+    pan: Vec2,
+    mode: ZoomMode,
+    show_inspector: bool,
+    show_difference: bool,
+    //highlights the pixels that changed since the previously shown entry
+    show_changes: bool,
+    changes_opacity: f32,
+    //only changes what's shown, never what's saved
+    simulating: Option<Cvd>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ZoomMode {
+    Fit,
+    //one output pixel per physical screen pixel
+    OneToOne,
+    Free,
+}
+
+#[derive(Copy, Clone)]
+struct SavedView {
+    zoom: f32,
+    pan: Vec2,
+    mode: ZoomMode,
 }
 
 impl PhotoBeingEdited {
-    pub fn new(last_start_save_dirs: (Option<PathBuf>, Option<PathBuf>)) -> Self {
-        let (worker_handle, requests_tx, results_rx, worker_should_stop) =
-            start_worker_thread(last_start_save_dirs.clone());
+    pub fn new(persisted: PersistedState) -> Self {
+        let (worker_handle, requests_tx, results_rx, worker_should_stop) = start_worker_thread((
+            persisted.last_start_dir.clone(),
+            persisted.last_save_dir.clone(),
+        ));
+        //looked for first, so this run's directory isn't one of them
+        let leftover_sessions = find_leftovers();
+        let autosave = if persisted.autosave_disabled {
+            None
+        } else {
+            AutosaveDir::create()
+        };
 
         Self {
             stage: RenderStage::Nothing,
+            render_job: RenderJob::new(0, &worker_should_stop),
             worker_handle: Some(worker_handle),
-            last_start_save_dirs,
+            persisted,
             requests_tx,
             results_rx,
             worker_should_stop,
             texture_options: TextureOptions::NEAREST,
             image_history: vec![],
+            original_input: None,
+            input_file: None,
+            input_frame: 0,
+            input_modified: None,
+            input_checked_at: None,
+            auto_reload_file: false,
+            input_recovery: None,
+            picking_frame: None,
+            previous_session: Session::load(),
+            restored_settings: None,
+            toasts: vec![],
+            pending_export: None,
+            export_scale: ExportScale::AsPreviewed,
+            palette_clusters: None,
+            image_stats: None,
+            input_histograms: None,
+            render_stats: RenderStats::default(),
+            pixel_changes: None,
+            autosave,
+            autosaved_count: 0,
+            leftover_sessions,
+            recovered_dirs: vec![],
         }
     }
 
@@ -103,222 +881,2525 @@ impl PhotoBeingEdited {
         self.requests_tx.send(ThreadRequest::GetInputImage).unwrap();
     }
 
-    pub fn save_file(&self, index: usize) {
+    pub fn load_recent_input(&self, file: PathBuf) {
         self.requests_tx
-            .send(ThreadRequest::GetOutputImage(index))
+            .send(ThreadRequest::LoadPath(file))
             .unwrap();
     }
 
-    pub fn process_thread_updates(
-        &mut self,
-        palette_settings: PaletteSettings,
-        output_settings: OutputSettings,
-        distance_algorithm: DistanceAlgorithm,
-        ctx: &Context,
-    ) {
-        for update in self.results_rx.try_iter() {
-            match update {
-                ThreadResult::ReadInFile(start_dir, input) => {
-                    let (progress_tx, progress_rx) = channel();
-                    self.stage = RenderStage::CreatingPalette {
-                        progress_rx,
-                        last_progress: (0, 1),
-                    };
-                    self.requests_tx
-                        .send(ThreadRequest::RenderPalette {
-                            input,
-                            palette_settings,
-                            distance_algorithm,
-                            progress_tx,
-                        })
-                        .unwrap();
+    fn note_input_modified(&mut self) {
+        self.input_modified = self.input_file.as_deref().and_then(modified_time);
+    }
 
-                    self.last_start_save_dirs.0 = Some(start_dir);
-                }
-                ThreadResult::RenderedPalette {
-                    input,
-                    palette,
-                    palette_settings,
-                } => {
-                    let (progress_tx, progress_rx) = channel();
-                    self.stage = RenderStage::CreatingOutput {
-                        palette_used: palette.clone(),
-                        progress_rx,
-                        last_progress: (0, 1),
-                    };
-                    self.requests_tx
-                        .send(ThreadRequest::RenderOutput {
-                            input,
-                            palette,
-                            palette_settings,
-                            output_settings,
-                            distance_algorithm,
-                            progress_tx,
-                        })
-                        .unwrap();
-                }
-                ThreadResult::RenderedImage {
-                    input,
-                    palette,
-                    output,
-                    settings,
-                } => {
-                    let handle = ctx.load_texture(
-                        "my-img",
-                        Self::color_image_from_dynamic_image(&output),
-                        self.texture_options,
-                    );
-                    let ri = RenderedImage {
-                        input,
-                        palette,
-                        output,
-                        handle,
-                        settings,
-                    };
+    //re-reads the input file if something else has changed it since it was loaded
+    pub fn reload_input_if_changed(&mut self) {
+        let now = Instant::now();
+        if self
+            .input_checked_at
+            .is_some_and(|checked| now.duration_since(checked) < INPUT_CHECK_INTERVAL)
+        {
+            return;
+        }
+        self.input_checked_at = Some(now);
 
-                    self.image_history.push(ri.clone());
-                    self.stage = RenderStage::DisplayingImage(self.image_history.len() - 1);
-                }
-                ThreadResult::GotDestination {
-                    file,
-                    index,
-                    save_dir,
-                } => {
-                    if let Some(output) = self.image_history.get(index) {
-                        let scaled = pixel_perfect_scale(output_settings, &output.output);
+        //don't pull the input out from under a render or the frame picker
+        if !matches!(
+            self.stage,
+            RenderStage::Nothing | RenderStage::DisplayingImage(_)
+        ) || self.picking_frame.is_some()
+        {
+            return;
+        }
+        let Some(file) = &self.input_file else {
+            return;
+        };
+        let modified = modified_time(file);
+        if modified.is_none() || modified == self.input_modified {
+            return;
+        }
 
-                        if let Err(e) = scaled.save(file) {
-                            eprintln!("Error saving file: {e:?}");
-                        }
-                    }
+        self.input_modified = modified;
+        self.requests_tx
+            .send(ThreadRequest::LoadFrame {
+                file: file.clone(),
+                frame: self.input_frame,
+            })
+            .unwrap();
+    }
 
-                    self.last_start_save_dirs.1 = Some(save_dir);
-                }
-            }
-        }
+    pub fn paste_new_input(&self) {
+        self.requests_tx
+            .send(ThreadRequest::PasteFromClipboard)
+            .unwrap();
+    }
 
-        match &mut self.stage {
-            RenderStage::CreatingOutput {
-                palette_used: _,
+    pub fn copy_to_clipboard(&self, index: usize, scale_output_to_original: bool) {
+        let ri = &self.image_history[index];
+
+        self.requests_tx
+            .send(ThreadRequest::CopyToClipboard {
+                output: ri.decoded_output().into_owned(),
+                output_settings: OutputSettings {
+                    scale_output_to_original,
+                    ..ri.settings.1
+                },
+            })
+            .unwrap();
+    }
+
+    pub fn request_difference(&mut self, index: usize) {
+        let ri = &mut self.image_history[index];
+        if ri.difference.is_some() || ri.difference_requested {
+            return;
+        }
+        ri.difference_requested = true;
+
+        let (_, output_settings, distance_algorithm, _, _) = ri.settings;
+        self.requests_tx
+            .send(ThreadRequest::RenderDifference {
+                index,
+                palette: ri.palette.clone(),
+                adjusted: ri.adjusted.clone(),
+                output: ri.decoded_output().into_owned(),
+                output_settings,
+                distance_algorithm,
+            })
+            .unwrap();
+    }
+
+    pub fn make_only_resident(
+        &mut self,
+        indices: &[usize],
+        simulating: Option<Cvd>,
+        ctx: &Context,
+    ) {
+        for (i, ri) in self.image_history.iter_mut().enumerate() {
+            if indices.contains(&i) {
+                ri.make_resident(ctx, self.texture_options);
+                if let (Some(cvd), Some(resident)) = (simulating, &mut ri.resident) {
+                    resident.simulate(cvd, ctx, self.texture_options);
+                }
+            } else {
+                ri.evict();
+            }
+        }
+    }
+
+    pub fn request_clusters(&mut self, palette: &Arc<Palette>) {
+        if self
+            .palette_clusters
+            .as_ref()
+            .is_some_and(|clustered| Arc::ptr_eq(&clustered.palette, palette))
+        {
+            return;
+        }
+
+        self.requests_tx
+            .send(ThreadRequest::ClusterPalette(palette.clone()))
+            .unwrap();
+        self.palette_clusters = Some(ClusteredPalette {
+            palette: palette.clone(),
+            clusters: None,
+            highlighted: None,
+            overlay: None,
+        });
+    }
+
+    pub fn request_image_stats(&mut self) {
+        let RenderStage::DisplayingImage(index) = self.stage else {
+            return;
+        };
+        let input = &self.image_history[index].input;
+        if self
+            .image_stats
+            .as_ref()
+            .is_some_and(|stats| Arc::ptr_eq(&stats.input, input))
+        {
+            return;
+        }
+
+        self.requests_tx
+            .send(ThreadRequest::AnalyzeImage(input.clone()))
+            .unwrap();
+        self.image_stats = Some(ImageStats {
+            input: input.clone(),
+            analysis: None,
+        });
+    }
+
+    pub fn request_input_histograms(&mut self) {
+        let RenderStage::DisplayingImage(index) = self.stage else {
+            return;
+        };
+        let adjusted = &self.image_history[index].adjusted;
+        if self
+            .input_histograms
+            .as_ref()
+            .is_some_and(|(made_for, _)| Arc::ptr_eq(made_for, adjusted))
+        {
+            return;
+        }
+
+        self.requests_tx
+            .send(ThreadRequest::ComputeHistograms(adjusted.clone()))
+            .unwrap();
+        self.input_histograms = Some((adjusted.clone(), None));
+    }
+
+    fn show_image_stats(&mut self, ctx: &Context) {
+        const HISTOGRAM_SIZE: Vec2 = vec2(256.0, 64.0);
+        const SWATCH_SIZE: Vec2 = vec2(16.0, 16.0);
+
+        if self.image_stats.is_none() {
+            return;
+        }
+        //follows whatever's being displayed
+        self.request_image_stats();
+        let Some(stats) = &self.image_stats else {
+            return;
+        };
+
+        let swatch = |ui: &mut egui::Ui, colour: Rgba<u8>| {
+            let [r, g, b] = colour.to_rgb().0;
+            let (rect, _) = ui.allocate_exact_size(SWATCH_SIZE, Sense::hover());
+            ui.painter()
+                .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+            ui.label(rgb_to_hex(colour));
+        };
+
+        let mut open = true;
+        egui::Window::new("Image Stats")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some(analysis) = &stats.analysis else {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Analysing input…");
+                    });
+                    return;
+                };
+
+                Grid::new("image_stats").show(ui, |ui| {
+                    ui.label("Mean:");
+                    ui.horizontal(|ui| swatch(ui, analysis.mean_color));
+                    ui.end_row();
+
+                    ui.label("Median:");
+                    ui.horizontal(|ui| swatch(ui, analysis.median_color));
+                    ui.end_row();
+                });
+
+                ui.separator();
+                ui.label("Most common colours:");
+                for (colour, percentage) in &analysis.dominant_colors {
+                    ui.horizontal(|ui| {
+                        swatch(ui, *colour);
+                        ui.label(format!("{percentage:.2}%"));
+                    });
+                }
+
+                ui.separator();
+                for (histogram, fill) in [
+                    (&analysis.r_histogram, Color32::RED),
+                    (&analysis.g_histogram, Color32::GREEN),
+                    (&analysis.b_histogram, Color32::BLUE),
+                ] {
+                    histogram_plot(ui, HISTOGRAM_SIZE, &[(histogram, fill)]);
+                }
+            });
+
+        if !open {
+            self.image_stats = None;
+        }
+    }
+
+    //the overlay is made from the output, so it's only made once the entry is resident
+    fn update_cluster_overlay(&mut self, ctx: &Context) {
+        let Some(clustered) = &mut self.palette_clusters else {
+            return;
+        };
+        let displaying = match self.stage {
+            RenderStage::DisplayingImage(index) => Some(index),
+            _ => None,
+        }
+        .filter(|index| Arc::ptr_eq(&self.image_history[*index].palette, &clustered.palette));
+        let wanted = displaying.zip(clustered.highlighted);
+        if clustered.overlay.as_ref().map(|(made_for, _)| *made_for) == wanted {
+            return;
+        }
+
+        clustered.overlay = None;
+        let Some((index, cluster)) = wanted else {
+            return;
+        };
+        let (Some(clusters), Some(resident)) =
+            (&clustered.clusters, &self.image_history[index].resident)
+        else {
+            return;
+        };
+
+        let in_cluster: HashSet<[u8; 3]> = clusters[cluster]
+            .colours
+            .iter()
+            .map(|colour| colour.to_rgb().0)
+            .collect();
+        let output = &resident.output;
+        let mut overlay = ColorImage::new(
+            [output.width() as usize, output.height() as usize],
+            Color32::TRANSPARENT,
+        );
+        for (x, y, px) in output.pixels() {
+            if !in_cluster.contains(&px.to_rgb().0) {
+                overlay[(x as usize, y as usize)] = Color32::from_black_alpha(180);
+            }
+        }
+
+        clustered.overlay = Some((
+            (index, cluster),
+            ctx.load_texture("cluster-overlay", overlay, self.texture_options),
+        ));
+    }
+
+    //the worker thread does the writing, so a slow disk doesn't hold up the ui
+    fn autosave(&mut self, ri: &RenderedImage) {
+        let Some(autosave) = &self.autosave else {
+            return;
+        };
+
+        //only outputs made straight from the file can be re-rendered from it later
+        let is_from_file = self
+            .original_input
+            .as_ref()
+            .is_some_and(|original| Arc::ptr_eq(original, &ri.input));
+        let (palette_settings, output_settings, distance_algorithm, adjustments, palette_method) =
+            ri.settings.clone();
+        self.requests_tx
+            .send(ThreadRequest::Autosave {
+                dir: autosave.path().to_path_buf(),
+                index: self.autosaved_count,
+                output: ri.output.clone(),
+                entry: AutosavedEntry {
+                    input: self.input_file.clone().filter(|_| is_from_file),
+                    frame: self.input_frame,
+                    settings: CurrentSettings {
+                        palette_settings,
+                        palette_method,
+                        output_settings,
+                        distance_algorithm,
+                        adjustments,
+                    },
+                    palette: (*ri.palette).clone(),
+                },
+            })
+            .unwrap();
+        self.autosaved_count += 1;
+    }
+
+    pub fn set_autosave(&mut self, enabled: bool) {
+        self.persisted.autosave_disabled = !enabled;
+        if enabled {
+            if self.autosave.is_none() {
+                self.autosave = AutosaveDir::create();
+                self.autosaved_count = 0;
+            }
+        } else if let Some(autosave) = self.autosave.take() {
+            autosave.remove();
+        }
+    }
+
+    fn add_recovered_entries(&mut self, entries: Vec<RecoveredEntry>) {
+        if entries.is_empty() {
+            self.toasts.push(Toast {
+                message: "None of the autosaved renders could be read".to_string(),
+                shown_at: Instant::now(),
+            });
+            return;
+        }
+
+        for RecoveredEntry { file, input, entry } in entries {
+            //the first input that could be found becomes the one to work from, if there isn't one already
+            if let (Some((file, frame)), None) = (&file, &self.original_input) {
+                self.original_input = Some(input.clone());
+                self.input_file = Some(file.clone());
+                self.input_frame = *frame;
+                self.input_recovery = None;
+                self.note_input_modified();
+            }
+
+            let ri = RenderedImage {
+                input,
+                adjusted: entry.adjusted,
+                palette: entry.palette,
+                output: entry.output,
+                resident: None,
+                view: None,
+                difference: None,
+                difference_requested: false,
+                quality_metric: None,
+                colour_error: None,
+                usage: entry.usage,
+                animation: None,
+                stand_in_input: file.is_none(),
+                settings: entry.settings,
+            };
+
+            self.requests_tx
+                .send(ThreadRequest::ComputeQuality {
+                    index: self.image_history.len(),
+                    palette: ri.palette.clone(),
+                    input: ri.input.clone(),
+                    adjusted: ri.adjusted.clone(),
+                    output: ri.decoded_output().into_owned(),
+                    distance_algorithm: ri.settings.2,
+                })
+                .unwrap();
+            self.image_history.push(ri);
+        }
+
+        let displaying = self.image_history.len() - 1;
+        self.restored_settings = Some(self.image_history[displaying].settings.clone());
+        self.stage = RenderStage::DisplayingImage(displaying);
+    }
+
+    fn show_recover_autosave_modal(&mut self, ctx: &Context) {
+        //the last session gets asked about first
+        if self.previous_session.is_some() {
+            return;
+        }
+        let Some(leftover) = self.leftover_sessions.first() else {
+            return;
+        };
+
+        let mut should_recover = false;
+        let mut should_discard = false;
+        let modal = egui::Modal::new(egui::Id::new("recover_autosave")).show(ctx, |ui| {
+            ui.heading("Recover unsaved renders?");
+            let age = format!("{} minute(s)", leftover.age.as_secs() / 60);
+            ui.label(format!(
+                "Pxls didn't close properly {age} ago, and had {} render(s) autosaved.",
+                leftover.entries.len()
+            ));
+            let without_input = leftover
+                .entries
+                .iter()
+                .filter(|(entry, _)| entry.input.as_ref().is_none_or(|input| !input.is_file()))
+                .count();
+            if without_input > 0 {
+                ui.weak(format!(
+                    "{without_input} of them can't find the image they were made from, so can be saved but not re-rendered."
+                ));
+            }
+
+            ui.horizontal(|ui| {
+                should_recover = ui.button("Recover").clicked();
+                should_discard = ui.button("Discard").clicked();
+            });
+        });
+
+        if should_recover {
+            let leftover = self.leftover_sessions.remove(0);
+            self.requests_tx
+                .send(ThreadRequest::RecoverAutosave(leftover.entries))
+                .unwrap();
+            self.recovered_dirs.push(leftover.dir);
+        } else if should_discard {
+            self.leftover_sessions.remove(0).discard();
+        } else if modal.should_close() {
+            //they'll be offered again next time, until they're too old
+            self.leftover_sessions.remove(0);
+        }
+    }
+
+    //on a clean exit nothing needs recovering
+    fn remove_autosaves(&mut self) {
+        if let Some(autosave) = self.autosave.take() {
+            autosave.remove();
+        }
+        for dir in self.recovered_dirs.drain(..) {
+            if let Err(e) = std::fs::remove_dir_all(dir) {
+                eprintln!("Error removing recovered autosave: {e:?}");
+            }
+        }
+    }
+
+    fn update_pixel_changes(&mut self, shown: usize, previous: usize, ctx: &Context) {
+        let (Some(shown_ri), Some(previous_ri)) = (
+            self.image_history.get(shown),
+            self.image_history.get(previous),
+        ) else {
+            self.pixel_changes = None;
+            return;
+        };
+        if self.pixel_changes.as_ref().is_some_and(|changes| {
+            changes.between == (shown, previous)
+                && Arc::ptr_eq(&changes.palettes.0, &shown_ri.palette)
+                && Arc::ptr_eq(&changes.palettes.1, &previous_ri.palette)
+        }) {
+            return;
+        }
+
+        let mask = changed_pixels(&previous_ri.decoded_output(), &shown_ri.decoded_output()).map(
+            |changed| {
+                let (width, height) = shown_ri.output.dimensions();
+                let pixels: Vec<_> = changed
+                    .iter()
+                    .map(|changed| {
+                        if *changed {
+                            Color32::from_rgb(255, 0, 255)
+                        } else {
+                            Color32::TRANSPARENT
+                        }
+                    })
+                    .collect();
+                let fraction = changed.iter().filter(|changed| **changed).count() as f32
+                    / changed.len().max(1) as f32;
+
+                let handle = ctx.load_texture(
+                    "pixel-changes",
+                    ColorImage {
+                        size: [width as usize, height as usize],
+                        pixels,
+                    },
+                    self.texture_options,
+                );
+                (handle, fraction)
+            },
+        );
+
+        self.pixel_changes = Some(PixelChanges {
+            between: (shown, previous),
+            palettes: (shown_ri.palette.clone(), previous_ri.palette.clone()),
+            mask,
+        });
+    }
+
+    //`None` when nothing has been loaded yet
+    fn status_text(&self) -> Option<String> {
+        let input = self.original_input.as_ref()?;
+        let name = self
+            .input_file
+            .as_ref()
+            .and_then(|file| file.file_name())
+            .map_or_else(
+                || "Pasted image".to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+
+        let mut parts = vec![format!("{name} ({}x{})", input.width(), input.height())];
+        if let Some(ri) = self
+            .stage
+            .shown_entry()
+            .and_then(|index| self.image_history.get(index))
+        {
+            let (width, height) = predicted_output_size(ri.input.dimensions(), ri.settings.1);
+            parts.push(format!("Output {width}x{height}"));
+            parts.push(format!("{} colours", ri.palette.len()));
+            parts.push(ri.settings.2.to_string());
+        }
+        if let Some(palette) = self.render_stats.palette {
+            let cache = palette
+                .cache_hit_rate
+                .map(|rate| format!(", {:.0}% cached", rate * 100.0))
+                .unwrap_or_default();
+            parts.push(format!(
+                "Palette {} ({} chunks{cache})",
+                format_duration(palette.duration),
+                palette.chunks_processed
+            ));
+        }
+        if let Some(dither) = self.render_stats.dither {
+            parts.push(format!(
+                "Dither {} ({} chunks)",
+                format_duration(dither.duration),
+                dither.chunks_processed
+            ));
+        }
+
+        Some(parts.join("  |  "))
+    }
+
+    pub fn history_memory_used(&self) -> usize {
+        self.image_history
+            .iter()
+            .map(RenderedImage::memory_used)
+            .sum()
+    }
+
+    pub fn export_all(&self) {
+        self.requests_tx
+            .send(ThreadRequest::PickExportDirectory)
+            .unwrap();
+    }
+
+    pub fn save_file(&self, index: usize) {
+        self.requests_tx
+            .send(ThreadRequest::GetOutputImage {
+                index,
+                palette: self.image_history[index].palette.clone(),
+            })
+            .unwrap();
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub fn process_thread_updates(
+        &mut self,
+        palette_settings: &PaletteSettings,
+        palette_method: &str,
+        output_settings: OutputSettings,
+        distance_algorithm: DistanceAlgorithm,
+        adjustments: Adjustments,
+        ctx: &Context,
+    ) {
+        while let Ok(update) = self.results_rx.try_recv() {
+            match update {
+                ThreadResult::ReadInFile {
+                    file,
+                    frame,
+                    input,
+                    recovery,
+                } => {
+                    self.original_input = Some(input.clone());
+                    self.input_file.clone_from(&file);
+                    self.input_frame = frame;
+                    self.input_recovery = recovery;
+                    self.note_input_modified();
+
+                    self.render_job = self.render_job.supersede(&self.worker_should_stop);
+                    let (progress_tx, progress_rx) = channel();
+                    //a new input has nothing to do with what was shown before
+                    self.stage = RenderStage::CreatingPalette {
+                        input: Some(input.clone()),
+                        superseded: None,
+                        progress_rx,
+                        last_progress: (0, 1),
+                    };
+                    self.requests_tx
+                        .send(ThreadRequest::RenderPalette {
+                            job: self.render_job.clone(),
+                            input,
+                            adjustments,
+                            palette_settings: palette_settings.clone(),
+                            palette_method: palette_method.to_string(),
+                            distance_algorithm,
+                            progress_tx,
+                        })
+                        .unwrap();
+
+                    if let Some(file) = file {
+                        self.persisted.last_start_dir = file.parent().map(Path::to_path_buf);
+                        self.persisted.recent_files.add(file);
+                    }
+                }
+                ThreadResult::RenderedPalette {
+                    job,
+                    input,
+                    adjusted,
+                    palette,
+                    palette_settings,
+                    palette_method,
+                    adjustments,
+                    stats,
+                } => {
+                    if job.generation != self.render_job.generation {
+                        continue;
+                    }
+                    self.render_stats = RenderStats {
+                        palette: Some(stats),
+                        dither: None,
+                    };
+
+                    //different settings can still come up with a palette that's already been dithered
+                    if let Some(existing) = find_reusable_render(
+                        &self.image_history,
+                        &input,
+                        adjustments,
+                        distance_algorithm,
+                        output_settings,
+                        SamePalette::Content(palette.content_hash()),
+                    ) {
+                        self.stage = RenderStage::DisplayingImage(existing);
+                        continue;
+                    }
+
+                    let (progress_tx, progress_rx) = channel();
+                    self.stage = RenderStage::CreatingOutput {
+                        input: input.clone(),
+                        adjusted: adjusted.clone(),
+                        palette_used: palette.clone(),
+                        palette_settings: palette_settings.clone(),
+                        palette_method: palette_method.clone(),
+                        adjustments,
+                        superseded: self.stage.shown_entry(),
+                        progress_rx,
+                        last_progress: (0, 1),
+                    };
+                    self.requests_tx
+                        .send(ThreadRequest::RenderOutput {
+                            job,
+                            input,
+                            adjusted,
+                            palette,
+                            palette_settings,
+                            palette_method,
+                            adjustments,
+                            output_settings,
+                            distance_algorithm,
+                            progress_tx,
+                        })
+                        .unwrap();
+                }
+                ThreadResult::RenderedImage {
+                    generation,
+                    input,
+                    adjusted,
+                    palette,
+                    output,
+                    compressed,
+                    usage,
+                    stats,
+                    settings,
+                } => {
+                    //a newer render has started since, so this one isn't wanted any more
+                    if generation != self.render_job.generation {
+                        continue;
+                    }
+                    self.render_stats.dither = Some(stats);
+
+                    let handle = ctx.load_texture(
+                        "my-img",
+                        Self::color_image_from_dynamic_image(&output),
+                        self.texture_options,
+                    );
+                    let ri = RenderedImage {
+                        input,
+                        adjusted,
+                        palette,
+                        output: compressed,
+                        resident: Some(ResidentOutput {
+                            output,
+                            handle,
+                            simulated: vec![],
+                        }),
+                        view: None,
+                        difference: None,
+                        difference_requested: false,
+                        quality_metric: None,
+                        colour_error: None,
+                        stand_in_input: false,
+                        usage,
+                        animation: None,
+                        settings,
+                    };
+
+                    self.requests_tx
+                        .send(ThreadRequest::ComputeQuality {
+                            index: self.image_history.len(),
+                            palette: ri.palette.clone(),
+                            input: ri.input.clone(),
+                            adjusted: ri.adjusted.clone(),
+                            output: ri.decoded_output().into_owned(),
+                            distance_algorithm: ri.settings.2,
+                        })
+                        .unwrap();
+
+                    self.autosave(&ri);
+                    self.image_history.push(ri.clone());
+                    self.stage = RenderStage::DisplayingImage(self.image_history.len() - 1);
+                }
+                ThreadResult::GotDestination {
+                    file,
+                    index,
+                    palette,
+                    save_dir,
+                } => {
+                    //renders can finish while the dialog is open, which might move the entry
+                    let is_entry = |ri: &&RenderedImage| Arc::ptr_eq(&ri.palette, &palette);
+                    if let Some(ri) = self
+                        .image_history
+                        .get(index)
+                        .filter(is_entry)
+                        .or_else(|| self.image_history.iter().find(is_entry))
+                    {
+                        //checked now rather than after picking a scale and waiting for it to be made
+                        let format = ExportFormat::from_path(&file);
+                        let error = if format.is_none() {
+                            Some("That isn't an image format that can be saved.".to_string())
+                        } else if check_writable(&file).is_err() {
+                            Some(NOT_WRITABLE_MESSAGE.to_string())
+                        } else {
+                            None
+                        };
+
+                        self.pending_export = Some(PendingExport {
+                            format: format.unwrap_or(ExportFormat::Png),
+                            file,
+                            ri: ri.clone(),
+                            error,
+                            confirmed_large: false,
+                        });
+                    }
+
+                    self.persisted.last_save_dir = Some(save_dir);
+                }
+                ThreadResult::RenderedDifference {
+                    index,
+                    palette,
+                    heatmap,
+                    max_distance,
+                } => {
+                    //the history might have changed while we were working it out
+                    //and it might not be on screen any more
+                    if let Some(ri) = self
+                        .image_history
+                        .get_mut(index)
+                        .filter(|ri| Arc::ptr_eq(&ri.palette, &palette) && ri.resident.is_some())
+                    {
+                        let handle = ctx.load_texture(
+                            "difference",
+                            Self::color_image_from_dynamic_image(&heatmap),
+                            self.texture_options,
+                        );
+                        ri.difference = Some((handle, max_distance));
+                    }
+                }
+                ThreadResult::QualityComputed {
+                    index,
+                    palette,
+                    ssim,
+                    colour_error,
+                } => {
+                    if let Some(ri) = self
+                        .image_history
+                        .get_mut(index)
+                        .filter(|ri| Arc::ptr_eq(&ri.palette, &palette))
+                    {
+                        ri.quality_metric = Some(ssim);
+                        ri.colour_error = Some(colour_error);
+                    }
+                }
+                ThreadResult::ClusteredPalette { palette, clusters } => {
+                    if let Some(clustered) = self
+                        .palette_clusters
+                        .as_mut()
+                        .filter(|clustered| Arc::ptr_eq(&clustered.palette, &palette))
+                    {
+                        clustered.clusters = Some(clusters);
+                    }
+                }
+                ThreadResult::AnalyzedImage { input, analysis } => {
+                    if let Some(stats) = self
+                        .image_stats
+                        .as_mut()
+                        .filter(|stats| Arc::ptr_eq(&stats.input, &input))
+                    {
+                        stats.analysis = Some(analysis);
+                    }
+                }
+                ThreadResult::ComputedHistograms {
+                    adjusted,
+                    histograms,
+                } => {
+                    if let Some((_, computed)) = self
+                        .input_histograms
+                        .as_mut()
+                        .filter(|(made_for, _)| Arc::ptr_eq(made_for, &adjusted))
+                    {
+                        *computed = Some(histograms);
+                    }
+                }
+                ThreadResult::GotExportDirectory(directory) => {
+                    if let RenderStage::DisplayingImage(displaying) = self.stage {
+                        let entries = self
+                            .image_history
+                            .iter()
+                            .map(|ri| ExportEntry {
+                                name: ri.export_name(),
+                                output: ri.output.clone(),
+                                output_settings: ri.settings.1,
+                            })
+                            .collect();
+
+                        let (progress_tx, progress_rx) = channel();
+                        self.stage = RenderStage::ExportingAll {
+                            displaying,
+                            last_progress: (0, 1),
+                            progress_rx,
+                        };
+                        self.requests_tx
+                            .send(ThreadRequest::ExportAll {
+                                directory: directory.clone(),
+                                entries,
+                                progress_tx,
+                            })
+                            .unwrap();
+                    }
+
+                    self.persisted.last_save_dir = Some(directory);
+                }
+                ThreadResult::ExportedAll { exported, failures } => {
+                    if let RenderStage::ExportingAll { displaying, .. } = self.stage {
+                        self.stage = RenderStage::DisplayingImage(displaying);
+                    }
+
+                    let message = if failures.is_empty() {
+                        format!("Exported {exported} images")
+                    } else {
+                        format!(
+                            "Exported {exported} images, {} failed:\n{}",
+                            failures.len(),
+                            failures.join("\n")
+                        )
+                    };
+                    self.toasts.push(Toast {
+                        message,
+                        shown_at: Instant::now(),
+                    });
+                }
+                ThreadResult::LoadPathFailed { file, message } => {
+                    self.persisted.recent_files.remove(&file);
+                    self.toasts.push(Toast {
+                        message,
+                        shown_at: Instant::now(),
+                    });
+                }
+                ThreadResult::RestoredSession {
+                    file,
+                    frame,
+                    input,
+                    entries,
+                    displaying,
+                } => {
+                    self.original_input = Some(input.clone());
+                    self.input_file = Some(file);
+                    self.input_frame = frame;
+                    self.input_recovery = None;
+                    self.note_input_modified();
+
+                    let first_index = self.image_history.len();
+                    for (offset, entry) in entries.into_iter().enumerate() {
+                        let ri = RenderedImage {
+                            input: input.clone(),
+                            adjusted: entry.adjusted,
+                            palette: entry.palette,
+                            output: entry.output,
+                            resident: None,
+                            view: None,
+                            difference: None,
+                            difference_requested: false,
+                            quality_metric: None,
+                            colour_error: None,
+                            stand_in_input: false,
+                            usage: entry.usage,
+                            animation: None,
+                            settings: entry.settings,
+                        };
+
+                        self.requests_tx
+                            .send(ThreadRequest::ComputeQuality {
+                                index: first_index + offset,
+                                palette: ri.palette.clone(),
+                                input: ri.input.clone(),
+                                adjusted: ri.adjusted.clone(),
+                                output: ri.decoded_output().into_owned(),
+                                distance_algorithm: ri.settings.2,
+                            })
+                            .unwrap();
+
+                        self.image_history.push(ri);
+                    }
+
+                    let displaying = first_index + displaying;
+                    self.restored_settings = Some(self.image_history[displaying].settings.clone());
+                    self.stage = RenderStage::DisplayingImage(displaying);
+                }
+                ThreadResult::AnimatedInput {
+                    file,
+                    frame_count,
+                    thumbnails,
+                } => {
+                    let thumbnails = thumbnails
+                        .iter()
+                        .enumerate()
+                        .map(|(i, thumbnail)| {
+                            ctx.load_texture(
+                                format!("frame-{i}-of-{frame_count}"),
+                                Self::color_image_from_dynamic_image(thumbnail),
+                                self.texture_options,
+                            )
+                        })
+                        .collect();
+                    self.picking_frame = Some(PickingFrame {
+                        file,
+                        thumbnails,
+                        chosen: 0,
+                    });
+                }
+                ThreadResult::RenderFailed {
+                    generation,
+                    message,
+                } => {
+                    if generation != self.render_job.generation {
+                        continue;
+                    }
+
+                    //back to whatever was on screen before, as there's nothing coming to replace it
+                    self.stage = self
+                        .stage
+                        .shown_entry()
+                        .map_or(RenderStage::Nothing, RenderStage::DisplayingImage);
+                    self.toasts.push(Toast {
+                        message: format!("Couldn't render: {message}"),
+                        shown_at: Instant::now(),
+                    });
+                }
+                ThreadResult::RenderedAnimation {
+                    generation,
+                    index,
+                    palette,
+                    frames,
+                } => {
+                    if generation != self.render_job.generation {
+                        continue;
+                    }
+                    if let RenderStage::RenderingAnimation { displaying, .. } = self.stage {
+                        self.stage = RenderStage::DisplayingImage(displaying);
+                    }
+
+                    match frames {
+                        Ok(frames) => {
+                            if let Some(ri) = self
+                                .image_history
+                                .get_mut(index)
+                                .filter(|ri| Arc::ptr_eq(&ri.palette, &palette))
+                            {
+                                ri.animation = Some(Animation {
+                                    frames,
+                                    textures: vec![],
+                                });
+                            }
+                        }
+                        Err(message) => self.toasts.push(Toast {
+                            message,
+                            shown_at: Instant::now(),
+                        }),
+                    }
+                }
+                ThreadResult::RecoveredAutosave(entries) => {
+                    self.add_recovered_entries(entries);
+                }
+                ThreadResult::RestoreFailed => {
+                    if matches!(self.stage, RenderStage::RestoringSession { .. }) {
+                        self.stage = RenderStage::Nothing;
+                    }
+                }
+                ThreadResult::Toast(message) => {
+                    self.toasts.push(Toast {
+                        message,
+                        shown_at: Instant::now(),
+                    });
+                }
+            }
+        }
+
+        match &mut self.stage {
+            RenderStage::CreatingOutput {
+                last_progress,
+                progress_rx,
+                ..
+            }
+            | RenderStage::CreatingPalette {
+                last_progress,
+                progress_rx,
+                ..
+            }
+            | RenderStage::ExportingAll {
+                last_progress,
+                progress_rx,
+                ..
+            }
+            | RenderStage::RenderingAnimation {
+                last_progress,
+                progress_rx,
+                ..
+            }
+            | RenderStage::RestoringSession {
                 last_progress,
                 progress_rx,
+            } => {
+                for prog in progress_rx.try_iter() {
+                    *last_progress = prog;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    //stops whatever's rendering and throws away everything the worker hasn't got to yet
+    pub fn clear_queue(&mut self) {
+        self.render_job = self.render_job.supersede(&self.worker_should_stop);
+        self.requests_tx.send(ThreadRequest::CancelCurrent).unwrap();
+
+        //nothing is coming back for anything that was waiting
+        self.stage = match self.stage {
+            RenderStage::ExportingAll { displaying, .. }
+            | RenderStage::RenderingAnimation { displaying, .. } => {
+                RenderStage::DisplayingImage(displaying)
+            }
+            RenderStage::DisplayingImage(index)
+            | RenderStage::CreatingPalette {
+                superseded: Some(index),
+                ..
+            }
+            | RenderStage::CreatingOutput {
+                superseded: Some(index),
+                ..
+            } => RenderStage::DisplayingImage(index),
+            _ if self.image_history.is_empty() => RenderStage::Nothing,
+            _ => RenderStage::DisplayingImage(self.image_history.len() - 1),
+        };
+        for ri in &mut self.image_history {
+            if ri.difference.is_none() {
+                ri.difference_requested = false;
+            }
+        }
+    }
+
+    //only gifs that are still the right way round, since the frames get read from the file again
+    pub fn can_render_animation(&self, index: usize) -> bool {
+        let ri = &self.image_history[index];
+        ri.animation.is_none()
+            && self
+                .original_input
+                .as_ref()
+                .is_some_and(|original| Arc::ptr_eq(&ri.input, original))
+            && self
+                .input_file
+                .as_ref()
+                .is_some_and(|file| ImageFormat::from_path(file).ok() == Some(ImageFormat::Gif))
+    }
+
+    pub fn render_animation(&mut self, index: usize) {
+        let Some(file) = self.input_file.clone() else {
+            return;
+        };
+        let ri = &self.image_history[index];
+        let (_, output_settings, distance_algorithm, adjustments, _) = ri.settings.clone();
+        let palette = ri.palette.clone();
+        let (progress_tx, progress_rx) = channel();
+
+        let job = self.start_render_job();
+        self.requests_tx
+            .send(ThreadRequest::RenderAnimation {
+                job,
+                index,
+                file,
+                palette,
+                adjustments,
+                output_settings,
+                distance_algorithm,
+                progress_tx,
+            })
+            .unwrap();
+
+        self.stage = RenderStage::RenderingAnimation {
+            displaying: index,
+            last_progress: (0, 1),
+            progress_rx,
+        };
+    }
+
+    fn start_render_job(&mut self) -> RenderJob {
+        self.render_job = self.render_job.supersede(&self.worker_should_stop);
+        self.render_job.clone()
+    }
+
+    //returns whether the change was dealt with, or whether it needs to wait until the current render is done
+    pub fn change_palette_settings_or_algo(
+        &mut self,
+        palette_settings: PaletteSettings,
+        palette_method: String,
+        distance_algorithm: DistanceAlgorithm,
+        adjustments: Adjustments,
+    ) -> bool {
+        if self.displaying_stand_in() {
+            return false;
+        }
+
+        let input = match &self.stage {
+            RenderStage::DisplayingImage(idx) => self.image_history[*idx].input.clone(),
+            RenderStage::CreatingPalette {
+                input: Some(input), ..
+            }
+            | RenderStage::CreatingOutput { input, .. } => input.clone(),
+            _ => return false,
+        };
+        let (progress_tx, progress_rx) = channel();
+
+        let job = self.start_render_job();
+        self.requests_tx
+            .send(ThreadRequest::RenderPalette {
+                job,
+                input: input.clone(),
+                adjustments,
+                palette_settings,
+                palette_method,
+                distance_algorithm,
+                progress_tx,
+            })
+            .unwrap();
+
+        self.stage = RenderStage::CreatingPalette {
+            input: Some(input),
+            superseded: self.stage.shown_entry(),
+            progress_rx,
+            last_progress: (0, 1),
+        };
+        true
+    }
+
+    //recovered entries without their input can be looked at and saved, but not rendered from
+    fn displaying_stand_in(&self) -> bool {
+        match self.stage {
+            RenderStage::DisplayingImage(index) => self.image_history[index].stand_in_input,
+            _ => false,
+        }
+    }
+
+    pub fn transform_input(
+        &mut self,
+        transform: InputTransform,
+        palette_settings: PaletteSettings,
+        palette_method: String,
+        distance_algorithm: DistanceAlgorithm,
+        adjustments: Adjustments,
+    ) {
+        if self.displaying_stand_in() {
+            return;
+        }
+
+        let originally_contained = std::mem::replace(&mut self.stage, RenderStage::Nothing);
+        if let RenderStage::DisplayingImage(idx) = originally_contained {
+            let input = self.image_history[idx].input.clone();
+            let (progress_tx, progress_rx) = channel();
+
+            let job = self.start_render_job();
+            self.requests_tx
+                .send(ThreadRequest::TransformInput {
+                    job,
+                    input,
+                    transform,
+                    adjustments,
+                    palette_settings,
+                    palette_method,
+                    distance_algorithm,
+                    progress_tx,
+                })
+                .unwrap();
+
+            self.stage = RenderStage::CreatingPalette {
+                input: None,
+                superseded: Some(idx),
+                progress_rx,
+                last_progress: (0, 1),
+            }
+        } else {
+            self.stage = originally_contained;
+        }
+    }
+
+    pub fn reset_orientation(
+        &mut self,
+        palette_settings: PaletteSettings,
+        palette_method: String,
+        distance_algorithm: DistanceAlgorithm,
+        adjustments: Adjustments,
+    ) {
+        let Some(original) = self.original_input.clone() else {
+            return;
+        };
+
+        let originally_contained = std::mem::replace(&mut self.stage, RenderStage::Nothing);
+        if let RenderStage::DisplayingImage(idx) = originally_contained {
+            let (progress_tx, progress_rx) = channel();
+
+            let job = self.start_render_job();
+            self.requests_tx
+                .send(ThreadRequest::RenderPalette {
+                    job,
+                    input: original.clone(),
+                    adjustments,
+                    palette_settings,
+                    palette_method,
+                    distance_algorithm,
+                    progress_tx,
+                })
+                .unwrap();
+
+            self.stage = RenderStage::CreatingPalette {
+                input: Some(original),
+                superseded: Some(idx),
+                progress_rx,
+                last_progress: (0, 1),
+            }
+        } else {
+            self.stage = originally_contained;
+        }
+    }
+
+    //the input that the settings currently apply to
+    pub fn input_dimensions(&self) -> Option<(u32, u32)> {
+        match &self.stage {
+            RenderStage::DisplayingImage(index) => {
+                Some(self.image_history[*index].input.dimensions())
+            }
+            RenderStage::CreatingPalette {
+                input: Some(input), ..
+            }
+            | RenderStage::CreatingOutput { input, .. } => Some(input.dimensions()),
+            _ => self.original_input.as_ref().map(|input| input.dimensions()),
+        }
+    }
+
+    pub fn is_orientation_changed(&self) -> bool {
+        match (&self.stage, &self.original_input) {
+            (RenderStage::DisplayingImage(index), Some(original)) => {
+                !Arc::ptr_eq(&self.image_history[*index].input, original)
+            }
+            _ => false,
+        }
+    }
+
+    //returns whether the change was dealt with, or whether it needs to wait until the current render is done
+    pub fn change_output_settings(
+        &mut self,
+        output_settings: OutputSettings,
+        distance_algorithm: DistanceAlgorithm,
+    ) -> bool {
+        if self.displaying_stand_in() {
+            return false;
+        }
+
+        let (input, adjusted, palette, palette_settings, palette_method, adjustments) =
+            match &self.stage {
+                RenderStage::DisplayingImage(index) => {
+                    let ri = &self.image_history[*index];
+                    (
+                        ri.input.clone(),
+                        ri.adjusted.clone(),
+                        ri.palette.clone(),
+                        ri.settings.0.clone(),
+                        ri.settings.4.clone(),
+                        ri.settings.3,
+                    )
+                }
+                RenderStage::CreatingOutput {
+                    input,
+                    adjusted,
+                    palette_used,
+                    palette_settings,
+                    palette_method,
+                    adjustments,
+                    ..
+                } => (
+                    input.clone(),
+                    adjusted.clone(),
+                    palette_used.clone(),
+                    palette_settings.clone(),
+                    palette_method.clone(),
+                    *adjustments,
+                ),
+                //the newest output settings get used once the palette is done anyway
+                RenderStage::CreatingPalette { .. } => return true,
+                _ => return false,
+            };
+
+        self.render_output(
+            (
+                input,
+                adjusted,
+                palette,
+                palette_settings,
+                palette_method,
+                adjustments,
+            ),
+            output_settings,
+            distance_algorithm,
+        );
+        true
+    }
+
+    //re-renders an entry with the colours that none of its output used taken out of its palette
+    pub fn remove_unused_colours(&mut self, index: usize) {
+        let ri = &self.image_history[index];
+        if ri.stand_in_input {
+            return;
+        }
+        let palette: Vec<_> = ri
+            .palette
+            .iter()
+            .zip(&ri.usage)
+            .filter(|(_, count)| **count > 0)
+            .map(|(colour, _)| *colour)
+            .collect();
+        let (palette_settings, output_settings, distance_algorithm, adjustments, palette_method) =
+            ri.settings.clone();
+
+        self.render_output(
+            (
+                ri.input.clone(),
+                ri.adjusted.clone(),
+                Arc::new(palette.into()),
+                palette_settings,
+                palette_method,
+                adjustments,
+            ),
+            output_settings,
+            distance_algorithm,
+        );
+    }
+
+    fn render_output(
+        &mut self,
+        (input, adjusted, palette, palette_settings, palette_method, adjustments): (
+            Arc<DynamicImage>,
+            Arc<DynamicImage>,
+            Arc<Palette>,
+            PaletteSettings,
+            String,
+            Adjustments,
+        ),
+        output_settings: OutputSettings,
+        distance_algorithm: DistanceAlgorithm,
+    ) {
+        let (progress_tx, progress_rx) = channel();
+
+        let job = self.start_render_job();
+        self.requests_tx
+            .send(ThreadRequest::RenderOutput {
+                job,
+                input: input.clone(),
+                adjusted: adjusted.clone(),
+                palette: palette.clone(),
+                palette_settings: palette_settings.clone(),
+                palette_method: palette_method.clone(),
+                adjustments,
+                output_settings,
+                distance_algorithm,
+                progress_tx,
+            })
+            .unwrap();
+
+        self.stage = RenderStage::CreatingOutput {
+            input,
+            adjusted,
+            palette_used: palette,
+            palette_settings,
+            palette_method,
+            adjustments,
+            superseded: self.stage.shown_entry(),
+            progress_rx,
+            last_progress: (0, 1),
+        };
+    }
+
+    fn export(image: &DynamicImage, file: &Path, format: ExportFormat) {
+        if let Err(e) = export_image(image, file, format) {
+            eprintln!("Error saving file: {e:?}");
+        }
+    }
+
+    fn show_restore_modal(&mut self, ctx: &Context) {
+        if self.previous_session.is_none() {
+            return;
+        }
+
+        let mut should_restore = false;
+        let mut should_close = false;
+        let modal = egui::Modal::new(egui::Id::new("restore_session")).show(ctx, |ui| {
+            ui.heading("Restore previous session?");
+            if let Some(session) = &self.previous_session {
+                ui.label(format!(
+                    "{} with {} history entries",
+                    session.input.display(),
+                    session.entries.len()
+                ));
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    should_restore = true;
+                }
+                if ui.button("Start Fresh").clicked() {
+                    should_close = true;
+                }
+            });
+        });
+
+        if modal.should_close() {
+            should_close = true;
+        }
+
+        if should_restore {
+            if let Some(session) = self.previous_session.take() {
+                let (progress_tx, progress_rx) = channel();
+                self.stage = RenderStage::RestoringSession {
+                    last_progress: (0, session.entries.len() as u32),
+                    progress_rx,
+                };
+                self.requests_tx
+                    .send(ThreadRequest::RestoreSession {
+                        session,
+                        progress_tx,
+                    })
+                    .unwrap();
             }
-            | RenderStage::CreatingPalette {
-                last_progress,
-                progress_rx,
-            } => {
-                for prog in progress_rx.try_iter() {
-                    *last_progress = prog;
+        } else if should_close {
+            self.previous_session = None;
+            Session::clear();
+        }
+    }
+
+    fn show_frame_picker_modal(&mut self, ctx: &Context) {
+        let Some(picking) = &mut self.picking_frame else {
+            return;
+        };
+
+        let mut should_load = false;
+        let mut should_close = false;
+        let modal = egui::Modal::new(egui::Id::new("pick_frame")).show(ctx, |ui| {
+            ui.heading("Pick a frame");
+            ui.label(format!(
+                "{} has {} frames",
+                picking.file.display(),
+                picking.thumbnails.len()
+            ));
+
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (i, thumbnail) in picking.thumbnails.iter().enumerate() {
+                        if ui
+                            .add(
+                                egui::ImageButton::new(egui::Image::from_texture(thumbnail))
+                                    .selected(picking.chosen == i),
+                            )
+                            .on_hover_text(format!("Frame {i}"))
+                            .clicked()
+                        {
+                            picking.chosen = i;
+                        }
+                    }
+                });
+            });
+
+            ui.horizontal(|ui| {
+                let label = if picking.chosen == 0 {
+                    "Use First Frame".to_string()
+                } else {
+                    format!("Use Frame {}", picking.chosen)
+                };
+                should_load = ui.button(label).clicked();
+                should_close = ui.button("Cancel").clicked();
+            });
+        });
+
+        if modal.should_close() {
+            should_close = true;
+        }
+
+        if should_load {
+            if let Some(PickingFrame { file, chosen, .. }) = self.picking_frame.take() {
+                self.requests_tx
+                    .send(ThreadRequest::LoadFrame {
+                        file,
+                        frame: chosen,
+                    })
+                    .unwrap();
+            }
+        } else if should_close {
+            self.picking_frame = None;
+        }
+    }
+
+    //only the entries made from the file as it was loaded can be re-rendered later
+    fn save_session(&self) {
+        //don't overwrite the last session before we know whether it's wanted
+        if self.previous_session.is_some() {
+            return;
+        }
+
+        let (Some(file), Some(original)) = (&self.input_file, &self.original_input) else {
+            Session::clear();
+            return;
+        };
+
+        let displaying = match self.stage {
+            RenderStage::DisplayingImage(index)
+            | RenderStage::ExportingAll {
+                displaying: index, ..
+            }
+            | RenderStage::RenderingAnimation {
+                displaying: index, ..
+            } => Some(index),
+            _ => None,
+        };
+
+        let mut entries = vec![];
+        let mut displaying_entry = 0;
+        for (index, ri) in self.image_history.iter().enumerate() {
+            if !Arc::ptr_eq(&ri.input, original) {
+                continue;
+            }
+            if displaying == Some(index) {
+                displaying_entry = entries.len();
+            }
+
+            let (
+                palette_settings,
+                output_settings,
+                distance_algorithm,
+                adjustments,
+                palette_method,
+            ) = ri.settings.clone();
+            entries.push(SessionEntry {
+                settings: CurrentSettings {
+                    palette_settings,
+                    palette_method,
+                    output_settings,
+                    distance_algorithm,
+                    adjustments,
+                },
+                palette: (*ri.palette).clone(),
+            });
+        }
+
+        if entries.is_empty() {
+            Session::clear();
+        } else {
+            Session::new(file.clone(), self.input_frame, entries, displaying_entry).save();
+        }
+    }
+
+    fn show_export_modal(&mut self, ctx: &Context) {
+        let Some(pending) = &mut self.pending_export else {
+            return;
+        };
+
+        let mut should_save = false;
+        let mut should_close = false;
+        let modal = egui::Modal::new(egui::Id::new("export")).show(ctx, |ui| {
+            ui.heading("Export");
+
+            let previewed = pending.predicted_size(ExportScale::AsPreviewed);
+            let original = pending.predicted_size(ExportScale::MatchOriginal);
+            let custom_factor = match self.export_scale {
+                ExportScale::Custom(factor) => factor,
+                _ => pending.original_factor(),
+            };
+            let sharing_max_dimension = match self.export_scale {
+                ExportScale::ForSharing(max_dimension) => max_dimension,
+                _ => SHARING_MAX_DIMENSION,
+            };
+
+            ui.radio_value(
+                &mut self.export_scale,
+                ExportScale::AsPreviewed,
+                format!("As previewed ({}x{})", previewed.0, previewed.1),
+            );
+            ui.radio_value(
+                &mut self.export_scale,
+                ExportScale::MatchOriginal,
+                format!("Match original size ({}x{})", original.0, original.1),
+            );
+            ui.horizontal(|ui| {
+                ui.radio_value(
+                    &mut self.export_scale,
+                    ExportScale::Custom(custom_factor),
+                    "Custom",
+                );
+                if let ExportScale::Custom(factor) = &mut self.export_scale {
+                    ui.add(egui::DragValue::new(factor).range(1..=u32::MAX).prefix("×"));
+                }
+                if let ExportScale::Custom(_) = self.export_scale {
+                    let (width, height) = pending.predicted_size(self.export_scale);
+                    ui.label(format!("({width}x{height})"));
                 }
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(
+                    &mut self.export_scale,
+                    ExportScale::ForSharing(sharing_max_dimension),
+                    "For the web",
+                )
+                .on_hover_text("Skips pixels to fit within a size, so it stays sharp but isn't huge");
+                if let ExportScale::ForSharing(max_dimension) = &mut self.export_scale {
+                    ui.add(
+                        egui::DragValue::new(max_dimension)
+                            .range(1..=u32::MAX)
+                            .prefix("≤ ")
+                            .suffix("px"),
+                    );
+                    let (width, height) = pending.predicted_size(self.export_scale);
+                    ui.label(format!("({width}x{height})"));
+                }
+            });
+
+            let (width, height) = pending.predicted_size(self.export_scale);
+            let is_large = width * height > LARGE_OUTPUT_PIXELS;
+            if is_large {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("This will make a {width}x{height} image, which could take a lot of memory and time."),
+                );
+                ui.checkbox(&mut pending.confirmed_large, "Export it anyway");
             }
-            _ => {}
+
+            //jpegs need the quality picking too
+            if let ExportFormat::Jpeg { quality } = &mut pending.format {
+                ui.separator();
+                ui.add(Slider::new(quality, 1..=100).text("Quality"));
+            }
+
+            if let Some(error) = &pending.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            let can_save = ExportFormat::from_path(&pending.file).is_some()
+                && (!is_large || pending.confirmed_large);
+            ui.horizontal(|ui| {
+                should_save = ui.add_enabled(can_save, Button::new("Save")).clicked();
+                should_close = ui.button("Cancel").clicked();
+            });
+        });
+
+        if should_save {
+            if let Some(animation) = &pending.ri.animation {
+                match pending.export_animation(animation, self.export_scale) {
+                    Ok(()) => self.pending_export = None,
+                    Err(e) => pending.error = Some(e),
+                }
+                return;
+            }
+
+            match pending.scaled(self.export_scale) {
+                Ok(image) => {
+                    Self::export(&image, &pending.file, pending.format);
+                    self.pending_export = None;
+                }
+                //keep the dialog open so a different scale can be picked
+                Err(e) => pending.error = Some(e.to_string()),
+            }
+        } else if should_close || modal.should_close() {
+            self.pending_export = None;
+        }
+    }
+
+    fn show_toasts(&mut self, ctx: &Context) {
+        const TOAST_LIFETIME: Duration = Duration::from_secs(4);
+
+        self.toasts
+            .retain(|toast| toast.shown_at.elapsed() < TOAST_LIFETIME);
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, vec2(-10.0, -10.0))
+            .show(ctx, |ui| {
+                for toast in &self.toasts {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(&toast.message);
+                    });
+                }
+            });
+
+        ctx.request_repaint_after(Duration::from_millis(250));
+    }
+
+    fn color_image_from_dynamic_image(img: &DynamicImage) -> ColorImage {
+        let size = [img.width() as _, img.height() as _];
+        match img {
+            DynamicImage::ImageRgb8(rgb) => {
+                ColorImage::from_rgb(size, rgb.as_flat_samples().as_slice())
+            }
+            DynamicImage::ImageRgba8(rgba) => {
+                ColorImage::from_rgba_unmultiplied(size, rgba.as_flat_samples().as_slice())
+            }
+            _ => {
+                let mut pixels = Vec::with_capacity(size[0] * size[1] * 3);
+
+                for y in 0..img.height() {
+                    for x in 0..img.width() {
+                        let rgb = img.get_pixel(x as _, y as _).to_rgb().0;
+                        pixels.extend_from_slice(rgb.as_slice());
+                    }
+                }
+
+                ColorImage::from_rgb(size, &pixels)
+            }
+        }
+    }
+}
+
+impl PxlsApp {
+    pub fn new(cc: &CreationContext<'_>) -> Self {
+        Self {
+            current: PhotoBeingEdited::new(PersistedState::load(cc.storage)),
+            show_palette: None,
+            palette_view: PaletteView::Grid,
+            distance_algorithm: DistanceAlgorithm::Euclidean,
+            palette_settings: PaletteSettings::default(),
+            palette_method: DEFAULT_QUANTIZER.to_string(),
+            output_settings: OutputSettings::default(),
+            adjustments: Adjustments::default(),
+            right_clicked_colour: None,
+            ramp_dialog: None,
+            custom_color_input: String::new(),
+            auto_update: true,
+            auto_update_debouncer: Debouncer::new(DEFAULT_AUTO_UPDATE_DELAY),
+            last_seen_settings: None,
+            view: View {
+                zoom: 1.0,
+                pan: Vec2::ZERO,
+                mode: ZoomMode::Fit,
+                show_inspector: false,
+                show_difference: false,
+                show_changes: false,
+                changes_opacity: 0.6,
+                simulating: None,
+            },
+            last_displayed_image_index: None,
+            previously_displayed_index: None,
+            pinned_entry: None,
+            playback: Playback {
+                playing: true,
+                position: Duration::ZERO,
+                last_update: Instant::now(),
+            },
+            eyedropper: None,
+            needs_to_refresh_output: false,
+            needs_to_refresh_palette: false,
+        }
+    }
+}
+
+//in seconds
+const DEFAULT_AUTO_UPDATE_DELAY: f64 = 0.3;
+//how often to look at whether the input file has been changed, when reloading it is on
+const INPUT_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+//how many times worse than the average a palette colour has to be before it gets flagged
+const HIGH_COLOUR_ERROR_RATIO: f32 = 1.5;
+
+const MIN_ZOOM: f32 = 0.01;
+const MAX_ZOOM: f32 = 64.0;
+const ZOOM_STEP: f32 = 1.25;
+
+impl View {
+    const fn set_mode(&mut self, mode: ZoomMode) {
+        self.mode = mode;
+        self.pan = Vec2::ZERO;
+    }
+
+    //`around` is relative to the centre of the panel
+    fn zoom_by(&mut self, factor: f32, around: Vec2) {
+        let new_zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan -= (around - self.pan) * (new_zoom / self.zoom - 1.0);
+        self.zoom = new_zoom;
+        self.mode = ZoomMode::Free;
+    }
+
+    fn apply_mode(&mut self, available: Rect, img_size: Vec2, pixels_per_point: f32) {
+        match self.mode {
+            ZoomMode::Fit => {
+                let fit_scale =
+                    (available.width() / img_size.x).min(available.height() / img_size.y);
+                self.zoom = fit_scale.clamp(MIN_ZOOM, MAX_ZOOM);
+                self.pan = Vec2::ZERO;
+            }
+            ZoomMode::OneToOne => self.zoom = 1.0 / pixels_per_point,
+            ZoomMode::Free => {}
+        }
+    }
+
+    const fn saved(&self) -> SavedView {
+        SavedView {
+            zoom: self.zoom,
+            pan: self.pan,
+            mode: self.mode,
+        }
+    }
+
+    const fn restore(&mut self, saved: SavedView) {
+        self.zoom = saved.zoom;
+        self.pan = saved.pan;
+        self.mode = saved.mode;
+    }
+}
+
+impl PxlsApp {
+    fn shown_pixel_changes(&self, index: usize) -> Option<&(TextureHandle, f32)> {
+        if !self.view.show_changes {
+            return None;
+        }
+        let previous = self.previously_displayed_index?;
+        self.current
+            .pixel_changes
+            .as_ref()
+            .filter(|changes| changes.between == (index, previous))
+            .and_then(|changes| changes.mask.as_ref())
+    }
+
+    fn switch_view_to(&mut self, index: usize) {
+        let history = &mut self.current.image_history;
+        let previous = self
+            .last_displayed_image_index
+            .filter(|previous| *previous < history.len());
+
+        //only re-fit for a new picture, so that tweaking the settings keeps the view where it was
+        let is_new_picture = previous.is_none_or(|previous| {
+            !Arc::ptr_eq(&history[previous].input, &history[index].input)
+                || history[previous].output.dimensions() != history[index].output.dimensions()
+        });
+
+        if let Some(previous) = previous {
+            history[previous].view = Some(self.view.saved());
+        }
+        if let Some(saved) = history[index].view {
+            self.view.restore(saved);
+        } else if is_new_picture {
+            self.view.set_mode(ZoomMode::Fit);
+        }
+
+        self.previously_displayed_index = previous;
+        self.last_displayed_image_index = Some(index);
+    }
+}
+
+impl PxlsApp {
+    fn show_ramp_modal(&mut self, ctx: &Context) {
+        let Some(dialog) = &mut self.ramp_dialog else {
+            return;
+        };
+
+        let mut should_add = false;
+        let mut should_close = false;
+        let modal = egui::Modal::new(egui::Id::new("generate_ramp")).show(ctx, |ui| {
+            ui.heading("Generate Ramp");
+
+            Grid::new("ramp_settings").show(ui, |ui| {
+                ui.label("Start:");
+                ui.color_edit_button_srgb(&mut dialog.start);
+                ui.end_row();
+
+                ui.label("End:");
+                ui.color_edit_button_srgb(&mut dialog.end);
+                ui.end_row();
+
+                ui.label("Steps:");
+                ui.add(Slider::new(&mut dialog.steps, 2..=32));
+                ui.end_row();
+
+                ui.label("Colour Space:");
+                egui::ComboBox::from_id_salt("ramp_color_space")
+                    .selected_text(dialog.color_space.to_str())
+                    .show_ui(ui, |ui| {
+                        for possibility in ALL_RAMP_COLOR_SPACES {
+                            ui.selectable_value(
+                                &mut dialog.color_space,
+                                possibility,
+                                possibility.to_str(),
+                            );
+                        }
+                    });
+                ui.end_row();
+            });
+
+            //preview, so you don't have to keep re-generating the palette
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 0.0;
+                for Rgba([r, g, b, _]) in dialog.ramp() {
+                    let (rect, _) = ui.allocate_exact_size(vec2(16.0, 16.0), Sense::hover());
+                    ui.painter()
+                        .rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
+                }
+            });
+
+            ui.horizontal(|ui| {
+                should_add = ui.button("Add to Palette").clicked();
+                should_close = ui.button("Cancel").clicked();
+            });
+        });
+
+        if should_add {
+            for colour in dialog.ramp() {
+                if !self.palette_settings.extra_colors.contains(&colour) {
+                    self.palette_settings.extra_colors.push(colour);
+                }
+            }
+            self.needs_to_refresh_palette = true;
+        }
+        if should_add || should_close || modal.should_close() {
+            self.ramp_dialog = None;
+        }
+    }
+
+    fn show_custom_color_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                TextEdit::singleline(&mut self.custom_color_input)
+                    .hint_text("#RRGGBB")
+                    .desired_width(100.0),
+            );
+            let parsed = parse_hex_color(&self.custom_color_input)
+                .or_else(|| named_color(&self.custom_color_input));
+
+            if !self.custom_color_input.is_empty() {
+                let outline = if parsed.is_some() {
+                    Color32::GREEN
+                } else {
+                    Color32::RED
+                };
+                ui.painter()
+                    .rect_stroke(response.rect, 2.0, Stroke::new(1.5, outline));
+            }
+
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            let clicked = ui.add_enabled(parsed.is_some(), Button::new("+")).clicked();
+            if let Some(colour) = parsed.filter(|_| entered || clicked) {
+                if !self.palette_settings.extra_colors.contains(&colour) {
+                    self.palette_settings.extra_colors.push(colour);
+                    self.needs_to_refresh_palette = true;
+                }
+                self.custom_color_input.clear();
+            }
+
+            let suggestions = named_colors_matching(&self.custom_color_input);
+            let popup_id = ui.make_persistent_id("custom_color_suggestions");
+            if response.changed() {
+                if suggestions.is_empty() {
+                    ui.memory_mut(egui::Memory::close_popup);
+                } else {
+                    ui.memory_mut(|memory| memory.open_popup(popup_id));
+                }
+            }
+            popup_below_widget(
+                ui,
+                popup_id,
+                &response,
+                PopupCloseBehavior::CloseOnClick,
+                |ui| {
+                    for (name, colour) in suggestions.into_iter().take(8) {
+                        let Rgba([r, g, b, _]) = colour;
+                        let picked = ui
+                            .horizontal(|ui| {
+                                let (rect, _) =
+                                    ui.allocate_exact_size(vec2(12.0, 12.0), Sense::hover());
+                                ui.painter()
+                                    .rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
+                                ui.selectable_label(false, name).clicked()
+                            })
+                            .inner;
+                        if picked {
+                            self.custom_color_input = rgb_to_hex(colour);
+                        }
+                    }
+                },
+            );
+        });
+    }
+
+    //what the threshold actually gets compared against
+    fn show_effective_threshold(ui: &mut egui::Ui, algo: DistanceAlgorithm, threshold: u32) {
+        let effective = algo.standardise_closeness_threshold(threshold);
+        if algo.squares_closeness_threshold() {
+            ui.label(format!("→ {effective} (squared)"));
+        } else {
+            ui.label(format!("→ {effective}"));
+        }
+    }
+
+    fn show_palette_usage(&mut self, ui: &mut egui::Ui) {
+        const BAR_SIZE: Vec2 = vec2(100.0, 12.0);
+
+        let RenderStage::DisplayingImage(index) = self.current.stage else {
+            return;
+        };
+        let ri = &self.current.image_history[index];
+        let total: u32 = ri.usage.iter().sum();
+        let most_used = ri.usage.iter().copied().max().unwrap_or(0).max(1);
+        let unused = ri.usage.iter().filter(|count| **count == 0).count();
+
+        let mut remove_unused = false;
+        egui::CollapsingHeader::new("Palette Usage").show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for (colour, count) in ri.palette.iter().zip(&ri.usage) {
+                        ui.horizontal(|ui| {
+                            let [r, g, b] = colour.to_rgb().0;
+                            let (swatch, _) = ui
+                                .allocate_exact_size(vec2(BAR_SIZE.y, BAR_SIZE.y), Sense::hover());
+                            ui.painter()
+                                .rect_filled(swatch, 2.0, Color32::from_rgb(r, g, b));
+
+                            let (rect, _) = ui.allocate_exact_size(BAR_SIZE, Sense::hover());
+                            let painter = ui.painter();
+                            if *count == 0 {
+                                painter.rect_stroke(
+                                    rect,
+                                    2.0,
+                                    Stroke::new(1.0, ui.visuals().warn_fg_color),
+                                );
+                                ui.colored_label(ui.visuals().warn_fg_color, "Unused");
+                            } else {
+                                painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+                                let mut filled = rect;
+                                filled.set_width(rect.width() * *count as f32 / most_used as f32);
+                                painter.rect_filled(filled, 2.0, ui.visuals().selection.bg_fill);
+                                ui.label(format!(
+                                    "{}: {:.1}%",
+                                    rgb_to_hex(*colour),
+                                    *count as f32 / total as f32 * 100.0
+                                ));
+                            }
+                        });
+                    }
+                });
+
+            if unused > 0 && ui.button(format!("Remove {unused} Unused")).clicked() {
+                remove_unused = true;
+            }
+        });
+
+        if remove_unused {
+            self.current.remove_unused_colours(index);
         }
     }
 
-    pub fn change_palette_settings_or_algo(
-        &mut self,
-        palette_settings: PaletteSettings,
-        distance_algorithm: DistanceAlgorithm,
+    fn show_input_histograms(&mut self, ui: &mut egui::Ui) {
+        const HISTOGRAM_SIZE: Vec2 = vec2(256.0, 48.0);
+
+        egui::CollapsingHeader::new("Histogram").show(ui, |ui| {
+            //only worked out while it's open, and kept for the last input while anything new renders
+            self.current.request_input_histograms();
+            let histograms = match &self.current.input_histograms {
+                Some((_, Some(histograms))) => histograms,
+                Some((_, None)) => {
+                    ui.spinner();
+                    return;
+                }
+                None => {
+                    ui.weak("Nothing loaded yet");
+                    return;
+                }
+            };
+
+            //matches whatever the scalar algorithms measure, so the markers line up with it
+            let (label, brightness) = if self.distance_algorithm == DistanceAlgorithm::Value {
+                ("Value:", &histograms.value)
+            } else {
+                ("Luminance:", &histograms.luminance)
+            };
+            ui.label(label);
+            let rect = histogram_plot(
+                ui,
+                HISTOGRAM_SIZE,
+                &[(brightness, ui.visuals().text_color())],
+            );
+
+            if let Some(markers) = threshold_markers(
+                self.distance_algorithm,
+                self.palette_settings.closeness_threshold,
+                brightness,
+            ) {
+                let stroke = egui::Stroke::new(1.0, ui.visuals().warn_fg_color);
+                for marker in &markers {
+                    let x = (marker / 256.0).mul_add(rect.width(), rect.left());
+                    ui.painter().vline(x, rect.y_range(), stroke);
+                }
+                ui.weak(format!(
+                    "The closeness threshold splits this into {} band(s)",
+                    markers.len()
+                ));
+            }
+
+            ui.label("Channels:");
+            histogram_plot(
+                ui,
+                HISTOGRAM_SIZE,
+                &[
+                    (&histograms.channels[0], Color32::RED),
+                    (&histograms.channels[1], Color32::GREEN),
+                    (&histograms.channels[2], Color32::BLUE),
+                ],
+            );
+        });
+    }
+
+    fn show_colour_error(&self, ui: &mut egui::Ui) {
+        const BAR_SIZE: Vec2 = vec2(100.0, 12.0);
+
+        let RenderStage::DisplayingImage(index) = self.current.stage else {
+            return;
+        };
+        let ri = &self.current.image_history[index];
+        let Some(colour_error) = &ri.colour_error else {
+            return;
+        };
+        let worst = colour_error
+            .first()
+            .map_or(0.0, |(_, error)| *error)
+            .max(f32::EPSILON);
+        let high_error = ri.high_error_colours();
+
+        egui::CollapsingHeader::new("Colour Error").show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt("colour-error")
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for (colour, error) in colour_error {
+                        ui.horizontal(|ui| {
+                            let [r, g, b] = colour.to_rgb().0;
+                            let (swatch, _) = ui
+                                .allocate_exact_size(vec2(BAR_SIZE.y, BAR_SIZE.y), Sense::hover());
+                            ui.painter()
+                                .rect_filled(swatch, 2.0, Color32::from_rgb(r, g, b));
+
+                            let (rect, _) = ui.allocate_exact_size(BAR_SIZE, Sense::hover());
+                            let painter = ui.painter();
+                            painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+                            let mut filled = rect;
+                            filled.set_width(rect.width() * error / worst);
+                            let fill = if high_error.contains(colour) {
+                                ui.visuals().warn_fg_color
+                            } else {
+                                ui.visuals().selection.bg_fill
+                            };
+                            painter.rect_filled(filled, 2.0, fill);
+                            ui.label(format!("{}: {error:.1}", rgb_to_hex(*colour)));
+                        });
+                    }
+                });
+
+            if !high_error.is_empty() {
+                ui.label(format!(
+                    "{} colour(s) cover their pixels badly, and might be worth replacing or splitting up",
+                    high_error.len()
+                ));
+            }
+        });
+    }
+
+    fn show_palette_clusters(&mut self, ui: &mut egui::Ui, palette: &Arc<Palette>) {
+        const SWATCH_SIZE: Vec2 = vec2(16.0, 16.0);
+
+        self.current.request_clusters(palette);
+        let Some(clustered) = &mut self.current.palette_clusters else {
+            return;
+        };
+        let Some(clusters) = &clustered.clusters else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Grouping colours...");
+            });
+            return;
+        };
+
+        let mut highlighted = clustered.highlighted;
+        egui::ScrollArea::vertical()
+            .id_salt("palette-clusters")
+            .show(ui, |ui| {
+                for (i, cluster) in clusters.iter().enumerate() {
+                    let id = ui.make_persistent_id(("palette-cluster", i));
+                    egui::collapsing_header::CollapsingState::load_with_default_open(
+                        ui.ctx(),
+                        id,
+                        true,
+                    )
+                    .show_header(ui, |ui| {
+                        let selected = highlighted == Some(i);
+                        if ui
+                            .selectable_label(
+                                selected,
+                                format!("{} ({})", cluster.name, cluster.colours.len()),
+                            )
+                            .on_hover_text("Highlight where these colours are used")
+                            .clicked()
+                        {
+                            highlighted = if selected { None } else { Some(i) };
+                        }
+                    })
+                    .body(|ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.spacing_mut().item_spacing = vec2(1.0, 1.0);
+                            for colour in &cluster.colours {
+                                let (rect, rsp) =
+                                    ui.allocate_exact_size(SWATCH_SIZE, Sense::click());
+                                if colour.0[3] < u8::MAX {
+                                    Self::paint_backing(
+                                        ui.painter(),
+                                        rect,
+                                        self.current.persisted.backing,
+                                    );
+                                }
+                                let [r, g, b, a] = self
+                                    .view
+                                    .simulating
+                                    .map_or(*colour, |cvd| simulate_cvd(*colour, cvd))
+                                    .0;
+                                ui.painter().rect_filled(
+                                    rect,
+                                    0.0,
+                                    Color32::from_rgba_unmultiplied(r, g, b, a),
+                                );
+
+                                let hex = rgb_to_hex(*colour);
+                                if rsp.clicked() {
+                                    ui.ctx().copy_text(hex.clone());
+                                }
+                                rsp.on_hover_text(format!("Click to Copy: {hex}"));
+                            }
+                        });
+                    });
+                }
+            });
+        clustered.highlighted = highlighted;
+    }
+
+    fn show_quality_bar(ui: &mut egui::Ui, ssim: f32) {
+        let colour = if ssim > 0.9 {
+            Color32::GREEN
+        } else if ssim >= 0.7 {
+            Color32::YELLOW
+        } else {
+            Color32::RED
+        };
+
+        let (rect, rsp) = ui.allocate_exact_size(vec2(60.0, 10.0), Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let mut filled = rect;
+        filled.set_width(rect.width() * ssim.clamp(0.0, 1.0));
+        painter.rect_filled(filled, 2.0, colour);
+        rsp.on_hover_text("Structural similarity to the original input - 1.0 is identical");
+
+        ui.label(format!("SSIM: {ssim:.3}"));
+    }
+
+    //both sides share the one view, so panning or zooming either of them moves both
+    fn show_comparison(
+        ui: &mut egui::Ui,
+        view: &mut View,
+        current: &PhotoBeingEdited,
+        pinned: usize,
+        index: usize,
     ) {
-        let originally_contained = std::mem::replace(&mut self.stage, RenderStage::Nothing);
-        if let RenderStage::DisplayingImage(idx) = originally_contained {
-            let input = self.image_history[idx].input.clone();
-            let (progress_tx, progress_rx) = channel();
+        let (Some(pinned_ri), Some(ri)) = (
+            current.image_history.get(pinned),
+            current.image_history.get(index),
+        ) else {
+            return;
+        };
+        let (Some(pinned_resident), Some(resident)) = (&pinned_ri.resident, &ri.resident) else {
+            return;
+        };
 
-            self.requests_tx
-                .send(ThreadRequest::RenderPalette {
-                    input,
-                    palette_settings,
-                    distance_algorithm,
-                    progress_tx,
-                })
-                .unwrap();
+        let settings = |ri: &RenderedImage| (ri.settings.0.clone(), ri.settings.1, ri.settings.2);
+        ui.label(format!(
+            "#{pinned} → #{index}: {}",
+            describe_settings_changes(&settings(pinned_ri), &settings(ri))
+        ));
 
-            self.stage = RenderStage::CreatingPalette {
-                progress_rx,
-                last_progress: (0, 1),
+        let available = ui.available_rect_before_wrap();
+        let half_size = vec2(available.width() / 2.0, available.height());
+        let halves = [
+            Rect::from_min_size(available.min, half_size),
+            Rect::from_min_size(available.min + vec2(half_size.x, 0.0), half_size),
+        ];
+
+        //the pinned one gets stretched to the same size, so the same bit of the input lines up on both sides
+        let img_size = vec2(
+            resident.output.width() as f32,
+            resident.output.height() as f32,
+        );
+        view.apply_mode(halves[1], img_size, ui.ctx().pixels_per_point());
+
+        let uv = Rect {
+            min: pos2(0.0, 0.0),
+            max: pos2(1.0, 1.0),
+        };
+        for ((half, resident), label) in halves
+            .into_iter()
+            .zip([pinned_resident, resident])
+            .zip([format!("Pinned #{pinned}"), format!("#{index}")])
+        {
+            let rect = Rect::from_center_size(half.center() + view.pan, img_size * view.zoom);
+            let painter = ui.painter_at(half);
+            Self::paint_backing(&painter, rect, current.persisted.backing);
+            painter.image(
+                TextureId::from(resident.texture(view.simulating)),
+                rect,
+                uv,
+                Color32::WHITE,
+            );
+            painter.text(
+                half.left_top() + vec2(10.0, 10.0),
+                egui::Align2::LEFT_TOP,
+                label,
+                egui::FontId::default(),
+                ui.visuals().strong_text_color(),
+            );
+
+            let rsp = ui.allocate_rect(half, Sense::drag());
+            if rsp.drag_delta() != Vec2::ZERO {
+                view.pan += rsp.drag_delta();
+                if view.mode == ZoomMode::Fit {
+                    view.mode = ZoomMode::Free;
+                }
+            }
+            if rsp.hovered() {
+                let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+                if scroll != 0.0 {
+                    let around = rsp
+                        .hover_pos()
+                        .map_or(Vec2::ZERO, |pointer| pointer - half.center());
+                    view.zoom_by((scroll / 200.0).exp(), around);
+                }
             }
-        } else {
-            self.stage = originally_contained;
         }
+
+        ui.painter().vline(
+            available.center().x,
+            available.y_range(),
+            ui.visuals().widgets.noninteractive.bg_stroke,
+        );
     }
 
-    pub fn change_output_settings(
-        &mut self,
-        output_settings: OutputSettings,
-        distance_algorithm: DistanceAlgorithm,
+    //the entry being replaced stays up, dimmed, so it can still be compared against
+    fn show_render_progress(
+        ui: &mut egui::Ui,
+        view: &mut View,
+        current: &PhotoBeingEdited,
+        superseded: Option<usize>,
+        label: &str,
+        (so_far, max): (u32, u32),
     ) {
-        let originally_contained = std::mem::replace(&mut self.stage, RenderStage::Nothing);
-        if let RenderStage::DisplayingImage(index) = originally_contained {
-            let (progress_tx, progress_rx) = channel();
-            let ri = &self.image_history[index];
+        let show_progress = |ui: &mut egui::Ui| {
+            ui.label(label);
+            ProgressBar::new((so_far as f32) / (max as f32))
+                .animate(true)
+                .show_percentage()
+                .ui(ui);
+        };
 
-            self.requests_tx
-                .send(ThreadRequest::RenderOutput {
-                    input: ri.input.clone(),
-                    palette: ri.palette.clone(),
-                    palette_settings: ri.settings.0,
-                    output_settings,
-                    distance_algorithm,
-                    progress_tx,
-                })
-                .unwrap();
+        let Some(resident) = superseded
+            .and_then(|index| current.image_history.get(index))
+            .and_then(|ri| ri.resident.as_ref())
+        else {
+            show_progress(ui);
+            return;
+        };
 
-            self.stage = RenderStage::CreatingOutput {
-                palette_used: ri.palette.clone(),
-                progress_rx,
-                last_progress: (0, 1),
-            }
-        } else {
-            self.stage = originally_contained;
-        }
+        let available = ui.available_rect_before_wrap();
+        let img_size = vec2(
+            resident.output.width() as f32,
+            resident.output.height() as f32,
+        );
+        view.apply_mode(available, img_size, ui.ctx().pixels_per_point());
+        let rect = Rect::from_center_size(available.center() + view.pan, img_size * view.zoom);
+
+        let painter = ui.painter_at(available);
+        Self::paint_backing(&painter, rect, current.persisted.backing);
+        painter.image(
+            TextureId::from(resident.texture(view.simulating)),
+            rect,
+            Rect {
+                min: pos2(0.0, 0.0),
+                max: pos2(1.0, 1.0),
+            },
+            Color32::from_gray(128),
+        );
+
+        let corner = Rect::from_min_size(available.left_top() + vec2(10.0, 10.0), vec2(240.0, 0.0));
+        ui.allocate_new_ui(egui::UiBuilder::new().max_rect(corner), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, show_progress);
+        });
     }
 
-    fn color_image_from_dynamic_image(img: &DynamicImage) -> ColorImage {
-        let size = [img.width() as _, img.height() as _];
-        match img {
-            DynamicImage::ImageRgb8(rgb) => {
-                ColorImage::from_rgb(size, rgb.as_flat_samples().as_slice())
-            }
-            DynamicImage::ImageRgba8(rgba) => {
-                ColorImage::from_rgba_unmultiplied(size, rgba.as_flat_samples().as_slice())
+    //drawn under images rather than baked into them, so exports keep their transparency
+    fn paint_backing(painter: &egui::Painter, rect: Rect, backing: Backing) {
+        //in screen pixels, so it stays the same size however far you zoom
+        const CHECKER_PIXELS: f32 = 8.0;
+        const LIGHT: Color32 = Color32::from_gray(204);
+        const DARK: Color32 = Color32::from_gray(153);
+
+        let rect = rect.intersect(painter.clip_rect());
+        if !rect.is_positive() {
+            return;
+        }
+
+        match backing {
+            Backing::Solid([r, g, b]) => {
+                painter.rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
             }
-            _ => {
-                let mut pixels = Vec::with_capacity(size[0] * size[1] * 3);
+            Backing::Checkerboard => {
+                painter.rect_filled(rect, 0.0, LIGHT);
 
-                for y in 0..img.height() {
-                    for x in 0..img.width() {
-                        let rgb = img.get_pixel(x as _, y as _).to_rgb().0;
-                        pixels.extend_from_slice(rgb.as_slice());
+                let size = CHECKER_PIXELS / painter.ctx().pixels_per_point();
+                #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+                let (columns, rows) = (
+                    (rect.width() / size).ceil() as usize,
+                    (rect.height() / size).ceil() as usize,
+                );
+                for row in 0..rows {
+                    for column in ((row % 2)..columns).step_by(2) {
+                        let min = rect.min + vec2(column as f32 * size, row as f32 * size);
+                        let square = Rect::from_min_size(min, vec2(size, size)).intersect(rect);
+                        painter.rect_filled(square, 0.0, DARK);
                     }
                 }
-
-                ColorImage::from_rgb(size, &pixels)
             }
         }
     }
-}
 
-impl PxlsApp {
-    pub fn new(cc: &CreationContext<'_>) -> Self {
-        const FALLBACK: (Option<PathBuf>, Option<PathBuf>) = (None, None);
-        let start_and_save_dirs = cc.storage.map_or(FALLBACK, |storage| {
-            storage
-                .get_string("start_and_save_dirs")
-                .map_or(FALLBACK, |sered| {
-                    serde_json::from_str(&sered).unwrap_or(FALLBACK)
-                })
-        });
+    fn show_heatmap_legend(ui: &egui::Ui, available: Rect, max_distance: u32) {
+        const SEGMENTS: usize = 32;
+        const BAR_SIZE: Vec2 = vec2(160.0, 12.0);
 
-        Self {
-            current: PhotoBeingEdited::new(start_and_save_dirs),
-            show_palette: None,
-            distance_algorithm: DistanceAlgorithm::Euclidean,
-            palette_settings: PaletteSettings::default(),
-            output_settings: OutputSettings::default(),
-            auto_update: true,
-            needs_to_refresh_output: false,
-            needs_to_refresh_palette: false,
+        let painter = ui.painter_at(available);
+        let bar = Rect::from_min_size(
+            available.left_bottom() + vec2(10.0, -10.0 - BAR_SIZE.y),
+            BAR_SIZE,
+        );
+
+        let segment_width = BAR_SIZE.x / SEGMENTS as f32;
+        for i in 0..SEGMENTS {
+            let Rgba([r, g, b, _]) = heatmap_colour(i as f32 / (SEGMENTS - 1) as f32);
+            painter.rect_filled(
+                Rect::from_min_size(
+                    bar.min + vec2(segment_width * i as f32, 0.0),
+                    vec2(segment_width, BAR_SIZE.y),
+                ),
+                0.0,
+                Color32::from_rgb(r, g, b),
+            );
+        }
+
+        for (pos, align, text) in [
+            (bar.left_top(), egui::Align2::LEFT_BOTTOM, "0".to_string()),
+            (
+                bar.right_top(),
+                egui::Align2::RIGHT_BOTTOM,
+                max_distance.to_string(),
+            ),
+        ] {
+            painter.text(
+                pos,
+                align,
+                text,
+                egui::FontId::default(),
+                ui.visuals().text_color(),
+            );
         }
     }
 }
@@ -327,11 +3408,69 @@ impl eframe::App for PxlsApp {
     #[allow(clippy::too_many_lines)]
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
         self.current.process_thread_updates(
-            self.palette_settings,
+            &self.palette_settings,
+            &self.palette_method,
             self.output_settings,
             self.distance_algorithm,
+            self.adjustments,
             ctx,
         );
+        if let Some((palette, output, distance, adjustments, palette_method)) =
+            self.current.restored_settings.take()
+        {
+            self.palette_settings = palette;
+            self.palette_method = palette_method;
+            self.output_settings = output;
+            self.distance_algorithm = distance;
+            self.adjustments = adjustments;
+
+            self.needs_to_refresh_output = false;
+            self.needs_to_refresh_palette = false;
+        }
+        if self.auto_update && self.current.auto_reload_file {
+            self.current.reload_input_if_changed();
+            ctx.request_repaint_after(INPUT_CHECK_INTERVAL);
+        }
+
+        if matches!(
+            &self.current.stage,
+            RenderStage::Nothing | RenderStage::DisplayingImage(_)
+        ) && !ctx.wants_keyboard_input()
+        {
+            //egui-winit eats the ctrl+v press when the clipboard doesn't have text in it, so look for the release instead
+            let pasted = ctx.input(|i| {
+                i.events.iter().any(|e| {
+                    matches!(e, egui::Event::Key {
+                        key: egui::Key::V,
+                        pressed: false,
+                        modifiers,
+                        ..
+                    } if modifiers.command)
+                })
+            });
+            if pasted {
+                self.current.paste_new_input();
+            }
+
+            ctx.input(|i| {
+                if i.modifiers.command || i.modifiers.alt {
+                    return;
+                }
+
+                if i.key_pressed(egui::Key::F) || i.key_pressed(egui::Key::Num0) {
+                    self.view.set_mode(ZoomMode::Fit);
+                }
+                if i.key_pressed(egui::Key::Num1) {
+                    self.view.set_mode(ZoomMode::OneToOne);
+                }
+                if i.key_pressed(egui::Key::Plus) || i.key_pressed(egui::Key::Equals) {
+                    self.view.zoom_by(ZOOM_STEP, Vec2::ZERO);
+                }
+                if i.key_pressed(egui::Key::Minus) {
+                    self.view.zoom_by(1.0 / ZOOM_STEP, Vec2::ZERO);
+                }
+            });
+        }
 
         egui::TopBottomPanel::new(TopBottomSide::Top, "top_panel").show(ctx, |ui| {
             if !matches!(
@@ -343,56 +3482,226 @@ impl eframe::App for PxlsApp {
 
             ui.horizontal(|ui| {
                 ui.vertical(|ui| {
-                    if ui.button("Select File").clicked() {
-                        self.current.pick_new_input();
-                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Select File").clicked() {
+                            self.current.pick_new_input();
+                        }
+                        ui.add_enabled_ui(!self.current.persisted.recent_files.is_empty(), |ui| {
+                            ui.menu_button("Recent", |ui| {
+                                self.current.persisted.recent_files.prune();
+
+                                let mut chosen = None;
+                                for file in self.current.persisted.recent_files.iter() {
+                                    let label = file.file_name().map_or_else(
+                                        || file.display().to_string(),
+                                        |name| name.to_string_lossy().to_string(),
+                                    );
+                                    if ui
+                                        .button(label)
+                                        .on_hover_text(file.display().to_string())
+                                        .clicked()
+                                    {
+                                        chosen = Some(file.clone());
+                                    }
+                                }
+
+                                if let Some(file) = chosen {
+                                    self.current.load_recent_input(file);
+                                    ui.close_menu();
+                                }
+                            });
+                        });
+                        if ui.button("Paste image").clicked() {
+                            self.current.paste_new_input();
+                        }
+                        let mut autosave = self.current.autosave.is_some();
+                        if ui
+                            .checkbox(&mut autosave, "Autosave")
+                            .on_hover_text(
+                                "Keep a copy of every render until Pxls closes, in case it crashes",
+                            )
+                            .changed()
+                        {
+                            self.current.set_autosave(autosave);
+                        }
+                        if let Some(recovery) = self.current.input_recovery {
+                            ui.colored_label(ui.visuals().warn_fg_color, "⚠ Damaged input")
+                                .on_hover_text(format!(
+                                    "This file couldn't be read normally, so {recovery}"
+                                ));
+                        }
+                    });
+
+                    let is_displaying =
+                        matches!(self.current.stage, RenderStage::DisplayingImage(_));
+                    ui.add_enabled_ui(is_displaying, |ui| {
+                        ui.horizontal(|ui| {
+                            let mut transform = None;
+                            for (label, hover, possibility) in [
+                                ("⟳", "Rotate clockwise", InputTransform::RotateClockwise),
+                                (
+                                    "⟲",
+                                    "Rotate anticlockwise",
+                                    InputTransform::RotateAnticlockwise,
+                                ),
+                                ("↔", "Flip horizontally", InputTransform::FlipHorizontal),
+                                ("↕", "Flip vertically", InputTransform::FlipVertical),
+                            ] {
+                                if ui.button(label).on_hover_text(hover).clicked() {
+                                    transform = Some(possibility);
+                                }
+                            }
+
+                            if let Some(transform) = transform {
+                                self.current.transform_input(
+                                    transform,
+                                    self.palette_settings.clone(),
+                                    self.palette_method.clone(),
+                                    self.distance_algorithm,
+                                    self.adjustments,
+                                );
+                            }
+                        });
+
+                        if ui
+                            .add_enabled(
+                                self.current.is_orientation_changed(),
+                                egui::Button::new("Reset orientation"),
+                            )
+                            .clicked()
+                        {
+                            self.current.reset_orientation(
+                                self.palette_settings.clone(),
+                                self.palette_method.clone(),
+                                self.distance_algorithm,
+                                self.adjustments,
+                            );
+                        }
+                        if ui.button("Image Stats").clicked() {
+                            self.current.request_image_stats();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.auto_update, "Auto-Update");
+                        if self.auto_update {
+                            ui.add(
+                                Slider::new(&mut self.auto_update_debouncer.delay, 0.0..=2.0)
+                                    .fixed_decimals(2)
+                                    .suffix("s"),
+                            )
+                            .on_hover_text("How long to wait after the last change");
+                            ui.checkbox(&mut self.current.auto_reload_file, "Reload input")
+                                .on_hover_text(
+                                    "Re-read the input whenever its file is changed on disk",
+                                );
+                        }
+                    });
 
-                    ui.checkbox(&mut self.auto_update, "Auto-Update");
+                    let now = ctx.input(|i| i.time);
+                    let settings = (
+                        self.palette_settings.clone(),
+                        self.output_settings,
+                        self.distance_algorithm,
+                        self.adjustments,
+                        self.palette_method.clone(),
+                    );
+                    if self.last_seen_settings.as_ref() != Some(&settings) {
+                        self.auto_update_debouncer.poke(now);
+                        self.last_seen_settings = Some(settings);
+                    }
 
-                    if self.needs_to_refresh_output || self.needs_to_refresh_palette {
-                        if let RenderStage::DisplayingImage(index) = &mut self.current.stage {
-                            let mut needs_to_update = self.auto_update;
-                            if !needs_to_update {
-                                needs_to_update = ui.button("Update").clicked();
+                    let pending_requests = self.current.requests_tx.pending();
+                    if pending_requests > 1 {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Queue: {pending_requests} pending"));
+                            if pending_requests > 3 && ui.button("Clear Queue").clicked() {
+                                self.current.clear_queue();
+                                self.needs_to_refresh_palette = false;
+                                self.needs_to_refresh_output = false;
                             }
+                        });
+                    }
 
-                            if needs_to_update {
-                                let mut found = false;
-                                for (
-                                    i,
-                                    RenderedImage {
-                                        input,
-                                        settings: (palette, output, distance),
-                                        ..
+                    //renders that are still going get superseded, rather than waiting for them to finish
+                    let can_update = matches!(
+                        self.current.stage,
+                        RenderStage::DisplayingImage(_)
+                            | RenderStage::CreatingPalette { .. }
+                            | RenderStage::CreatingOutput { .. }
+                    );
+                    if can_update && (self.needs_to_refresh_output || self.needs_to_refresh_palette)
+                    {
+                        let large_render = self
+                            .current
+                            .input_dimensions()
+                            .map(|dimensions| {
+                                predicted_output_size(
+                                    dimensions,
+                                    OutputSettings {
+                                        scale_output_to_original: false,
+                                        ..self.output_settings
                                     },
-                                ) in self.current.image_history.iter().enumerate()
-                                {
-                                    //hopefully short-circuiting should ensure that the input is compared last :)
-                                    if self.distance_algorithm == *distance
-                                        && self.palette_settings == *palette
-                                        && self.output_settings == *output
-                                        && &self.current.image_history[*index].input == input
-                                    {
-                                        *index = i;
-                                        found = true;
-                                        break;
-                                    }
-                                }
+                                )
+                            })
+                            .filter(|(width, height)| {
+                                u64::from(*width) * u64::from(*height) > LARGE_OUTPUT_PIXELS
+                            });
 
-                                if !found {
-                                    if self.needs_to_refresh_palette {
-                                        self.current.change_palette_settings_or_algo(
-                                            self.palette_settings,
-                                            self.distance_algorithm,
-                                        );
-                                    } else if self.needs_to_refresh_output {
-                                        self.current.change_output_settings(
-                                            self.output_settings,
-                                            self.distance_algorithm,
-                                        );
-                                    }
+                        //big renders always need asking for, even with auto-update on
+                        let needs_to_update = if let Some((width, height)) = large_render {
+                            ui.colored_label(
+                                ui.visuals().warn_fg_color,
+                                format!("This will render a {width}x{height} image"),
+                            );
+                            ui.button("Render Anyway").clicked()
+                        } else if self.auto_update {
+                            //anything already rendering gets superseded once this goes through
+                            let settled = self.auto_update_debouncer.is_settled(now);
+                            if let Some(remaining) = self.auto_update_debouncer.remaining(now) {
+                                ui.weak("Pending…");
+                                ctx.request_repaint_after(Duration::from_secs_f64(remaining));
+                            }
+                            settled
+                        } else {
+                            ui.button("Update").clicked()
+                        };
+
+                        if needs_to_update {
+                            let mut found = false;
+                            if let RenderStage::DisplayingImage(index) = &mut self.current.stage {
+                                if let Some(existing) = find_reusable_render(
+                                    &self.current.image_history,
+                                    &self.current.image_history[*index].input,
+                                    self.adjustments,
+                                    self.distance_algorithm,
+                                    self.output_settings,
+                                    SamePalette::Settings(
+                                        &self.palette_settings,
+                                        &self.palette_method,
+                                    ),
+                                ) {
+                                    *index = existing;
+                                    found = true;
                                 }
+                            }
 
+                            let dealt_with = found
+                                || if self.needs_to_refresh_palette {
+                                    self.current.change_palette_settings_or_algo(
+                                        self.palette_settings.clone(),
+                                        self.palette_method.clone(),
+                                        self.distance_algorithm,
+                                        self.adjustments,
+                                    )
+                                } else {
+                                    self.current.change_output_settings(
+                                        self.output_settings,
+                                        self.distance_algorithm,
+                                    )
+                                };
+
+                            if dealt_with {
                                 self.needs_to_refresh_palette = false;
                                 self.needs_to_refresh_output = false;
                             }
@@ -412,7 +3721,17 @@ impl eframe::App for PxlsApp {
                 ui.separator();
 
                 ui.vertical(|ui| {
-                    ui.label("Distance Algorithm:");
+                    //otherwise updating wouldn't do anything, which is easy to mistake for it being broken
+                    let settings_match_current = !self.needs_to_refresh_palette
+                        && !self.needs_to_refresh_output
+                        && matches!(self.current.stage, RenderStage::DisplayingImage(_));
+                    ui.horizontal(|ui| {
+                        ui.label("Distance Algorithm:");
+                        if settings_match_current {
+                            ui.colored_label(Color32::GREEN, "✓ Current")
+                                .on_hover_text("No changes since the last render");
+                        }
+                    });
 
                     let current = self.distance_algorithm;
                     for possibility in ALL_ALGOS {
@@ -424,6 +3743,16 @@ impl eframe::App for PxlsApp {
                     }
 
                     if current != self.distance_algorithm {
+                        //keep the thresholds meaning roughly the same thing in the new units
+                        for threshold in [
+                            &mut self.palette_settings.closeness_threshold,
+                            &mut self.palette_settings.exclude_threshold,
+                        ] {
+                            *threshold =
+                                self.distance_algorithm.closeness_threshold_from_normalised(
+                                    current.normalised_closeness_threshold(*threshold),
+                                );
+                        }
                         self.needs_to_refresh_palette = true;
                     }
                 });
@@ -432,6 +3761,38 @@ impl eframe::App for PxlsApp {
 
                 ui.vertical(|ui| {
                     Grid::new("settings").show(ui, |ui| {
+                        {
+                            ui.label("Palette Method: ");
+
+                            //anything registered by the time the gui starts shows up here too
+                            let quantizers = available_quantizers(
+                                &self.palette_settings,
+                                self.distance_algorithm,
+                            );
+                            let old_method = self.palette_method.clone();
+                            egui::ComboBox::from_id_salt("palette_method")
+                                .selected_text(&old_method)
+                                .show_ui(ui, |ui| {
+                                    for quantizer in &quantizers {
+                                        if ui
+                                            .selectable_label(
+                                                quantizer.name() == old_method,
+                                                quantizer.name(),
+                                            )
+                                            .on_hover_text(quantizer.description())
+                                            .clicked()
+                                        {
+                                            self.palette_method = quantizer.name().to_string();
+                                        }
+                                    }
+                                });
+
+                            if old_method != self.palette_method {
+                                self.needs_to_refresh_palette = true;
+                            }
+
+                            ui.end_row();
+                        }
                         {
                             ui.label("Chunks per Dimension: ");
                             let old_cpd = self.palette_settings.chunks_per_dimension;
@@ -453,13 +3814,20 @@ impl eframe::App for PxlsApp {
                             ui.label("Closeness Threshold: ");
 
                             let old_ct = self.palette_settings.closeness_threshold;
-                            ui.add(
-                                Slider::new(
-                                    &mut self.palette_settings.closeness_threshold,
-                                    0..=255,
-                                )
-                                .logarithmic(true),
-                            );
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    Slider::new(
+                                        &mut self.palette_settings.closeness_threshold,
+                                        self.distance_algorithm.closeness_threshold_range(),
+                                    )
+                                    .logarithmic(true),
+                                );
+                                Self::show_effective_threshold(
+                                    ui,
+                                    self.distance_algorithm,
+                                    self.palette_settings.closeness_threshold,
+                                );
+                            });
 
                             if self.palette_settings.closeness_threshold != old_ct {
                                 self.needs_to_refresh_palette = true;
@@ -467,6 +3835,42 @@ impl eframe::App for PxlsApp {
 
                             ui.end_row();
                         }
+                        if !self.palette_settings.exclude_colors.is_empty() {
+                            ui.label("Exclusion Threshold: ");
+
+                            let old_et = self.palette_settings.exclude_threshold;
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    Slider::new(
+                                        &mut self.palette_settings.exclude_threshold,
+                                        self.distance_algorithm.closeness_threshold_range(),
+                                    )
+                                    .logarithmic(true),
+                                );
+                                Self::show_effective_threshold(
+                                    ui,
+                                    self.distance_algorithm,
+                                    self.palette_settings.exclude_threshold,
+                                );
+                            });
+
+                            if self.palette_settings.exclude_threshold != old_et {
+                                self.needs_to_refresh_palette = true;
+                            }
+
+                            ui.end_row();
+
+                            ui.label(format!(
+                                "Excluded Colours: {}",
+                                self.palette_settings.exclude_colors.len()
+                            ));
+                            if ui.button("Clear Exclusions").clicked() {
+                                self.palette_settings.exclude_colors.clear();
+                                self.needs_to_refresh_palette = true;
+                            }
+
+                            ui.end_row();
+                        }
                         {
                             ui.separator();
                             ui.end_row();
@@ -476,7 +3880,7 @@ impl eframe::App for PxlsApp {
                             let old_px_size = self.output_settings.output_px_size;
 
                             //make sure we don't get images that are too big to display. this is a pretty lazy solution, but i also can't see an alternative because we might not have an image yet lol
-                            let min = self.output_settings.dithering_scale.ilog2() + 1;
+                            let min = self.output_settings.effective_dithering_scale().ilog2() + 1;
                             ui.add(Slider::new(
                                 &mut self.output_settings.output_px_size,
                                 min..=10,
@@ -485,43 +3889,175 @@ impl eframe::App for PxlsApp {
                             if old_px_size != self.output_settings.output_px_size {
                                 self.needs_to_refresh_output = true;
                             }
-
-                            ui.end_row();
+
+                            ui.end_row();
+                        }
+                        {
+                            ui.label("Dither Mode: ");
+
+                            let old_mode = self.output_settings.dither_mode;
+                            egui::ComboBox::from_id_salt("dither_mode")
+                                .selected_text(old_mode.to_str())
+                                .show_ui(ui, |ui| {
+                                    for possibility in ALL_DITHER_MODES {
+                                        //keep the current parameters if it's the same kind of mode
+                                        let is_current = possibility.to_str() == old_mode.to_str();
+                                        if ui
+                                            .selectable_label(is_current, possibility.to_str())
+                                            .clicked()
+                                            && !is_current
+                                        {
+                                            self.output_settings.dither_mode = *possibility;
+                                        }
+                                    }
+                                });
+
+                            if old_mode != self.output_settings.dither_mode {
+                                self.needs_to_refresh_output = true;
+                                self.output_settings.output_px_size =
+                                    (self.output_settings.effective_dithering_scale().ilog2() + 1)
+                                        .max(self.output_settings.output_px_size);
+                            }
+
+                            ui.end_row();
+                        }
+                        if self.output_settings.dither_mode == DitherMode::Legacy {
+                            {
+                                ui.label("Dithering Mode: ");
+
+                                let old_dm = self.output_settings.dithering_mode;
+                                let is_ratio = matches!(
+                                    self.output_settings.dithering_mode,
+                                    DitheringMode::Ratio(_)
+                                );
+                                ui.add_enabled_ui(self.output_settings.dithering_scale > 1, |ui| {
+                                    ui.horizontal(|ui| {
+                                        if ui.radio(is_ratio, "Ratio").clicked() && !is_ratio {
+                                            self.output_settings.dithering_mode =
+                                                OutputSettings::default().dithering_mode;
+                                        }
+                                        if ui.radio(!is_ratio, "Fraction").clicked() && is_ratio {
+                                            self.output_settings.dithering_mode =
+                                                DitheringMode::Fraction(0.5);
+                                        }
+                                    });
+                                });
+
+                                ui.end_row();
+
+                                ui.label("Dithering Factor: ");
+                                ui.add_enabled(
+                                    self.output_settings.dithering_scale > 1,
+                                    match &mut self.output_settings.dithering_mode {
+                                        DitheringMode::Ratio(ratio) => Slider::new(ratio, 1..=5),
+                                        DitheringMode::Fraction(fraction) => {
+                                            Slider::new(fraction, 0.0..=1.0)
+                                        }
+                                    },
+                                );
+
+                                if old_dm != self.output_settings.dithering_mode {
+                                    self.needs_to_refresh_output = true;
+                                }
+
+                                ui.end_row();
+                            }
+                            {
+                                ui.label("Dithering Scale: ");
+
+                                let old_ds = self.output_settings.dithering_scale;
+                                ui.add(Slider::new(
+                                    &mut self.output_settings.dithering_scale,
+                                    1..=4,
+                                ));
+
+                                if old_ds != self.output_settings.dithering_scale {
+                                    self.needs_to_refresh_output = true;
+                                    self.output_settings.output_px_size =
+                                        (self.output_settings.dithering_scale.ilog2() + 1)
+                                            .max(self.output_settings.output_px_size);
+                                }
+
+                                ui.end_row();
+                            }
+                        }
+                        {
+                            let old_mode = self.output_settings.dither_mode;
+                            match &mut self.output_settings.dither_mode {
+                                DitherMode::Bayer { strength }
+                                | DitherMode::BlueNoise { strength } => {
+                                    ui.label("Strength: ");
+                                    ui.add(Slider::new(strength, 0..=100));
+                                    ui.end_row();
+                                }
+                                DitherMode::Random { strength, seed } => {
+                                    ui.label("Strength: ");
+                                    ui.add(Slider::new(strength, 0..=100));
+                                    ui.end_row();
+
+                                    ui.label("Seed: ");
+                                    ui.add(egui::DragValue::new(seed));
+                                    ui.end_row();
+                                }
+                                DitherMode::FloydSteinberg { direction } => {
+                                    ui.label("Direction: ");
+                                    egui::ComboBox::from_id_salt("error_diffusion_direction")
+                                        .selected_text(direction.to_str())
+                                        .show_ui(ui, |ui| {
+                                            for possibility in ALL_ERROR_DIFFUSION_DIRECTIONS {
+                                                ui.selectable_value(
+                                                    direction,
+                                                    *possibility,
+                                                    possibility.to_str(),
+                                                );
+                                            }
+                                        });
+                                    ui.end_row();
+                                }
+                                DitherMode::None | DitherMode::Legacy => {}
+                            }
+
+                            if old_mode != self.output_settings.dither_mode {
+                                self.needs_to_refresh_output = true;
+                            }
                         }
-                        {
-                            ui.label("Dithering Factor: ");
 
-                            let old_dl = self.output_settings.dithering_likelihood;
-                            ui.add_enabled(
-                                self.output_settings.dithering_scale > 1,
-                                Slider::new(&mut self.output_settings.dithering_likelihood, 1..=5),
-                            );
+                        {
+                            ui.label("Sharpening: ");
 
-                            if old_dl != self.output_settings.dithering_likelihood {
+                            if ui
+                                .add(Slider::new(
+                                    &mut self.output_settings.post_sharpen,
+                                    0.0..=MAX_POST_SHARPEN,
+                                ))
+                                .changed()
+                            {
                                 self.needs_to_refresh_output = true;
                             }
 
                             ui.end_row();
                         }
-                        {
-                            ui.label("Dithering Scale: ");
-
-                            let old_ds = self.output_settings.dithering_scale;
-                            ui.add(Slider::new(
-                                &mut self.output_settings.dithering_scale,
-                                1..=4,
-                            ));
+                        if let Some(dimensions) = self.current.input_dimensions() {
+                            let (width, height) = predicted_output_size(
+                                dimensions,
+                                OutputSettings {
+                                    scale_output_to_original: false,
+                                    ..self.output_settings
+                                },
+                            );
 
-                            if old_ds != self.output_settings.dithering_scale {
-                                self.needs_to_refresh_output = true;
-                                self.output_settings.output_px_size =
-                                    (self.output_settings.dithering_scale.ilog2() + 1)
-                                        .max(self.output_settings.output_px_size);
+                            ui.label("Output Size:");
+                            if self.output_settings.scale_output_to_original {
+                                let (saved_width, saved_height) =
+                                    predicted_output_size(dimensions, self.output_settings);
+                                ui.label(format!(
+                                    "{width}x{height}, saved at {saved_width}x{saved_height}"
+                                ));
+                            } else {
+                                ui.label(format!("{width}x{height}"));
                             }
-
                             ui.end_row();
                         }
-
                         {
                             let palette_len = match &self.current.stage {
                                 RenderStage::DisplayingImage(index) => {
@@ -544,21 +4080,116 @@ impl eframe::App for PxlsApp {
                     });
                 });
 
-                let palette: Option<Arc<[Rgba<u8>]>> = match &self.current.stage {
+                ui.separator();
+
+                ui.vertical(|ui| {
+                    egui::CollapsingHeader::new("Adjustments").show(ui, |ui| {
+                        let old_adjustments = self.adjustments;
+
+                        Grid::new("adjustments").show(ui, |ui| {
+                            for (label, value) in [
+                                ("Brightness: ", &mut self.adjustments.brightness),
+                                ("Contrast: ", &mut self.adjustments.contrast),
+                                ("Saturation: ", &mut self.adjustments.saturation),
+                            ] {
+                                ui.label(label);
+                                ui.add(Slider::new(value, -100..=100));
+                                ui.end_row();
+                            }
+                        });
+
+                        if ui
+                            .add_enabled(
+                                !self.adjustments.is_identity(),
+                                egui::Button::new("Reset Adjustments"),
+                            )
+                            .clicked()
+                        {
+                            self.adjustments = Adjustments::default();
+                        }
+
+                        if old_adjustments != self.adjustments {
+                            self.needs_to_refresh_palette = true;
+                        }
+                    });
+
+                    self.show_input_histograms(ui);
+                    self.show_palette_usage(ui);
+                    self.show_colour_error(ui);
+                });
+
+                ui.separator();
+
+                ui.vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Palette View: ");
+                        ui.selectable_value(&mut self.palette_view, PaletteView::Grid, "Grid");
+                        ui.selectable_value(
+                            &mut self.palette_view,
+                            PaletteView::Clusters,
+                            "Clusters",
+                        );
+                    });
+
+                    if ui.button("Generate Ramp…").clicked() {
+                        self.ramp_dialog = Some(RampDialog::default());
+                    }
+
+                    self.show_custom_color_input(ui);
+
+                    if !self.palette_settings.extra_colors.is_empty() {
+                        ui.label("Custom Colours:");
+                        let mut to_remove = None;
+                        ui.horizontal_wrapped(|ui| {
+                            for (i, colour) in self.palette_settings.extra_colors.iter().enumerate()
+                            {
+                                let Rgba([r, g, b, _]) = *colour;
+                                ui.group(|ui| {
+                                    ui.spacing_mut().item_spacing.x = 2.0;
+                                    let (rect, _) =
+                                        ui.allocate_exact_size(vec2(12.0, 12.0), Sense::hover());
+                                    ui.painter()
+                                        .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+                                    ui.label(rgb_to_hex(*colour));
+                                    if ui.small_button("×").on_hover_text("Remove").clicked() {
+                                        to_remove = Some(i);
+                                    }
+                                });
+                            }
+                        });
+                        if let Some(i) = to_remove {
+                            self.palette_settings.extra_colors.remove(i);
+                            self.needs_to_refresh_palette = true;
+                        }
+                        if ui.button("Clear Custom Colours").clicked() {
+                            self.palette_settings.extra_colors.clear();
+                            self.needs_to_refresh_palette = true;
+                        }
+                    }
+                });
+
+                let palette: Option<Arc<Palette>> = match &self.current.stage {
                     RenderStage::DisplayingImage(index) => {
                         Some(self.current.image_history[*index].palette.clone())
                     }
                     RenderStage::CreatingOutput { palette_used, .. } => Some(palette_used.clone()),
                     _ => None,
                 };
-                if let Some(palette) = palette {
+                if let Some(palette) = palette
+                    .as_ref()
+                    .filter(|_| self.palette_view == PaletteView::Clusters)
+                {
+                    self.show_palette_clusters(ui, palette);
+                } else if let Some(palette) = palette {
                     let available_rect = ui.available_rect_before_wrap();
 
+                    //the colours are laid out in order, so only the exact same palette can reuse the texture
                     let palette_to_show = {
                         match self.show_palette.as_ref() {
                             Some(old_palette)
-                                if Arc::ptr_eq(&old_palette.input.0, &palette)
-                                    && old_palette.input.1 == available_rect =>
+                                if *old_palette.input.0 == *palette
+                                    && old_palette.input.1 == available_rect
+                                    && old_palette.input.2 == self.view.simulating =>
                             {
                                 old_palette
                             }
@@ -586,8 +4217,15 @@ impl eframe::App for PxlsApp {
 
                                 'outer: for row in 0..image_height {
                                     for column in 0..image_width {
-                                        let [r, g, b] = palette[palette_index].to_rgb().0;
-                                        color_image[(column, row)] = Color32::from_rgb(r, g, b);
+                                        //matches the image when it's being viewed as a colour vision deficiency
+                                        let colour = palette[palette_index];
+                                        let [r, g, b, a] = self
+                                            .view
+                                            .simulating
+                                            .map_or(colour, |cvd| simulate_cvd(colour, cvd))
+                                            .0;
+                                        color_image[(column, row)] =
+                                            Color32::from_rgba_unmultiplied(r, g, b, a);
 
                                         palette_index += 1;
                                         if palette_index >= palette.len() {
@@ -605,7 +4243,7 @@ impl eframe::App for PxlsApp {
 
                                 //yes i could chuck some unsafe in here, but if LLVM doesn't catch this one i'll be VERY surprised
                                 self.show_palette = Some(RenderedPalette {
-                                    input: (palette, available_rect),
+                                    input: (palette, available_rect, self.view.simulating),
                                     dimensions,
                                     handle,
                                 });
@@ -645,6 +4283,23 @@ impl eframe::App for PxlsApp {
                         (Rect { min, max }, cell_size)
                     };
 
+                    for (i, colour) in palette_to_show.input.0.iter().enumerate() {
+                        if colour.0[3] == u8::MAX {
+                            continue;
+                        }
+                        let (column, row) = (
+                            i % palette_to_show.dimensions[0],
+                            i / palette_to_show.dimensions[0],
+                        );
+                        let min = display_rect.min
+                            + vec2(column as f32 * cell_size, row as f32 * cell_size);
+                        Self::paint_backing(
+                            painter,
+                            Rect::from_min_size(min, vec2(cell_size, cell_size)),
+                            self.current.persisted.backing,
+                        );
+                    }
+
                     let texid = TextureId::from(&palette_to_show.handle);
 
                     painter.image(
@@ -657,6 +4312,13 @@ impl eframe::App for PxlsApp {
                         Color32::WHITE,
                     );
 
+                    let high_error = match self.current.stage {
+                        RenderStage::DisplayingImage(index) => {
+                            self.current.image_history[index].high_error_colours()
+                        }
+                        _ => vec![],
+                    };
+
                     let mut palette_index = 0;
                     'outer: for row in 0..palette_to_show.dimensions[1] {
                         for col in 0..palette_to_show.dimensions[0] {
@@ -664,7 +4326,17 @@ impl eframe::App for PxlsApp {
                             let min =
                                 display_rect.min + vec2(x_index * cell_size, y_index * cell_size);
                             let max = min + vec2(cell_size, cell_size);
-                            let [r, g, b, _] = palette_to_show.input.0[palette_index].0;
+                            let colour = palette_to_show.input.0[palette_index];
+                            let [r, g, b, _] = colour.0;
+
+                            let is_high_error = high_error.contains(&colour);
+                            if is_high_error {
+                                ui.painter().rect_filled(
+                                    Rect { min, max },
+                                    0.0,
+                                    Color32::from_rgba_unmultiplied(255, 0, 0, 48),
+                                );
+                            }
 
                             let rsp = ui.allocate_rect(Rect { min, max }, Sense::click());
                             if rsp.clicked() {
@@ -672,6 +4344,12 @@ impl eframe::App for PxlsApp {
                             }
                             rsp.on_hover_ui(|ui| {
                                 ui.label(format!("Click to Copy: #{r:02X}{g:02X}{b:02X}"));
+                                if is_high_error {
+                                    ui.colored_label(
+                                        ui.visuals().warn_fg_color,
+                                        "This colour is far from a lot of its pixels",
+                                    );
+                                }
                             });
 
                             palette_index += 1;
@@ -684,10 +4362,30 @@ impl eframe::App for PxlsApp {
             });
         });
 
+        //added first so it sits underneath the bottom panel
+        egui::TopBottomPanel::new(TopBottomSide::Bottom, "status-bar")
+            .exact_height(18.0)
+            .show(ctx, |ui| {
+                let changed = match self.current.stage {
+                    RenderStage::DisplayingImage(index) => self
+                        .shown_pixel_changes(index)
+                        .map(|(_, fraction)| format!("{:.1}% of pixels changed", fraction * 100.0)),
+                    _ => None,
+                };
+                ui.horizontal_centered(|ui| match self.current.status_text() {
+                    Some(status) => ui.small(changed.map_or_else(
+                        || status.clone(),
+                        |changed| format!("{status}  |  {changed}"),
+                    )),
+                    None => ui.weak("Open or paste in an image to get started"),
+                });
+            });
+
         if matches!(self.current.stage, RenderStage::DisplayingImage(_)) {
             egui::TopBottomPanel::new(TopBottomSide::Bottom, "bottom-panel").show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     let mut needs_to_reset = false;
+                    let history_memory_used = self.current.history_memory_used();
                     if let RenderStage::DisplayingImage(index) = &mut self.current.stage {
                         ui.label("History: ");
                         let previous = *index;
@@ -700,6 +4398,30 @@ impl eframe::App for PxlsApp {
 
                         let mut needs_to_update_settings = previous != *index;
 
+                        if let Some(ssim) = self.current.image_history[*index].quality_metric {
+                            Self::show_quality_bar(ui, ssim);
+
+                            //so you can see at a glance whether the last tweak helped
+                            let previous_ssim = index.checked_sub(1).and_then(|previous| {
+                                self.current.image_history[previous].quality_metric
+                            });
+                            if let Some(previous_ssim) = previous_ssim {
+                                ui.label(format!("({:+.3} vs previous)", ssim - previous_ssim));
+                            }
+                        }
+
+                        if self.pinned_entry.is_some() {
+                            if ui.button("Stop Comparing").clicked() {
+                                self.pinned_entry = None;
+                            }
+                        } else if ui
+                            .button("Pin for Comparison")
+                            .on_hover_text("Then pick another entry to see them side by side")
+                            .clicked()
+                        {
+                            self.pinned_entry = Some(*index);
+                        }
+
                         if ui.button("Clear History").clicked() {
                             self.current.image_history.clear();
                             needs_to_reset = true;
@@ -711,6 +4433,10 @@ impl eframe::App for PxlsApp {
                                 needs_to_reset = true;
                             } else {
                                 self.current.image_history.remove(*index);
+                                self.pinned_entry =
+                                    self.pinned_entry.filter(|pinned| pinned != index).map(
+                                        |pinned| if pinned > *index { pinned - 1 } else { pinned },
+                                    );
 
                                 if *index == self.current.image_history.len() {
                                     *index = self.current.image_history.len() - 1;
@@ -723,16 +4449,24 @@ impl eframe::App for PxlsApp {
                         if ui.button("Set History to Current").clicked() {
                             let current = self.current.image_history.swap_remove(*index);
                             self.current.image_history = vec![current];
+                            self.pinned_entry = None;
                             *index = 0;
                             needs_to_update_settings = true;
                         }
 
+                        ui.label(format!(
+                            "History: {:.1} MB",
+                            history_memory_used as f32 / 1_000_000.0
+                        ));
+
                         if needs_to_update_settings {
-                            let (palette, output, distance) =
-                                self.current.image_history[*index].settings;
+                            let (palette, output, distance, adjustments, palette_method) =
+                                self.current.image_history[*index].settings.clone();
                             self.palette_settings = palette;
+                            self.palette_method = palette_method;
                             self.output_settings = output;
                             self.distance_algorithm = distance;
+                            self.adjustments = adjustments;
 
                             self.needs_to_refresh_output = false;
                             self.needs_to_refresh_palette = false;
@@ -741,17 +4475,172 @@ impl eframe::App for PxlsApp {
 
                     ui.separator();
 
+                    let mut animation_to_render = None;
                     if let RenderStage::DisplayingImage(index) = &self.current.stage {
                         if ui.button("Save").clicked() {
                             self.current.save_file(*index);
                         }
+                        if ui
+                            .button("Save for Web")
+                            .on_hover_text(format!(
+                                "Save it shrunk to fit within {SHARING_MAX_DIMENSION}px"
+                            ))
+                            .clicked()
+                        {
+                            if !matches!(self.current.export_scale, ExportScale::ForSharing(_)) {
+                                self.current.export_scale =
+                                    ExportScale::ForSharing(SHARING_MAX_DIMENSION);
+                            }
+                            self.current.save_file(*index);
+                        }
+                        if ui.button("Export all…").clicked() {
+                            self.current.export_all();
+                        }
+                        if self.current.image_history[*index].stand_in_input {
+                            ui.colored_label(ui.visuals().warn_fg_color, "⚠ Recovered")
+                                .on_hover_text(
+                                    "This was recovered from an autosave without the image it was made from, so it can be saved but not re-rendered",
+                                );
+                        }
+                        if let Some(animation) = &self.current.image_history[*index].animation {
+                            let label = if self.playback.playing {
+                                "Pause"
+                            } else {
+                                "Play"
+                            };
+                            if ui.button(label).clicked() {
+                                self.playback.toggle();
+                            }
+                            ui.label(format!(
+                                "Frame {}/{}",
+                                animation.frame_at(self.playback.position).0 + 1,
+                                animation.frames.len()
+                            ));
+                        } else if ui
+                            .add_enabled(
+                                self.current.can_render_animation(*index),
+                                Button::new("Process all frames"),
+                            )
+                            .on_disabled_hover_text(
+                                "Only for GIF inputs that haven't been rotated or flipped",
+                            )
+                            .clicked()
+                        {
+                            animation_to_render = Some(*index);
+                        }
+                        ui.separator();
+                        if ui.button("Fit").on_hover_text("F or 0").clicked() {
+                            self.view.set_mode(ZoomMode::Fit);
+                        }
+                        if ui.button("1:1").on_hover_text("1").clicked() {
+                            self.view.set_mode(ZoomMode::OneToOne);
+                        }
+                        ui.label(format!(
+                            "{:.0}%",
+                            self.view.zoom * ctx.pixels_per_point() * 100.0
+                        ));
+                        ui.separator();
+                        ui.checkbox(&mut self.view.show_inspector, "Inspector");
+                        let mut eyedropping = self.eyedropper.is_some();
+                        if ui
+                            .toggle_value(&mut eyedropping, "Eyedropper")
+                            .on_hover_text("Click the input to add its colours to the palette")
+                            .changed()
+                        {
+                            self.eyedropper = eyedropping.then(Eyedropper::default);
+                        }
+                        if let Some(eyedropper) = &mut self.eyedropper {
+                            if let Some(colour) = eyedropper.sampled {
+                                let Rgba([r, g, b, _]) = colour;
+                                let (rect, _) =
+                                    ui.allocate_exact_size(vec2(16.0, 16.0), Sense::hover());
+                                ui.painter()
+                                    .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+                                ui.label(rgb_to_hex(colour));
+                                if ui.button("Add to Palette").clicked() {
+                                    if !self.palette_settings.extra_colors.contains(&colour) {
+                                        self.palette_settings.extra_colors.push(colour);
+                                        self.needs_to_refresh_palette = true;
+                                    }
+                                    eyedropper.sampled = None;
+                                }
+                            } else {
+                                ui.weak("Click the input to pick a colour");
+                            }
+                        }
+                        ui.checkbox(&mut self.view.show_difference, "Show difference");
+
+                        let history = &self.current.image_history;
+                        let previous = self
+                            .previously_displayed_index
+                            .filter(|previous| *previous < history.len() && previous != index);
+                        let cant_show_changes = match previous {
+                            None => Some("There isn't a previous entry to compare against"),
+                            Some(previous)
+                                if history[previous].output.dimensions()
+                                    != history[*index].output.dimensions() =>
+                            {
+                                Some("The previous entry's output is a different size")
+                            }
+                            Some(_) => None,
+                        };
+                        ui.add_enabled(
+                            cant_show_changes.is_none(),
+                            egui::Checkbox::new(&mut self.view.show_changes, "Diff with previous"),
+                        )
+                        .on_hover_text("Highlight the pixels that changed since the previous entry")
+                        .on_disabled_hover_text(cant_show_changes.unwrap_or_default());
+                        if self.view.show_changes && cant_show_changes.is_none() {
+                            ui.add(
+                                Slider::new(&mut self.view.changes_opacity, 0.0..=1.0)
+                                    .text("Opacity"),
+                            );
+                        }
+                        egui::ComboBox::from_label("View as")
+                            .selected_text(self.view.simulating.map_or("Normal", Cvd::to_str))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.view.simulating, None, "Normal");
+                                for possibility in ALL_CVDS {
+                                    ui.selectable_value(
+                                        &mut self.view.simulating,
+                                        Some(possibility),
+                                        possibility.to_str(),
+                                    );
+                                }
+                            });
+                        ui.menu_button("Background", |ui| {
+                            let backing = &mut self.current.persisted.backing;
+                            ui.radio_value(backing, Backing::Checkerboard, "Checkerboard");
+                            let solid = match *backing {
+                                Backing::Solid(colour) => colour,
+                                Backing::Checkerboard => [0, 0, 0],
+                            };
+                            ui.horizontal(|ui| {
+                                ui.radio_value(backing, Backing::Solid(solid), "Solid");
+                                if let Backing::Solid(colour) = backing {
+                                    ui.color_edit_button_srgb(colour);
+                                }
+                            });
+                        });
+                        if ui.button("Copy image").clicked() {
+                            self.current.copy_to_clipboard(
+                                *index,
+                                self.output_settings.scale_output_to_original,
+                            );
+                        }
+                    }
+                    if let Some(index) = animation_to_render {
+                        self.current.render_animation(index);
                     }
 
                     if needs_to_reset {
                         self.current.stage = RenderStage::Nothing;
+                        self.pinned_entry = None;
                         self.distance_algorithm = DistanceAlgorithm::Euclidean;
                         self.palette_settings = PaletteSettings::default();
+                        self.palette_method = DEFAULT_QUANTIZER.to_string();
                         self.output_settings = OutputSettings::default();
+                        self.adjustments = Adjustments::default();
                         self.needs_to_refresh_output = false;
                         self.needs_to_refresh_palette = false;
                     }
@@ -759,15 +4648,106 @@ impl eframe::App for PxlsApp {
             });
         }
 
+        //whatever's being replaced is still on screen, so it has to stay resident
+        if let Some(index) = self.current.stage.shown_entry() {
+            match self.pinned_entry {
+                Some(pinned) => {
+                    self.current
+                        .make_only_resident(&[index, pinned], self.view.simulating, ctx);
+                }
+                None => self
+                    .current
+                    .make_only_resident(&[index], self.view.simulating, ctx),
+            }
+            if self.last_displayed_image_index != Some(index) {
+                self.switch_view_to(index);
+            }
+            if self.view.show_difference {
+                self.current.request_difference(index);
+            }
+
+            if self.palette_view == PaletteView::Clusters {
+                self.current.update_cluster_overlay(ctx);
+            }
+            if let Some(previous) = self
+                .previously_displayed_index
+                .filter(|previous| self.view.show_changes && *previous != index)
+            {
+                self.current.update_pixel_changes(index, previous, ctx);
+            }
+
+            if let Some(eyedropper) = &mut self.eyedropper {
+                let input = &self.current.image_history[index].input;
+                if !eyedropper
+                    .input
+                    .as_ref()
+                    .is_some_and(|(existing, _)| Arc::ptr_eq(existing, input))
+                {
+                    let handle = ctx.load_texture(
+                        "eyedropper-input",
+                        PhotoBeingEdited::color_image_from_dynamic_image(input),
+                        self.current.texture_options,
+                    );
+                    eyedropper.input = Some((input.clone(), handle));
+                }
+            }
+
+            self.playback.advance();
+            if let Some(animation) = &self.current.image_history[index].animation {
+                if self.playback.playing {
+                    ctx.request_repaint_after(animation.frame_at(self.playback.position).1);
+                }
+            }
+        }
+
+        let mut colour_to_exclude = None;
         egui::CentralPanel::default().show(ctx, |ui| {
             match &self.current.stage {
                 RenderStage::Nothing => {
+                    self.last_displayed_image_index = None;
                     ui.centered_and_justified(|ui| {
                         ui.label("Pick a file!");
                     });
                 }
-                RenderStage::CreatingPalette { last_progress, .. } => {
-                    ui.label("Creating palette...");
+                RenderStage::CreatingPalette {
+                    last_progress,
+                    superseded,
+                    ..
+                } => {
+                    Self::show_render_progress(
+                        ui,
+                        &mut self.view,
+                        &self.current,
+                        *superseded,
+                        "Creating palette...",
+                        *last_progress,
+                    );
+                }
+                RenderStage::CreatingOutput {
+                    last_progress,
+                    superseded,
+                    ..
+                } => {
+                    Self::show_render_progress(
+                        ui,
+                        &mut self.view,
+                        &self.current,
+                        *superseded,
+                        "Converting and dithering...",
+                        *last_progress,
+                    );
+                }
+                RenderStage::RestoringSession { last_progress, .. } => {
+                    ui.label("Restoring session...");
+
+                    let (so_far, max) = last_progress;
+                    ProgressBar::new((*so_far as f32) / (*max as f32))
+                        .animate(true)
+                        .show_percentage()
+                        .ui(ui);
+                }
+                RenderStage::RenderingAnimation { last_progress, .. } => {
+                    ui.label("Rendering frames...");
 
                     let (so_far, max) = last_progress;
                     ProgressBar::new((*so_far as f32) / (*max as f32))
@@ -775,8 +4755,8 @@ impl eframe::App for PxlsApp {
                         .show_percentage()
                         .ui(ui);
                 }
-                RenderStage::CreatingOutput { last_progress, .. } => {
-                    ui.label("Converting and dithering...");
+                RenderStage::ExportingAll { last_progress, .. } => {
+                    ui.label("Exporting images...");
 
                     let (so_far, max) = last_progress;
                     ProgressBar::new((*so_far as f32) / (*max as f32))
@@ -785,57 +4765,593 @@ impl eframe::App for PxlsApp {
                         .ui(ui);
                 }
                 RenderStage::DisplayingImage(index) => {
-                    let RenderedImage { output, handle, .. } = &self.current.image_history[*index];
+                    if let Some(pinned) = self
+                        .pinned_entry
+                        .filter(|pinned| pinned != index && self.eyedropper.is_none())
+                    {
+                        Self::show_comparison(ui, &mut self.view, &self.current, pinned, *index);
+                        return;
+                    }
 
-                    let texture_id = TextureId::from(handle);
+                    let current = &self.current.image_history[*index];
+                    let RenderedImage {
+                        resident: Some(resident),
+                        difference,
+                        ..
+                    } = current
+                    else {
+                        return;
+                    };
+                    let output = &resident.output;
+                    let handle = resident.texture(self.view.simulating);
+
+                    let difference = difference.as_ref().filter(|_| self.view.show_difference);
+                    //animations play in place of the output, but the inspector still looks at the output
+                    let frame = current
+                        .animation
+                        .as_ref()
+                        .filter(|_| self.view.simulating.is_none())
+                        .and_then(|animation| {
+                            animation
+                                .textures
+                                .get(animation.frame_at(self.playback.position).0)
+                        });
+                    //the input is stretched over the output, so they line up
+                    let eyedropper_input = self
+                        .eyedropper
+                        .as_ref()
+                        .and_then(|eyedropper| eyedropper.input.as_ref())
+                        .filter(|(input, _)| Arc::ptr_eq(input, &current.input));
+                    let texture_id = TextureId::from(eyedropper_input.map_or_else(
+                        || difference.map_or_else(|| frame.unwrap_or(handle), |(handle, _)| handle),
+                        |(_, handle)| handle,
+                    ));
 
                     let uv = Rect {
                         min: pos2(0.0, 0.0),
                         max: pos2(1.0, 1.0),
-                    }; //TODO: pan & zoom?
+                    };
+
+                    let available = ui.available_rect_before_wrap();
+                    let img_size = vec2(output.width() as f32, output.height() as f32);
 
-                    let mut rect = ui.available_rect_before_wrap();
+                    self.view
+                        .apply_mode(available, img_size, ui.ctx().pixels_per_point());
+
+                    let rect = Rect::from_center_size(
+                        available.center() + self.view.pan,
+                        img_size * self.view.zoom,
+                    );
+
+                    let painter = ui.painter_at(available);
+                    Self::paint_backing(&painter, rect, self.current.persisted.backing);
+                    painter.image(texture_id, rect, uv, Color32::WHITE);
+
+                    let cluster_overlay = self
+                        .current
+                        .palette_clusters
+                        .as_ref()
+                        .and_then(|clustered| clustered.overlay.as_ref())
+                        .filter(|((overlay_index, _), _)| {
+                            overlay_index == index && self.palette_view == PaletteView::Clusters
+                        });
+                    if let Some((_, overlay)) = cluster_overlay
+                        .filter(|_| eyedropper_input.is_none() && difference.is_none())
                     {
-                        let (img_width, img_height) =
-                            (output.width() as f32, output.height() as f32);
-                        let img_aspect = img_width / img_height;
-                        let available_aspect = rect.width() / rect.height();
+                        painter.image(TextureId::from(overlay), rect, uv, Color32::WHITE);
+                    }
+                    if let Some((changes, _)) = self.shown_pixel_changes(*index) {
+                        painter.image(
+                            TextureId::from(changes),
+                            rect,
+                            uv,
+                            Color32::WHITE.gamma_multiply(self.view.changes_opacity),
+                        );
+                    }
 
-                        let (sf_x, sf_y) = if available_aspect > img_aspect {
-                            (available_aspect / img_aspect, 1.0)
-                        } else {
-                            (1.0, img_aspect / available_aspect)
-                        };
+                    if let Some((_, max_distance)) = difference {
+                        Self::show_heatmap_legend(ui, available, *max_distance);
+                    } else if self.view.show_difference {
+                        ui.painter().text(
+                            available.left_bottom() + vec2(10.0, -10.0),
+                            egui::Align2::LEFT_BOTTOM,
+                            "Working out the difference...",
+                            egui::FontId::default(),
+                            ui.visuals().text_color(),
+                        );
+                    }
+
+                    let rsp = ui.allocate_rect(available, Sense::click_and_drag());
+                    if rsp.drag_delta() != Vec2::ZERO {
+                        self.view.pan += rsp.drag_delta();
+                        if self.view.mode == ZoomMode::Fit {
+                            self.view.mode = ZoomMode::Free;
+                        }
+                    }
+                    if rsp.hovered() {
+                        let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+                        if scroll != 0.0 {
+                            //zoom around the cursor rather than the centre
+                            let around = rsp
+                                .hover_pos()
+                                .map_or(Vec2::ZERO, |pointer| pointer - available.center());
+                            self.view.zoom_by((scroll / 200.0).exp(), around);
+                        }
+                    }
+                    if rsp.clicked() {
+                        rsp.request_focus();
+                    }
+                    if rsp.has_focus()
+                        && ui.input(|i| i.events.iter().any(|e| matches!(e, egui::Event::Copy)))
+                    {
+                        self.current.copy_to_clipboard(
+                            *index,
+                            self.output_settings.scale_output_to_original,
+                        );
+                    }
+
+                    let to_image_px = |pos: egui::Pos2, image: &DynamicImage| {
+                        let relative = (pos - rect.min) / rect.size();
+                        #[allow(clippy::cast_sign_loss)]
+                        (
+                            ((relative.x * image.width() as f32) as u32).min(image.width() - 1),
+                            ((relative.y * image.height() as f32) as u32).min(image.height() - 1),
+                        )
+                    };
+                    let to_output_px = |pos: egui::Pos2| to_image_px(pos, output);
+
+                    let mut sampled = None;
+                    if let Some((input, _)) = eyedropper_input {
+                        if rsp.hovered() {
+                            ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+                        }
+                        if let Some(pos) = rsp
+                            .interact_pointer_pos()
+                            .filter(|pos| rsp.clicked() && rect.contains(*pos))
+                        {
+                            let (x, y) = to_image_px(pos, input);
+                            sampled = Some(input.get_pixel(x, y));
+                        }
+                    }
 
-                        rect.max.x = rect.min.x + rect.width() / sf_x;
-                        rect.max.y = rect.min.y + rect.height() / sf_y;
+                    if rsp.secondary_clicked() {
+                        self.right_clicked_colour = None;
+                        if let Some(pos) =
+                            rsp.interact_pointer_pos().filter(|pos| rect.contains(*pos))
+                        {
+                            let (x, y) = to_output_px(pos);
+                            self.right_clicked_colour = Some(output.get_pixel(x, y));
+                        }
                     }
 
-                    ui.painter().image(texture_id, rect, uv, Color32::WHITE);
+                    if self.view.show_inspector && eyedropper_input.is_none() {
+                        if let Some(pos) = rsp.hover_pos().filter(|pos| rect.contains(*pos)) {
+                            let (x, y) = to_output_px(pos);
+                            let output_colour = output.get_pixel(x, y);
+                            let (_, output_settings, distance_algorithm, _, _) = current.settings;
+                            let source_colour =
+                                source_chunk_colour(&current.adjusted, output_settings, x, y);
+                            let palette_index = current
+                                .palette
+                                .iter()
+                                .position(|colour| colour.to_rgb() == output_colour.to_rgb());
+
+                            rsp.clone().on_hover_ui_at_pointer(|ui| {
+                                ui.label(format!("Output ({x}, {y})"));
+                                ui.label(format!(
+                                    "Palette: {}{}",
+                                    rgb_to_hex(output_colour),
+                                    palette_index.map_or_else(String::new, |i| format!(" (#{i})")),
+                                ));
+                                if let Some(source_colour) = source_colour {
+                                    ui.label(format!("Source: {}", rgb_to_hex(source_colour)));
+                                    ui.label(format!(
+                                        "{distance_algorithm} distance: {}",
+                                        distance_algorithm.distance(output_colour, source_colour)
+                                    ));
+                                }
+                            });
+                        }
+                    }
+                    if let (Some(colour), Some(eyedropper)) = (sampled, &mut self.eyedropper) {
+                        eyedropper.sampled = Some(colour);
+                    }
+                    rsp.context_menu(|ui| {
+                        if let Some(colour) = self.right_clicked_colour {
+                            let [r, g, b, _] = colour.0;
+                            ui.label(format!("#{r:02X}{g:02X}{b:02X}"));
+                            if ui.button("Exclude this color").clicked() {
+                                colour_to_exclude = Some(colour);
+                                ui.close_menu();
+                            }
+                        }
+                    });
                 }
             }
         });
-    }
 
-    fn save(&mut self, storage: &mut dyn Storage) {
-        if self.current.last_start_save_dirs.0.is_some()
-            || self.current.last_start_save_dirs.1.is_some()
-        {
-            if let Ok(sered) = serde_json::to_string(&self.current.last_start_save_dirs) {
-                storage.set_string("start_and_save_dirs", sered);
+        if let Some(colour) = colour_to_exclude {
+            self.palette_settings.exclude_colors.push(colour);
+            if self.current.change_palette_settings_or_algo(
+                self.palette_settings.clone(),
+                self.palette_method.clone(),
+                self.distance_algorithm,
+                self.adjustments,
+            ) {
+                self.needs_to_refresh_palette = false;
             }
         }
+
+        self.current.show_restore_modal(ctx);
+        self.current.show_recover_autosave_modal(ctx);
+        self.current.show_frame_picker_modal(ctx);
+        self.current.show_export_modal(ctx);
+        self.current.show_image_stats(ctx);
+        self.show_ramp_modal(ctx);
+        self.current.show_toasts(ctx);
+    }
+
+    fn save(&mut self, storage: &mut dyn Storage) {
+        self.current.persisted.save(storage);
+        self.current.save_session();
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        self.current
-            .worker_should_stop
-            .store(true, Ordering::Relaxed);
+        //takes whatever render is going with it
+        self.current.worker_should_stop.cancel();
 
         if let Some(handle) = self.current.worker_handle.take() {
             if handle.join().is_err() {
                 eprintln!("Error joining thread");
             }
         }
+        //only once the worker's finished, so it can't be writing into them
+        self.current.remove_autosaves();
+        //eframe saves just before this, but the session is only for getting back what a crash lost
+        Session::clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use pxls::PaletteSortOrder;
+
+    fn entry(
+        input: &Arc<DynamicImage>,
+        palette: Palette,
+        settings: PaletteSettings,
+    ) -> RenderedImage {
+        RenderedImage {
+            input: input.clone(),
+            adjusted: input.clone(),
+            palette: Arc::new(palette),
+            output: CompressedImage::encode(input).unwrap(),
+            resident: None,
+            view: None,
+            difference: None,
+            difference_requested: false,
+            quality_metric: None,
+            colour_error: None,
+            usage: vec![],
+            animation: None,
+            stand_in_input: false,
+            settings: (
+                settings,
+                OutputSettings::default(),
+                DistanceAlgorithm::Euclidean,
+                Adjustments::default(),
+                DEFAULT_QUANTIZER.to_string(),
+            ),
+        }
+    }
+
+    fn palette(colours: &[[u8; 4]]) -> Palette {
+        Palette::from_colours(colours.iter().copied().map(Rgba))
+    }
+
+    //without autosaving, so it doesn't leave anything behind
+    fn photo() -> PhotoBeingEdited {
+        PhotoBeingEdited::new(PersistedState {
+            autosave_disabled: true,
+            ..PersistedState::default()
+        })
+    }
+
+    fn find(
+        history: &[RenderedImage],
+        input: &Arc<DynamicImage>,
+        palette: SamePalette,
+    ) -> Option<usize> {
+        find_reusable_render(
+            history,
+            input,
+            Adjustments::default(),
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+            palette,
+        )
+    }
+
+    #[test]
+    fn content_hash_ignores_order() {
+        let colours = palette(&[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]);
+        let reversed = palette(&[[0, 0, 255, 255], [0, 255, 0, 255], [255, 0, 0, 255]]);
+        assert_eq!(colours.content_hash(), reversed.content_hash());
+        let resorted = reversed.sorted(PaletteSortOrder::Hue);
+        assert_eq!(colours.content_hash(), resorted.content_hash());
+        assert_ne!(
+            colours.content_hash(),
+            palette(&[[255, 0, 0, 255], [0, 255, 0, 255]]).content_hash()
+        );
+    }
+
+    #[test]
+    fn same_palette_content_reuses_the_render() {
+        let input = Arc::new(DynamicImage::new_rgb8(4, 4));
+        let history = [
+            entry(
+                &input,
+                palette(&[[0, 0, 0, 255]]),
+                PaletteSettings::default(),
+            ),
+            entry(
+                &input,
+                palette(&[[255, 0, 0, 255], [0, 0, 255, 255]]),
+                PaletteSettings {
+                    closeness_threshold: 80,
+                    ..PaletteSettings::default()
+                },
+            ),
+        ];
+
+        //made with different settings, but the same colours in a different order
+        let same = palette(&[[0, 0, 255, 255], [255, 0, 0, 255]]);
+        assert_eq!(
+            find(&history, &input, SamePalette::Content(same.content_hash())),
+            Some(1)
+        );
+
+        let different = palette(&[[0, 0, 255, 255], [0, 255, 0, 255]]);
+        assert_eq!(
+            find(
+                &history,
+                &input,
+                SamePalette::Content(different.content_hash())
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn palette_settings_reuse_the_render() {
+        let input = Arc::new(DynamicImage::new_rgb8(4, 4));
+        let history = [entry(
+            &input,
+            palette(&[[0, 0, 0, 255]]),
+            PaletteSettings::default(),
+        )];
+
+        assert_eq!(
+            find(
+                &history,
+                &input,
+                SamePalette::Settings(&PaletteSettings::default(), DEFAULT_QUANTIZER)
+            ),
+            Some(0)
+        );
+        let other_settings = PaletteSettings {
+            chunks_per_dimension: 7,
+            ..PaletteSettings::default()
+        };
+        assert_eq!(
+            find(
+                &history,
+                &input,
+                SamePalette::Settings(&other_settings, DEFAULT_QUANTIZER)
+            ),
+            None
+        );
+        //the same settings, but a different way of making the palette from them
+        assert_eq!(
+            find(
+                &history,
+                &input,
+                SamePalette::Settings(&PaletteSettings::default(), "superpixels")
+            ),
+            None
+        );
+        //the same everything, but from a different image
+        let other_input = Arc::new(DynamicImage::new_rgb8(4, 5));
+        assert_eq!(
+            find(
+                &history,
+                &other_input,
+                SamePalette::Settings(&PaletteSettings::default(), DEFAULT_QUANTIZER)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn only_resident_entries_keep_their_decoded_output() {
+        let ctx = Context::default();
+        let input = Arc::new(DynamicImage::ImageRgba8(RgbaImage::from_fn(
+            8,
+            6,
+            |x, y| Rgba([x as u8 * 30, y as u8 * 40, 0, 255]),
+        )));
+        let mut ri = entry(
+            &input,
+            palette(&[[0, 0, 0, 255]]),
+            PaletteSettings::default(),
+        );
+        let compressed = ri.memory_used();
+        assert_eq!(compressed, ri.output.len_bytes());
+        assert_eq!(*ri.decoded_output(), *input);
+
+        ri.make_resident(&ctx, TextureOptions::NEAREST);
+        assert!(ri.resident.is_some());
+        assert!(matches!(ri.decoded_output(), Cow::Borrowed(_)));
+        assert_eq!(*ri.decoded_output(), *input);
+        assert_eq!(ri.memory_used(), compressed + 8 * 6 * 4);
+
+        ri.evict();
+        assert!(ri.resident.is_none());
+        assert_eq!(ri.memory_used(), compressed);
+        assert_eq!(*ri.decoded_output(), *input);
+    }
+
+    #[test]
+    fn switching_entries_evicts_the_others() {
+        let ctx = Context::default();
+        let mut current = photo();
+        let input = Arc::new(DynamicImage::new_rgb8(4, 4));
+        let settings = |chunks_per_dimension| PaletteSettings {
+            chunks_per_dimension,
+            ..PaletteSettings::default()
+        };
+        current.image_history = (1..=3)
+            .map(|chunks| entry(&input, palette(&[[0, 0, 0, 255]]), settings(chunks)))
+            .collect();
+
+        current.make_only_resident(&[0], None, &ctx);
+        let resident: Vec<_> = current
+            .image_history
+            .iter()
+            .map(|ri| ri.resident.is_some())
+            .collect();
+        assert_eq!(resident, [true, false, false]);
+        let one_resident = current.history_memory_used();
+
+        current.make_only_resident(&[1, 2], Some(Cvd::Deuteranopia), &ctx);
+        let resident: Vec<_> = current
+            .image_history
+            .iter()
+            .map(|ri| {
+                ri.resident
+                    .as_ref()
+                    .map(|resident| resident.simulated.len())
+            })
+            .collect();
+        assert_eq!(resident, [None, Some(1), Some(1)]);
+        assert!(current.history_memory_used() > one_resident);
+        //switching back still has the settings it was rendered with
+        assert_eq!(current.image_history[2].settings.0, settings(3));
+
+        current.worker_should_stop.cancel();
+    }
+
+    #[test]
+    fn durations_are_in_ms_until_a_second() {
+        assert_eq!(format_duration(Duration::ZERO), "0ms");
+        assert_eq!(format_duration(Duration::from_micros(12_900)), "12ms");
+        assert_eq!(format_duration(Duration::from_millis(999)), "999ms");
+        assert_eq!(format_duration(Duration::from_secs(1)), "1.0s");
+        assert_eq!(format_duration(Duration::from_millis(2_450)), "2.5s");
+        assert_eq!(format_duration(Duration::from_mins(2)), "120.0s");
+    }
+
+    #[test]
+    fn status_shows_the_input_output_and_timings() {
+        let mut current = photo();
+        assert_eq!(current.status_text(), None);
+
+        let input = Arc::new(DynamicImage::new_rgb8(64, 32));
+        current.original_input = Some(input.clone());
+        assert_eq!(current.status_text().unwrap(), "Pasted image (64x32)");
+
+        current.input_file = Some(PathBuf::from("some/dir/cat.png"));
+        current.image_history = vec![entry(
+            &input,
+            palette(&[[0, 0, 0, 255], [255, 255, 255, 255]]),
+            PaletteSettings::default(),
+        )];
+        current.stage = RenderStage::DisplayingImage(0);
+        let (width, height) = predicted_output_size((64, 32), OutputSettings::default());
+        assert_eq!(
+            current.status_text().unwrap(),
+            format!("cat.png (64x32)  |  Output {width}x{height}  |  2 colours  |  Euclidean")
+        );
+
+        current.render_stats = RenderStats {
+            palette: Some(Stats {
+                duration: Duration::from_millis(40),
+                chunks_processed: 64,
+                cache_hit_rate: Some(0.756),
+                ..Stats::default()
+            }),
+            dither: Some(Stats {
+                duration: Duration::from_millis(1_500),
+                chunks_processed: 8,
+                ..Stats::default()
+            }),
+        };
+        assert!(current.status_text().unwrap().ends_with(
+            "Euclidean  |  Palette 40ms (64 chunks, 76% cached)  |  Dither 1.5s (8 chunks)"
+        ));
+
+        current.worker_should_stop.cancel();
+    }
+
+    #[test]
+    fn pixel_changes_are_only_found_between_the_same_size() {
+        let ctx = Context::default();
+        let mut current = photo();
+        let black = Arc::new(DynamicImage::new_rgb8(4, 4));
+        let mut one_red = black.to_rgb8();
+        one_red.put_pixel(1, 2, image::Rgb([255, 0, 0]));
+        let one_red = Arc::new(DynamicImage::ImageRgb8(one_red));
+        let bigger = Arc::new(DynamicImage::new_rgb8(8, 4));
+        current.image_history = [&black, &one_red, &bigger]
+            .into_iter()
+            .map(|input| {
+                entry(
+                    input,
+                    palette(&[[0, 0, 0, 255]]),
+                    PaletteSettings::default(),
+                )
+            })
+            .collect();
+
+        current.update_pixel_changes(1, 0, &ctx);
+        let changes = current.pixel_changes.as_ref().unwrap();
+        assert_eq!(changes.between, (1, 0));
+        let (mask, fraction) = changes.mask.as_ref().unwrap();
+        assert_eq!(mask.size(), [4, 4]);
+        assert!((fraction - 1.0 / 16.0).abs() < f32::EPSILON);
+
+        current.update_pixel_changes(2, 1, &ctx);
+        let changes = current.pixel_changes.as_ref().unwrap();
+        assert_eq!(changes.between, (2, 1));
+        assert!(changes.mask.is_none());
+
+        //the entry it was compared with has gone
+        current.update_pixel_changes(1, 3, &ctx);
+        assert!(current.pixel_changes.is_none());
+
+        current.worker_should_stop.cancel();
+    }
+
+    #[test]
+    fn saving_uses_the_entrys_own_settings() {
+        let input = Arc::new(DynamicImage::new_rgb8(3, 2));
+        let mut ri = entry(
+            &input,
+            palette(&[[0, 0, 0, 255]]),
+            PaletteSettings::default(),
+        );
+        ri.settings.1 = OutputSettings {
+            output_px_size: 4,
+            scale_output_to_original: true,
+            ..OutputSettings::default()
+        };
+        assert_eq!(ri.image_to_save().unwrap().dimensions(), (12, 8));
+
+        ri.settings.1.scale_output_to_original = false;
+        let saved = ri.image_to_save().unwrap();
+        assert_eq!(saved.dimensions(), (3, 2));
+        assert_eq!(saved, *input);
     }
 }
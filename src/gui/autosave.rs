@@ -0,0 +1,202 @@
+use crate::gui::history::CompressedImage;
+use pxls::{saved_settings::CurrentSettings, Palette};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const AUTOSAVE_DIR: &str = "pxls-autosave";
+//leftovers older than this aren't offered back, since whatever crashed has probably been redone by now
+const MAX_OFFERED_AGE: Duration = Duration::from_hours(1);
+//and once they're older than this, they get deleted
+const MAX_AGE: Duration = Duration::from_hours(7 * 24);
+
+//written next to each output, so it can be put back into the history
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AutosavedEntry {
+    //`None` if the output wasn't made straight from a file, eg. it was pasted in or rotated
+    pub input: Option<PathBuf>,
+    pub frame: usize,
+    #[serde(flatten, with = "pxls::saved_settings::versioned")]
+    pub settings: CurrentSettings,
+    pub palette: Palette,
+}
+
+fn root() -> PathBuf {
+    std::env::temp_dir().join(AUTOSAVE_DIR)
+}
+
+//every render from this run of the app, in case it doesn't exit cleanly. it's deleted when it does
+pub struct AutosaveDir(PathBuf);
+
+impl AutosaveDir {
+    pub fn create() -> Option<Self> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let dir = root().join(format!("{}-{}", started.as_secs(), std::process::id()));
+
+        match fs::create_dir_all(&dir) {
+            Ok(()) => Some(Self(dir)),
+            Err(e) => {
+                eprintln!("Error creating autosave directory: {e:?}");
+                None
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn remove(self) {
+        remove_dir(&self.0);
+    }
+}
+
+fn remove_dir(dir: &Path) {
+    if let Err(e) = fs::remove_dir_all(dir) {
+        eprintln!("Error removing autosave directory: {e:?}");
+    }
+}
+
+//called on the worker thread, since the output's already a png there's no encoding to do
+pub fn save_entry(
+    dir: &Path,
+    index: usize,
+    output: &CompressedImage,
+    entry: &AutosavedEntry,
+) -> std::io::Result<()> {
+    let sered = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+    fs::write(dir.join(format!("{index:04}.png")), output.png_bytes())?;
+    //the json goes last, so an entry only counts once its output is all there
+    fs::write(dir.join(format!("{index:04}.json")), sered)
+}
+
+//the renders from a run of the app that didn't exit cleanly
+pub struct LeftoverSession {
+    pub dir: PathBuf,
+    pub age: Duration,
+    //in the order they were rendered, with where their output is
+    pub entries: Vec<(AutosavedEntry, PathBuf)>,
+}
+
+impl LeftoverSession {
+    pub fn discard(self) {
+        remove_dir(&self.dir);
+    }
+}
+
+//newest first. anything much too old or with nothing in it gets deleted while we're looking
+pub fn find_leftovers() -> Vec<LeftoverSession> {
+    find_leftovers_in(&root())
+}
+
+fn find_leftovers_in(root: &Path) -> Vec<LeftoverSession> {
+    let Ok(read_dir) = fs::read_dir(root) else {
+        return vec![];
+    };
+
+    let mut leftovers = vec![];
+    for dir in read_dir.flatten().map(|entry| entry.path()) {
+        let age = fs::metadata(&dir)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .unwrap_or_default();
+        if age > MAX_AGE {
+            remove_dir(&dir);
+            continue;
+        }
+        if age > MAX_OFFERED_AGE {
+            continue;
+        }
+
+        let Ok(files) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<_> = files
+            .flatten()
+            .map(|file| file.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .filter_map(|path| {
+                let entry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+                Some((path.with_extension("png"), entry))
+            })
+            .filter(|(png, _)| png.is_file())
+            .collect();
+        if entries.is_empty() {
+            remove_dir(&dir);
+            continue;
+        }
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        leftovers.push(LeftoverSession {
+            dir,
+            age,
+            entries: entries
+                .into_iter()
+                .map(|(png, entry)| (entry, png))
+                .collect(),
+        });
+    }
+
+    leftovers.sort_by_key(|leftover| leftover.age);
+    leftovers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+    use pxls::{
+        preprocess::Adjustments, quantizer::DEFAULT_QUANTIZER, DistanceAlgorithm, OutputSettings,
+        PaletteSettings,
+    };
+    use std::fs::File;
+
+    //a leftover with one entry in it, last touched `age` ago
+    fn leftover(root: &Path, name: &str, age: Duration) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let entry = AutosavedEntry {
+            input: None,
+            frame: 0,
+            settings: CurrentSettings {
+                palette_settings: PaletteSettings::default(),
+                palette_method: DEFAULT_QUANTIZER.to_string(),
+                output_settings: OutputSettings::default(),
+                distance_algorithm: DistanceAlgorithm::Euclidean,
+                adjustments: Adjustments::default(),
+            },
+            palette: Palette::default(),
+        };
+        let output = CompressedImage::encode(&DynamicImage::new_rgb8(1, 1)).unwrap();
+        save_entry(&dir, 0, &output, &entry).unwrap();
+        File::open(&dir)
+            .and_then(|dir| dir.set_modified(SystemTime::now() - age))
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn only_recent_leftovers_are_offered() {
+        let root = std::env::temp_dir().join(format!("pxls-test-autosave-{}", std::process::id()));
+        let recent = leftover(&root, "recent", Duration::from_mins(1));
+        let stale = leftover(&root, "stale", MAX_OFFERED_AGE * 2);
+        let ancient = leftover(&root, "ancient", MAX_AGE * 2);
+
+        let found = find_leftovers_in(&root);
+        let dirs: Vec<_> = found.iter().map(|leftover| leftover.dir.clone()).collect();
+        let (stale_kept, ancient_kept) = (stale.exists(), ancient.exists());
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(dirs, [recent]);
+        assert_eq!(found[0].entries.len(), 1);
+        assert!(stale_kept);
+        assert!(!ancient_kept);
+    }
+}
@@ -0,0 +1,100 @@
+//waits for things to go quiet before letting something happen, eg. only rendering once a slider stops moving
+//all times are in seconds, as given by egui
+pub struct Debouncer {
+    pub delay: f64,
+    deadline: Option<f64>,
+}
+
+impl Debouncer {
+    pub const fn new(delay: f64) -> Self {
+        Self {
+            delay,
+            deadline: None,
+        }
+    }
+
+    //something changed, so (re)start the wait
+    pub fn poke(&mut self, now: f64) {
+        self.deadline = Some(now + self.delay);
+    }
+
+    //whether the wait is over, or there wasn't one
+    pub fn is_settled(&mut self, now: f64) -> bool {
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            self.deadline = None;
+        }
+        self.deadline.is_none()
+    }
+
+    pub fn remaining(&self, now: f64) -> Option<f64> {
+        self.deadline.map(|deadline| (deadline - now).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //pokes at each of `pokes`, and then which of `checks` have settled by then
+    fn settled_at(delay: f64, pokes: &[f64], checks: &[f64]) -> Vec<bool> {
+        let mut debouncer = Debouncer::new(delay);
+        let mut pokes = pokes.iter().copied().peekable();
+        checks
+            .iter()
+            .map(|&now| {
+                while let Some(poke) = pokes.next_if(|poke| *poke <= now) {
+                    debouncer.poke(poke);
+                }
+                debouncer.is_settled(now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settled_without_any_changes() {
+        let mut debouncer = Debouncer::new(0.3);
+        assert!(debouncer.is_settled(0.0));
+        assert_eq!(debouncer.remaining(0.0), None);
+    }
+
+    #[test]
+    fn settles_once_the_delay_has_passed() {
+        assert_eq!(
+            settled_at(0.3, &[1.0], &[1.0, 1.2, 1.3, 1.5]),
+            [false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn each_change_restarts_the_wait() {
+        //a slider being dragged, with a change every 0.1s until 1.5s
+        let pokes: Vec<_> = (10..=15).map(|tenths| f64::from(tenths) / 10.0).collect();
+        let checks: Vec<_> = (10..=20).map(|tenths| f64::from(tenths) / 10.0).collect();
+        let settled = settled_at(0.3, &pokes, &checks);
+        assert_eq!(
+            settled,
+            [false, false, false, false, false, false, false, false, true, true, true]
+        );
+    }
+
+    #[test]
+    fn only_settles_once_per_change() {
+        let mut debouncer = Debouncer::new(0.3);
+        debouncer.poke(1.0);
+        assert!((debouncer.remaining(1.1).unwrap() - 0.2).abs() < 1e-9);
+        assert!(!debouncer.is_settled(1.1));
+        assert!(debouncer.is_settled(1.4));
+        //it's been dealt with, so there's nothing left to wait for
+        assert_eq!(debouncer.remaining(1.4), None);
+        assert_eq!(debouncer.remaining(5.0), None);
+        assert_eq!(debouncer.remaining(0.0), None);
+    }
+
+    #[test]
+    fn no_delay_settles_straight_away() {
+        assert_eq!(settled_at(0.0, &[1.0, 2.0], &[1.0, 2.0]), [true, true]);
+        let mut debouncer = Debouncer::new(0.0);
+        debouncer.poke(1.0);
+        assert_eq!(debouncer.remaining(1.0), Some(0.0));
+    }
+}
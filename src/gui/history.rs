@@ -0,0 +1,85 @@
+use image::{DynamicImage, ImageFormat, ImageReader, ImageResult};
+use std::{io::Cursor, sync::Arc, time::Duration};
+
+//outputs are kept as pngs, since they're mostly big blocks of the same few colours and so compress really well
+#[derive(Clone)]
+pub struct CompressedImage {
+    png: Arc<[u8]>,
+    width: u32,
+    height: u32,
+}
+
+impl CompressedImage {
+    pub fn encode(image: &DynamicImage) -> ImageResult<Self> {
+        let mut bytes = Cursor::new(vec![]);
+        image.write_to(&mut bytes, ImageFormat::Png)?;
+
+        Ok(Self {
+            png: bytes.into_inner().into(),
+            width: image.width(),
+            height: image.height(),
+        })
+    }
+
+    //for pngs that were saved from one of these, so are already the right format
+    pub fn from_png(png: Vec<u8>) -> ImageResult<Self> {
+        let (width, height) =
+            ImageReader::with_format(Cursor::new(&png), ImageFormat::Png).into_dimensions()?;
+        Ok(Self {
+            png: png.into(),
+            width,
+            height,
+        })
+    }
+
+    pub fn decode(&self) -> ImageResult<DynamicImage> {
+        image::load_from_memory_with_format(&self.png, ImageFormat::Png)
+    }
+
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn png_bytes(&self) -> &[u8] {
+        &self.png
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.png.len()
+    }
+}
+
+#[derive(Clone)]
+pub struct AnimationFrame {
+    pub output: CompressedImage,
+    //a smaller copy that's quicker to decode for playing back in the gui
+    pub preview: CompressedImage,
+    pub delay: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn compressed_images_decode_to_what_went_in() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(12, 5, |x, y| {
+            Rgba([x as u8 * 20, y as u8 * 50, 7, 255 - x as u8])
+        }));
+        let compressed = CompressedImage::encode(&image).unwrap();
+        assert_eq!(compressed.dimensions(), (12, 5));
+        assert_eq!(compressed.width(), 12);
+        assert_eq!(compressed.decode().unwrap(), image);
+        assert_eq!(compressed.len_bytes(), compressed.png_bytes().len());
+
+        let from_png = CompressedImage::from_png(compressed.png_bytes().to_vec()).unwrap();
+        assert_eq!(from_png.dimensions(), (12, 5));
+        assert_eq!(from_png.decode().unwrap(), image);
+        assert!(CompressedImage::from_png(vec![1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use eframe::Storage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const STATE_KEY: &str = "pxls_state";
+//before the state was versioned, only the directories were stored, as a tuple
+const LEGACY_DIRS_KEY: &str = "start_and_save_dirs";
+const CURRENT_VERSION: u32 = 1;
+
+const MAX_RECENT_FILES: usize = 10;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RecentFiles(Vec<PathBuf>);
+
+impl RecentFiles {
+    //most recent first - re-opening a file just moves it to the front
+    pub fn add(&mut self, file: PathBuf) {
+        self.0.retain(|existing| existing != &file);
+        self.0.insert(0, file);
+        self.0.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn remove(&mut self, file: &Path) {
+        self.0.retain(|existing| existing != file);
+    }
+
+    pub fn prune(&mut self) {
+        self.0.retain(|file| file.is_file());
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PathBuf> {
+        self.0.iter()
+    }
+}
+
+//what's shown behind transparent parts of images and palettes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backing {
+    #[default]
+    Checkerboard,
+    Solid([u8; 3]),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PersistedState {
+    pub version: u32,
+    pub last_start_dir: Option<PathBuf>,
+    pub last_save_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub recent_files: RecentFiles,
+    #[serde(default)]
+    pub backing: Backing,
+    //the other way round so that autosaving is on for anyone who hasn't said otherwise
+    #[serde(default)]
+    pub autosave_disabled: bool,
+}
+
+impl PersistedState {
+    pub fn load(storage: Option<&dyn Storage>) -> Self {
+        let Some(storage) = storage else {
+            return Self::default();
+        };
+
+        let mut state = storage
+            .get_string(STATE_KEY)
+            .and_then(|sered| serde_json::from_str(&sered).ok())
+            .or_else(|| {
+                let (last_start_dir, last_save_dir) =
+                    serde_json::from_str(&storage.get_string(LEGACY_DIRS_KEY)?).ok()?;
+                Some(Self {
+                    version: CURRENT_VERSION,
+                    last_start_dir,
+                    last_save_dir,
+                    recent_files: RecentFiles::default(),
+                    backing: Backing::default(),
+                    autosave_disabled: false,
+                })
+            })
+            .unwrap_or_default();
+
+        state.version = CURRENT_VERSION;
+        state.recent_files.prune();
+        state
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage) {
+        match serde_json::to_string(self) {
+            Ok(sered) => storage.set_string(STATE_KEY, sered),
+            Err(e) => eprintln!("Error serialising state: {e:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl Storage for MemoryStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_string(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    fn recent(files: &[&str]) -> RecentFiles {
+        let mut recent = RecentFiles::default();
+        for file in files.iter().rev() {
+            recent.add(PathBuf::from(file));
+        }
+        recent
+    }
+
+    fn names(recent: &RecentFiles) -> Vec<&str> {
+        recent.iter().map(|file| file.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn reopened_files_move_to_the_front() {
+        let mut recent = recent(&["a", "b", "c"]);
+        recent.add(PathBuf::from("c"));
+        assert_eq!(names(&recent), ["c", "a", "b"]);
+        recent.remove(Path::new("a"));
+        assert_eq!(names(&recent), ["c", "b"]);
+    }
+
+    #[test]
+    fn only_the_newest_files_are_kept() {
+        let mut recent = RecentFiles::default();
+        for i in 0..MAX_RECENT_FILES + 5 {
+            recent.add(PathBuf::from(i.to_string()));
+        }
+        assert_eq!(recent.iter().count(), MAX_RECENT_FILES);
+        assert_eq!(recent.iter().next().unwrap(), Path::new("14"));
+        assert!(!recent.iter().any(|file| file == Path::new("4")));
+    }
+
+    #[test]
+    fn missing_files_are_pruned() {
+        let existing =
+            std::env::temp_dir().join(format!("pxls-test-{}-recent", std::process::id()));
+        std::fs::write(&existing, []).unwrap();
+        let missing = existing.with_extension("missing");
+
+        let mut recent = RecentFiles::default();
+        recent.add(missing);
+        recent.add(existing.clone());
+        recent.prune();
+        assert_eq!(recent.iter().collect::<Vec<_>>(), [&existing]);
+        recent.remove(&existing);
+        assert!(recent.is_empty());
+
+        std::fs::remove_file(existing).unwrap();
+    }
+
+    #[test]
+    fn state_survives_a_round_trip() {
+        let file = std::env::temp_dir().join(format!("pxls-test-{}-state", std::process::id()));
+        std::fs::write(&file, []).unwrap();
+
+        let mut state = PersistedState {
+            last_start_dir: Some(PathBuf::from("start")),
+            backing: Backing::Solid([1, 2, 3]),
+            ..PersistedState::default()
+        };
+        state.recent_files.add(file.clone());
+        state.recent_files.add(file.with_extension("missing"));
+        let mut storage = MemoryStorage::default();
+        state.save(&mut storage);
+
+        let loaded = PersistedState::load(Some(&storage));
+        assert_eq!(loaded.version, CURRENT_VERSION);
+        assert_eq!(loaded.last_start_dir, state.last_start_dir);
+        assert_eq!(loaded.backing, state.backing);
+        assert_eq!(loaded.recent_files.iter().collect::<Vec<_>>(), [&file]);
+
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn older_storage_still_loads() {
+        let mut storage = MemoryStorage::default();
+        storage.set_string(LEGACY_DIRS_KEY, r#"["start", null]"#.to_string());
+        let loaded = PersistedState::load(Some(&storage));
+        assert_eq!(loaded.version, CURRENT_VERSION);
+        assert_eq!(loaded.last_start_dir, Some(PathBuf::from("start")));
+        assert_eq!(loaded.last_save_dir, None);
+        assert!(loaded.recent_files.is_empty());
+
+        //from before there was a list of recent files
+        let mut storage = MemoryStorage::default();
+        storage.set_string(
+            STATE_KEY,
+            r#"{"version":0,"last_start_dir":null,"last_save_dir":"save"}"#.to_string(),
+        );
+        let loaded = PersistedState::load(Some(&storage));
+        assert_eq!(loaded.last_save_dir, Some(PathBuf::from("save")));
+        assert!(loaded.recent_files.is_empty());
+        assert!(!loaded.autosave_disabled);
+
+        assert!(PersistedState::load(None).recent_files.is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use pxls::{saved_settings::CurrentSettings, Palette};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const SESSION_FILE: &str = "pxls-session.json";
+const CURRENT_VERSION: u32 = 1;
+//only the newest entries are kept, since every save re-writes every palette
+const MAX_ENTRIES: usize = 5;
+//it's deleted on a clean exit, so it's only there after a crash, and one that's older than this is probably not wanted
+const MAX_AGE: Duration = Duration::from_hours(1);
+
+//the outputs aren't stored - they get re-rendered from the palettes when the session is restored
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionEntry {
+    #[serde(flatten, with = "pxls::saved_settings::versioned")]
+    pub settings: CurrentSettings,
+    pub palette: Palette,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub input: PathBuf,
+    //which frame of an animated input was being used
+    #[serde(default)]
+    pub frame: usize,
+    pub entries: Vec<SessionEntry>,
+    //which entry was being displayed
+    pub displaying: usize,
+}
+
+impl Session {
+    pub fn new(
+        input: PathBuf,
+        frame: usize,
+        mut entries: Vec<SessionEntry>,
+        mut displaying: usize,
+    ) -> Self {
+        if entries.len() > MAX_ENTRIES {
+            let dropped = entries.len() - MAX_ENTRIES;
+            entries.drain(..dropped);
+            //if the displayed one got dropped, the oldest one that's left is shown instead
+            displaying = displaying.saturating_sub(dropped);
+        }
+
+        Self {
+            version: CURRENT_VERSION,
+            input,
+            frame,
+            entries,
+            displaying,
+        }
+    }
+
+    fn path() -> PathBuf {
+        std::env::temp_dir().join(SESSION_FILE)
+    }
+
+    //anything wrong with the file just means starting fresh
+    pub fn load() -> Option<Self> {
+        Self::load_from(&Self::path())
+    }
+
+    fn load_from(path: &Path) -> Option<Self> {
+        let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > MAX_AGE {
+            return None;
+        }
+        let sered = fs::read_to_string(path).ok()?;
+        let session: Self = serde_json::from_str(&sered).ok()?;
+
+        (session.version == CURRENT_VERSION
+            && !session.entries.is_empty()
+            && session.input.is_file())
+        .then_some(session)
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = self.save_to(&Self::path()) {
+            eprintln!("Error saving session: {e:?}");
+        }
+    }
+
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(
+            path,
+            serde_json::to_string(self).map_err(std::io::Error::other)?,
+        )
+    }
+
+    pub fn clear() {
+        let path = Self::path();
+        if path.exists() {
+            if let Err(e) = fs::remove_file(path) {
+                eprintln!("Error clearing session: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pxls::{
+        preprocess::Adjustments, quantizer::DEFAULT_QUANTIZER, DistanceAlgorithm, OutputSettings,
+        PaletteSettings,
+    };
+    use std::{fs::File, time::SystemTime};
+
+    fn entry() -> SessionEntry {
+        SessionEntry {
+            settings: CurrentSettings {
+                palette_settings: PaletteSettings::default(),
+                palette_method: DEFAULT_QUANTIZER.to_string(),
+                output_settings: OutputSettings::default(),
+                distance_algorithm: DistanceAlgorithm::Euclidean,
+                adjustments: Adjustments::default(),
+            },
+            palette: Palette::default(),
+        }
+    }
+
+    //the input has to exist for it to load, so it's the session file itself
+    fn saved(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pxls-test-{}-{name}", std::process::id()));
+        Session::new(path.clone(), 0, vec![entry()], 0)
+            .save_to(&path)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn new_sessions_load() {
+        let path = saved("new");
+        let loaded = Session::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn old_sessions_are_ignored() {
+        let path = saved("old");
+        File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now() - MAX_AGE * 2))
+            .unwrap();
+        let loaded = Session::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.is_none());
+    }
+
+    #[test]
+    fn only_the_newest_entries_are_kept() {
+        let session = Session::new(PathBuf::new(), 0, vec![entry(); MAX_ENTRIES + 2], 1);
+        assert_eq!(session.entries.len(), MAX_ENTRIES);
+        //it was displaying one that got dropped
+        assert_eq!(session.displaying, 0);
+
+        let session = Session::new(PathBuf::new(), 0, vec![entry(); MAX_ENTRIES + 2], 4);
+        assert_eq!(session.displaying, 2);
+    }
+}
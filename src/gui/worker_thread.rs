@@ -1,189 +1,1492 @@
-use image::{DynamicImage, ImageReader, Rgba};
+use crate::gui::{
+    autosave::{save_entry, AutosavedEntry},
+    history::{AnimationFrame, CompressedImage},
+    session::Session,
+};
+use arboard::{Clipboard, ImageData};
+use image::{
+    codecs::gif::GifDecoder, imageops::FilterType, AnimationDecoder, DynamicImage, Frames,
+    ImageBuffer, ImageFormat, Rgba,
+};
 use pxls::{
-    dither_original_with_palette, get_palette, pixel_operations::rgb_to_hsv, DistanceAlgorithm,
-    OutputSettings, PaletteSettings,
+    analyze_image,
+    cancellation::CancellationToken,
+    clustering::{cluster_palette, default_cluster_count, PaletteCluster},
+    difference_heatmap, dither_original_with_palette, dither_original_with_stats,
+    export::{export_image, with_default_extension, ExportFormat, EXPORT_EXTENSIONS},
+    input_histograms,
+    loading::{load_image_best_effort, Recovery},
+    palette_color_error, palette_usage, pixel_perfect_scale,
+    preprocess::{adjust, Adjustments},
+    progress::NoProgress,
+    quantizer::{find_quantizer, BuiltInQuantizer},
+    ssim, DistanceAlgorithm, ImageAnalysis, InputHistograms, OutputSettings, Palette,
+    PaletteAlgorithm, PaletteSettings, PaletteSortOrder, PxlsError, Stats,
 };
 use rfd::FileDialog;
 use std::{
+    collections::HashMap,
     env::current_dir,
-    path::PathBuf,
+    fs::File,
+    io::BufReader,
+    mem::{discriminant, Discriminant},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::{channel, Receiver, Sender},
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, SendError, Sender},
         Arc,
     },
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
+#[derive(Copy, Clone, Debug)]
+pub enum InputTransform {
+    RotateClockwise,
+    RotateAnticlockwise,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl InputTransform {
+    pub fn apply(self, input: &DynamicImage) -> DynamicImage {
+        match self {
+            Self::RotateClockwise => input.rotate90(),
+            Self::RotateAnticlockwise => input.rotate270(),
+            Self::FlipHorizontal => input.fliph(),
+            Self::FlipVertical => input.flipv(),
+        }
+    }
+}
+
+//each render gets a new one, so that a newer render can stop an older one and the gui can ignore what it sends back.
+//they're all children of the worker's token, so shutting down stops whichever one is going
+#[derive(Clone, Debug)]
+pub struct RenderJob {
+    pub generation: u64,
+    pub should_stop: CancellationToken,
+}
+
+impl RenderJob {
+    pub fn new(generation: u64, worker_should_stop: &CancellationToken) -> Self {
+        Self {
+            generation,
+            should_stop: worker_should_stop.child(),
+        }
+    }
+
+    pub fn supersede(&self, worker_should_stop: &CancellationToken) -> Self {
+        self.should_stop.cancel();
+        Self::new(self.generation + 1, worker_should_stop)
+    }
+}
+
+//counts each request from when it's sent until the worker has dealt with it, so the gui can show how far behind it is
+#[derive(Clone)]
+pub struct RequestSender {
+    tx: Sender<ThreadRequest>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl RequestSender {
+    #[allow(clippy::result_large_err)] //the same as `Sender::send`
+    pub fn send(&self, req: ThreadRequest) -> Result<(), SendError<ThreadRequest>> {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.tx.send(req).inspect_err(|_| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
 pub enum ThreadRequest {
+    //drops everything that was sent before it and hasn't been started yet
+    CancelCurrent,
     GetInputImage,
-    GetOutputImage(usize),
+    LoadPath(PathBuf),
+    //once a frame of an animated input has been picked
+    LoadFrame {
+        file: PathBuf,
+        frame: usize,
+    },
+    PasteFromClipboard,
+    GetOutputImage {
+        index: usize,
+        //the history can change while the dialog is open, so this is what picks the entry
+        palette: Arc<Palette>,
+    },
+    PickExportDirectory,
+    RenderDifference {
+        index: usize,
+        //only used to check that the history entry is still the same one once we're done
+        palette: Arc<Palette>,
+        adjusted: Arc<DynamicImage>,
+        output: DynamicImage,
+        output_settings: OutputSettings,
+        distance_algorithm: DistanceAlgorithm,
+    },
+    ComputeQuality {
+        index: usize,
+        //only used to check that the history entry is still the same one once we're done
+        palette: Arc<Palette>,
+        input: Arc<DynamicImage>,
+        adjusted: Arc<DynamicImage>,
+        output: DynamicImage,
+        distance_algorithm: DistanceAlgorithm,
+    },
+    ClusterPalette(Arc<Palette>),
+    AnalyzeImage(Arc<DynamicImage>),
+    ComputeHistograms(Arc<DynamicImage>),
+    ExportAll {
+        directory: PathBuf,
+        entries: Vec<ExportEntry>,
+        progress_tx: Sender<(u32, u32)>,
+    },
     RenderPalette {
+        job: RenderJob,
+        input: Arc<DynamicImage>,
+        adjustments: Adjustments,
+        palette_settings: PaletteSettings,
+        palette_method: String,
+        distance_algorithm: DistanceAlgorithm,
+        progress_tx: Sender<(u32, u32)>,
+    },
+    TransformInput {
+        job: RenderJob,
         input: Arc<DynamicImage>,
+        transform: InputTransform,
+        adjustments: Adjustments,
         palette_settings: PaletteSettings,
+        palette_method: String,
         distance_algorithm: DistanceAlgorithm,
         progress_tx: Sender<(u32, u32)>,
     },
+    CopyToClipboard {
+        output: DynamicImage,
+        output_settings: OutputSettings,
+    },
     RenderOutput {
+        job: RenderJob,
         input: Arc<DynamicImage>,
-        palette: Arc<[Rgba<u8>]>,
+        adjusted: Arc<DynamicImage>,
+        palette: Arc<Palette>,
         palette_settings: PaletteSettings,
+        palette_method: String,
+        adjustments: Adjustments,
         output_settings: OutputSettings,
         distance_algorithm: DistanceAlgorithm,
         progress_tx: Sender<(u32, u32)>,
     },
+    RestoreSession {
+        session: Session,
+        progress_tx: Sender<(u32, u32)>,
+    },
+    Autosave {
+        dir: PathBuf,
+        index: usize,
+        output: CompressedImage,
+        entry: AutosavedEntry,
+    },
+    //the autosaved entries of a run that crashed, with where their outputs are
+    RecoverAutosave(Vec<(AutosavedEntry, PathBuf)>),
+    //every frame of an animated input, with the palette of an existing history entry
+    RenderAnimation {
+        job: RenderJob,
+        index: usize,
+        file: PathBuf,
+        palette: Arc<Palette>,
+        adjustments: Adjustments,
+        output_settings: OutputSettings,
+        distance_algorithm: DistanceAlgorithm,
+        progress_tx: Sender<(u32, u32)>,
+    },
+}
+
+impl ThreadRequest {
+    const fn render_generation(&self) -> Option<u64> {
+        match self {
+            Self::RenderPalette { job, .. }
+            | Self::TransformInput { job, .. }
+            | Self::RenderOutput { job, .. }
+            | Self::RenderAnimation { job, .. } => Some(job.generation),
+            _ => None,
+        }
+    }
+}
+
+//only the newest render of each kind is worth doing, since the gui has stopped waiting for any of the others
+fn coalesce_requests(mut requests: Vec<ThreadRequest>) -> Vec<ThreadRequest> {
+    if let Some(last_cancel) = requests
+        .iter()
+        .rposition(|req| matches!(req, ThreadRequest::CancelCurrent))
+    {
+        requests.drain(..=last_cancel);
+    }
+
+    let mut newest_renders: HashMap<Discriminant<ThreadRequest>, u64> = HashMap::new();
+    for req in &requests {
+        if let Some(generation) = req.render_generation() {
+            let newest = newest_renders
+                .entry(discriminant(req))
+                .or_insert(generation);
+            *newest = (*newest).max(generation);
+        }
+    }
+
+    requests
+        .into_iter()
+        .filter(|req| {
+            req.render_generation().is_none_or(|generation| {
+                newest_renders.get(&discriminant(req)) == Some(&generation)
+            })
+        })
+        .collect()
+}
+
+pub struct ExportEntry {
+    //without the index prefix or the extension
+    pub name: String,
+    pub output: CompressedImage,
+    pub output_settings: OutputSettings,
+}
+
+pub struct RestoredEntry {
+    pub adjusted: Arc<DynamicImage>,
+    pub palette: Arc<Palette>,
+    pub output: CompressedImage,
+    pub usage: Vec<u32>,
+    pub settings: (
+        PaletteSettings,
+        OutputSettings,
+        DistanceAlgorithm,
+        Adjustments,
+        String,
+    ),
+}
+
+pub struct RecoveredEntry {
+    //the file that the input came from, if it could be found
+    pub file: Option<(PathBuf, usize)>,
+    //if it couldn't, this is the output scaled back up so that there's something to show and save from
+    pub input: Arc<DynamicImage>,
+    pub entry: RestoredEntry,
 }
 
 pub enum ThreadResult {
-    ReadInFile(PathBuf, Arc<DynamicImage>),
+    //the file is `None` when the image didn't come from a file, eg. from the clipboard
+    ReadInFile {
+        file: Option<PathBuf>,
+        //which frame of an animated input this is, otherwise 0
+        frame: usize,
+        input: Arc<DynamicImage>,
+        //set if the file was damaged, and only loaded on a best-effort basis
+        recovery: Option<Recovery>,
+    },
+    //needs a frame picking before it can be used
+    AnimatedInput {
+        file: PathBuf,
+        frame_count: usize,
+        thumbnails: Vec<DynamicImage>,
+    },
+    LoadPathFailed {
+        file: PathBuf,
+        message: String,
+    },
+    RestoredSession {
+        file: PathBuf,
+        frame: usize,
+        input: Arc<DynamicImage>,
+        entries: Vec<RestoredEntry>,
+        displaying: usize,
+    },
+    //not worth telling anyone about, we just start fresh
+    RestoreFailed,
+    RecoveredAutosave(Vec<RecoveredEntry>),
+    Toast(String),
+    GotExportDirectory(PathBuf),
+    RenderedDifference {
+        index: usize,
+        palette: Arc<Palette>,
+        heatmap: DynamicImage,
+        max_distance: u32,
+    },
+    QualityComputed {
+        index: usize,
+        palette: Arc<Palette>,
+        ssim: f32,
+        colour_error: Vec<(Rgba<u8>, f32)>,
+    },
+    ClusteredPalette {
+        palette: Arc<Palette>,
+        clusters: Vec<PaletteCluster>,
+    },
+    AnalyzedImage {
+        input: Arc<DynamicImage>,
+        //boxed since the histograms make it much bigger than everything else
+        analysis: Box<ImageAnalysis>,
+    },
+    ComputedHistograms {
+        adjusted: Arc<DynamicImage>,
+        histograms: Box<InputHistograms>,
+    },
+    ExportedAll {
+        exported: usize,
+        failures: Vec<String>,
+    },
     GotDestination {
         file: PathBuf,
         index: usize,
+        palette: Arc<Palette>,
         save_dir: PathBuf,
     },
     RenderedPalette {
+        job: RenderJob,
         input: Arc<DynamicImage>,
-        palette: Arc<[Rgba<u8>]>,
+        adjusted: Arc<DynamicImage>,
+        palette: Arc<Palette>,
         palette_settings: PaletteSettings,
+        palette_method: String,
+        adjustments: Adjustments,
+        stats: Stats,
+    },
+    //for the render with this generation, which won't be sending anything else
+    RenderFailed {
+        generation: u64,
+        message: String,
+    },
+    RenderedAnimation {
+        generation: u64,
+        index: usize,
+        //only used to check that the history entry is still the same one once we're done
+        palette: Arc<Palette>,
+        frames: Result<Vec<AnimationFrame>, String>,
     },
     RenderedImage {
+        generation: u64,
         input: Arc<DynamicImage>,
-        palette: Arc<[Rgba<u8>]>,
+        adjusted: Arc<DynamicImage>,
+        palette: Arc<Palette>,
         output: DynamicImage,
-        settings: (PaletteSettings, OutputSettings, DistanceAlgorithm),
+        compressed: CompressedImage,
+        usage: Vec<u32>,
+        //just the dithering, not the compressing and usage counting afterwards
+        stats: Stats,
+        settings: (
+            PaletteSettings,
+            OutputSettings,
+            DistanceAlgorithm,
+            Adjustments,
+            String,
+        ),
     },
 }
 
+fn render_palette(
+    job: RenderJob,
+    input: Arc<DynamicImage>,
+    adjustments: Adjustments,
+    palette_settings: PaletteSettings,
+    palette_method: String,
+    distance_algorithm: DistanceAlgorithm,
+    progress_tx: &Sender<(u32, u32)>,
+) -> Result<ThreadResult, PxlsError> {
+    let started = Instant::now();
+
+    //keep hold of the adjusted image so that output-only changes don't need to recompute it
+    let adjusted = if adjustments.is_identity() {
+        input.clone()
+    } else {
+        Arc::new(adjust(&input, adjustments))
+    };
+
+    //the method can only be missing if it came from a session saved by a build that had more of them
+    let quantizer = find_quantizer(&palette_method, &palette_settings, distance_algorithm)
+        .unwrap_or_else(|| {
+            Box::new(BuiltInQuantizer {
+                algorithm: PaletteAlgorithm::Chunks,
+                settings: palette_settings.clone(),
+                dist_algo: distance_algorithm,
+            })
+        });
+    let (palette, stats) =
+        quantizer.palette_with_stats(&adjusted, None, progress_tx, &job.should_stop)?;
+    let mut palette: Vec<_> = palette.into();
+
+    PaletteSortOrder::Hue.sort(&mut palette);
+
+    Ok(ThreadResult::RenderedPalette {
+        job,
+        input,
+        adjusted,
+        palette: Arc::new(palette.into()),
+        palette_settings,
+        palette_method,
+        adjustments,
+        //the adjustments count as part of making the palette
+        stats: Stats {
+            duration: started.elapsed(),
+            ..stats
+        },
+    })
+}
+
+//a cancelled render has already been replaced by a newer one, so nothing's waiting to hear about it
+fn render_result(generation: u64, result: Result<ThreadResult, PxlsError>) -> Option<ThreadResult> {
+    match result {
+        Ok(result) => Some(result),
+        Err(PxlsError::Cancelled) => None,
+        Err(e) => Some(ThreadResult::RenderFailed {
+            generation,
+            message: e.to_string(),
+        }),
+    }
+}
+
+fn restore_session(
+    Session {
+        input: file,
+        frame,
+        entries,
+        displaying,
+        ..
+    }: Session,
+    progress_tx: &Sender<(u32, u32)>,
+    should_stop: &CancellationToken,
+) -> ThreadResult {
+    let Ok((input, _)) = open_frame(&file, frame) else {
+        return ThreadResult::RestoreFailed;
+    };
+    let input = Arc::new(input);
+
+    let total = entries.len() as u32;
+    let mut adjusted_so_far: Vec<(Adjustments, Arc<DynamicImage>)> = vec![];
+    let mut restored = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        if should_stop.is_cancelled() {
+            return ThreadResult::RestoreFailed;
+        }
+
+        //lots of entries usually share adjustments, so only work each one out once
+        let adjusted = if let Some((_, adjusted)) = adjusted_so_far
+            .iter()
+            .find(|(adjustments, _)| *adjustments == entry.settings.adjustments)
+        {
+            adjusted.clone()
+        } else {
+            let adjusted = if entry.settings.adjustments.is_identity() {
+                input.clone()
+            } else {
+                Arc::new(adjust(&input, entry.settings.adjustments))
+            };
+            adjusted_so_far.push((entry.settings.adjustments, adjusted.clone()));
+            adjusted
+        };
+
+        let Ok(output) = dither_original_with_palette(
+            &adjusted,
+            &entry.palette,
+            entry.settings.distance_algorithm,
+            OutputSettings {
+                scale_output_to_original: false,
+                ..entry.settings.output_settings
+            },
+            &NoProgress,
+            should_stop,
+        ) else {
+            return ThreadResult::RestoreFailed;
+        };
+        let usage = palette_usage(&output, &entry.palette);
+        let Ok(output) = CompressedImage::encode(&output) else {
+            return ThreadResult::RestoreFailed;
+        };
+
+        restored.push(RestoredEntry {
+            adjusted,
+            palette: Arc::new(entry.palette),
+            output,
+            usage,
+            settings: (
+                entry.settings.palette_settings,
+                entry.settings.output_settings,
+                entry.settings.distance_algorithm,
+                entry.settings.adjustments,
+                entry.settings.palette_method,
+            ),
+        });
+        let _ = progress_tx.send((i as u32 + 1, total));
+    }
+
+    ThreadResult::RestoredSession {
+        file,
+        frame,
+        input,
+        displaying: displaying.min(restored.len() - 1),
+        entries: restored,
+    }
+}
+
+fn recover_autosave(entries: Vec<(AutosavedEntry, PathBuf)>) -> ThreadResult {
+    //entries from the same input share it, like they did before
+    let mut inputs: Vec<((PathBuf, usize), Arc<DynamicImage>)> = vec![];
+    let mut recovered = vec![];
+    for (entry, png) in entries {
+        let Some(output) = std::fs::read(&png)
+            .ok()
+            .and_then(|png| CompressedImage::from_png(png).ok())
+        else {
+            continue;
+        };
+        let Ok(decoded) = output.decode() else {
+            continue;
+        };
+
+        let file = entry.input.clone().map(|file| (file, entry.frame));
+        let existing = file.as_ref().and_then(|file| {
+            inputs
+                .iter()
+                .find(|(loaded, _)| loaded == file)
+                .map(|(_, input)| input.clone())
+        });
+        let input = existing.or_else(|| {
+            let file = file.clone()?;
+            let (input, _) = open_frame(&file.0, file.1).ok()?;
+            let input = Arc::new(input);
+            inputs.push((file, input.clone()));
+            Some(input)
+        });
+
+        let (file, input, adjusted) = if let Some(input) = input {
+            let adjusted = if entry.settings.adjustments.is_identity() {
+                input.clone()
+            } else {
+                Arc::new(adjust(&input, entry.settings.adjustments))
+            };
+            (file, input, adjusted)
+        } else {
+            let Ok(stand_in) = pixel_perfect_scale(
+                OutputSettings {
+                    scale_output_to_original: true,
+                    ..entry.settings.output_settings
+                },
+                &decoded,
+            ) else {
+                continue;
+            };
+            let stand_in = Arc::new(stand_in);
+            (None, stand_in.clone(), stand_in)
+        };
+
+        recovered.push(RecoveredEntry {
+            file,
+            input,
+            entry: RestoredEntry {
+                adjusted,
+                usage: palette_usage(&decoded, &entry.palette),
+                palette: Arc::new(entry.palette),
+                output,
+                settings: (
+                    entry.settings.palette_settings,
+                    entry.settings.output_settings,
+                    entry.settings.distance_algorithm,
+                    entry.settings.adjustments,
+                    entry.settings.palette_method,
+                ),
+            },
+        });
+    }
+
+    ThreadResult::RecoveredAutosave(recovered)
+}
+
+//damaged files still get loaded if possible, with how they were recovered
+fn open_image(file: &Path) -> Result<(DynamicImage, Option<Recovery>), String> {
+    load_image_best_effort(file).map_err(|e| format!("Error reading image: {e:#}"))
+}
+
+const FRAME_THUMBNAIL_SIZE: u32 = 64;
+
+//animated gifs need a frame picking, so they just get thumbnails of each frame for now
+fn load_input(file: PathBuf) -> Result<ThreadResult, String> {
+    if ImageFormat::from_path(&file).ok() == Some(ImageFormat::Gif) {
+        let thumbnails = gif_frames(&file)?
+            .map(|frame| {
+                frame.map(|frame| {
+                    DynamicImage::from(frame.into_buffer())
+                        .thumbnail(FRAME_THUMBNAIL_SIZE, FRAME_THUMBNAIL_SIZE)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Error decoding image: {e}"))?;
+
+        if thumbnails.len() > 1 {
+            return Ok(ThreadResult::AnimatedInput {
+                file,
+                frame_count: thumbnails.len(),
+                thumbnails,
+            });
+        }
+    }
+
+    let (input, recovery) = open_image(&file)?;
+    Ok(ThreadResult::ReadInFile {
+        file: Some(file),
+        frame: 0,
+        input: Arc::new(input),
+        recovery,
+    })
+}
+
+const ANIMATION_PREVIEW_SIZE: u32 = 256;
+//what browsers use for frames that don't say how long they are
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+fn render_animation(
+    file: &Path,
+    palette: &Palette,
+    adjustments: Adjustments,
+    output_settings: OutputSettings,
+    distance_algorithm: DistanceAlgorithm,
+    progress_tx: &Sender<(u32, u32)>,
+    should_stop: &CancellationToken,
+) -> Result<Vec<AnimationFrame>, String> {
+    //decoded twice rather than keeping every full-size frame around just to know how many there are
+    let total = gif_frames(file)?.count() as u32;
+
+    let mut rendered = Vec::with_capacity(total as usize);
+    for (i, frame) in gif_frames(file)?.enumerate() {
+        if should_stop.is_cancelled() {
+            break;
+        }
+
+        let frame = frame.map_err(|e| format!("Error decoding frame {i}: {e}"))?;
+        let delay = Duration::from(frame.delay());
+        let frame = DynamicImage::from(frame.into_buffer());
+        let adjusted = if adjustments.is_identity() {
+            frame
+        } else {
+            adjust(&frame, adjustments)
+        };
+
+        let output = match dither_original_with_palette(
+            &adjusted,
+            palette,
+            distance_algorithm,
+            OutputSettings {
+                scale_output_to_original: false,
+                ..output_settings
+            },
+            &NoProgress,
+            should_stop,
+        ) {
+            Ok(output) => output,
+            Err(PxlsError::Cancelled) => break,
+            Err(e) => return Err(format!("Error rendering frame {i}: {e}")),
+        };
+        let preview = if output.width().max(output.height()) > ANIMATION_PREVIEW_SIZE {
+            output.resize(
+                ANIMATION_PREVIEW_SIZE,
+                ANIMATION_PREVIEW_SIZE,
+                FilterType::Nearest,
+            )
+        } else {
+            output.clone()
+        };
+
+        let compress =
+            |image: &DynamicImage| CompressedImage::encode(image).map_err(|e| e.to_string());
+        rendered.push(AnimationFrame {
+            output: compress(&output)?,
+            preview: compress(&preview)?,
+            delay: if delay.is_zero() {
+                DEFAULT_FRAME_DELAY
+            } else {
+                delay
+            },
+        });
+        let _ = progress_tx.send((i as u32 + 1, total));
+    }
+
+    Ok(rendered)
+}
+
+fn gif_frames(file: &Path) -> Result<Frames<'static>, String> {
+    let reader =
+        BufReader::new(File::open(file).map_err(|e| format!("Error reading image file: {e}"))?);
+    let decoder = GifDecoder::new(reader).map_err(|e| format!("Error decoding image: {e}"))?;
+    Ok(decoder.into_frames())
+}
+
+//anything that isn't animated only has the first frame
+fn open_frame(file: &Path, frame: usize) -> Result<(DynamicImage, Option<Recovery>), String> {
+    if frame == 0 {
+        return open_image(file);
+    }
+
+    let frame = gif_frames(file)?
+        .nth(frame)
+        .ok_or_else(|| format!("{} doesn't have a frame {frame}", file.display()))?
+        .map_err(|e| format!("Error decoding image: {e}"))?;
+    Ok((DynamicImage::from(frame.into_buffer()), None))
+}
+
+fn export_all(
+    directory: &Path,
+    entries: Vec<ExportEntry>,
+    progress_tx: &Sender<(u32, u32)>,
+) -> ThreadResult {
+    let total = entries.len() as u32;
+    let mut exported = 0;
+    let mut failures = vec![];
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        let stem = format!("{i:02}_{}", entry.name);
+        let mut file = directory.join(format!("{stem}.png"));
+        let mut suffix = 1;
+        while file.exists() {
+            file = directory.join(format!("{stem}_{suffix}.png"));
+            suffix += 1;
+        }
+
+        let result = entry
+            .output
+            .decode()
+            .map_err(|e| e.to_string())
+            .and_then(|output| {
+                pixel_perfect_scale(entry.output_settings, &output).map_err(|e| e.to_string())
+            })
+            .and_then(|scaled| {
+                export_image(&scaled, &file, ExportFormat::Png).map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => exported += 1,
+            Err(e) => failures.push(format!("{}: {e}", file.display())),
+        }
+
+        let _ = progress_tx.send((i as u32 + 1, total));
+    }
+
+    ThreadResult::ExportedAll { exported, failures }
+}
+
+const MAX_CLIPBOARD_PIXELS: u64 = 4096 * 4096;
+
+fn get_clipboard(clipboard: &mut Option<Clipboard>) -> Result<&mut Clipboard, arboard::Error> {
+    Ok(match clipboard {
+        Some(clipboard) => clipboard,
+        None => clipboard.insert(Clipboard::new()?),
+    })
+}
+
+fn paste_from_clipboard(clipboard: &mut Option<Clipboard>) -> Result<DynamicImage, String> {
+    let image = get_clipboard(clipboard)
+        .and_then(Clipboard::get_image)
+        .map_err(|e| match e {
+            arboard::Error::ContentNotAvailable => {
+                "The clipboard doesn't contain an image".to_string()
+            }
+            e => format!("Error reading from clipboard: {e}"),
+        })?;
+
+    ImageBuffer::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+    .map(DynamicImage::ImageRgba8)
+    .ok_or_else(|| "The clipboard image had an unexpected size".to_string())
+}
+
+//returns whether the image was scaled, as big images only get copied unscaled
+fn copy_to_clipboard(
+    clipboard: &mut Option<Clipboard>,
+    output: &DynamicImage,
+    output_settings: OutputSettings,
+) -> Result<bool, arboard::Error> {
+    let clipboard = get_clipboard(clipboard)?;
+
+    //anything that can't be scaled gets copied as it is, the same as anything too big
+    let scaled = pixel_perfect_scale(output_settings, output)
+        .ok()
+        .filter(|scaled| (scaled.width() as u64 * scaled.height() as u64) <= MAX_CLIPBOARD_PIXELS);
+    let was_scaled = scaled.is_some();
+    let to_copy = scaled.unwrap_or_else(|| output.clone()).into_rgba8();
+
+    clipboard.set_image(ImageData {
+        width: to_copy.width() as usize,
+        height: to_copy.height() as usize,
+        bytes: to_copy.into_raw().into(),
+    })?;
+
+    Ok(was_scaled)
+}
+
+//dialogs block until they're closed, so they get their own thread to keep renders from queueing up behind them
+fn start_dialog_thread(
+    (last_start_dir, last_save_dir): (Option<PathBuf>, Option<PathBuf>),
+    res_tx: Sender<ThreadResult>,
+) -> Sender<ThreadRequest> {
+    let (req_tx, req_rx) = channel();
+
+    //not joined, since it could be stuck waiting on a dialog when we exit - it stops once the worker drops its sender
+    std::thread::spawn(move || {
+        let mut last_start_dir =
+            last_start_dir.unwrap_or_else(|| current_dir().unwrap_or_else(|_| "/".into()));
+        let mut last_save_dir = last_save_dir.unwrap_or_else(|| last_start_dir.clone());
+
+        for req in req_rx {
+            match req {
+                ThreadRequest::GetInputImage => {
+                    if let Some(file) = FileDialog::new().set_directory(&last_start_dir).pick_file()
+                    {
+                        if let Some(parent) = file.parent() {
+                            last_start_dir = parent.to_path_buf();
+                        }
+                        match load_input(file) {
+                            Ok(result) => {
+                                res_tx.send(result).unwrap();
+                            }
+                            Err(e) => {
+                                eprintln!("{e}");
+                            }
+                        }
+                    }
+                }
+                ThreadRequest::LoadPath(file) => {
+                    if let Some(parent) = file.parent() {
+                        last_start_dir = parent.to_path_buf();
+                    }
+                    res_tx
+                        .send(match load_input(file.clone()) {
+                            Ok(result) => result,
+                            Err(message) => ThreadResult::LoadPathFailed { file, message },
+                        })
+                        .unwrap();
+                }
+                ThreadRequest::GetOutputImage { index, palette } => {
+                    if let Some(file) = FileDialog::new()
+                        .add_filter("Image Files", EXPORT_EXTENSIONS)
+                        .set_directory(&last_save_dir)
+                        .save_file()
+                    {
+                        let file = with_default_extension(file);
+                        if let Some(parent) = file.parent() {
+                            last_save_dir = parent.to_path_buf();
+                        }
+
+                        res_tx
+                            .send(ThreadResult::GotDestination {
+                                file,
+                                index,
+                                palette,
+                                save_dir: last_save_dir.clone(),
+                            })
+                            .unwrap();
+                    }
+                }
+                ThreadRequest::PickExportDirectory => {
+                    if let Some(directory) = FileDialog::new()
+                        .set_directory(&last_save_dir)
+                        .pick_folder()
+                    {
+                        last_save_dir.clone_from(&directory);
+                        res_tx
+                            .send(ThreadResult::GotExportDirectory(directory))
+                            .unwrap();
+                    }
+                }
+                _ => unreachable!("only dialog requests get sent to the dialog thread"),
+            }
+        }
+    });
+
+    req_tx
+}
+
 #[allow(clippy::type_complexity)]
 #[allow(clippy::too_many_lines)]
 pub fn start_worker_thread(
     (last_start_dir, last_save_dir): (Option<PathBuf>, Option<PathBuf>),
 ) -> (
     JoinHandle<()>,
-    Sender<ThreadRequest>,
+    RequestSender,
     Receiver<ThreadResult>,
-    Arc<AtomicBool>,
+    CancellationToken,
 ) {
     let (req_tx, req_rx) = channel();
+    let req_tx = RequestSender {
+        tx: req_tx,
+        pending: Arc::new(AtomicUsize::new(0)),
+    };
+    let pending = req_tx.pending.clone();
     let (res_tx, res_rx) = channel();
-    let should_stop = Arc::new(AtomicBool::new(false));
+    let should_stop = CancellationToken::new();
     let ret_should_stop = should_stop.clone();
 
+    let dialog_tx = start_dialog_thread((last_start_dir, last_save_dir), res_tx.clone());
+
     let handle = std::thread::spawn(move || {
-        let mut last_start_dir =
-            last_start_dir.unwrap_or_else(|| current_dir().unwrap_or_else(|_| "/".into()));
-        let mut last_save_dir = last_save_dir.unwrap_or_else(|| last_start_dir.clone());
+        //on some platforms the clipboard contents only live as long as the `Clipboard`, so keep it around
+        let mut clipboard = None;
 
         loop {
-            if should_stop.load(Ordering::Relaxed) {
+            if should_stop.is_cancelled() {
                 break;
             }
 
-            for req in req_rx.try_iter() {
+            let requests: Vec<_> = req_rx.try_iter().collect();
+            let received = requests.len();
+            let requests = coalesce_requests(requests);
+            pending.fetch_sub(received - requests.len(), Ordering::Relaxed);
+
+            for req in requests {
                 match req {
-                    ThreadRequest::GetInputImage => {
-                        if let Some(file) =
-                            FileDialog::new().set_directory(&last_start_dir).pick_file()
-                        {
-                            if let Some(parent) = file.parent() {
-                                last_start_dir = parent.to_path_buf();
-                            }
-                            match ImageReader::open(file) {
-                                Ok(img) => match img.decode() {
-                                    Ok(img) => {
-                                        res_tx
-                                            .send(ThreadResult::ReadInFile(
-                                                last_start_dir.clone(),
-                                                Arc::new(img),
-                                            ))
-                                            .unwrap();
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Error decoding image: {e:?}");
-                                    }
+                    ThreadRequest::CancelCurrent => {
+                        unreachable!("cancels get dealt with when coalescing")
+                    }
+                    ThreadRequest::GetInputImage
+                    | ThreadRequest::LoadPath(_)
+                    | ThreadRequest::GetOutputImage { .. }
+                    | ThreadRequest::PickExportDirectory => {
+                        dialog_tx.send(req).unwrap();
+                    }
+                    ThreadRequest::LoadFrame { file, frame } => {
+                        res_tx
+                            .send(match open_frame(&file, frame) {
+                                Ok((input, recovery)) => ThreadResult::ReadInFile {
+                                    file: Some(file),
+                                    frame,
+                                    input: Arc::new(input),
+                                    recovery,
                                 },
-                                Err(e) => {
-                                    eprintln!("Error reading image file: {e:?}");
-                                }
-                            }
+                                Err(message) => ThreadResult::Toast(message),
+                            })
+                            .unwrap();
+                    }
+                    ThreadRequest::RenderAnimation {
+                        job,
+                        index,
+                        file,
+                        palette,
+                        adjustments,
+                        output_settings,
+                        distance_algorithm,
+                        progress_tx,
+                    } => {
+                        let frames = render_animation(
+                            &file,
+                            &palette,
+                            adjustments,
+                            output_settings,
+                            distance_algorithm,
+                            &progress_tx,
+                            &job.should_stop,
+                        );
+                        res_tx
+                            .send(ThreadResult::RenderedAnimation {
+                                generation: job.generation,
+                                index,
+                                palette,
+                                frames,
+                            })
+                            .unwrap();
+                    }
+                    ThreadRequest::RestoreSession {
+                        session,
+                        progress_tx,
+                    } => {
+                        res_tx
+                            .send(restore_session(session, &progress_tx, &should_stop))
+                            .unwrap();
+                    }
+                    ThreadRequest::Autosave {
+                        dir,
+                        index,
+                        output,
+                        entry,
+                    } => {
+                        if let Err(e) = save_entry(&dir, index, &output, &entry) {
+                            eprintln!("Error autosaving: {e:?}");
                         }
                     }
+                    ThreadRequest::RecoverAutosave(entries) => {
+                        res_tx.send(recover_autosave(entries)).unwrap();
+                    }
                     ThreadRequest::RenderPalette {
+                        job,
                         input,
+                        adjustments,
                         palette_settings,
+                        palette_method,
                         distance_algorithm,
                         progress_tx,
                     } => {
-                        let mut palette = get_palette(
-                            &input,
+                        let generation = job.generation;
+                        let result = render_palette(
+                            job,
+                            input,
+                            adjustments,
                             palette_settings,
+                            palette_method,
                             distance_algorithm,
                             &progress_tx,
-                            should_stop.clone(),
                         );
+                        if let Some(result) = render_result(generation, result) {
+                            res_tx.send(result).unwrap();
+                        }
+                    }
+                    ThreadRequest::TransformInput {
+                        job,
+                        input,
+                        transform,
+                        adjustments,
+                        palette_settings,
+                        palette_method,
+                        distance_algorithm,
+                        progress_tx,
+                    } => {
+                        let input = Arc::new(transform.apply(&input));
 
-                        palette.sort_by_cached_key(|x| rgb_to_hsv(*x)[0]);
-
-                        res_tx
-                            .send(ThreadResult::RenderedPalette {
-                                input,
-                                palette: palette.into(),
-                                palette_settings,
-                            })
-                            .unwrap();
+                        let generation = job.generation;
+                        let result = render_palette(
+                            job,
+                            input,
+                            adjustments,
+                            palette_settings,
+                            palette_method,
+                            distance_algorithm,
+                            &progress_tx,
+                        );
+                        if let Some(result) = render_result(generation, result) {
+                            res_tx.send(result).unwrap();
+                        }
                     }
                     ThreadRequest::RenderOutput {
+                        job,
                         input,
+                        adjusted,
                         palette,
                         palette_settings,
+                        palette_method,
+                        adjustments,
                         output_settings,
                         distance_algorithm,
                         progress_tx,
                     } => {
-                        let output = dither_original_with_palette(
-                            &input,
-                            &palette,
+                        let output = dither_original_with_stats(
+                            &adjusted,
+                            &*palette,
                             distance_algorithm,
                             OutputSettings {
                                 scale_output_to_original: false,
                                 ..output_settings
                             },
                             &progress_tx,
-                            should_stop.clone(),
+                            &job.should_stop,
                         );
+                        let (output, stats) = match output {
+                            Ok(output) => output,
+                            Err(e) => {
+                                if let Some(failed) = render_result(job.generation, Err(e)) {
+                                    res_tx.send(failed).unwrap();
+                                }
+                                continue;
+                            }
+                        };
 
-                        res_tx
-                            .send(ThreadResult::RenderedImage {
+                        //these are done here so the ui thread doesn't stutter
+                        let usage = palette_usage(&output, &palette);
+                        let result = match CompressedImage::encode(&output) {
+                            Ok(compressed) => ThreadResult::RenderedImage {
+                                generation: job.generation,
                                 input,
+                                adjusted,
                                 palette,
                                 output,
-                                settings: (palette_settings, output_settings, distance_algorithm),
+                                compressed,
+                                usage,
+                                stats,
+                                settings: (
+                                    palette_settings,
+                                    output_settings,
+                                    distance_algorithm,
+                                    adjustments,
+                                    palette_method,
+                                ),
+                            },
+                            Err(e) => ThreadResult::Toast(format!("Error compressing output: {e}")),
+                        };
+                        res_tx.send(result).unwrap();
+                    }
+                    ThreadRequest::ExportAll {
+                        directory,
+                        entries,
+                        progress_tx,
+                    } => {
+                        res_tx
+                            .send(export_all(&directory, entries, &progress_tx))
+                            .unwrap();
+                    }
+                    ThreadRequest::RenderDifference {
+                        index,
+                        palette,
+                        adjusted,
+                        output,
+                        output_settings,
+                        distance_algorithm,
+                    } => {
+                        let (heatmap, max_distance) = difference_heatmap(
+                            &adjusted,
+                            &output,
+                            output_settings,
+                            distance_algorithm,
+                        );
+
+                        res_tx
+                            .send(ThreadResult::RenderedDifference {
+                                index,
+                                palette,
+                                heatmap,
+                                max_distance,
                             })
                             .unwrap();
                     }
-                    ThreadRequest::GetOutputImage(index) => {
-                        if let Some(file) = FileDialog::new()
-                            .add_filter("Image Files", &["png", "jpg"])
-                            .set_directory(&last_save_dir)
-                            .save_file()
-                        {
-                            if let Some(parent) = file.parent() {
-                                last_save_dir = parent.to_path_buf();
-                            }
+                    ThreadRequest::ComputeQuality {
+                        index,
+                        palette,
+                        input,
+                        adjusted,
+                        output,
+                        distance_algorithm,
+                    } => {
+                        let colour_error =
+                            palette_color_error(&adjusted, &palette, distance_algorithm);
+                        res_tx
+                            .send(ThreadResult::QualityComputed {
+                                index,
+                                palette,
+                                ssim: ssim(&input, &output),
+                                colour_error,
+                            })
+                            .unwrap();
+                    }
+                    ThreadRequest::ClusterPalette(palette) => {
+                        let clusters =
+                            cluster_palette(&palette, default_cluster_count(palette.len()));
+                        res_tx
+                            .send(ThreadResult::ClusteredPalette { palette, clusters })
+                            .unwrap();
+                    }
+                    ThreadRequest::AnalyzeImage(input) => {
+                        let analysis = Box::new(analyze_image(&input));
+                        res_tx
+                            .send(ThreadResult::AnalyzedImage { input, analysis })
+                            .unwrap();
+                    }
+                    ThreadRequest::ComputeHistograms(adjusted) => {
+                        let histograms = Box::new(input_histograms(&adjusted));
+                        res_tx
+                            .send(ThreadResult::ComputedHistograms {
+                                adjusted,
+                                histograms,
+                            })
+                            .unwrap();
+                    }
+                    ThreadRequest::PasteFromClipboard => {
+                        res_tx
+                            .send(match paste_from_clipboard(&mut clipboard) {
+                                Ok(img) => ThreadResult::ReadInFile {
+                                    file: None,
+                                    frame: 0,
+                                    input: Arc::new(img),
+                                    recovery: None,
+                                },
+                                Err(message) => ThreadResult::Toast(message),
+                            })
+                            .unwrap();
+                    }
+                    ThreadRequest::CopyToClipboard {
+                        output,
+                        output_settings,
+                    } => {
+                        let message = match copy_to_clipboard(
+                            &mut clipboard,
+                            &output,
+                            output_settings,
+                        ) {
+                            Ok(true) => "Copied image to clipboard".to_string(),
+                            Ok(false) => "Image was too big to copy at full size, so it was copied without scaling".to_string(),
+                            Err(e) => format!("Error copying image to clipboard: {e}"),
+                        };
 
-                            res_tx
-                                .send(ThreadResult::GotDestination {
-                                    file,
-                                    index,
-                                    save_dir: last_save_dir.clone(),
-                                })
-                                .unwrap();
-                        }
+                        res_tx.send(ThreadResult::Toast(message)).unwrap();
                     }
                 }
+
+                pending.fetch_sub(1, Ordering::Relaxed);
             }
         }
     });
 
     (handle, req_tx, res_rx, ret_should_stop)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pxls::{pipeline::Pxls, quantizer::DEFAULT_QUANTIZER};
+
+    fn render(generation: u64, worker_should_stop: &CancellationToken) -> ThreadRequest {
+        ThreadRequest::RenderPalette {
+            job: RenderJob::new(generation, worker_should_stop),
+            input: Arc::new(DynamicImage::new_rgb8(1, 1)),
+            adjustments: Adjustments::default(),
+            palette_settings: PaletteSettings::default(),
+            palette_method: DEFAULT_QUANTIZER.to_string(),
+            distance_algorithm: DistanceAlgorithm::Euclidean,
+            progress_tx: std::sync::mpsc::channel().0,
+        }
+    }
+
+    //what's left, as the generation of each render or `None` for anything else
+    fn coalesced(requests: Vec<ThreadRequest>) -> Vec<Option<u64>> {
+        coalesce_requests(requests)
+            .iter()
+            .map(ThreadRequest::render_generation)
+            .collect()
+    }
+
+    #[test]
+    fn only_the_newest_render_is_kept() {
+        let stop = CancellationToken::new();
+        let requests = vec![
+            render(1, &stop),
+            ThreadRequest::GetInputImage,
+            render(2, &stop),
+            render(3, &stop),
+            ThreadRequest::PickExportDirectory,
+        ];
+        assert_eq!(coalesced(requests), [None, Some(3), None]);
+
+        //whichever was sent last, it's the generation that counts
+        let requests = vec![render(5, &stop), render(4, &stop)];
+        assert_eq!(coalesced(requests), [Some(5)]);
+
+        let requests = vec![ThreadRequest::GetInputImage, ThreadRequest::GetInputImage];
+        assert_eq!(coalesced(requests), [None, None]);
+        assert!(coalesced(vec![]).is_empty());
+    }
+
+    #[test]
+    fn the_newest_of_each_kind_is_kept() {
+        let stop = CancellationToken::new();
+        let transform = |generation| ThreadRequest::TransformInput {
+            job: RenderJob::new(generation, &stop),
+            input: Arc::new(DynamicImage::new_rgb8(1, 1)),
+            transform: InputTransform::FlipHorizontal,
+            adjustments: Adjustments::default(),
+            palette_settings: PaletteSettings::default(),
+            palette_method: DEFAULT_QUANTIZER.to_string(),
+            distance_algorithm: DistanceAlgorithm::Euclidean,
+            progress_tx: std::sync::mpsc::channel().0,
+        };
+        let output = |generation| ThreadRequest::RenderOutput {
+            job: RenderJob::new(generation, &stop),
+            input: Arc::new(DynamicImage::new_rgb8(1, 1)),
+            adjusted: Arc::new(DynamicImage::new_rgb8(1, 1)),
+            palette: Arc::new(Palette::default()),
+            palette_settings: PaletteSettings::default(),
+            palette_method: DEFAULT_QUANTIZER.to_string(),
+            adjustments: Adjustments::default(),
+            output_settings: OutputSettings::default(),
+            distance_algorithm: DistanceAlgorithm::Euclidean,
+            progress_tx: std::sync::mpsc::channel().0,
+        };
+        let animation = |generation| ThreadRequest::RenderAnimation {
+            job: RenderJob::new(generation, &stop),
+            index: 0,
+            file: PathBuf::from("animation.gif"),
+            palette: Arc::new(Palette::default()),
+            adjustments: Adjustments::default(),
+            output_settings: OutputSettings::default(),
+            distance_algorithm: DistanceAlgorithm::Euclidean,
+            progress_tx: std::sync::mpsc::channel().0,
+        };
+        let kinds = |requests: &[ThreadRequest]| -> Vec<(&str, u64)> {
+            requests
+                .iter()
+                .map(|req| {
+                    let kind = match req {
+                        ThreadRequest::RenderPalette { .. } => "palette",
+                        ThreadRequest::TransformInput { .. } => "transform",
+                        ThreadRequest::RenderOutput { .. } => "output",
+                        ThreadRequest::RenderAnimation { .. } => "animation",
+                        _ => "other",
+                    };
+                    (kind, req.render_generation().unwrap_or(0))
+                })
+                .collect()
+        };
+
+        let requests = vec![
+            transform(1),
+            animation(2),
+            render(3, &stop),
+            output(4),
+            ThreadRequest::GetInputImage,
+            output(6),
+            animation(5),
+            render(7, &stop),
+            transform(0),
+        ];
+        //a newer render of another kind doesn't drop the ones still being waited on
+        assert_eq!(
+            kinds(&coalesce_requests(requests)),
+            [
+                ("transform", 1),
+                ("other", 0),
+                ("output", 6),
+                ("animation", 5),
+                ("palette", 7)
+            ]
+        );
+
+        let requests = vec![
+            animation(1),
+            output(2),
+            ThreadRequest::CancelCurrent,
+            output(3),
+            transform(4),
+            output(5),
+        ];
+        assert_eq!(
+            kinds(&coalesce_requests(requests)),
+            [("transform", 4), ("output", 5)]
+        );
+    }
+
+    #[test]
+    fn cancelling_drops_everything_before_it() {
+        let stop = CancellationToken::new();
+        let requests = vec![
+            render(1, &stop),
+            ThreadRequest::GetInputImage,
+            ThreadRequest::CancelCurrent,
+            ThreadRequest::PickExportDirectory,
+            ThreadRequest::CancelCurrent,
+            render(2, &stop),
+        ];
+        assert_eq!(coalesced(requests), [Some(2)]);
+
+        let requests = vec![render(1, &stop), ThreadRequest::CancelCurrent];
+        assert!(coalesced(requests).is_empty());
+    }
+
+    #[test]
+    fn superseded_renders_are_stopped() {
+        let worker_should_stop = CancellationToken::new();
+        let first = RenderJob::new(0, &worker_should_stop);
+        let second = first.supersede(&worker_should_stop);
+        assert_eq!(second.generation, 1);
+        assert!(first.should_stop.is_cancelled());
+        assert!(!second.should_stop.is_cancelled());
+
+        //shutting down stops whichever one is going
+        worker_should_stop.cancel();
+        assert!(second.should_stop.is_cancelled());
+    }
+
+    //the same as the CLI's pipeline test, which can't get at the worker from outside the binary
+    #[test]
+    fn the_worker_renders_what_the_pipeline_makes() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+            let (block_x, block_y) = ((x / 16) as u8, (y / 16) as u8);
+            let flat = Rgba([block_x * 60, block_y * 60, 200 - block_x * 30, 255]);
+            if y % 16 == 15 {
+                Rgba([flat[0] + (x % 16) as u8 * 3, flat[1], flat[2], 255])
+            } else {
+                flat
+            }
+        }));
+        let palette_settings = PaletteSettings {
+            chunks_per_dimension: 4,
+            closeness_threshold: 0,
+            ..PaletteSettings::default()
+        };
+        let output_settings = OutputSettings {
+            output_px_size: 3,
+            ..OutputSettings::default()
+        };
+
+        let (handle, req_tx, res_rx, worker_should_stop) = start_worker_thread((None, None));
+        let timeout = Duration::from_mins(1);
+        req_tx
+            .send(ThreadRequest::RenderPalette {
+                job: RenderJob::new(1, &worker_should_stop),
+                input: Arc::new(image.clone()),
+                adjustments: Adjustments::default(),
+                palette_settings,
+                palette_method: DEFAULT_QUANTIZER.to_string(),
+                distance_algorithm: DistanceAlgorithm::Euclidean,
+                progress_tx: std::sync::mpsc::channel().0,
+            })
+            .unwrap();
+        let Ok(ThreadResult::RenderedPalette {
+            job,
+            input,
+            adjusted,
+            palette,
+            palette_settings,
+            palette_method,
+            adjustments,
+            ..
+        }) = res_rx.recv_timeout(timeout)
+        else {
+            panic!("the palette wasn't rendered");
+        };
+        req_tx
+            .send(ThreadRequest::RenderOutput {
+                job,
+                input,
+                adjusted,
+                palette: palette.clone(),
+                palette_settings: palette_settings.clone(),
+                palette_method,
+                adjustments,
+                output_settings,
+                distance_algorithm: DistanceAlgorithm::Euclidean,
+                progress_tx: std::sync::mpsc::channel().0,
+            })
+            .unwrap();
+        let Ok(ThreadResult::RenderedImage { output, .. }) = res_rx.recv_timeout(timeout) else {
+            panic!("the output wasn't rendered");
+        };
+        worker_should_stop.cancel();
+        handle.join().unwrap();
+
+        //the gui only scales the output up when exporting it
+        let expected = Pxls::new(&image)
+            .palette_settings(palette_settings)
+            .output_settings(OutputSettings {
+                scale_output_to_original: false,
+                ..output_settings
+            })
+            .algorithm(DistanceAlgorithm::Euclidean)
+            .sort_palette(PaletteSortOrder::Hue)
+            .run()
+            .unwrap();
+        assert_eq!(*palette, expected.palette);
+        assert_eq!(output.to_rgba8(), expected.output.to_rgba8());
+    }
+
+    #[test]
+    fn cancelled_renders_send_nothing_back() {
+        assert!(render_result(3, Err(PxlsError::Cancelled)).is_none());
+        assert!(matches!(
+            render_result(3, Err(PxlsError::EmptyPalette)),
+            Some(ThreadResult::RenderFailed { generation: 3, .. })
+        ));
+    }
+}
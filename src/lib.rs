@@ -1,16 +1,54 @@
-use crate::pixel_operations::{luminance, rgb_to_hsv};
-use image::{ColorType, DynamicImage, GenericImage, GenericImageView, Pixel, Rgba};
+use crate::{
+    cancellation::CancellationToken,
+    pixel_operations::{luminance, rgb_to_hsv},
+    progress::{NoProgress, ProgressReporter, Stage},
+};
+use image::{
+    ColorType, DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, Pixel, Rgba,
+    RgbaImage,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{hash_map::Entry, HashMap},
-    fmt::{Display, Formatter},
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
-        Arc,
-    },
+    collections::{hash_map::Entry, HashMap, HashSet},
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::{Deref, Index, RangeInclusive},
+    path::Path,
+    time::Duration,
 };
+//`std`'s clock panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+pub mod cancellation;
+pub mod clustering;
+pub mod css_colors;
+#[cfg(feature = "data-url")]
+pub mod data_url;
+pub mod export;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "lenient-loading")]
+pub mod loading;
+pub mod palette_export;
+pub mod palette_io;
+pub mod pipeline;
+pub mod preprocess;
+pub mod progress;
+pub mod quantizer;
+pub mod ramp;
+#[cfg(feature = "serde")]
+pub mod saved_settings;
+pub mod streaming;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DistanceAlgorithm {
     Euclidean,
     HSVEuclidean,
@@ -30,12 +68,71 @@ impl DistanceAlgorithm {
         }
     }
 
-    pub const fn standardise_closeness_threshold(self, n: u32) -> u32 {
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Euclidean => "straight-line distance between the RGB values",
+            Self::HSVEuclidean => "straight-line distance between the hue, saturation and value",
+            Self::Manhattan => "sum of the differences in each RGB channel",
+            Self::Luminance => "difference in perceived brightness",
+            Self::Value => "difference in the brightest channel",
+        }
+    }
+
+    //these distances are squared, so the thresholds get squared to match
+    pub const fn squares_closeness_threshold(self) -> bool {
         match self {
-            Self::Euclidean | Self::Luminance | Self::HSVEuclidean => n * n,
-            Self::Manhattan | Self::Value => n,
+            Self::Euclidean | Self::Luminance | Self::HSVEuclidean => true,
+            Self::Manhattan | Self::Value => false,
+        }
+    }
+
+    pub const fn standardise_closeness_threshold(self, n: u32) -> u32 {
+        if self.squares_closeness_threshold() {
+            n * n
+        } else {
+            n
+        }
+    }
+
+    //the biggest closeness threshold that still means something, ie. one that standardises to the biggest distance
+    pub const fn closeness_threshold_range(self) -> RangeInclusive<u32> {
+        let max = *threshold_range(self).end();
+        0..=if self.squares_closeness_threshold() {
+            max.isqrt()
+        } else {
+            max
         }
     }
+
+    //from 0 to 1, so that thresholds can be carried over between algorithms
+    pub fn normalised_closeness_threshold(self, n: u32) -> f32 {
+        self.standardise_closeness_threshold(n) as f32 / *threshold_range(self).end() as f32
+    }
+
+    //rounding the square root can go one past the range, which wouldn't validate
+    pub fn closeness_threshold_from_normalised(self, normalised: f32) -> u32 {
+        let distance = (normalised.clamp(0.0, 1.0) * *threshold_range(self).end() as f32).round();
+        let threshold = match self {
+            Self::Euclidean | Self::Luminance | Self::HSVEuclidean => {
+                distance.sqrt().round() as u32
+            }
+            Self::Manhattan | Self::Value => distance as u32,
+        };
+        threshold.min(*self.closeness_threshold_range().end())
+    }
+}
+
+//what `DistanceAlgorithm::distance` can return - the closeness thresholds get standardised into these units
+pub const fn threshold_range(algo: DistanceAlgorithm) -> RangeInclusive<u32> {
+    const MAX_CHANNEL: u32 = u8::MAX as u32;
+    0..=match algo {
+        DistanceAlgorithm::Euclidean => 3 * MAX_CHANNEL * MAX_CHANNEL,
+        //hue gets rounded, so can be up to 360, but saturation gets rounded to either 0 or 1
+        DistanceAlgorithm::HSVEuclidean => 360 * 360 + 1 + MAX_CHANNEL * MAX_CHANNEL,
+        DistanceAlgorithm::Manhattan => 3 * MAX_CHANNEL,
+        DistanceAlgorithm::Luminance => luminance(Rgba([u8::MAX; 4])),
+        DistanceAlgorithm::Value => MAX_CHANNEL,
+    }
 }
 
 impl Display for DistanceAlgorithm {
@@ -53,6 +150,7 @@ pub const ALL_ALGOS: &[DistanceAlgorithm] = &[
 ];
 
 pub mod pixel_operations {
+    use crate::ramp::{linear_to_srgb, srgb_to_linear};
     use image::Rgba;
 
     // https://stackoverflow.com/questions/596216/formula-to-determine-perceived-brightness-of-rgb-color :)
@@ -96,6 +194,318 @@ pub mod pixel_operations {
 
         [hue, saturation, value]
     }
+
+    //accepts `#RRGGBB` or `RRGGBB`
+    pub fn rgb_from_hex(hex: &str) -> Option<Rgba<u8>> {
+        let hex = hex.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..(i + 2)], 16).ok();
+        Some(Rgba([channel(0)?, channel(2)?, channel(4)?, u8::MAX]))
+    }
+
+    //takes `#rgb`, `#rrggbb` (with or without the `#`), `rgb(r, g, b)` and `rgba(r, g, b, a)` with `a` from 0 to 1
+    pub fn parse_hex_color(s: &str) -> Option<Rgba<u8>> {
+        let s = s.trim().to_lowercase();
+
+        if let Some(args) = s
+            .strip_prefix("rgba(")
+            .or_else(|| s.strip_prefix("rgb("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let args: Vec<_> = args.split(',').map(str::trim).collect();
+            let has_alpha = s.starts_with("rgba(");
+            if args.len() != if has_alpha { 4 } else { 3 } {
+                return None;
+            }
+
+            let channel = |i: usize| args[i].parse::<u8>().ok();
+            let alpha = if has_alpha {
+                let alpha = args[3].parse::<f32>().ok()?;
+                if !(0.0..=1.0).contains(&alpha) {
+                    return None;
+                }
+                (alpha * 255.0).round() as u8
+            } else {
+                u8::MAX
+            };
+            return Some(Rgba([channel(0)?, channel(1)?, channel(2)?, alpha]));
+        }
+
+        let hex = s.strip_prefix('#').unwrap_or(&s);
+        if hex.len() == 3 && hex.is_ascii() {
+            //each digit gets doubled, so `f80` is `ff8800`
+            let channel = |i: usize| u8::from_str_radix(&hex[i..=i], 16).ok().map(|x| x * 17);
+            return Some(Rgba([channel(0)?, channel(1)?, channel(2)?, u8::MAX]));
+        }
+        rgb_from_hex(hex)
+    }
+
+    pub fn rgb_to_hex(Rgba([r, g, b, _]): Rgba<u8>) -> String {
+        format!("#{r:02X}{g:02X}{b:02X}")
+    }
+
+    //colour vision deficiencies, all simulated at full severity
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Cvd {
+        Protanopia,
+        Deuteranopia,
+        Tritanopia,
+    }
+
+    impl Cvd {
+        pub const fn to_str(self) -> &'static str {
+            match self {
+                Self::Protanopia => "Protanopia",
+                Self::Deuteranopia => "Deuteranopia",
+                Self::Tritanopia => "Tritanopia",
+            }
+        }
+
+        // https://www.inf.ufrgs.br/~oliveira/pubs_files/CVD_Simulation/CVD_Simulation.html, for linear RGB
+        const fn matrix(self) -> [[f32; 3]; 3] {
+            match self {
+                Self::Protanopia => [
+                    [0.152_286, 1.052_583, -0.204_868],
+                    [0.114_503, 0.786_281, 0.099_216],
+                    [-0.003_882, -0.048_116, 1.051_998],
+                ],
+                Self::Deuteranopia => [
+                    [0.367_322, 0.860_646, -0.227_968],
+                    [0.280_085, 0.672_501, 0.047_413],
+                    [-0.011_820, 0.042_940, 0.968_881],
+                ],
+                Self::Tritanopia => [
+                    [1.255_528, -0.076_749, -0.178_779],
+                    [-0.078_411, 0.930_809, 0.147_602],
+                    [0.004_733, 0.691_367, 0.303_900],
+                ],
+            }
+        }
+    }
+
+    pub const ALL_CVDS: [Cvd; 3] = [Cvd::Protanopia, Cvd::Deuteranopia, Cvd::Tritanopia];
+
+    //roughly how the colour looks to someone with that deficiency - alpha is left alone
+    pub fn simulate_cvd(Rgba([r, g, b, a]): Rgba<u8>, cvd: Cvd) -> Rgba<u8> {
+        let linear = [r, g, b].map(|c| srgb_to_linear(f32::from(c) / 255.0));
+        let [r, g, b] = cvd.matrix().map(|row| {
+            let c = row[2].mul_add(linear[2], row[0].mul_add(linear[0], row[1] * linear[1]));
+            (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8
+        });
+        Rgba([r, g, b, a])
+    }
+}
+
+//`Rgba` doesn't implement serde's traits, so colours get stored as their channels
+#[cfg(feature = "serde")]
+mod serde_colours {
+    use image::Rgba;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        colours: &[Rgba<u8>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        colours
+            .iter()
+            .map(|Rgba(channels)| *channels)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Rgba<u8>>, D::Error> {
+        Ok(Vec::<[u8; 4]>::deserialize(deserializer)?
+            .into_iter()
+            .map(Rgba)
+            .collect())
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Palette(#[cfg_attr(feature = "serde", serde(with = "serde_colours"))] Vec<Rgba<u8>>);
+
+impl From<Vec<Rgba<u8>>> for Palette {
+    fn from(colours: Vec<Rgba<u8>>) -> Self {
+        Self(colours)
+    }
+}
+
+impl From<Palette> for Vec<Rgba<u8>> {
+    fn from(palette: Palette) -> Self {
+        palette.0
+    }
+}
+
+impl AsRef<[Rgba<u8>]> for Palette {
+    fn as_ref(&self) -> &[Rgba<u8>] {
+        &self.0
+    }
+}
+
+impl Deref for Palette {
+    type Target = [Rgba<u8>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromIterator<Rgba<u8>> for Palette {
+    fn from_iter<T: IntoIterator<Item = Rgba<u8>>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Palette {
+    type Item = Rgba<u8>;
+    type IntoIter = std::vec::IntoIter<Rgba<u8>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Palette {
+    type Item = &'a Rgba<u8>;
+    type IntoIter = std::slice::Iter<'a, Rgba<u8>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl Index<usize> for Palette {
+    type Output = Rgba<u8>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+//smaller keys come first. a plain function so it can be picked at compile time, eg. to sort by distance from a
+//brand colour:
+//  fn closest_to_brand_first(px: Rgba<u8>) -> u64 {
+//      u64::from(DistanceAlgorithm::Euclidean.distance(px, Rgba([0xE3, 0x1B, 0x23, u8::MAX])))
+//  }
+pub type PaletteSortKey = fn(Rgba<u8>) -> u64;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub enum PaletteSortOrder {
+    //what the GUI shows palettes in
+    #[default]
+    Hue,
+    CustomKey(PaletteSortKey),
+}
+
+impl PaletteSortOrder {
+    pub fn key(self, colour: Rgba<u8>) -> u64 {
+        match self {
+            Self::Hue => u64::from(pixel_operations::rgb_to_hsv(colour)[0]),
+            Self::CustomKey(key) => key(colour),
+        }
+    }
+
+    //stable, so colours with the same key stay in the order they were found in
+    pub fn sort(self, colours: &mut [Rgba<u8>]) {
+        colours.sort_by_cached_key(|colour| self.key(*colour));
+    }
+}
+
+impl Palette {
+    pub fn from_colours(colours: impl IntoIterator<Item = Rgba<u8>>) -> Self {
+        colours.into_iter().collect()
+    }
+
+    #[must_use]
+    pub fn sorted(mut self, order: PaletteSortOrder) -> Self {
+        self.sort(order);
+        self
+    }
+
+    pub fn sort(&mut self, order: PaletteSortOrder) {
+        order.sort(&mut self.0);
+    }
+
+    //the index of the closest colour and how far away it is, or `None` if the palette is empty
+    pub fn nearest(&self, px: Rgba<u8>, algo: DistanceAlgorithm) -> Option<(usize, u32)> {
+        self.nearest_two(px, algo).map(|(i, dist, _)| (i, dist))
+    }
+
+    //see the free `nearest_two`, but `None` if the palette is empty
+    #[allow(clippy::type_complexity)]
+    pub fn nearest_two(
+        &self,
+        px: Rgba<u8>,
+        algo: DistanceAlgorithm,
+    ) -> Option<(usize, u32, Option<(usize, u32)>)> {
+        (!self.0.is_empty()).then(|| nearest_two(&self.0, px, algo))
+    }
+
+    //see `merge_palettes`
+    #[must_use]
+    pub fn merge(self, other: Self, closeness_threshold: u32, algo: DistanceAlgorithm) -> Self {
+        merge_palettes([self, other], closeness_threshold, algo)
+    }
+
+    //see `dedup_palette`
+    pub fn dedupe(&mut self) {
+        self.0 = dedup_palette(std::mem::take(&mut self.0));
+    }
+
+    pub fn load(path: &Path) -> Result<Self, palette_io::PaletteIoError> {
+        palette_io::load_palette(path).map(Self)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), palette_io::PaletteIoError> {
+        palette_io::save_palette(path, &self.0)
+    }
+
+    //the same for any palettes with the same colours, whatever order they're in. only stable within one run, so it
+    //mustn't be saved anywhere
+    pub fn content_hash(&self) -> u64 {
+        let mut colours: Vec<[u8; 4]> = self.0.iter().map(|colour| colour.0).collect();
+        colours.sort_unstable();
+        let mut hasher = DefaultHasher::new();
+        colours.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl Display for Palette {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let hexes: Vec<_> = self
+            .0
+            .iter()
+            .copied()
+            .map(pixel_operations::rgb_to_hex)
+            .collect();
+        write!(f, "{}", hexes.join(", "))
+    }
+}
+
+impl Debug for Palette {
+    //sorted so that two palettes with the same colours in a different order look the same
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut hexes: Vec<_> = self
+            .0
+            .iter()
+            .copied()
+            .map(pixel_operations::rgb_to_hex)
+            .collect();
+        hexes.sort_unstable();
+
+        f.debug_struct("Palette")
+            .field("len", &self.0.len())
+            .field("colours", &hexes)
+            .finish()
+    }
 }
 
 impl DistanceAlgorithm {
@@ -155,277 +565,4621 @@ impl DistanceAlgorithm {
             Self::Value => value_distance(a, b),
         }
     }
+
+    //luminance and value only look at one number per colour, and the distance is how far apart those are
+    pub fn brightness(self, colour: Rgba<u8>) -> Option<u32> {
+        let Rgba([r, g, b, _]) = colour;
+        match self {
+            Self::Luminance => Some(luminance(colour)),
+            Self::Value => Some(r.max(g).max(b) as u32),
+            Self::Euclidean | Self::HSVEuclidean | Self::Manhattan => None,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+//anything missing from older saves gets its default, so new fields don't break loading them
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct PaletteSettings {
     pub chunks_per_dimension: u32,
     pub closeness_threshold: u32,
+    #[cfg_attr(feature = "serde", serde(with = "serde_colours"))]
+    pub exclude_colors: Vec<Rgba<u8>>,
+    pub exclude_threshold: u32,
+    //always added to the palette, eg. from a generated ramp
+    #[cfg_attr(feature = "serde", serde(with = "serde_colours", default))]
+    pub extra_colors: Vec<Rgba<u8>>,
 }
 
-impl Default for PaletteSettings {
-    fn default() -> Self {
-        Self {
-            chunks_per_dimension: 100,
-            closeness_threshold: 50,
-        }
-    }
+#[derive(Debug, Copy, Clone, PartialEq, thiserror::Error)]
+pub enum ValidationError {
+    #[error("chunks_per_dimension must be greater than 0")]
+    NoChunks,
+    #[error(
+        "closeness_threshold must be at most {max} for {algorithm}, but was {closeness_threshold}"
+    )]
+    ClosenessThresholdTooBig {
+        closeness_threshold: u32,
+        algorithm: DistanceAlgorithm,
+        max: u32,
+    },
+    #[error("output_px_size must be at least 1")]
+    NoOutputPxSize,
+    #[error("output_px_size must be at most {max}, but was {0}", max = MAX_OUTPUT_PX_SIZE)]
+    OutputPxSizeTooBig(u32),
+    #[error("dithering_scale must be at least 1")]
+    NoDitheringScale,
+    #[error("dither strength must be at most 100, but was {0}")]
+    DitherStrengthTooBig(u32),
+    #[error("dithering ratio must be at least 1")]
+    NoDitheringRatio,
+    #[error("dithering fraction must be between 0.0 and 1.0, but was {0}")]
+    DitheringFractionOutOfRange(f32),
+    #[error("post_sharpen can't be negative, but was {0}")]
+    NegativeSharpening(f32),
+    #[error("output_px_size must be at least {min} for a dithering_scale of {dithering_scale}, but was {output_px_size}")]
+    OutputPxSizeTooSmallForDithering {
+        output_px_size: u32,
+        dithering_scale: u32,
+        min: u32,
+    },
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct OutputSettings {
-    pub output_px_size: u32,
-    pub dithering_likelihood: u32,
-    pub dithering_scale: u32,
-    pub scale_output_to_original: bool,
+//what a palette or dither did, from the `_with_stats` versions of each
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub duration: Duration,
+    pub chunks_processed: u64,
+    pub pixels_sampled: u64,
+    //the fraction of pixels that didn't need checking against the palette so far, or `None` if nothing was cached
+    pub cache_hit_rate: Option<f32>,
+    pub palette_size: usize,
 }
 
-impl PartialEq for OutputSettings {
-    fn eq(&self, other: &Self) -> bool {
-        if self.dithering_scale == 1 || other.dithering_scale == 1 {
-            if self.dithering_scale != other.dithering_scale {
-                false
-            } else {
-                self.output_px_size == other.output_px_size
-                    && self.scale_output_to_original == other.scale_output_to_original
-            }
-        } else {
-            self.output_px_size == other.output_px_size
-                && self.dithering_likelihood == other.dithering_likelihood
-                && self.dithering_scale == other.dithering_scale
-                && self.scale_output_to_original == other.scale_output_to_original
-        }
+impl Stats {
+    fn cache_hit_rate(hits: u64, pixels_sampled: u64) -> Option<f32> {
+        (pixels_sampled > 0).then(|| hits as f32 / pixels_sampled as f32)
     }
 }
 
-impl Eq for OutputSettings {}
+//everything the pipeline can fail with, so library users can handle it rather than getting a panic
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PxlsError {
+    InvalidSettings(ValidationError),
+    EmptyPalette,
+    ImageTooSmall { needed: u32, got: u32 },
+    //checked before anything divides by the size of the image
+    ZeroDimension,
+    //the stop flag was set part way through, so anything made so far is incomplete
+    Cancelled,
+    Scale(ScaleError),
+}
 
-impl Default for OutputSettings {
-    fn default() -> Self {
-        Self {
-            output_px_size: 5,
-            dithering_likelihood: 4,
-            dithering_scale: 2,
-            scale_output_to_original: true,
+impl Display for PxlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSettings(e) => write!(f, "invalid settings: {e}"),
+            Self::EmptyPalette => write!(f, "the palette doesn't have any colours"),
+            Self::ImageTooSmall { needed, got } => write!(
+                f,
+                "the image needs to be at least {needed}px each way, but is only {got}px"
+            ),
+            Self::ZeroDimension => write!(f, "the image is empty"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Scale(e) => write!(f, "{e}"),
         }
     }
 }
 
-//tyvm https://stackoverflow.com/questions/26885198/find-closest-factor-to-a-number-of-a-number
-pub fn get_closest_factor(target: u32, number: u32) -> u32 {
-    for i in 0..number {
-        if number % (target + i) == 0 {
-            return target + i;
-        } else if number % (target - i) == 0 {
-            return target - i;
+impl Error for PxlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidSettings(e) => Some(e),
+            Self::Scale(e) => e.source(),
+            _ => None,
         }
     }
-    number
 }
 
-pub fn get_palette(
-    image: &DynamicImage,
-    PaletteSettings {
-        chunks_per_dimension,
-        closeness_threshold,
-    }: PaletteSettings,
-    dist_algo: DistanceAlgorithm,
-    progress_sender: &Sender<(u32, u32)>,
-    stop: Arc<AtomicBool>,
-) -> Vec<Rgba<u8>> {
-    let chunks_per_dimension =
-        get_closest_factor(chunks_per_dimension, image.width().min(image.height()));
-    let (width_chunk_size, height_chunk_size) = (
-        image.width() / chunks_per_dimension,
-        image.height() / chunks_per_dimension,
-    );
+impl From<ValidationError> for PxlsError {
+    fn from(e: ValidationError) -> Self {
+        Self::InvalidSettings(e)
+    }
+}
 
-    let num_chunks = chunks_per_dimension * chunks_per_dimension;
-    let mut progress_bar = 0;
+impl From<ScaleError> for PxlsError {
+    fn from(e: ScaleError) -> Self {
+        Self::Scale(e)
+    }
+}
 
-    let mut av_px_colours = Vec::with_capacity(num_chunks as usize);
-    let mut cache = HashMap::new();
+fn check_not_empty(image: &impl GenericImageView) -> Result<(), PxlsError> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(PxlsError::ZeroDimension);
+    }
+    Ok(())
+}
 
-    for chunk_x in 0..chunks_per_dimension {
-        for chunk_y in 0..chunks_per_dimension {
-            if stop.load(Ordering::Relaxed) {
-                return av_px_colours;
-            }
+impl PaletteSettings {
+    //the threshold's units depend on the algorithm, so it can go as high as `closeness_threshold_range` lets it
+    pub fn validated(self, algorithm: DistanceAlgorithm) -> Result<Self, ValidationError> {
+        if self.chunks_per_dimension == 0 {
+            return Err(ValidationError::NoChunks);
+        }
+        let max = *algorithm.closeness_threshold_range().end();
+        if self.closeness_threshold > max {
+            return Err(ValidationError::ClosenessThresholdTooBig {
+                closeness_threshold: self.closeness_threshold,
+                algorithm,
+                max,
+            });
+        }
 
-            let mut occurencces_of_suitably_far: HashMap<_, u32> = HashMap::new();
-            for px_x in (width_chunk_size * chunk_x)..(width_chunk_size * (chunk_x + 1)) {
-                for px_y in (height_chunk_size * chunk_y)..(height_chunk_size * (chunk_y + 1)) {
-                    let px = image.get_pixel(px_x, px_y);
+        Ok(self)
+    }
+}
 
-                    let too_close = match cache.entry(px) {
-                        Entry::Occupied(occ) => *occ.get(),
-                        Entry::Vacant(vac) => {
-                            let mut too_close = false;
-                            for so_far in av_px_colours.iter().copied() {
-                                if dist_algo.distance(px, so_far)
-                                    < dist_algo.standardise_closeness_threshold(closeness_threshold)
-                                {
-                                    too_close = true;
-                                    break;
-                                }
-                            }
+impl Default for PaletteSettings {
+    fn default() -> Self {
+        Self {
+            chunks_per_dimension: 100,
+            closeness_threshold: 50,
+            exclude_colors: vec![],
+            exclude_threshold: 10,
+            extra_colors: vec![],
+        }
+    }
+}
 
-                            *vac.insert(too_close)
-                        }
-                    };
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DitheringMode {
+    //the original behaviour - higher means less dithering
+    Ratio(u32),
+    //0.0 never dithers, 1.0 always dithers if there are two candidates
+    Fraction(f32),
+}
 
-                    if !too_close {
-                        *occurencces_of_suitably_far.entry(px).or_default() += 1;
-                    }
-                }
+impl DitheringMode {
+    pub fn should_dither(self, first_distance: u32, second_distance: u32, between: u32) -> bool {
+        match self {
+            //lower ratios dither more, so no ratio at all always dithers
+            Self::Ratio(ratio) => {
+                first_distance.abs_diff(second_distance)
+                    <= between.checked_div(ratio).unwrap_or(u32::MAX)
             }
-
-            if let Some((most_common, _)) = occurencces_of_suitably_far
-                .into_iter()
-                .max_by_key(|(_, count)| *count)
-            {
-                av_px_colours.push(most_common);
-                cache.clear();
+            Self::Fraction(fraction) => {
+                let ratio = if second_distance == 0 {
+                    1.0
+                } else {
+                    first_distance as f32 / second_distance as f32
+                };
+                fraction > 0.0 && ratio > 1.0 - fraction
             }
-
-            progress_bar += 1;
-            let _ = progress_sender.send((progress_bar, num_chunks));
         }
     }
+}
 
-    av_px_colours
+impl PartialEq for DitheringMode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Ratio(a), Self::Ratio(b)) => a == b,
+            (Self::Fraction(a), Self::Fraction(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
 }
 
-pub fn dither_original_with_palette(
-    input: &DynamicImage,
-    palette: &[Rgba<u8>],
-    distance_algorithm: DistanceAlgorithm,
-    output_settings: OutputSettings,
-    progress_sender: &Sender<(u32, u32)>,
-    stop: Arc<AtomicBool>,
-) -> DynamicImage {
-    let output_px_size =
-        get_closest_factor(1 << (output_settings.output_px_size - 1), input.width());
+impl Eq for DitheringMode {}
 
-    let (width, height) = input.dimensions();
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DitherMode {
+    //just the closest colour for each chunk
+    None,
+    //the original two-colour checkerboard, using `dithering_mode` and `dithering_scale`
+    Legacy,
+    FloydSteinberg { direction: ErrorDiffusionDirection },
+    //the ordered modes all have a strength from 0 to 100
+    Bayer { strength: u32 },
+    BlueNoise { strength: u32 },
+    Random { strength: u32, seed: u64 },
+}
 
-    let (num_width_chunks, num_height_chunks) = (width / output_px_size, height / output_px_size);
-    let (output_w, output_h) = (
-        num_width_chunks * output_settings.dithering_scale,
-        num_height_chunks * output_settings.dithering_scale,
-    );
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ErrorDiffusionDirection {
+    LeftToRight,
+    RightToLeft,
+    //alternates every row, which stops the error from flowing into diagonal "worms"
+    #[default]
+    Serpentine,
+}
 
-    let mut output = DynamicImage::new(output_w, output_h, ColorType::Rgb8);
+impl ErrorDiffusionDirection {
+    pub const fn to_str(self) -> &'static str {
+        match self {
+            Self::LeftToRight => "Left to Right",
+            Self::RightToLeft => "Right to Left",
+            Self::Serpentine => "Serpentine",
+        }
+    }
 
-    let total_chunks = num_width_chunks * num_height_chunks;
-    let mut chunks_progress_bar = 0;
+    const fn is_right_to_left(self, row: u32) -> bool {
+        match self {
+            Self::LeftToRight => false,
+            Self::RightToLeft => true,
+            Self::Serpentine => row % 2 == 1,
+        }
+    }
+}
+
+impl Display for ErrorDiffusionDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+pub const ALL_ERROR_DIFFUSION_DIRECTIONS: &[ErrorDiffusionDirection] = &[
+    ErrorDiffusionDirection::LeftToRight,
+    ErrorDiffusionDirection::RightToLeft,
+    ErrorDiffusionDirection::Serpentine,
+];
+
+impl DitherMode {
+    pub const DEFAULT_STRENGTH: u32 = 50;
+
+    pub const fn to_str(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Legacy => "Legacy",
+            Self::FloydSteinberg { .. } => "Floyd-Steinberg",
+            Self::Bayer { .. } => "Bayer",
+            Self::BlueNoise { .. } => "Blue Noise",
+            Self::Random { .. } => "Random",
+        }
+    }
+
+    pub const fn strength(self) -> Option<u32> {
+        match self {
+            Self::Bayer { strength }
+            | Self::BlueNoise { strength }
+            | Self::Random { strength, .. } => Some(strength),
+            _ => None,
+        }
+    }
+
+    //a threshold between -0.5 and 0.5 for the ordered modes
+    fn threshold(self, x: u32, y: u32) -> f32 {
+        match self {
+            Self::Bayer { .. } => {
+                let (x, y) = (x & 7, y & 7);
+                let xy = x ^ y;
+                let index = ((xy & 1) << 5)
+                    | ((x & 1) << 4)
+                    | ((xy & 2) << 2)
+                    | ((x & 2) << 1)
+                    | ((xy & 4) >> 1)
+                    | ((x & 4) >> 2);
+                (index as f32 + 0.5) / 64.0 - 0.5
+            }
+            //interleaved gradient noise - not true blue noise, but close enough without shipping a texture
+            Self::BlueNoise { .. } => {
+                let inner = 0.067_110_56_f32.mul_add(x as f32, 0.005_837_15 * y as f32);
+                (52.982_918 * inner.fract()).fract() - 0.5
+            }
+            Self::Random { seed, .. } => {
+                //splitmix64
+                let mut z = seed ^ ((x as u64) << 32 | y as u64);
+                z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                z ^= z >> 31;
+                (z >> 40) as f32 / (1 << 24) as f32 - 0.5
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl Display for DitherMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+pub const ALL_DITHER_MODES: &[DitherMode] = &[
+    DitherMode::None,
+    DitherMode::Legacy,
+    DitherMode::FloydSteinberg {
+        direction: ErrorDiffusionDirection::Serpentine,
+    },
+    DitherMode::Bayer {
+        strength: DitherMode::DEFAULT_STRENGTH,
+    },
+    DitherMode::BlueNoise {
+        strength: DitherMode::DEFAULT_STRENGTH,
+    },
+    DitherMode::Random {
+        strength: DitherMode::DEFAULT_STRENGTH,
+        seed: 0,
+    },
+];
+
+//equal only if every field is, even the ones that don't change the output - compare `canonical`s for that
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct OutputSettings {
+    pub output_px_size: u32,
+    pub dither_mode: DitherMode,
+    pub dithering_mode: DitheringMode,
+    pub dithering_scale: u32,
+    pub scale_output_to_original: bool,
+    //how strongly to unsharp-mask the output, up to `MAX_POST_SHARPEN`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub post_sharpen: f32,
+}
+
+impl Eq for OutputSettings {}
+
+impl OutputSettings {
+    //the same settings with anything that can't change the output set to a fixed value, so two settings make the
+    //same output exactly when their canonical forms are equal:
+    //- the dithering mode and scale only matter for legacy dithering
+    //- legacy dithering with a scale of 1 has no checkerboard, so its dithering mode doesn't matter either
+    #[must_use]
+    pub fn canonical(self) -> Self {
+        let dont_care = Self::default().dithering_mode;
+        match (self.dither_mode, self.dithering_scale) {
+            (DitherMode::Legacy, 1) => Self {
+                dithering_mode: dont_care,
+                ..self
+            },
+            (DitherMode::Legacy, _) => self,
+            _ => Self {
+                dithering_mode: dont_care,
+                dithering_scale: 1,
+                ..self
+            },
+        }
+    }
+
+    pub fn validated(self) -> Result<Self, ValidationError> {
+        if self.output_px_size == 0 {
+            return Err(ValidationError::NoOutputPxSize);
+        }
+        if self.output_px_size > MAX_OUTPUT_PX_SIZE {
+            return Err(ValidationError::OutputPxSizeTooBig(self.output_px_size));
+        }
+        if self.dithering_scale == 0 {
+            return Err(ValidationError::NoDitheringScale);
+        }
+        if let Some(strength) = self.dither_mode.strength() {
+            if strength > 100 {
+                return Err(ValidationError::DitherStrengthTooBig(strength));
+            }
+        }
+        match self.dithering_mode {
+            DitheringMode::Ratio(0) => return Err(ValidationError::NoDitheringRatio),
+            DitheringMode::Fraction(fraction) if !(0.0..=1.0).contains(&fraction) => {
+                return Err(ValidationError::DitheringFractionOutOfRange(fraction));
+            }
+            _ => {}
+        }
+
+        if self.post_sharpen.is_nan() || self.post_sharpen < 0.0 {
+            return Err(ValidationError::NegativeSharpening(self.post_sharpen));
+        }
+
+        let min = self.effective_dithering_scale().ilog2() + 1;
+        if self.output_px_size < min {
+            return Err(ValidationError::OutputPxSizeTooSmallForDithering {
+                output_px_size: self.output_px_size,
+                dithering_scale: self.dithering_scale,
+                min,
+            });
+        }
+
+        Ok(self)
+    }
+
+    //only legacy dithering makes more than one output pixel per chunk
+    pub fn effective_dithering_scale(self) -> u32 {
+        if self.dither_mode == DitherMode::Legacy {
+            self.dithering_scale
+        } else {
+            1
+        }
+    }
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            output_px_size: 5,
+            dither_mode: DitherMode::Legacy,
+            dithering_mode: DitheringMode::Ratio(4),
+            dithering_scale: 2,
+            scale_output_to_original: true,
+            post_sharpen: 0.0,
+        }
+    }
+}
+
+//eg. "threshold 40 → 80, algo Euclidean → Hue", or "same settings" if nothing differs
+pub fn describe_settings_changes(
+    (from_palette, from_output, from_algo): &(PaletteSettings, OutputSettings, DistanceAlgorithm),
+    (to_palette, to_output, to_algo): &(PaletteSettings, OutputSettings, DistanceAlgorithm),
+) -> String {
+    fn change<T: PartialEq + Display>(changes: &mut Vec<String>, name: &str, from: T, to: T) {
+        if from != to {
+            changes.push(format!("{name} {from} → {to}"));
+        }
+    }
+    let dithering_mode = |mode: DitheringMode| match mode {
+        DitheringMode::Ratio(ratio) => format!("ratio {ratio}"),
+        DitheringMode::Fraction(fraction) => format!("fraction {fraction}"),
+    };
+
+    let mut changes = vec![];
+    change(&mut changes, "algo", from_algo, to_algo);
+    change(
+        &mut changes,
+        "chunks",
+        from_palette.chunks_per_dimension,
+        to_palette.chunks_per_dimension,
+    );
+    change(
+        &mut changes,
+        "threshold",
+        from_palette.closeness_threshold,
+        to_palette.closeness_threshold,
+    );
+    change(
+        &mut changes,
+        "excluded colours",
+        from_palette.exclude_colors.len(),
+        to_palette.exclude_colors.len(),
+    );
+    change(
+        &mut changes,
+        "exclude threshold",
+        from_palette.exclude_threshold,
+        to_palette.exclude_threshold,
+    );
+    change(
+        &mut changes,
+        "custom colours",
+        from_palette.extra_colors.len(),
+        to_palette.extra_colors.len(),
+    );
+    change(
+        &mut changes,
+        "px size",
+        from_output.output_px_size,
+        to_output.output_px_size,
+    );
+    change(
+        &mut changes,
+        "dither",
+        //just the kind, as the strength gets its own change
+        from_output.dither_mode.to_str(),
+        to_output.dither_mode.to_str(),
+    );
+    if let (Some(from), Some(to)) = (
+        from_output.dither_mode.strength(),
+        to_output.dither_mode.strength(),
+    ) {
+        change(&mut changes, "strength", from, to);
+    }
+    match (from_output.dither_mode, to_output.dither_mode) {
+        (
+            DitherMode::FloydSteinberg { direction: from },
+            DitherMode::FloydSteinberg { direction: to },
+        ) => change(&mut changes, "direction", from.to_str(), to.to_str()),
+        (DitherMode::Random { seed: from, .. }, DitherMode::Random { seed: to, .. }) => {
+            change(&mut changes, "seed", from, to);
+        }
+        _ => {}
+    }
+    change(
+        &mut changes,
+        "dithering",
+        dithering_mode(from_output.dithering_mode),
+        dithering_mode(to_output.dithering_mode),
+    );
+    change(
+        &mut changes,
+        "dithering scale",
+        from_output.dithering_scale,
+        to_output.dithering_scale,
+    );
+    change(
+        &mut changes,
+        "sharpening",
+        from_output.post_sharpen,
+        to_output.post_sharpen,
+    );
+    change(
+        &mut changes,
+        "scaled to original",
+        from_output.scale_output_to_original,
+        to_output.scale_output_to_original,
+    );
+
+    if changes.is_empty() {
+        "same settings".to_string()
+    } else {
+        changes.join(", ")
+    }
+}
+
+#[cfg(feature = "factor-cache")]
+thread_local! {
+    //the gui asks for the same factors every time a setting changes
+    static FACTOR_CACHE: std::cell::RefCell<lru::LruCache<(u32, u32), u32>> =
+        std::cell::RefCell::new(lru::LruCache::new(std::num::NonZeroUsize::new(256).unwrap()));
+}
+
+//the factor of `number` nearest to `target`, with the bigger one winning a tie. a `target` of 0 gets 1, as that's
+//always a factor, and a `number` of 0 has no factors so gets 0 back
+#[cfg(feature = "factor-cache")]
+pub fn get_closest_factor(target: u32, number: u32) -> u32 {
+    if let Some(factor) =
+        FACTOR_CACHE.with_borrow_mut(|cache| cache.get(&(target, number)).copied())
+    {
+        return factor;
+    }
+
+    let factor = find_closest_factor(target, number);
+    FACTOR_CACHE.with_borrow_mut(|cache| cache.put((target, number), factor));
+    factor
+}
+
+#[cfg(not(feature = "factor-cache"))]
+pub fn get_closest_factor(target: u32, number: u32) -> u32 {
+    find_closest_factor(target, number)
+}
+
+//tyvm https://stackoverflow.com/questions/26885198/find-closest-factor-to-a-number-of-a-number
+fn find_closest_factor(target: u32, number: u32) -> u32 {
+    if number == 0 {
+        return 0;
+    }
+    //nothing past `number` can be a factor, and `number` itself always is
+    if target >= number {
+        return number;
+    }
+    let target = target.max(1);
+
+    //working outwards, only ever between 1 and `number` - which are both factors, so this always finds one
+    for distance in 0..number {
+        let above = target + distance;
+        if above <= number && number.is_multiple_of(above) {
+            return above;
+        }
+        if let Some(below) = target.checked_sub(distance).filter(|below| *below > 0) {
+            if number.is_multiple_of(below) {
+                return below;
+            }
+        }
+    }
+    unreachable!("1 is a factor of everything")
+}
+
+//only 8-bit RGB and RGBA images can be read directly - the channel count comes from `image.color()`
+pub fn as_raw_rgb_slice(image: &DynamicImage) -> Option<&[u8]> {
+    match image {
+        DynamicImage::ImageRgb8(image) => Some(image.as_raw()),
+        DynamicImage::ImageRgba8(image) => Some(image.as_raw()),
+        _ => None,
+    }
+}
+
+//what the palette and dither can be made from, so an `RgbaImage` or a `SubImage` crop doesn't need copying into a
+//`DynamicImage` first. anything that can't give its raw buffer gets read through `get_pixel`
+pub trait PixelSource: GenericImageView<Pixel = Rgba<u8>> {
+    //every pixel row by row with no padding, and how many channels each pixel has - only RGB and RGBA will do
+    fn raw_rgb(&self) -> Option<(&[u8], usize)> {
+        None
+    }
+
+    //the RGB of a pixel in 16 bits per channel, so that averaging deeper inputs doesn't band
+    fn get_pixel_wide(&self, x: u32, y: u32) -> [u16; 3] {
+        let [r, g, b, _] = self.get_pixel(x, y).0;
+        [r, g, b].map(|channel| u16::from(channel) * 257)
+    }
+}
+
+impl PixelSource for DynamicImage {
+    fn raw_rgb(&self) -> Option<(&[u8], usize)> {
+        as_raw_rgb_slice(self).map(|raw| (raw, usize::from(self.color().channel_count())))
+    }
+
+    fn get_pixel_wide(&self, x: u32, y: u32) -> [u16; 3] {
+        match self {
+            Self::ImageRgb16(image) => image.get_pixel(x, y).0,
+            Self::ImageRgba16(image) => {
+                let [r, g, b, _] = image.get_pixel(x, y).0;
+                [r, g, b]
+            }
+            Self::ImageLuma8(image) => [u16::from(image.get_pixel(x, y).0[0]) * 257; 3],
+            Self::ImageLuma16(image) => [image.get_pixel(x, y).0[0]; 3],
+            Self::ImageLumaA16(image) => [image.get_pixel(x, y).0[0]; 3],
+            _ => {
+                let [r, g, b, _] = self.get_pixel(x, y).0;
+                [r, g, b].map(|channel| u16::from(channel) * 257)
+            }
+        }
+    }
+}
+
+//`RgbaImage`, or one borrowing its pixels from elsewhere
+impl<C: Deref<Target = [u8]>> PixelSource for ImageBuffer<Rgba<u8>, C> {
+    fn raw_rgb(&self) -> Option<(&[u8], usize)> {
+        Some((self.as_raw(), 4))
+    }
+}
+
+//for any other view, eg. `ViewSource(&*image.view(x, y, width, height))` or one that converts pixels as it goes
+#[derive(Copy, Clone)]
+pub struct ViewSource<'a, I>(pub &'a I);
+
+impl<I: GenericImageView<Pixel = Rgba<u8>>> GenericImageView for ViewSource<'_, I> {
+    type Pixel = Rgba<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+        self.0.get_pixel(x, y)
+    }
+}
+
+impl<I: GenericImageView<Pixel = Rgba<u8>>> PixelSource for ViewSource<'_, I> {}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    name = "get_palette",
+    skip_all,
+    fields(
+        width = image.width(),
+        height = image.height(),
+        chunks_per_dimension = settings.chunks_per_dimension,
+        algorithm = ?dist_algo,
+        chunks = tracing::field::Empty,
+        palette_len = tracing::field::Empty,
+    ),
+))]
+pub fn get_palette_with_stats(
+    image: &DynamicImage,
+    settings: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(Palette, Stats), PxlsError> {
+    let started = Instant::now();
+    let (palette, stats) = match image {
+        DynamicImage::ImageLuma8(grey) if dist_algo.brightness(Rgba([0; 4])).is_some() => {
+            get_palette_grey(grey, settings, dist_algo, progress, stop)
+        }
+        _ => palette_and_stats_from_source(image, settings, dist_algo, progress, stop),
+    }?;
+    record_in_span("palette_len", palette.len() as u64);
+
+    Ok((
+        palette,
+        Stats {
+            duration: started.elapsed(),
+            ..stats
+        },
+    ))
+}
+
+/// The most common colour of each chunk of the image, leaving out any that are too close to one it already has.
+///
+/// # Examples
+///
+/// ```
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// use pxls::{
+///     cancellation::CancellationToken, get_palette, progress::NoProgress, DistanceAlgorithm,
+///     PaletteSettings,
+/// };
+///
+/// let (red, blue) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+/// let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, _| if x < 4 { red } else { blue }));
+/// let settings = PaletteSettings {
+///     chunks_per_dimension: 2,
+///     ..PaletteSettings::default()
+/// };
+///
+/// let palette = get_palette(
+///     &image,
+///     settings,
+///     DistanceAlgorithm::Euclidean,
+///     &NoProgress,
+///     &CancellationToken::new(),
+/// )?;
+/// assert_eq!(*palette, [red, blue]);
+/// # Ok::<(), pxls::PxlsError>(())
+/// ```
+pub fn get_palette(
+    image: &DynamicImage,
+    settings: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<Palette, PxlsError> {
+    get_palette_with_stats(image, settings, dist_algo, progress, stop).map(|(palette, _)| palette)
+}
+
+//fills in one of the empty fields on the span of whichever instrumented function this was called from
+#[allow(unused_variables)]
+fn record_in_span(field: &'static str, value: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record(field, value);
+}
+
+//`get_palette` for greyscale images and brightness-only distances, which can count the 256 greys in a flat histogram
+//rather than hashing every pixel. the palette is the same, apart from which colour wins when two are equally common
+fn get_palette_grey(
+    image: &GrayImage,
+    PaletteSettings {
+        chunks_per_dimension,
+        closeness_threshold,
+        exclude_colors,
+        exclude_threshold,
+        extra_colors,
+    }: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(Palette, Stats), PxlsError> {
+    PaletteSettings {
+        chunks_per_dimension,
+        closeness_threshold,
+        ..PaletteSettings::default()
+    }
+    .validated(dist_algo)?;
+    check_not_empty(image)?;
+    let smallest_dimension = image.width().min(image.height());
+
+    let chunks_per_dimension = get_closest_factor(chunks_per_dimension, smallest_dimension);
+    let (width_chunk_size, height_chunk_size) = (
+        image.width() / chunks_per_dimension,
+        image.height() / chunks_per_dimension,
+    );
+
+    let num_chunks = chunks_per_dimension * chunks_per_dimension;
+    record_in_span("chunks", u64::from(num_chunks));
+    let mut progress_bar = 0;
+
+    let grey = |level: u8| Rgba([level, level, level, u8::MAX]);
+    let brightness = |colour| dist_algo.brightness(colour).unwrap_or_default();
+    let levels: [u32; 256] = std::array::from_fn(|level| brightness(grey(level as u8)));
+    let exclude_brightnesses: Vec<_> = exclude_colors.iter().copied().map(brightness).collect();
+    let exclude_threshold = dist_algo.standardise_closeness_threshold(exclude_threshold);
+    let closeness_threshold = dist_algo.standardise_closeness_threshold(closeness_threshold);
+
+    let mut av_px_colours = Vec::with_capacity(num_chunks as usize);
+    let mut palette_brightnesses: Vec<u32> = Vec::with_capacity(num_chunks as usize);
+    let mut cache = [None; 256];
+    let mut cache_hits = 0_u64;
+
+    for chunk_x in 0..chunks_per_dimension {
+        for chunk_y in 0..chunks_per_dimension {
+            if stop.is_cancelled() {
+                //so whoever's listening knows where it got to, even if the last few ticks were skipped
+                progress.report(Stage::Palette, progress_bar, num_chunks);
+                return Err(PxlsError::Cancelled);
+            }
+
+            let mut occurences = [0_u32; 256];
+            for px_y in (height_chunk_size * chunk_y)..(height_chunk_size * (chunk_y + 1)) {
+                for px_x in (width_chunk_size * chunk_x)..(width_chunk_size * (chunk_x + 1)) {
+                    let level = image.get_pixel(px_x, px_y).0[0];
+                    let cached = &mut cache[level as usize];
+                    cache_hits += u64::from(cached.is_some());
+                    let too_close = *cached.get_or_insert_with(|| {
+                        let level = levels[level as usize];
+                        exclude_brightnesses
+                            .iter()
+                            .any(|excluded| level.abs_diff(*excluded) < exclude_threshold)
+                            || palette_brightnesses
+                                .iter()
+                                .any(|so_far| level.abs_diff(*so_far) < closeness_threshold)
+                    });
+
+                    if !too_close {
+                        occurences[level as usize] += 1;
+                    }
+                }
+            }
+
+            if let Some((most_common, _)) = occurences
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .max_by_key(|(_, count)| **count)
+            {
+                av_px_colours.push(grey(most_common as u8));
+                palette_brightnesses.push(levels[most_common]);
+                cache = [None; 256];
+            }
+
+            progress_bar += 1;
+            progress.tick(Stage::Palette, progress_bar, num_chunks);
+        }
+    }
+
+    av_px_colours.extend(extra_colors);
+
+    Ok(chunked_palette(
+        av_px_colours,
+        num_chunks,
+        (width_chunk_size, height_chunk_size),
+        cache_hits,
+    ))
+}
+
+//the palette from a grid of chunks, with how much work it took to get there
+fn chunked_palette(
+    colours: Vec<Rgba<u8>>,
+    num_chunks: u32,
+    (chunk_width, chunk_height): (u32, u32),
+    cache_hits: u64,
+) -> (Palette, Stats) {
+    let palette: Palette = dedup_palette(colours).into();
+    let pixels_sampled = u64::from(num_chunks) * u64::from(chunk_width) * u64::from(chunk_height);
+    let stats = Stats {
+        duration: Duration::ZERO,
+        chunks_processed: u64::from(num_chunks),
+        pixels_sampled,
+        cache_hit_rate: Stats::cache_hit_rate(cache_hits, pixels_sampled),
+        palette_size: palette.len(),
+    };
+    (palette, stats)
+}
+
+pub fn get_palette_from_source(
+    image: &impl PixelSource,
+    settings: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<Palette, PxlsError> {
+    palette_and_stats_from_source(image, settings, dist_algo, progress, stop)
+        .map(|(palette, _)| palette)
+}
+
+fn palette_and_stats_from_source(
+    image: &impl PixelSource,
+    PaletteSettings {
+        chunks_per_dimension,
+        closeness_threshold,
+        exclude_colors,
+        exclude_threshold,
+        extra_colors,
+    }: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(Palette, Stats), PxlsError> {
+    PaletteSettings {
+        chunks_per_dimension,
+        closeness_threshold,
+        ..PaletteSettings::default()
+    }
+    .validated(dist_algo)?;
+    check_not_empty(image)?;
+    let smallest_dimension = image.width().min(image.height());
+
+    let chunks_per_dimension = get_closest_factor(chunks_per_dimension, smallest_dimension);
+    let (width_chunk_size, height_chunk_size) = (
+        image.width() / chunks_per_dimension,
+        image.height() / chunks_per_dimension,
+    );
+
+    let num_chunks = chunks_per_dimension * chunks_per_dimension;
+    record_in_span("chunks", u64::from(num_chunks));
+    let mut progress_bar = 0;
+
+    let mut av_px_colours = Vec::with_capacity(num_chunks as usize);
+    let mut cache = HashMap::new();
+    let mut cache_hits = 0_u64;
+
+    //the common layouts get read straight out of the buffer, rather than going through `get_pixel` every time
+    let raw = image.raw_rgb();
+    let width = image.width() as usize;
+
+    for chunk_x in 0..chunks_per_dimension {
+        for chunk_y in 0..chunks_per_dimension {
+            if stop.is_cancelled() {
+                progress.report(Stage::Palette, progress_bar, num_chunks);
+                return Err(PxlsError::Cancelled);
+            }
+
+            let mut occurencces_of_suitably_far: HashMap<_, u32> = HashMap::new();
+            //rows on the outside, so the buffer gets read in order
+            for px_y in (height_chunk_size * chunk_y)..(height_chunk_size * (chunk_y + 1)) {
+                for px_x in (width_chunk_size * chunk_x)..(width_chunk_size * (chunk_x + 1)) {
+                    let px = match raw {
+                        Some((raw, channels)) => {
+                            let offset = (px_y as usize * width + px_x as usize) * channels;
+                            let channels = &raw[offset..offset + channels];
+                            Rgba([
+                                channels[0],
+                                channels[1],
+                                channels[2],
+                                channels.get(3).copied().unwrap_or(u8::MAX),
+                            ])
+                        }
+                        None => image.get_pixel(px_x, px_y),
+                    };
+
+                    let too_close = match cache.entry(px) {
+                        Entry::Occupied(occ) => {
+                            cache_hits += 1;
+                            *occ.get()
+                        }
+                        Entry::Vacant(vac) => {
+                            let mut too_close = exclude_colors.iter().any(|excluded| {
+                                dist_algo.distance(px, *excluded)
+                                    < dist_algo.standardise_closeness_threshold(exclude_threshold)
+                            });
+                            for so_far in av_px_colours.iter().copied() {
+                                if dist_algo.distance(px, so_far)
+                                    < dist_algo.standardise_closeness_threshold(closeness_threshold)
+                                {
+                                    too_close = true;
+                                    break;
+                                }
+                            }
+
+                            *vac.insert(too_close)
+                        }
+                    };
+
+                    if !too_close {
+                        *occurencces_of_suitably_far.entry(px).or_default() += 1;
+                    }
+                }
+            }
+
+            if let Some((most_common, _)) = occurencces_of_suitably_far
+                .into_iter()
+                .max_by_key(|(_, count)| *count)
+            {
+                av_px_colours.push(most_common);
+                cache.clear();
+            }
+
+            progress_bar += 1;
+            progress.tick(Stage::Palette, progress_bar, num_chunks);
+        }
+    }
+
+    av_px_colours.extend(extra_colors);
+
+    Ok(chunked_palette(
+        av_px_colours,
+        num_chunks,
+        (width_chunk_size, height_chunk_size),
+        cache_hits,
+    ))
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PaletteAlgorithm {
+    //the most common colour of each of a grid of equally-sized chunks
+    #[default]
+    Chunks,
+    //the average colour of each of roughly `target` superpixels, see `get_palette_superpixel`
+    Superpixel {
+        target: usize,
+        compactness: f32,
+    },
+    //the local maxima of a histogram with bins `quantization` wide, see `get_palette_histogram_peaks`
+    HistogramPeaks {
+        quantization: u8,
+    },
+}
+
+//what `PaletteAlgorithm`s get when nothing more specific is asked for
+pub const DEFAULT_SUPERPIXEL_TARGET: usize = 64;
+pub const DEFAULT_SUPERPIXEL_COMPACTNESS: f32 = 10.0;
+pub const DEFAULT_HISTOGRAM_QUANTIZATION: u8 = 16;
+
+impl PaletteAlgorithm {
+    pub fn get_palette(
+        self,
+        image: &DynamicImage,
+        settings: PaletteSettings,
+        dist_algo: DistanceAlgorithm,
+        progress: &impl ProgressReporter,
+        stop: &CancellationToken,
+    ) -> Result<Palette, PxlsError> {
+        self.get_palette_with_stats(image, settings, dist_algo, progress, stop)
+            .map(|(palette, _)| palette)
+    }
+
+    //`Chunks` is the only one that goes chunk by chunk with a cache, so the others just count every pixel
+    pub fn get_palette_with_stats(
+        self,
+        image: &DynamicImage,
+        settings: PaletteSettings,
+        dist_algo: DistanceAlgorithm,
+        progress: &impl ProgressReporter,
+        stop: &CancellationToken,
+    ) -> Result<(Palette, Stats), PxlsError> {
+        let started = Instant::now();
+        let settings = settings.validated(dist_algo)?;
+        check_not_empty(image)?;
+        let palette = match self {
+            Self::Chunks => {
+                return get_palette_with_stats(image, settings, dist_algo, progress, stop)
+            }
+            Self::Superpixel {
+                target,
+                compactness,
+            } => get_palette_superpixel(
+                image,
+                target,
+                compactness,
+                settings,
+                dist_algo,
+                progress,
+                stop,
+            ),
+            Self::HistogramPeaks { quantization } => get_palette_histogram_peaks(
+                image,
+                quantization,
+                settings,
+                dist_algo,
+                progress,
+                stop,
+            ),
+        };
+
+        //these stop early with whatever they have so far
+        if stop.is_cancelled() {
+            return Err(PxlsError::Cancelled);
+        }
+        let stats = Stats {
+            duration: started.elapsed(),
+            chunks_processed: 0,
+            pixels_sampled: u64::from(image.width()) * u64::from(image.height()),
+            cache_hit_rate: None,
+            palette_size: palette.len(),
+        };
+        Ok((palette, stats))
+    }
+}
+
+const SUPERPIXEL_ITERATIONS: u32 = 10;
+
+//a simplified SLIC (https://infoscience.epfl.ch/record/177415) - clusters on RGB and position, starting from a grid.
+//higher compactness makes the superpixels more square, lower makes them follow the colours more closely.
+//bigger superpixels get their colours in first, then they go through the same closeness checks as `get_palette`
+pub fn get_palette_superpixel(
+    image: &DynamicImage,
+    target_superpixels: usize,
+    compactness: f32,
+    PaletteSettings {
+        closeness_threshold,
+        exclude_colors,
+        exclude_threshold,
+        extra_colors,
+        ..
+    }: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Palette {
+    #[derive(Copy, Clone, Default)]
+    struct Cluster {
+        colour: [f32; 3],
+        x: f32,
+        y: f32,
+    }
+
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    if width == 0 || height == 0 {
+        return dedup_palette(extra_colors).into();
+    }
+
+    //the grid spacing, which is also roughly how big each superpixel ends up
+    let step = ((width as f32 * height as f32) / target_superpixels.max(1) as f32)
+        .sqrt()
+        .max(1.0);
+    let colour_at = |x: u32, y: u32| rgb.get_pixel(x, y).0.map(f32::from);
+
+    let mut clusters = vec![];
+    let mut y = step / 2.0;
+    while y < height as f32 {
+        let mut x = step / 2.0;
+        while x < width as f32 {
+            clusters.push(Cluster {
+                colour: colour_at(x as u32, y as u32),
+                x,
+                y,
+            });
+            x += step;
+        }
+        y += step;
+    }
+
+    let spatial_weight = (compactness / step).powi(2);
+    let mut labels = vec![0_usize; (width * height) as usize];
+    for iteration in 0..SUPERPIXEL_ITERATIONS {
+        if stop.is_cancelled() {
+            break;
+        }
+
+        let mut best_distances = vec![f32::INFINITY; labels.len()];
+        for (i, cluster) in clusters.iter().enumerate() {
+            //each cluster only looks at the pixels within a step of it
+            let min_x = (cluster.x - step).max(0.0) as u32;
+            let max_x = ((cluster.x + step) as u32).min(width - 1);
+            let min_y = (cluster.y - step).max(0.0) as u32;
+            let max_y = ((cluster.y + step) as u32).min(height - 1);
+
+            for y in min_y..=max_y {
+                for x in min_x..=max_x {
+                    let colour = colour_at(x, y);
+                    let colour_distance: f32 = colour
+                        .iter()
+                        .zip(cluster.colour)
+                        .map(|(a, b)| (a - b).powi(2))
+                        .sum();
+                    let spatial_distance =
+                        (x as f32 - cluster.x).powi(2) + (y as f32 - cluster.y).powi(2);
+                    let distance = spatial_distance.mul_add(spatial_weight, colour_distance);
+
+                    let index = (y * width + x) as usize;
+                    if distance < best_distances[index] {
+                        best_distances[index] = distance;
+                        labels[index] = i;
+                    }
+                }
+            }
+        }
+
+        let mut sums = vec![(Cluster::default(), 0_u32); clusters.len()];
+        for (index, label) in labels.iter().copied().enumerate() {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            let (sum, count) = &mut sums[label];
+            for (total, channel) in sum.colour.iter_mut().zip(colour_at(x, y)) {
+                *total += channel;
+            }
+            sum.x += x as f32;
+            sum.y += y as f32;
+            *count += 1;
+        }
+        for (cluster, (sum, count)) in clusters.iter_mut().zip(&sums) {
+            //a cluster that lost all of its pixels just stays where it was
+            if *count > 0 {
+                let count = *count as f32;
+                *cluster = Cluster {
+                    colour: sum.colour.map(|total| total / count),
+                    x: sum.x / count,
+                    y: sum.y / count,
+                };
+            }
+        }
+
+        progress.tick(Stage::Palette, iteration + 1, SUPERPIXEL_ITERATIONS);
+    }
+
+    let mut sizes = vec![0_u32; clusters.len()];
+    for label in labels {
+        sizes[label] += 1;
+    }
+    let mut candidates: Vec<_> = clusters
+        .into_iter()
+        .zip(sizes)
+        .filter(|(_, size)| *size > 0)
+        .collect();
+    candidates.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+    let closeness_threshold = dist_algo.standardise_closeness_threshold(closeness_threshold);
+    let exclude_threshold = dist_algo.standardise_closeness_threshold(exclude_threshold);
+    let mut palette: Vec<Rgba<u8>> = vec![];
+    for (cluster, _) in candidates {
+        let [r, g, b] = cluster.colour.map(|channel| channel.round() as u8);
+        let colour = Rgba([r, g, b, u8::MAX]);
+
+        let too_close = exclude_colors
+            .iter()
+            .any(|excluded| dist_algo.distance(colour, *excluded) < exclude_threshold)
+            || palette
+                .iter()
+                .any(|so_far| dist_algo.distance(colour, *so_far) < closeness_threshold);
+        if !too_close {
+            palette.push(colour);
+        }
+    }
+
+    palette.extend(extra_colors);
+    dedup_palette(palette).into()
+}
+
+//every colour goes into a 3d histogram, with bins `quantization` wide in each channel. a bin that's more common
+//than all 26 of its neighbours is a peak, and the average colour of each peak's pixels is a candidate.
+//more common peaks get their colours in first, then they go through the same closeness checks as `get_palette`
+pub fn get_palette_histogram_peaks(
+    image: &DynamicImage,
+    quantization: u8,
+    PaletteSettings {
+        closeness_threshold,
+        exclude_colors,
+        exclude_threshold,
+        extra_colors,
+        ..
+    }: PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Palette {
+    let rgb = image.to_rgb8();
+    let quantization = usize::from(quantization.max(1));
+    let bins_per_channel = 256_usize.div_ceil(quantization);
+    let bin_of = |[r, g, b]: [u8; 3]| {
+        let [r, g, b] = [r, g, b].map(|channel| usize::from(channel) / quantization);
+        (r * bins_per_channel + g) * bins_per_channel + b
+    };
+
+    let mut counts = vec![0_u32; bins_per_channel.pow(3)];
+    for px in rgb.pixels() {
+        counts[bin_of(px.0)] += 1;
+    }
+
+    let mut peaks = HashMap::new();
+    for r in 0..bins_per_channel {
+        if stop.is_cancelled() {
+            return dedup_palette(extra_colors).into();
+        }
+
+        for g in 0..bins_per_channel {
+            for b in 0..bins_per_channel {
+                let index = (r * bins_per_channel + g) * bins_per_channel + b;
+                let count = counts[index];
+                if count == 0 {
+                    continue;
+                }
+
+                let is_peak = (-1_isize..=1)
+                    .flat_map(|dr| {
+                        (-1_isize..=1)
+                            .flat_map(move |dg| (-1_isize..=1).map(move |db| [dr, dg, db]))
+                    })
+                    .filter(|offset| *offset != [0, 0, 0])
+                    .all(|offset| {
+                        let mut neighbour = 0;
+                        for (position, offset) in [r, g, b].into_iter().zip(offset) {
+                            match position.checked_add_signed(offset) {
+                                Some(position) if position < bins_per_channel => {
+                                    neighbour = neighbour * bins_per_channel + position;
+                                }
+                                //off the edge of the cube
+                                _ => return true,
+                            }
+                        }
+                        //ties go to the first of the two bins, so a flat top still gets one peak
+                        if neighbour < index {
+                            count > counts[neighbour]
+                        } else {
+                            count >= counts[neighbour]
+                        }
+                    });
+                if is_peak {
+                    peaks.insert(index, ([0_u64; 3], count));
+                }
+            }
+        }
+
+        progress.tick(Stage::Palette, r as u32 + 1, bins_per_channel as u32);
+    }
+
+    //the bins are coarse, so the actual colours that landed in each one get averaged
+    for px in rgb.pixels() {
+        if let Some((sum, _)) = peaks.get_mut(&bin_of(px.0)) {
+            for (total, channel) in sum.iter_mut().zip(px.0) {
+                *total += u64::from(channel);
+            }
+        }
+    }
+    let mut candidates: Vec<_> = peaks.into_values().collect();
+    candidates.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let closeness_threshold = dist_algo.standardise_closeness_threshold(closeness_threshold);
+    let exclude_threshold = dist_algo.standardise_closeness_threshold(exclude_threshold);
+    let mut palette: Vec<Rgba<u8>> = vec![];
+    for (sum, count) in candidates {
+        let [r, g, b] = sum.map(|total| (total / u64::from(count)) as u8);
+        let colour = Rgba([r, g, b, u8::MAX]);
+
+        let too_close = exclude_colors
+            .iter()
+            .any(|excluded| dist_algo.distance(colour, *excluded) < exclude_threshold)
+            || palette
+                .iter()
+                .any(|so_far| dist_algo.distance(colour, *so_far) < closeness_threshold);
+        if !too_close {
+            palette.push(colour);
+        }
+    }
+
+    palette.extend(extra_colors);
+    dedup_palette(palette).into()
+}
+
+//each palette is merged in order, leaving out any colours too close to (or the same as) one that's already in
+pub fn merge_palettes(
+    palettes: impl IntoIterator<Item = Palette>,
+    closeness_threshold: u32,
+    algo: DistanceAlgorithm,
+) -> Palette {
+    let closeness_threshold = algo.standardise_closeness_threshold(closeness_threshold);
+
+    let mut merged: Vec<Rgba<u8>> = vec![];
+    for palette in palettes {
+        for colour in palette {
+            if !merged.iter().any(|so_far| {
+                *so_far == colour || algo.distance(colour, *so_far) < closeness_threshold
+            }) {
+                merged.push(colour);
+            }
+        }
+    }
+
+    merged.into()
+}
+
+//palettes from downscaled copies of the image pick up its broad colours, and the full size one picks up the details.
+//coarser scales usually want a lower closeness threshold, so that the broad colours aren't all merged into one.
+//the scales are paired up with the settings, and anything without a pair is ignored
+pub fn get_palette_multiscale(
+    image: &DynamicImage,
+    scales: &[f32],
+    settings_per_scale: &[PaletteSettings],
+    algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<Palette, PxlsError> {
+    let total = scales.len().min(settings_per_scale.len()) as u32;
+    //the merge shouldn't throw away anything that one of the scales thought was different enough
+    let merge_threshold = settings_per_scale
+        .iter()
+        .map(|settings| settings.closeness_threshold)
+        .min()
+        .unwrap_or_default();
+
+    let mut palettes = vec![];
+    for (i, (scale, settings)) in scales.iter().zip(settings_per_scale).enumerate() {
+        let palette = if *scale >= 1.0 {
+            get_palette(image, settings.clone(), algo, &NoProgress, stop)?
+        } else {
+            let scaled = image.resize_exact(
+                ((image.width() as f32 * scale) as u32).max(1),
+                ((image.height() as f32 * scale) as u32).max(1),
+                image::imageops::FilterType::Triangle,
+            );
+            get_palette(&scaled, settings.clone(), algo, &NoProgress, stop)?
+        };
+        palettes.push(palette);
+
+        progress.tick(Stage::Palette, i as u32 + 1, total);
+    }
+
+    Ok(merge_palettes(palettes, merge_threshold, algo))
+}
+
+//keeps the first of each colour, so the order of the palette doesn't change
+pub fn dedup_palette(palette: Vec<Rgba<u8>>) -> Vec<Rgba<u8>> {
+    let mut seen = HashSet::with_capacity(palette.len());
+    palette.into_iter().filter(|px| seen.insert(*px)).collect()
+}
+
+//palettes are small enough that checking every pair is quicker than hashing them all
+pub fn palette_has_duplicates(palette: &[Rgba<u8>]) -> bool {
+    palette
+        .iter()
+        .enumerate()
+        .any(|(i, px)| palette[(i + 1)..].contains(px))
+}
+
+//colours this close (in squared euclidean distance) are too similar to both be seeds
+const HISTOGRAM_SEED_MIN_DISTANCE: u32 = 32 * 32;
+
+//seeds for clustering: the most common colours that aren't too close together, topped up with whichever colours are furthest from the seeds so far
+pub fn initialize_palette_from_histogram(
+    image: &DynamicImage,
+    k: usize,
+    sample_every: u32,
+) -> Palette {
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    for (_, _, px) in image.pixels().step_by(sample_every.max(1) as usize) {
+        *histogram.entry(px.to_rgb().0).or_default() += 1;
+    }
+
+    let mut by_frequency: Vec<_> = histogram.into_iter().collect();
+    //ties broken by colour so that the result doesn't depend on the hashmap's order
+    by_frequency.sort_unstable_by(|(a_colour, a_count), (b_colour, b_count)| {
+        b_count.cmp(a_count).then(a_colour.cmp(b_colour))
+    });
+
+    let to_rgba = |[r, g, b]: [u8; 3]| Rgba([r, g, b, u8::MAX]);
+    let distance =
+        |a: [u8; 3], b: [u8; 3]| DistanceAlgorithm::Euclidean.distance(to_rgba(a), to_rgba(b));
+
+    let mut seeds: Vec<[u8; 3]> = Vec::with_capacity(k);
+    //the distance from each histogram entry to its closest seed
+    let mut closest_seed = vec![u32::MAX; by_frequency.len()];
+    let add_seed = |seeds: &mut Vec<[u8; 3]>, closest_seed: &mut [u32], seed: [u8; 3]| {
+        seeds.push(seed);
+        for ((colour, _), closest) in by_frequency.iter().zip(closest_seed.iter_mut()) {
+            *closest = (*closest).min(distance(*colour, seed));
+        }
+    };
+
+    for i in 0..by_frequency.len() {
+        if seeds.len() >= k {
+            break;
+        }
+        if closest_seed[i] > HISTOGRAM_SEED_MIN_DISTANCE {
+            add_seed(&mut seeds, &mut closest_seed, by_frequency[i].0);
+        }
+    }
+
+    while seeds.len() < k {
+        let Some((furthest, _)) = closest_seed
+            .iter()
+            .enumerate()
+            .filter(|(_, closest)| **closest > 0)
+            .max_by_key(|(_, closest)| **closest)
+        else {
+            break;
+        };
+        add_seed(&mut seeds, &mut closest_seed, by_frequency[furthest].0);
+    }
+
+    seeds.into_iter().map(to_rgba).collect()
+}
+
+pub fn subdivide_palette(
+    image: &DynamicImage,
+    palette: Palette,
+    max_extra_colors: usize,
+    settings: PaletteSettings,
+    algo: DistanceAlgorithm,
+) -> Palette {
+    #[derive(Copy, Clone)]
+    struct Region {
+        total_error: u64,
+        count: u64,
+        min: (u32, u32),
+        max: (u32, u32),
+    }
+
+    if palette.is_empty() {
+        return palette;
+    }
+    let mut palette: Vec<_> = palette.into();
+
+    let error_threshold = algo.standardise_closeness_threshold(settings.closeness_threshold) as u64;
+    let tighter_settings = PaletteSettings {
+        closeness_threshold: settings.closeness_threshold / 2,
+        ..settings.clone()
+    };
+    let tighter_threshold =
+        algo.standardise_closeness_threshold(tighter_settings.closeness_threshold);
+
+    let stop = CancellationToken::new();
+
+    let mut extra_colors_added = 0;
+    //entries that we've already tried to subdivide but that didn't give us anything new
+    let mut exhausted = vec![false; palette.len()];
+
+    while extra_colors_added < max_extra_colors {
+        let mut regions: Vec<Option<Region>> = vec![None; palette.len()];
+
+        for (x, y, px) in image.pixels() {
+            let Some((closest_index, distance)) = palette
+                .iter()
+                .map(|candidate| algo.distance(px, *candidate))
+                .enumerate()
+                .min_by_key(|(_, distance)| *distance)
+            else {
+                continue;
+            };
+
+            let region = regions[closest_index].get_or_insert(Region {
+                total_error: 0,
+                count: 0,
+                min: (x, y),
+                max: (x, y),
+            });
+            region.total_error += distance as u64;
+            region.count += 1;
+            region.min = (region.min.0.min(x), region.min.1.min(y));
+            region.max = (region.max.0.max(x), region.max.1.max(y));
+        }
+
+        let Some((worst_index, worst_region)) = regions
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !exhausted[*i])
+            .filter_map(|(i, region)| region.map(|region| (i, region)))
+            .max_by_key(|(_, region)| region.total_error)
+        else {
+            break;
+        };
+
+        //mean error is a fairer comparison than the total, as otherwise a big region will always look bad
+        if worst_region.total_error / worst_region.count < error_threshold {
+            break;
+        }
+
+        let sub_image = image.crop_imm(
+            worst_region.min.0,
+            worst_region.min.1,
+            worst_region.max.0 - worst_region.min.0 + 1,
+            worst_region.max.1 - worst_region.min.1 + 1,
+        );
+        //the only way for this to fail is being stopped
+        let Ok(sub_palette) = get_palette(
+            &sub_image,
+            tighter_settings.clone(),
+            algo,
+            &NoProgress,
+            &stop,
+        ) else {
+            break;
+        };
+
+        let mut found_new = false;
+        for candidate in sub_palette {
+            if extra_colors_added >= max_extra_colors {
+                break;
+            }
+
+            if palette
+                .iter()
+                .all(|existing| algo.distance(candidate, *existing) >= tighter_threshold)
+            {
+                palette.push(candidate);
+                exhausted.push(false);
+                extra_colors_added += 1;
+                found_new = true;
+            }
+        }
+
+        if !found_new {
+            exhausted[worst_index] = true;
+        }
+    }
+
+    palette.into()
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    name = "dither_original_with_palette",
+    skip_all,
+    fields(
+        width = input.width(),
+        height = input.height(),
+        palette_len = palette.as_ref().len(),
+        algorithm = ?distance_algorithm,
+        dither_mode = ?output_settings.dither_mode,
+        chunks = tracing::field::Empty,
+    ),
+))]
+pub fn dither_original_with_stats(
+    input: &DynamicImage,
+    palette: impl AsRef<[Rgba<u8>]>,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(DynamicImage, Stats), PxlsError> {
+    let started = Instant::now();
+    let palette_size = palette.as_ref().len();
+    let output = dither_source_with_palette(
+        input,
+        palette,
+        distance_algorithm,
+        output_settings,
+        progress,
+        stop,
+    )?;
+
+    //it worked, so the settings are fine to work the chunks out from. every pixel of every chunk gets averaged
+    let output_px_size = dither_chunk_size(input.dimensions(), output_settings)?;
+    let chunks_processed =
+        u64::from(input.width() / output_px_size) * u64::from(input.height() / output_px_size);
+    let stats = Stats {
+        duration: started.elapsed(),
+        chunks_processed,
+        pixels_sampled: chunks_processed * u64::from(output_px_size) * u64::from(output_px_size),
+        cache_hit_rate: None,
+        palette_size,
+    };
+    Ok((output, stats))
+}
+
+/// Redraws the image in only the colours of the palette, shrunk down to virtual pixels and then scaled back up if the
+/// output settings ask for it.
+///
+/// # Examples
+///
+/// ```
+/// use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+/// use pxls::{
+///     cancellation::CancellationToken, dither_original_with_palette, progress::NoProgress,
+///     DistanceAlgorithm, OutputSettings,
+/// };
+///
+/// let gradient = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, _| {
+///     let grey = (x * 16) as u8;
+///     Rgba([grey, grey, grey, 255])
+/// }));
+/// let palette = [Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])];
+///
+/// let output = dither_original_with_palette(
+///     &gradient,
+///     palette,
+///     DistanceAlgorithm::Euclidean,
+///     OutputSettings::default(),
+///     &NoProgress,
+///     &CancellationToken::new(),
+/// )?;
+/// assert_eq!(output.dimensions(), gradient.dimensions());
+/// assert!(output.pixels().all(|(_, _, px)| palette.contains(&px)));
+/// # Ok::<(), pxls::PxlsError>(())
+/// ```
+pub fn dither_original_with_palette(
+    input: &DynamicImage,
+    palette: impl AsRef<[Rgba<u8>]>,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<DynamicImage, PxlsError> {
+    dither_original_with_stats(
+        input,
+        palette,
+        distance_algorithm,
+        output_settings,
+        progress,
+        stop,
+    )
+    .map(|(output, _)| output)
+}
+
+pub fn dither_source_with_palette(
+    input: &impl PixelSource,
+    palette: impl AsRef<[Rgba<u8>]>,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<DynamicImage, PxlsError> {
+    let mut output = RgbaImage::new(0, 0);
+    dither_into(
+        input,
+        palette,
+        distance_algorithm,
+        output_settings,
+        &mut output,
+        progress,
+        stop,
+    )?;
+    Ok(DynamicImage::ImageRgb8(
+        DynamicImage::ImageRgba8(output).into_rgb8(),
+    ))
+}
+
+//dithers straight into `out`, which ends up `predicted_output_size` big. its allocation gets reused, and only grows
+//if the new size needs more room, so calling this again and again (eg. for each frame of a video) doesn't allocate
+//a fresh output each time. the output is always opaque
+pub fn dither_into(
+    input: &impl PixelSource,
+    palette: impl AsRef<[Rgba<u8>]>,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    out: &mut RgbaImage,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(), PxlsError> {
+    let output_settings = output_settings.validated()?;
+    let palette = palette.as_ref();
+    if palette.is_empty() {
+        return Err(PxlsError::EmptyPalette);
+    }
+    check_not_empty(input)?;
+    let output_px_size = dither_chunk_size(input.dimensions(), output_settings)?;
+
+    dither_chunks_into(
+        input,
+        palette,
+        distance_algorithm,
+        output_settings,
+        output_px_size,
+        out,
+        progress,
+        stop,
+    )
+}
+
+//how many input pixels wide and high each chunk of the dither is
+fn dither_chunk_size(
+    (width, height): (u32, u32),
+    output_settings: OutputSettings,
+) -> Result<u32, PxlsError> {
+    let output_px_size = get_closest_factor(1 << (output_settings.output_px_size - 1), width);
+    //the closest factor of the width might still not fit in the height, which would make an empty output
+    let smallest_dimension = width.min(height);
+    if smallest_dimension < output_px_size.max(1) {
+        return Err(PxlsError::ImageTooSmall {
+            needed: output_px_size.max(1),
+            got: smallest_dimension,
+        });
+    }
+    Ok(output_px_size)
+}
+
+//`dither_into` once the settings have been checked and the chunk size worked out, so that it can be kept the same
+//for pieces of a bigger image
+#[allow(clippy::too_many_arguments)]
+fn dither_chunks_into(
+    input: &impl PixelSource,
+    palette: &[Rgba<u8>],
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    output_px_size: u32,
+    out: &mut RgbaImage,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(), PxlsError> {
+    record_in_span(
+        "chunks",
+        u64::from(input.width() / output_px_size) * u64::from(input.height() / output_px_size),
+    );
+
+    let (grid_w, grid_h) = (
+        input.width() / output_px_size * output_settings.effective_dithering_scale(),
+        input.height() / output_px_size * output_settings.effective_dithering_scale(),
+    );
+    let factor = output_scale_factor(output_settings);
+    if output_settings.scale_output_to_original {
+        check_scale(grid_w, grid_h, factor)?;
+    }
+
+    //every pixel is about to be written over anyway
+    resize_keeping_buffer(out, grid_w * factor, grid_h * factor);
+
+    let dither = |output: &mut BlockWriter| {
+        if output_settings.dither_mode == DitherMode::Legacy {
+            dither_legacy(
+                input,
+                palette,
+                distance_algorithm,
+                output_settings,
+                output_px_size,
+                output,
+                progress,
+                stop,
+            )
+        } else {
+            dither_chunk_grid(
+                input,
+                palette,
+                distance_algorithm,
+                output_settings,
+                output_px_size,
+                output,
+                progress,
+                stop,
+            )
+        }
+    };
+
+    //sharpening needs the whole unscaled output at once, so only then does it go through a smaller image first
+    if output_settings.post_sharpen > 0.0 {
+        let mut grid = RgbaImage::new(grid_w, grid_h);
+        dither(&mut BlockWriter {
+            out: &mut grid,
+            factor: 1,
+        })?;
+
+        let mut output = BlockWriter { out, factor };
+        for (x, y, px) in
+            post_sharpen(DynamicImage::ImageRgba8(grid), output_settings.post_sharpen).pixels()
+        {
+            output.put(x, y, px);
+        }
+        Ok(())
+    } else {
+        dither(&mut BlockWriter { out, factor })
+    }
+}
+
+//reuses the image's allocation if it's big enough, but leaves its pixels in a mess
+fn resize_keeping_buffer(image: &mut RgbaImage, width: u32, height: u32) {
+    if image.dimensions() != (width, height) {
+        let mut buffer = std::mem::take(image).into_raw();
+        buffer.resize(width as usize * height as usize * 4, 0);
+        *image =
+            RgbaImage::from_raw(width, height, buffer).expect("the buffer was just resized to fit");
+    }
+}
+
+//where the dither writes its output, with each pixel becoming a `factor` by `factor` block
+struct BlockWriter<'o> {
+    out: &'o mut RgbaImage,
+    factor: u32,
+}
+
+impl BlockWriter<'_> {
+    fn put(&mut self, x: u32, y: u32, Rgba([r, g, b, _]): Rgba<u8>) {
+        let px = Rgba([r, g, b, u8::MAX]);
+        for px_y in (self.factor * y)..(self.factor * (y + 1)) {
+            for px_x in (self.factor * x)..(self.factor * (x + 1)) {
+                self.out.put_pixel(px_x, px_y, px);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn dither_legacy(
+    input: &impl PixelSource,
+    palette: &[Rgba<u8>],
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    output_px_size: u32,
+    output: &mut BlockWriter,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(), PxlsError> {
+    let (width, height) = input.dimensions();
+    let (num_width_chunks, num_height_chunks) = (width / output_px_size, height / output_px_size);
+
+    let total_chunks = num_width_chunks * num_height_chunks;
+    let mut chunks_progress_bar = 0;
+
+    for chunk_x in 0..num_width_chunks {
+        for chunk_y in 0..num_height_chunks {
+            if stop.is_cancelled() {
+                progress.report(Stage::Dithering, chunks_progress_bar, total_chunks);
+                return Err(PxlsError::Cancelled);
+            }
+
+            let candidate = dither_candidate(
+                input,
+                palette,
+                output_px_size,
+                (chunk_x, chunk_y),
+                distance_algorithm,
+                output_settings,
+            );
+            let first = candidate.first;
+            let second = if candidate.using_dither {
+                candidate.second
+            } else {
+                first
+            };
+
+            for px_x in (output_settings.dithering_scale * chunk_x)
+                ..(output_settings.dithering_scale * (chunk_x + 1))
+            {
+                for px_y in (output_settings.dithering_scale * chunk_y)
+                    ..(output_settings.dithering_scale * (chunk_y + 1))
+                {
+                    let mut is_even_px = px_y % 2 == 0;
+                    if px_x % 2 == 0 {
+                        is_even_px = !is_even_px;
+                    }
+                    is_even_px &= output_settings.dithering_scale > 1;
+
+                    output.put(px_x, px_y, if is_even_px { first } else { second });
+                }
+            }
+
+            chunks_progress_bar += 1;
+            progress.tick(Stage::Dithering, chunks_progress_bar, total_chunks);
+        }
+    }
+
+    Ok(())
+}
+
+//the two closest palette colours to a chunk, for working out how to fill it in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DitherCandidate {
+    pub chunk_x: u32,
+    pub chunk_y: u32,
+    pub first: Rgba<u8>,
+    pub second: Rgba<u8>,
+    pub first_distance: u32,
+    pub second_distance: u32,
+    //whether the dithering mode thinks the chunk should be a mix of the two, rather than just `first`
+    pub using_dither: bool,
+}
+
+//one per chunk, column by column, so that other ways of dithering can reuse the palette lookup.
+//an empty palette or invalid settings have no candidates
+pub fn compute_dither_candidates(
+    image: &impl PixelSource,
+    palette: &[Rgba<u8>],
+    output_px_size: u32,
+    algo: DistanceAlgorithm,
+    output_settings: OutputSettings,
+) -> Vec<DitherCandidate> {
+    let Ok(output_settings) = output_settings.validated() else {
+        return vec![];
+    };
+    if palette.is_empty() {
+        return vec![];
+    }
+    let output_px_size = output_px_size.max(1);
+
+    let (num_width_chunks, num_height_chunks) = (
+        image.width() / output_px_size,
+        image.height() / output_px_size,
+    );
+    (0..num_width_chunks)
+        .flat_map(|chunk_x| (0..num_height_chunks).map(move |chunk_y| (chunk_x, chunk_y)))
+        .map(|chunk| dither_candidate(image, palette, output_px_size, chunk, algo, output_settings))
+        .collect()
+}
+
+//the index of the closest colour in the palette and how far away it is, then the same for the second closest, which
+//is only `None` for a palette with one colour.
+//on a tie, the earliest colour wins, and a tie for first puts the later colour second.
+//panics on an empty palette
+pub fn nearest_two(
+    palette: &[Rgba<u8>],
+    target: Rgba<u8>,
+    algo: DistanceAlgorithm,
+) -> (usize, u32, Option<(usize, u32)>) {
+    let mut first: Option<(usize, u32)> = None;
+    let mut second: Option<(usize, u32)> = None;
+
+    for (i, colour) in palette.iter().enumerate() {
+        let dist = algo.distance(*colour, target);
+
+        if first.is_none_or(|(_, first_dist)| dist < first_dist) {
+            second = first;
+            first = Some((i, dist));
+        } else if second.is_none_or(|(_, second_dist)| dist < second_dist) {
+            second = Some((i, dist));
+        }
+    }
+
+    let (first, first_distance) = first.expect("the palette shouldn't be empty");
+    (first, first_distance, second)
+}
+
+fn dither_candidate(
+    input: &impl PixelSource,
+    palette: &[Rgba<u8>],
+    output_px_size: u32,
+    (chunk_x, chunk_y): (u32, u32),
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+) -> DitherCandidate {
+    let [r, g, b] = chunk_average(input, output_px_size, chunk_x, chunk_y);
+    let av_px = Rgba([r as u8, g as u8, b as u8, u8::MAX]);
+
+    let (first, first_distance, second) = nearest_two(palette, av_px, distance_algorithm);
+    let (second, second_distance) = second.unwrap_or((first, u32::MAX));
+    let (first, second) = (palette[first], palette[second]);
+
+    DitherCandidate {
+        chunk_x,
+        chunk_y,
+        first,
+        second,
+        first_distance,
+        second_distance,
+        using_dither: output_settings.dithering_mode.should_dither(
+            first_distance,
+            second_distance,
+            distance_algorithm.distance(first, second),
+        ),
+    }
+}
+
+fn chunk_average(
+    input: &impl PixelSource,
+    output_px_size: u32,
+    chunk_x: u32,
+    chunk_y: u32,
+) -> [i32; 3] {
+    let (mut accum_r, mut accum_g, mut accum_b) = (0_u64, 0_u64, 0_u64);
+
+    //summed at 16 bits and only brought down to 8 at the end, so 16-bit inputs keep their precision until then.
+    //for 8-bit inputs this comes out exactly the same as averaging them directly
+    for px_x in (output_px_size * chunk_x)..(output_px_size * (chunk_x + 1)) {
+        for px_y in (output_px_size * chunk_y)..(output_px_size * (chunk_y + 1)) {
+            let [r, g, b] = input.get_pixel_wide(px_x, px_y);
+            accum_r += r as u64;
+            accum_g += g as u64;
+            accum_b += b as u64;
+        }
+    }
+
+    let divisor = (output_px_size * output_px_size) as u64;
+    [
+        (accum_r / divisor / 257) as i32,
+        (accum_g / divisor / 257) as i32,
+        (accum_b / divisor / 257) as i32,
+    ]
+}
+
+//how many input pixels wide each chunk is and how many output pixels wide it gets dithered into, or `None` if
+//dithering with these settings would fail
+fn chunk_size_and_scale(
+    dimensions: (u32, u32),
+    output_settings: OutputSettings,
+) -> Option<(u32, u32)> {
+    let output_settings = output_settings.validated().ok()?;
+    let output_px_size = dither_chunk_size(dimensions, output_settings).ok()?;
+    Some((output_px_size, output_settings.effective_dithering_scale()))
+}
+
+//the average colour of the input chunk that became the given pixel of an unscaled output
+pub fn source_chunk_colour(
+    input: &DynamicImage,
+    output_settings: OutputSettings,
+    output_x: u32,
+    output_y: u32,
+) -> Option<Rgba<u8>> {
+    let (output_px_size, scale) = chunk_size_and_scale(input.dimensions(), output_settings)?;
+    let (chunk_x, chunk_y) = (output_x / scale, output_y / scale);
+
+    if (chunk_x + 1) * output_px_size > input.width()
+        || (chunk_y + 1) * output_px_size > input.height()
+    {
+        return None;
+    }
+
+    Some(chunk_colour(input, output_px_size, chunk_x, chunk_y))
+}
+
+fn chunk_colour(input: &DynamicImage, output_px_size: u32, chunk_x: u32, chunk_y: u32) -> Rgba<u8> {
+    let [r, g, b] = chunk_average(input, output_px_size, chunk_x, chunk_y);
+    Rgba([r as u8, g as u8, b as u8, u8::MAX])
+}
+
+//blue for 0.0 through cyan, green and yellow to red for 1.0
+pub fn heatmap_colour(t: f32) -> Rgba<u8> {
+    let h = (1.0 - t.clamp(0.0, 1.0)) * 4.0;
+    let (r, g, b) = if h < 1.0 {
+        (1.0, h, 0.0)
+    } else if h < 2.0 {
+        (2.0 - h, 1.0, 0.0)
+    } else if h < 3.0 {
+        (0.0, 1.0, h - 2.0)
+    } else {
+        (0.0, 4.0 - h, 1.0)
+    };
+
+    Rgba([
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8,
+        u8::MAX,
+    ])
+}
+
+//shows how far each pixel of an unscaled output is from the input chunk it came from, along with the biggest distance (which is what red means)
+pub fn difference_heatmap(
+    input: &DynamicImage,
+    output: &DynamicImage,
+    output_settings: OutputSettings,
+    distance_algorithm: DistanceAlgorithm,
+) -> (DynamicImage, u32) {
+    //each chunk only gets averaged once, however many pixels of the output it was dithered into
+    let (chunks_wide, scale, sources) =
+        match chunk_size_and_scale(input.dimensions(), output_settings) {
+            Some((output_px_size, scale)) => {
+                let (chunks_wide, chunks_high) = (
+                    input.width() / output_px_size,
+                    input.height() / output_px_size,
+                );
+                let sources: Vec<_> = (0..chunks_high)
+                    .flat_map(|chunk_y| (0..chunks_wide).map(move |chunk_x| (chunk_x, chunk_y)))
+                    .map(|(chunk_x, chunk_y)| chunk_colour(input, output_px_size, chunk_x, chunk_y))
+                    .collect();
+                (chunks_wide, scale, sources)
+            }
+            None => (0, 1, vec![]),
+        };
+
+    let distances: Vec<_> = output
+        .pixels()
+        .map(|(x, y, px)| {
+            let (chunk_x, chunk_y) = (x / scale, y / scale);
+            if chunk_x >= chunks_wide {
+                return 0;
+            }
+            sources
+                .get(chunk_y as usize * chunks_wide as usize + chunk_x as usize)
+                .map_or(0, |source| distance_algorithm.distance(px, *source))
+        })
+        .collect();
+    let max_distance = distances.iter().copied().max().unwrap_or(0).max(1);
+
+    let mut heatmap = DynamicImage::new(output.width(), output.height(), ColorType::Rgb8);
+    for ((x, y, _), distance) in output.pixels().zip(distances) {
+        heatmap.put_pixel(x, y, heatmap_colour(distance as f32 / max_distance as f32));
+    }
+
+    (heatmap, max_distance)
+}
+
+//which pixels differ between two outputs, in row-major order, or `None` if they aren't the same size
+pub fn changed_pixels(before: &DynamicImage, after: &DynamicImage) -> Option<Vec<bool>> {
+    if before.dimensions() != after.dimensions() {
+        return None;
+    }
+
+    Some(
+        before
+            .pixels()
+            .zip(after.pixels())
+            .map(|((_, _, before), (_, _, after))| before != after)
+            .collect(),
+    )
+}
+
+//how many pixels of the output each palette colour ended up being used for, in palette order
+pub fn palette_usage(output: &DynamicImage, palette: &[Rgba<u8>]) -> Vec<u32> {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for (_, _, px) in output.pixels() {
+        *counts.entry(px.to_rgb().0).or_default() += 1;
+    }
+
+    palette
+        .iter()
+        .map(|colour| counts.get(&colour.to_rgb().0).copied().unwrap_or(0))
+        .collect()
+}
+
+//how far, on average, the pixels closest to each palette colour are from it - the worst represented colours come first.
+//colours that no pixels are closest to have an error of 0
+pub fn palette_color_error(
+    image: &DynamicImage,
+    palette: &[Rgba<u8>],
+    algo: DistanceAlgorithm,
+) -> Vec<(Rgba<u8>, f32)> {
+    let mut totals = vec![(0.0_f64, 0_u64); palette.len()];
+    for (_, _, px) in image.pixels() {
+        let Some((closest_index, distance)) = palette
+            .iter()
+            .map(|candidate| algo.distance(px, *candidate))
+            .enumerate()
+            .min_by_key(|(_, distance)| *distance)
+        else {
+            break;
+        };
+
+        //squared distances would make a few far-off pixels dominate the mean
+        let distance = match algo {
+            DistanceAlgorithm::Euclidean | DistanceAlgorithm::HSVEuclidean => {
+                f64::from(distance).sqrt()
+            }
+            _ => f64::from(distance),
+        };
+        totals[closest_index].0 += distance;
+        totals[closest_index].1 += 1;
+    }
+
+    let mut errors: Vec<_> = palette
+        .iter()
+        .zip(totals)
+        .map(|(colour, (total, count))| {
+            let mean = if count == 0 {
+                0.0
+            } else {
+                total / count as f64
+            };
+            (*colour, mean as f32)
+        })
+        .collect();
+    errors.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    errors
+}
+
+const DOMINANT_COLOURS: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct ImageAnalysis {
+    pub r_histogram: [u32; 256],
+    pub g_histogram: [u32; 256],
+    pub b_histogram: [u32; 256],
+    pub mean_color: Rgba<u8>,
+    //the median of each channel on its own, so it might not be a colour that's in the image
+    pub median_color: Rgba<u8>,
+    //the most common exact colours, with the percentage of pixels that are each one, most common first
+    pub dominant_colors: Vec<(Rgba<u8>, f32)>,
+}
+
+//alpha is ignored throughout, so the colours are all opaque
+pub fn analyze_image(image: &DynamicImage) -> ImageAnalysis {
+    let mut histograms = [[0_u32; 256]; 3];
+    let mut totals = [0_u64; 3];
+    let mut occurrences: HashMap<[u8; 3], u32> = HashMap::new();
+    for px in image.to_rgb8().pixels() {
+        for ((histogram, total), channel) in histograms.iter_mut().zip(&mut totals).zip(px.0) {
+            histogram[usize::from(channel)] += 1;
+            *total += u64::from(channel);
+        }
+        *occurrences.entry(px.0).or_default() += 1;
+    }
+
+    let pixels = u64::from(image.width()) * u64::from(image.height());
+    let [r, g, b] = totals.map(|total| total.checked_div(pixels).unwrap_or(0) as u8);
+    let mean_color = Rgba([r, g, b, u8::MAX]);
+
+    let [r, g, b] = histograms.map(|histogram| {
+        let mut seen = 0;
+        histogram
+            .iter()
+            .position(|count| {
+                seen += u64::from(*count);
+                seen * 2 > pixels
+            })
+            .unwrap_or(0) as u8
+    });
+    let median_color = Rgba([r, g, b, u8::MAX]);
+
+    let mut dominant_colors: Vec<_> = occurrences.into_iter().collect();
+    //ties are broken by colour so the same image always gives the same answer
+    dominant_colors.sort_unstable_by_key(|(colour, count)| (std::cmp::Reverse(*count), *colour));
+    dominant_colors.truncate(DOMINANT_COLOURS);
+    let dominant_colors = dominant_colors
+        .into_iter()
+        .map(|([r, g, b], count)| {
+            (
+                Rgba([r, g, b, u8::MAX]),
+                (f64::from(count) * 100.0 / pixels as f64) as f32,
+            )
+        })
+        .collect();
+
+    let [r_histogram, g_histogram, b_histogram] = histograms;
+    ImageAnalysis {
+        r_histogram,
+        g_histogram,
+        b_histogram,
+        mean_color,
+        median_color,
+        dominant_colors,
+    }
+}
+
+//256 buckets each, for seeing how the input's spread out before picking adjustments and thresholds
+#[derive(Clone, Debug)]
+pub struct InputHistograms {
+    pub channels: [[u32; 256]; 3],
+    //bucketed by the square root of `luminance`, so it's in the same units as the luminance closeness threshold
+    pub luminance: [u32; 256],
+    //HSV value, ie. the brightest channel
+    pub value: [u32; 256],
+}
+
+pub fn input_histograms(image: &DynamicImage) -> InputHistograms {
+    let mut histograms = InputHistograms {
+        channels: [[0; 256]; 3],
+        luminance: [0; 256],
+        value: [0; 256],
+    };
+    for px in image.to_rgba8().pixels() {
+        for (histogram, channel) in histograms.channels.iter_mut().zip(px.0) {
+            histogram[usize::from(channel)] += 1;
+        }
+        histograms.luminance[luminance(*px).isqrt() as usize] += 1;
+        histograms.value[usize::from(px.0[..3].iter().copied().max().unwrap_or(0))] += 1;
+    }
+    histograms
+}
+
+const SSIM_WINDOW: u32 = 8;
+
+//mean SSIM over non-overlapping windows of luma, with the (smaller) output stretched over the input
+// https://en.wikipedia.org/wiki/Structural_similarity_index_measure
+pub fn ssim(input: &DynamicImage, output: &DynamicImage) -> f32 {
+    const C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    if output.width() == 0 || output.height() == 0 {
+        return 0.0;
+    }
+
+    let luma = |Rgba([r, g, b, _]): Rgba<u8>| {
+        0.114f32.mul_add(
+            f32::from(b),
+            0.299f32.mul_add(f32::from(r), 0.587 * f32::from(g)),
+        )
+    };
+    let output_px = |x: u32, y: u32| {
+        let output_x = (u64::from(x) * u64::from(output.width()) / u64::from(input.width())) as u32;
+        let output_y =
+            (u64::from(y) * u64::from(output.height()) / u64::from(input.height())) as u32;
+        output.get_pixel(
+            output_x.min(output.width() - 1),
+            output_y.min(output.height() - 1),
+        )
+    };
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for window_y in (0..input.height()).step_by(SSIM_WINDOW as usize) {
+        for window_x in (0..input.width()).step_by(SSIM_WINDOW as usize) {
+            let pairs: Vec<(f32, f32)> = (window_y..(window_y + SSIM_WINDOW).min(input.height()))
+                .flat_map(|y| {
+                    (window_x..(window_x + SSIM_WINDOW).min(input.width())).map(move |x| (x, y))
+                })
+                .map(|(x, y)| (luma(input.get_pixel(x, y)), luma(output_px(x, y))))
+                .collect();
+            let n = pairs.len() as f32;
+
+            let mean_a = pairs.iter().map(|(a, _)| a).sum::<f32>() / n;
+            let mean_b = pairs.iter().map(|(_, b)| b).sum::<f32>() / n;
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (a, b) in &pairs {
+                var_a += (a - mean_a).powi(2) / n;
+                var_b += (b - mean_b).powi(2) / n;
+                covariance += (a - mean_a) * (b - mean_b) / n;
+            }
+
+            total += (2.0f32.mul_add(mean_a * mean_b, C1) * 2.0f32.mul_add(covariance, C2))
+                / ((mean_a.mul_add(mean_a, mean_b * mean_b) + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        0.0
+    } else {
+        total / windows as f32
+    }
+}
+
+//everything apart from legacy dithering makes one output pixel per chunk
+#[allow(clippy::too_many_arguments)]
+fn dither_chunk_grid(
+    input: &impl PixelSource,
+    palette: &[Rgba<u8>],
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    output_px_size: u32,
+    output: &mut BlockWriter,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(), PxlsError> {
+    let (num_width_chunks, num_height_chunks) = (
+        input.width() / output_px_size,
+        input.height() / output_px_size,
+    );
+
+    let total_chunks = num_width_chunks * num_height_chunks;
+    let mut chunks_progress_bar = 0;
+
+    //the floyd-steinberg error waiting to be added to each chunk
+    let mut errors = vec![[0_i32; 3]; total_chunks as usize];
+    let strength = output_settings.dither_mode.strength().unwrap_or(0) as f32;
+
+    //row-by-row so that the error only ever gets pushed forwards
+    for chunk_y in 0..num_height_chunks {
+        let right_to_left = match output_settings.dither_mode {
+            DitherMode::FloydSteinberg { direction } => direction.is_right_to_left(chunk_y),
+            _ => false,
+        };
+        //the kernel gets mirrored on right-to-left rows, so "forwards" is whichever way we're going
+        let forwards = if right_to_left { -1 } else { 1 };
+
+        for step in 0..num_width_chunks {
+            let chunk_x = if right_to_left {
+                num_width_chunks - 1 - step
+            } else {
+                step
+            };
+            if stop.is_cancelled() {
+                progress.report(Stage::Dithering, chunks_progress_bar, total_chunks);
+                return Err(PxlsError::Cancelled);
+            }
+
+            let index = (chunk_y * num_width_chunks + chunk_x) as usize;
+            let mut target = chunk_average(input, output_px_size, chunk_x, chunk_y);
+            match output_settings.dither_mode {
+                DitherMode::FloydSteinberg { .. } => {
+                    for (channel, error) in target.iter_mut().zip(errors[index]) {
+                        *channel += error;
+                    }
+                }
+                DitherMode::Bayer { .. }
+                | DitherMode::BlueNoise { .. }
+                | DitherMode::Random { .. } => {
+                    //at full strength, the offset can move a channel by half of its range
+                    let offset = (output_settings.dither_mode.threshold(chunk_x, chunk_y)
+                        * strength
+                        * 2.55) as i32;
+                    for channel in &mut target {
+                        *channel += offset;
+                    }
+                }
+                DitherMode::None | DitherMode::Legacy => {}
+            }
+
+            let target = target.map(|channel| channel.clamp(0, 255));
+            let target_px = Rgba([target[0] as u8, target[1] as u8, target[2] as u8, u8::MAX]);
+            let Some(chosen) = palette
+                .iter()
+                .copied()
+                .min_by_key(|candidate| distance_algorithm.distance(*candidate, target_px))
+            else {
+                return Err(PxlsError::EmptyPalette);
+            };
+
+            if matches!(
+                output_settings.dither_mode,
+                DitherMode::FloydSteinberg { .. }
+            ) {
+                let error: [i32; 3] = std::array::from_fn(|i| target[i] - i32::from(chosen.0[i]));
+                let mut spread = |dx: i32, dy: u32, weight: i32| {
+                    let (x, y) = (chunk_x as i32 + dx, chunk_y + dy);
+                    if x < 0 || x >= num_width_chunks as i32 || y >= num_height_chunks {
+                        return;
+                    }
+                    let neighbour = &mut errors[(y * num_width_chunks + x as u32) as usize];
+                    for (channel, error) in neighbour.iter_mut().zip(error) {
+                        *channel += error * weight / 16;
+                    }
+                };
+                spread(forwards, 0, 7);
+                spread(-forwards, 1, 3);
+                spread(0, 1, 5);
+                spread(forwards, 1, 1);
+            }
+
+            output.put(chunk_x, chunk_y, chosen);
+
+            chunks_progress_bar += 1;
+            progress.tick(Stage::Dithering, chunks_progress_bar, total_chunks);
+        }
+    }
+
+    Ok(())
+}
+
+//sharpening any more than this mostly just makes halos
+pub const MAX_POST_SHARPEN: f32 = 1.0;
+
+//an unsharp mask - done before scaling, so it sharpens the edges between chunks rather than inside them
+pub fn post_sharpen(output: DynamicImage, factor: f32) -> DynamicImage {
+    if factor <= 0.0 {
+        return output;
+    }
+    let factor = factor.min(MAX_POST_SHARPEN);
+
+    let original = output.to_rgb8();
+    let blurred = image::imageops::blur(&original, 1.0);
+
+    let mut sharpened = original.clone();
+    for (sharpened, (original, blurred)) in sharpened
+        .pixels_mut()
+        .zip(original.pixels().zip(blurred.pixels()))
+    {
+        for ((sharpened, original), blurred) in
+            sharpened.0.iter_mut().zip(original.0).zip(blurred.0)
+        {
+            let original = f32::from(original);
+            *sharpened = factor
+                .mul_add(original - f32::from(blurred), original)
+                .round()
+                .clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    DynamicImage::ImageRgb8(sharpened)
+}
+
+//anything bigger than this is almost certainly a typo, and would take forever to encode
+pub const MAX_SCALED_PIXELS: u64 = 256 * 1024 * 1024;
+//big enough to be worth checking with whoever asked for it first
+pub const LARGE_OUTPUT_PIXELS: u64 = 64 * 1024 * 1024;
+
+//the size that rendering with these settings (and then scaling, if asked for) will make, without making it.
+//(0, 0) if the settings are invalid or the image is too small for them, as rendering would fail
+pub fn predicted_output_size(
+    (width, height): (u32, u32),
+    output_settings: OutputSettings,
+) -> (u32, u32) {
+    let Some(output_px_size) = output_settings
+        .validated()
+        .ok()
+        .and_then(|output_settings| dither_chunk_size((width, height), output_settings).ok())
+    else {
+        return (0, 0);
+    };
+    let dithering_scale = output_settings.effective_dithering_scale();
+    let (output_w, output_h) = (
+        width / output_px_size * dithering_scale,
+        height / output_px_size * dithering_scale,
+    );
+
+    if output_settings.scale_output_to_original {
+        let factor = original_scale_factor(output_settings);
+        (
+            output_w.saturating_mul(factor),
+            output_h.saturating_mul(factor),
+        )
+    } else {
+        (output_w, output_h)
+    }
+}
+
+//virtual pixels are `1 << (output_px_size - 1)` wide, which has to fit in a u32
+pub const MAX_OUTPUT_PX_SIZE: u32 = 32;
+
+//can be 0 if the dithering scale is bigger than the virtual pixels, which scales down to nothing
+//how much bigger the output gets scaled after dithering
+fn output_scale_factor(output_settings: OutputSettings) -> u32 {
+    if output_settings.scale_output_to_original {
+        original_scale_factor(output_settings)
+    } else {
+        1
+    }
+}
+
+fn original_scale_factor(output_settings: OutputSettings) -> u32 {
+    (1 << (output_settings.output_px_size - 1)) / output_settings.effective_dithering_scale()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScaleError {
+    NoScaleFactor,
+    TooManyPixels {
+        factor: u32,
+        width: u64,
+        height: u64,
+    },
+}
+
+impl Display for ScaleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoScaleFactor => write!(f, "scale factor must be at least 1"),
+            Self::TooManyPixels {
+                factor,
+                width,
+                height,
+            } => write!(
+                f,
+                "scaling by {factor} would make a {width}x{height} image, which is over the limit of {MAX_SCALED_PIXELS} pixels or {} each way",
+                u32::MAX
+            ),
+        }
+    }
+}
+
+impl Error for ScaleError {}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(
+    skip_all,
+    fields(
+        width = from.width(),
+        height = from.height(),
+        scale_output_to_original = output_settings.scale_output_to_original,
+    ),
+))]
+pub fn pixel_perfect_scale(
+    output_settings: OutputSettings,
+    from: &DynamicImage,
+) -> Result<DynamicImage, PxlsError> {
+    if !output_settings.scale_output_to_original {
+        return Ok(from.clone());
+    }
+
+    let output_settings = output_settings.validated()?;
+    Ok(pixel_perfect_scale_by(
+        from,
+        original_scale_factor(output_settings),
+    )?)
+}
+
+//for scaling the stored output to whatever size is wanted at export time, without re-rendering
+pub fn pixel_perfect_scale_by(
+    from: &DynamicImage,
+    factor: u32,
+) -> Result<DynamicImage, ScaleError> {
+    check_scale(from.width(), from.height(), factor)?;
+    Ok(scale_by(from, factor))
+}
+
+fn check_scale(width: u32, height: u32, factor: u32) -> Result<(), ScaleError> {
+    if factor == 0 {
+        return Err(ScaleError::NoScaleFactor);
+    }
+
+    let (width, height) = (
+        u64::from(width) * u64::from(factor),
+        u64::from(height) * u64::from(factor),
+    );
+    //an empty image still can't be wider than a u32 says
+    let too_wide = width.max(height) > u64::from(u32::MAX);
+    if too_wide || width * height > MAX_SCALED_PIXELS {
+        return Err(ScaleError::TooManyPixels {
+            factor,
+            width,
+            height,
+        });
+    }
+    Ok(())
+}
+
+fn scale_by(from: &DynamicImage, scaling_factor: u32) -> DynamicImage {
+    let (final_w, final_h) = (
+        from.width() * scaling_factor,
+        from.height() * scaling_factor,
+    );
+    let mut final_img = DynamicImage::new(final_w, final_h, ColorType::Rgb8);
+
+    for x in 0..from.width() {
+        for y in 0..from.height() {
+            let px = from.get_pixel(x, y);
+
+            for px_x in (scaling_factor * x)..(scaling_factor * (x + 1)) {
+                for px_y in (scaling_factor * y)..(scaling_factor * (y + 1)) {
+                    final_img.put_pixel(px_x, px_y, px);
+                }
+            }
+        }
+    }
+
+    final_img
+}
+
+//big enough for most screens, without sharing a huge pixel-perfect export
+pub const SHARING_MAX_DIMENSION: u32 = 1920;
+
+fn sharing_step(longest: u32, max_dimension: u32) -> u32 {
+    longest.div_ceil(max_dimension.max(1)).max(1)
+}
+
+pub fn downsampled_size((width, height): (u32, u32), max_dimension: u32) -> (u32, u32) {
+    let step = sharing_step(width.max(height), max_dimension);
+    (width.div_ceil(step), height.div_ceil(step))
+}
+
+//keeps every n-th pixel rather than averaging them, so pixel art stays blocky
+pub fn downsample_for_sharing(image: &DynamicImage, max_dimension: u32) -> DynamicImage {
+    let step = sharing_step(image.width().max(image.height()), max_dimension);
+    if step == 1 {
+        return image.clone();
+    }
+
+    let (width, height) = downsampled_size(image.dimensions(), max_dimension);
+    let mut downsampled = DynamicImage::new(width, height, image.color());
+    for y in 0..height {
+        for x in 0..width {
+            downsampled.put_pixel(x, y, image.get_pixel(x * step, y * step));
+        }
+    }
+
+    downsampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use proptest::prelude::*;
+    use std::sync::Arc;
+
+    fn palette_settings(closeness_threshold: u32) -> PaletteSettings {
+        PaletteSettings {
+            closeness_threshold,
+            ..PaletteSettings::default()
+        }
+    }
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 255 / width) as u8, (y * 255 / height) as u8, 128, 255])
+        }))
+    }
+
+    fn dither(
+        image: &DynamicImage,
+        palette: impl AsRef<[Rgba<u8>]>,
+        output_settings: OutputSettings,
+    ) -> Result<DynamicImage, PxlsError> {
+        dither_original_with_palette(
+            image,
+            palette,
+            DistanceAlgorithm::Euclidean,
+            output_settings,
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+    }
+
+    #[test]
+    fn invalid_settings_are_an_error() {
+        let settings = OutputSettings {
+            output_px_size: 0,
+            ..OutputSettings::default()
+        };
+        assert_eq!(
+            dither(&gradient(8, 8), [Rgba([0; 4])], settings),
+            Err(PxlsError::InvalidSettings(ValidationError::NoOutputPxSize))
+        );
+    }
+
+    #[test]
+    fn empty_palette_is_an_error() {
+        assert_eq!(
+            dither(&gradient(8, 8), [], OutputSettings::default()),
+            Err(PxlsError::EmptyPalette)
+        );
+    }
+
+    #[test]
+    fn image_smaller_than_a_virtual_pixel_is_an_error() {
+        let settings = OutputSettings {
+            output_px_size: 4,
+            ..OutputSettings::default()
+        };
+        //8 wide means the virtual pixels are 8 wide, which is too tall for the image
+        assert_eq!(
+            dither(&gradient(8, 2), [Rgba([0; 4])], settings),
+            Err(PxlsError::ImageTooSmall { needed: 8, got: 2 })
+        );
+    }
+
+    #[test]
+    fn empty_image_is_an_error() {
+        let empty = DynamicImage::new_rgba8(0, 4);
+        assert_eq!(
+            get_palette(
+                &empty,
+                PaletteSettings::default(),
+                DistanceAlgorithm::Euclidean,
+                &NoProgress,
+                &CancellationToken::new(),
+            ),
+            Err(PxlsError::ZeroDimension)
+        );
+        assert_eq!(
+            dither(&empty, [Rgba([0; 4])], OutputSettings::default()),
+            Err(PxlsError::ZeroDimension)
+        );
+    }
+
+    #[test]
+    fn cancelling_is_an_error() {
+        let stop = CancellationToken::new();
+        stop.cancel();
+        let image = gradient(16, 16);
+        assert_eq!(
+            get_palette(
+                &image,
+                PaletteSettings::default(),
+                DistanceAlgorithm::Euclidean,
+                &NoProgress,
+                &stop,
+            ),
+            Err(PxlsError::Cancelled)
+        );
+        assert_eq!(
+            dither_original_with_palette(
+                &image,
+                [Rgba([0; 4])],
+                DistanceAlgorithm::Euclidean,
+                OutputSettings::default(),
+                &NoProgress,
+                &stop,
+            ),
+            Err(PxlsError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn scaling_too_big_is_an_error() {
+        let settings = OutputSettings {
+            output_px_size: 16,
+            ..OutputSettings::default()
+        };
+        assert!(matches!(
+            pixel_perfect_scale(settings, &gradient(64, 64)),
+            Err(PxlsError::Scale(ScaleError::TooManyPixels { .. }))
+        ));
+    }
+
+    #[test]
+    fn errors_are_std_errors() {
+        let error: Box<dyn std::error::Error> = Box::new(PxlsError::EmptyPalette);
+        assert_eq!(error.to_string(), "the palette doesn't have any colours");
+    }
+
+    #[test]
+    fn manhattan_threshold_goes_up_to_765() {
+        let manhattan = DistanceAlgorithm::Manhattan;
+        assert!(palette_settings(765).validated(manhattan).is_ok());
+        assert_eq!(
+            palette_settings(766).validated(manhattan),
+            Err(ValidationError::ClosenessThresholdTooBig {
+                closeness_threshold: 766,
+                algorithm: manhattan,
+                max: 765,
+            })
+        );
+    }
+
+    #[test]
+    fn every_threshold_the_sliders_offer_is_valid() {
+        for &algorithm in ALL_ALGOS {
+            let range = algorithm.closeness_threshold_range();
+            let max = *range.end();
+            for closeness_threshold in range {
+                assert!(
+                    palette_settings(closeness_threshold)
+                        .validated(algorithm)
+                        .is_ok(),
+                    "{algorithm} rejected {closeness_threshold}"
+                );
+            }
+            assert!(palette_settings(max + 1).validated(algorithm).is_err());
+        }
+    }
+
+    #[test]
+    fn thresholds_carry_over_between_algorithms() {
+        for &from in ALL_ALGOS {
+            for &to in ALL_ALGOS {
+                let normalised =
+                    from.normalised_closeness_threshold(*from.closeness_threshold_range().end());
+                let converted = to.closeness_threshold_from_normalised(normalised);
+                assert!(to.closeness_threshold_range().contains(&converted));
+            }
+        }
+    }
+
+    fn brute_force_closest_factor(target: u32, number: u32) -> u32 {
+        let target = target.max(1);
+        (1..=number)
+            .filter(|factor| number.is_multiple_of(*factor))
+            .min_by_key(|factor| (factor.abs_diff(target), std::cmp::Reverse(*factor)))
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn closest_factor_is_the_nearest_divisor() {
+        for number in 0..=120 {
+            for target in 0..=130 {
+                let factor = find_closest_factor(target, number);
+                assert_eq!(
+                    factor,
+                    brute_force_closest_factor(target, number),
+                    "target {target}, number {number}"
+                );
+                if number > 0 {
+                    assert!((1..=number).contains(&factor));
+                    assert!(number.is_multiple_of(factor));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn closest_factor_edge_cases() {
+        //1 only has itself
+        assert_eq!(get_closest_factor(0, 1), 1);
+        assert_eq!(get_closest_factor(1, 1), 1);
+        assert_eq!(get_closest_factor(50, 1), 1);
+        //a prime only has 1 and itself, and the bigger one wins a tie
+        assert_eq!(get_closest_factor(3, 97), 1);
+        assert_eq!(get_closest_factor(49, 97), 97);
+        assert_eq!(get_closest_factor(60, 97), 97);
+        //past the number is clamped to it
+        assert_eq!(get_closest_factor(1000, 24), 24);
+        assert_eq!(get_closest_factor(u32::MAX, 24), 24);
+        assert_eq!(get_closest_factor(7, 0), 0);
+    }
+
+    #[test]
+    fn closest_factor_used_to_panic() {
+        //searching further than `target` used to underflow, and 0 used to divide by zero
+        assert_eq!(get_closest_factor(5, 1000), 5);
+        assert_eq!(get_closest_factor(3, 1024), 4);
+        assert_eq!(get_closest_factor(0, 100), 1);
+        assert_eq!(get_closest_factor(u32::MAX - 1, u32::MAX), u32::MAX);
+    }
+
+    #[cfg(feature = "factor-cache")]
+    #[test]
+    fn repeated_factors_come_from_the_cache() {
+        //other tests can end up on the same thread
+        FACTOR_CACHE.with_borrow_mut(lru::LruCache::clear);
+        let cached = || FACTOR_CACHE.with_borrow(lru::LruCache::len);
+        let first = get_closest_factor(100, 1000);
+        assert_eq!(cached(), 1);
+        assert_eq!(get_closest_factor(100, 1000), first);
+        assert_eq!(cached(), 1);
+        assert_eq!(first, find_closest_factor(100, 1000));
+
+        get_closest_factor(3, 1024);
+        assert_eq!(cached(), 2);
+        for target in 0..300 {
+            get_closest_factor(target, 2048);
+        }
+        assert_eq!(cached(), 256);
+    }
+
+    //the hand-written `PartialEq` that `canonical` replaced
+    fn old_output_settings_eq(a: OutputSettings, b: OutputSettings) -> bool {
+        if a.dither_mode != b.dither_mode || a.post_sharpen != b.post_sharpen {
+            false
+        } else if a.dither_mode != DitherMode::Legacy {
+            a.output_px_size == b.output_px_size
+                && a.scale_output_to_original == b.scale_output_to_original
+        } else if a.dithering_scale == 1 || b.dithering_scale == 1 {
+            a.dithering_scale == b.dithering_scale
+                && a.output_px_size == b.output_px_size
+                && a.scale_output_to_original == b.scale_output_to_original
+        } else {
+            a.output_px_size == b.output_px_size
+                && a.dithering_mode == b.dithering_mode
+                && a.dithering_scale == b.dithering_scale
+                && a.scale_output_to_original == b.scale_output_to_original
+        }
+    }
+
+    fn output_settings_grid() -> Vec<OutputSettings> {
+        let mut grid = vec![];
+        for &dither_mode in ALL_DITHER_MODES {
+            for dithering_mode in [
+                DitheringMode::Ratio(1),
+                DitheringMode::Ratio(4),
+                DitheringMode::Fraction(0.5),
+            ] {
+                for dithering_scale in 1..=3 {
+                    for output_px_size in 3..=4 {
+                        for scale_output_to_original in [false, true] {
+                            for post_sharpen in [0.0, 0.5] {
+                                grid.push(OutputSettings {
+                                    output_px_size,
+                                    dither_mode,
+                                    dithering_mode,
+                                    dithering_scale,
+                                    scale_output_to_original,
+                                    post_sharpen,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn duplicates_are_removed_in_order() {
+        let (red, green, blue) = (
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 255, 0, 255]),
+            Rgba([0, 0, 255, 255]),
+        );
+        let palette = vec![red, green, red, blue, green, red];
+        assert!(palette_has_duplicates(&palette));
+        let deduped = dedup_palette(palette.clone());
+        assert_eq!(deduped, [red, green, blue]);
+        assert!(!palette_has_duplicates(&deduped));
+
+        let mut palette = Palette::from(palette);
+        palette.dedupe();
+        assert_eq!(*palette, [red, green, blue]);
+
+        assert!(!palette_has_duplicates(&[]));
+        assert!(dedup_palette(vec![]).is_empty());
+        //only exact duplicates go, not colours that are close or only differ in alpha
+        let close = vec![red, Rgba([254, 0, 0, 255]), Rgba([255, 0, 0, 0])];
+        assert!(!palette_has_duplicates(&close));
+        assert_eq!(dedup_palette(close.clone()), close);
+    }
+
+    #[test]
+    fn extra_colours_are_not_duplicated() {
+        let (red, blue) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, red));
+        let settings = PaletteSettings {
+            chunks_per_dimension: 2,
+            extra_colors: vec![red, blue, blue],
+            ..PaletteSettings::default()
+        };
+        let palette = get_palette(
+            &image,
+            settings,
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(*palette, [red, blue]);
+    }
+
+    #[test]
+    fn predicted_sizes_cover_the_scaling_cases() {
+        let legacy = OutputSettings {
+            output_px_size: 5,
+            dither_mode: DitherMode::Legacy,
+            dithering_scale: 2,
+            scale_output_to_original: false,
+            ..OutputSettings::default()
+        };
+        //16px virtual pixels, each dithered into 2x2
+        assert_eq!(predicted_output_size((64, 48), legacy), (8, 6));
+        let scaled = OutputSettings {
+            scale_output_to_original: true,
+            ..legacy
+        };
+        assert_eq!(predicted_output_size((64, 48), scaled), (64, 48));
 
-    for chunk_x in 0..num_width_chunks {
-        for chunk_y in 0..num_height_chunks {
-            if stop.load(Ordering::Relaxed) {
-                return output;
+        //only the legacy mode dithers into more than one pixel
+        let bayer = OutputSettings {
+            dither_mode: DitherMode::Bayer {
+                strength: DitherMode::DEFAULT_STRENGTH,
+            },
+            ..legacy
+        };
+        assert_eq!(predicted_output_size((64, 48), bayer), (4, 3));
+        let scaled_bayer = OutputSettings {
+            scale_output_to_original: true,
+            ..bayer
+        };
+        assert_eq!(predicted_output_size((64, 48), scaled_bayer), (64, 48));
+
+        //the virtual pixels are the nearest factor of the width, so 15 rather than 16, and anything left over at
+        //the bottom is dropped
+        assert_eq!(predicted_output_size((60, 50), legacy), (8, 6));
+        assert_eq!(predicted_output_size((60, 50), scaled), (64, 48));
+
+        //dithering into more pixels than a virtual pixel has scales down to nothing
+        let tiny = OutputSettings {
+            output_px_size: 1,
+            dithering_scale: 2,
+            ..scaled
+        };
+        assert_eq!(predicted_output_size((8, 8), tiny), (0, 0));
+
+        //anything that rendering would turn down is nothing, rather than a panic
+        for output_px_size in [0, MAX_OUTPUT_PX_SIZE + 1, u32::MAX] {
+            let invalid = OutputSettings {
+                output_px_size,
+                ..legacy
+            };
+            assert_eq!(predicted_output_size((64, 48), invalid), (0, 0));
+        }
+        let no_ratio = OutputSettings {
+            dithering_mode: DitheringMode::Ratio(0),
+            ..legacy
+        };
+        assert_eq!(predicted_output_size((64, 48), no_ratio), (0, 0));
+        for dimensions in [(0, 48), (64, 0), (0, 0), (64, 8)] {
+            assert_eq!(
+                predicted_output_size(dimensions, scaled),
+                (0, 0),
+                "{dimensions:?}"
+            );
+        }
+
+        for settings in [legacy, scaled, bayer, scaled_bayer] {
+            let input = DynamicImage::new_rgb8(60, 50);
+            let output = dither(&input, [Rgba([0, 0, 0, 255])], settings).unwrap();
+            assert_eq!(
+                output.dimensions(),
+                predicted_output_size((60, 50), settings),
+                "{settings:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sharpening_a_flat_image_changes_nothing() {
+        let flat = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 12, Rgb([90, 160, 30])));
+        for factor in [0.5, 1.0] {
+            assert_eq!(post_sharpen(flat.clone(), factor), flat, "{factor}");
+        }
+        //not sharpening at all leaves it as it was, whatever type it was
+        let rgba = gradient(8, 8);
+        assert_eq!(post_sharpen(rgba.clone(), 0.0), rgba);
+    }
+
+    #[test]
+    fn sharpening_is_clamped() {
+        //a hard edge, which sharpening pushes past black and white on either side
+        let edge = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                Rgb([20, 20, 20])
+            } else {
+                Rgb([235, 235, 235])
             }
+        }));
+        let strongest = post_sharpen(edge.clone(), MAX_POST_SHARPEN);
+        assert_eq!(post_sharpen(edge.clone(), 5.0), strongest);
+        assert_ne!(strongest, edge);
+        let strongest = strongest.to_rgb8();
+        assert_eq!(strongest.get_pixel(7, 8), &Rgb([0, 0, 0]));
+        assert_eq!(strongest.get_pixel(8, 8), &Rgb([255, 255, 255]));
+        //away from the edge, it's still flat
+        assert_eq!(strongest.get_pixel(0, 8), &Rgb([20, 20, 20]));
+        assert_eq!(strongest.get_pixel(15, 8), &Rgb([235, 235, 235]));
+    }
 
-            let (mut accum_r, mut accum_g, mut accum_b) = (0_u64, 0_u64, 0_u64);
+    #[test]
+    fn dithering_into_reuses_the_buffer() {
+        let palette = [RED, GREEN, BLUE, Rgba([255, 255, 255, 255])];
+        let stop = CancellationToken::new();
+        let input = gradient(64, 48);
+        let source = ViewSource(input.as_rgba8().unwrap());
 
-            for px_x in (output_px_size * chunk_x)..(output_px_size * (chunk_x + 1)) {
-                for px_y in (output_px_size * chunk_y)..(output_px_size * (chunk_y + 1)) {
-                    let [r, g, b] = input.get_pixel(px_x, px_y).to_rgb().0;
-                    accum_r += r as u64;
-                    accum_g += g as u64;
-                    accum_b += b as u64;
-                }
+        //big enough for the biggest output, which is scaled back up to the original
+        let mut out = RgbaImage::new(64, 48);
+        let (pointer, capacity) = (out.as_raw().as_ptr(), out.as_raw().capacity());
+
+        for &dither_mode in ALL_DITHER_MODES {
+            for (output_px_size, scale_output_to_original) in [(3, true), (3, false), (4, false)] {
+                let settings = OutputSettings {
+                    output_px_size,
+                    dither_mode,
+                    scale_output_to_original,
+                    ..OutputSettings::default()
+                };
+                dither_into(
+                    &source,
+                    palette,
+                    DistanceAlgorithm::Euclidean,
+                    settings,
+                    &mut out,
+                    &NoProgress,
+                    &stop,
+                )
+                .unwrap();
+                assert_eq!(out.as_raw().as_ptr(), pointer, "{settings:?}");
+                assert_eq!(out.as_raw().capacity(), capacity, "{settings:?}");
+
+                let allocated = dither(&input, palette, settings).unwrap();
+                assert_eq!(out, allocated.to_rgba8(), "{settings:?}");
             }
+        }
+
+        //and only grows when it has to
+        let input = gradient(128, 96);
+        let settings = OutputSettings {
+            output_px_size: 3,
+            scale_output_to_original: true,
+            ..OutputSettings::default()
+        };
+        dither_into(
+            &ViewSource(input.as_rgba8().unwrap()),
+            palette,
+            DistanceAlgorithm::Euclidean,
+            settings,
+            &mut out,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(out.dimensions(), predicted_output_size((128, 96), settings));
+        assert!(out.as_raw().capacity() > capacity);
+    }
+
+    #[test]
+    fn settings_changes_are_described_in_order() {
+        let from = (
+            PaletteSettings::default(),
+            OutputSettings::default(),
+            DistanceAlgorithm::Euclidean,
+        );
+        assert_eq!(describe_settings_changes(&from, &from), "same settings");
+
+        let to = (
+            PaletteSettings {
+                closeness_threshold: 80,
+                ..from.0.clone()
+            },
+            from.1,
+            DistanceAlgorithm::Manhattan,
+        );
+        assert_eq!(
+            describe_settings_changes(&from, &to),
+            format!(
+                "algo Euclidean → Manhattan, threshold {} → 80",
+                from.0.closeness_threshold
+            )
+        );
+        assert_eq!(
+            describe_settings_changes(&to, &from),
+            format!(
+                "algo Manhattan → Euclidean, threshold 80 → {}",
+                from.0.closeness_threshold
+            )
+        );
 
-            let divisor = (output_px_size * output_px_size) as u64;
+        let to = (
+            PaletteSettings {
+                extra_colors: vec![Rgba([0; 4]), Rgba([255; 4])],
+                ..from.0.clone()
+            },
+            OutputSettings {
+                dithering_mode: DitheringMode::Fraction(0.5),
+                scale_output_to_original: !from.1.scale_output_to_original,
+                ..from.1
+            },
+            from.2,
+        );
+        assert_eq!(
+            describe_settings_changes(&from, &to),
+            format!(
+                "custom colours 0 → 2, dithering ratio 4 → fraction 0.5, scaled to original {} → {}",
+                from.1.scale_output_to_original, to.1.scale_output_to_original
+            )
+        );
+    }
+
+    #[test]
+    fn dither_mode_changes_only_show_what_changed() {
+        let settings = |dither_mode| {
+            (
+                PaletteSettings::default(),
+                OutputSettings {
+                    dither_mode,
+                    ..OutputSettings::default()
+                },
+                DistanceAlgorithm::Euclidean,
+            )
+        };
+        let weak = settings(DitherMode::Bayer { strength: 25 });
+        let strong = settings(DitherMode::Bayer { strength: 75 });
+        assert_eq!(
+            describe_settings_changes(&weak, &strong),
+            "strength 25 → 75"
+        );
+        let forwards = settings(DitherMode::FloydSteinberg {
+            direction: ErrorDiffusionDirection::LeftToRight,
+        });
+        let backwards = settings(DitherMode::FloydSteinberg {
+            direction: ErrorDiffusionDirection::RightToLeft,
+        });
+        assert_eq!(
+            describe_settings_changes(&forwards, &backwards),
+            "direction Left to Right → Right to Left"
+        );
+        let random = settings(DitherMode::Random {
+            strength: 25,
+            seed: 1,
+        });
+        let reseeded = settings(DitherMode::Random {
+            strength: 25,
+            seed: 2,
+        });
+        assert_eq!(describe_settings_changes(&random, &reseeded), "seed 1 → 2");
+        assert_eq!(
+            describe_settings_changes(&weak, &random),
+            "dither Bayer → Random"
+        );
+        assert_eq!(
+            describe_settings_changes(&weak, &settings(DitherMode::Legacy)),
+            format!("dither {} → {}", weak.1.dither_mode, DitherMode::Legacy)
+        );
+    }
 
-            let av_px = Rgba([
-                (accum_r / divisor) as u8,
-                (accum_g / divisor) as u8,
-                (accum_b / divisor) as u8,
-                u8::MAX,
-            ]);
+    #[test]
+    fn changed_pixels_are_in_row_major_order() {
+        let before = DynamicImage::ImageRgba8(RgbaImage::from_pixel(3, 2, Rgba([0, 0, 0, 255])));
+        assert_eq!(changed_pixels(&before, &before), Some(vec![false; 6]));
 
-            let mut first = None;
-            let mut first_distance = u32::MAX;
-            let mut second = None;
-            let mut second_distance = u32::MAX;
+        let mut after = before.to_rgba8();
+        after.put_pixel(2, 0, Rgba([255, 0, 0, 255]));
+        after.put_pixel(0, 1, Rgba([0, 0, 0, 254]));
+        assert_eq!(
+            changed_pixels(&before, &DynamicImage::ImageRgba8(after)),
+            Some(vec![false, false, true, true, false, false])
+        );
 
-            for px in palette.iter().copied() {
-                let dist = distance_algorithm.distance(px, av_px);
+        //only the colours count, not how they're stored
+        assert_eq!(
+            changed_pixels(&before, &DynamicImage::new_rgb8(3, 2)),
+            Some(vec![false; 6])
+        );
+        assert_eq!(changed_pixels(&before, &DynamicImage::new_rgb8(2, 3)), None);
+        assert_eq!(
+            changed_pixels(&DynamicImage::new_rgb8(0, 0), &DynamicImage::new_rgb8(0, 0)),
+            Some(vec![])
+        );
+    }
 
-                if dist < first_distance {
-                    second = first;
-                    second_distance = first_distance;
+    #[test]
+    fn every_invalid_output_setting_is_reported() {
+        let bayer = |strength| DitherMode::Bayer { strength };
+        let cases = [
+            (
+                OutputSettings {
+                    output_px_size: MAX_OUTPUT_PX_SIZE + 1,
+                    ..OutputSettings::default()
+                },
+                ValidationError::OutputPxSizeTooBig(MAX_OUTPUT_PX_SIZE + 1),
+            ),
+            (
+                OutputSettings {
+                    dithering_scale: 0,
+                    ..OutputSettings::default()
+                },
+                ValidationError::NoDitheringScale,
+            ),
+            (
+                OutputSettings {
+                    dither_mode: bayer(101),
+                    ..OutputSettings::default()
+                },
+                ValidationError::DitherStrengthTooBig(101),
+            ),
+            (
+                OutputSettings {
+                    dithering_mode: DitheringMode::Ratio(0),
+                    ..OutputSettings::default()
+                },
+                ValidationError::NoDitheringRatio,
+            ),
+            (
+                OutputSettings {
+                    dithering_mode: DitheringMode::Fraction(1.5),
+                    ..OutputSettings::default()
+                },
+                ValidationError::DitheringFractionOutOfRange(1.5),
+            ),
+            (
+                OutputSettings {
+                    post_sharpen: -1.0,
+                    ..OutputSettings::default()
+                },
+                ValidationError::NegativeSharpening(-1.0),
+            ),
+            (
+                OutputSettings {
+                    output_px_size: 1,
+                    dithering_scale: 2,
+                    ..OutputSettings::default()
+                },
+                ValidationError::OutputPxSizeTooSmallForDithering {
+                    output_px_size: 1,
+                    dithering_scale: 2,
+                    min: 2,
+                },
+            ),
+        ];
+        for (settings, expected) in cases {
+            assert_eq!(
+                dither(&gradient(8, 8), [Rgba([0; 4])], settings),
+                Err(PxlsError::InvalidSettings(expected))
+            );
+        }
 
-                    first = Some(px);
-                    first_distance = dist;
-                } else if dist < second_distance {
-                    second = Some(px);
-                    second_distance = dist;
-                }
+        //only the legacy mode dithers into more than one pixel
+        let settings = OutputSettings {
+            output_px_size: 1,
+            dithering_scale: 2,
+            dither_mode: bayer(100),
+            ..OutputSettings::default()
+        };
+        assert!(dither(&gradient(8, 8), [Rgba([0; 4])], settings).is_ok());
+    }
+
+    #[test]
+    fn scaling_by_nothing_is_an_error() {
+        assert_eq!(
+            pixel_perfect_scale_by(&gradient(4, 4), 0),
+            Err(ScaleError::NoScaleFactor)
+        );
+        assert_eq!(
+            pixel_perfect_scale_by(&gradient(4, 4), 2)
+                .unwrap()
+                .dimensions(),
+            (8, 8)
+        );
+    }
+
+    #[test]
+    fn palettes_can_be_passed_however_theyre_held() {
+        let image = gradient(16, 16);
+        let colours = [Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])];
+        let shared: Arc<[Rgba<u8>]> = Arc::from(colours);
+        let stop = CancellationToken::new();
+        let expected = dither(&image, colours, OutputSettings::default()).unwrap();
+
+        let from_arc = dither_original_with_palette(
+            &image,
+            shared.clone(),
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(from_arc, expected);
+        assert_eq!(
+            dither(&image, &shared, OutputSettings::default()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            dither(&image, Vec::from(colours), OutputSettings::default()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            dither(&image, &colours[..], OutputSettings::default()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            dither(
+                &image,
+                Palette::from(colours.to_vec()),
+                OutputSettings::default()
+            )
+            .unwrap(),
+            expected
+        );
+    }
+
+    //an `RgbImage` read as RGBA, without converting all of it up front
+    struct Opaque<'a>(&'a RgbImage);
+
+    impl GenericImageView for Opaque<'_> {
+        type Pixel = Rgba<u8>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            self.0.dimensions()
+        }
+
+        fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+            self.0.get_pixel(x, y).to_rgba()
+        }
+    }
+
+    //the palette and output from `source`, which should be the same as from `expected`
+    fn assert_same_as_dynamic(source: &impl PixelSource, expected: &DynamicImage) {
+        let settings = PaletteSettings {
+            chunks_per_dimension: 4,
+            ..PaletteSettings::default()
+        };
+        let stop = CancellationToken::new();
+        let palette = get_palette_from_source(
+            source,
+            settings.clone(),
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        let expected_palette = get_palette(
+            expected,
+            settings,
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(palette, expected_palette);
+
+        let output = dither_source_with_palette(
+            source,
+            &palette,
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            dither(expected, &palette, OutputSettings::default()).unwrap()
+        );
+    }
+
+    //flat blocks of `block` pixels, starting `offset` pixels in, with a black pixel in the corner of each so that
+    //there's never a tie for the most common colour in a chunk that lines up with them
+    fn blocks(width: u32, height: u32, block: (u32, u32), offset: (u32, u32)) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x + offset.0, y + offset.1);
+            if x % block.0 == 0 && y % block.1 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([(x / block.0 * 37) as u8, (y / block.1 * 53) as u8, 128, 255])
             }
+        })
+    }
 
-            let first = first.unwrap();
-            let mut second = second.unwrap_or(first);
+    #[test]
+    fn image_buffers_can_be_used_directly() {
+        let image = blocks(48, 32, (12, 8), (0, 0));
+        assert_same_as_dynamic(&image, &DynamicImage::ImageRgba8(image.clone()));
+    }
 
-            //TODO: make DL more ergonomic and easier to understand
-            if first_distance.abs_diff(second_distance)
-                > (distance_algorithm.distance(first, second)
-                    / output_settings.dithering_likelihood)
-            {
-                second = first;
+    #[test]
+    fn converting_views_can_be_used() {
+        let rgb = DynamicImage::ImageRgba8(blocks(48, 32, (12, 8), (0, 0))).to_rgb8();
+        assert_same_as_dynamic(
+            &ViewSource(&Opaque(&rgb)),
+            &DynamicImage::ImageRgb8(rgb.clone()),
+        );
+    }
+
+    #[test]
+    fn crops_can_be_used_without_copying() {
+        //the crop's chunks are 10x8, so the blocks line up with them once it's cropped
+        let image = blocks(64, 64, (10, 8), (2, 0));
+        let crop = image.view(8, 16, 40, 32);
+        let copied = DynamicImage::ImageRgba8(crop.to_image());
+        assert_same_as_dynamic(&ViewSource(&*crop), &copied);
+    }
+
+    #[test]
+    fn sixteen_bit_chunks_are_averaged_at_full_precision() {
+        //a red gradient that doesn't line up with the 8-bit levels
+        let red = |x: u32| x * 1000 + 100;
+        let wide = DynamicImage::ImageRgb16(image::ImageBuffer::from_fn(64, 8, |x, _| {
+            image::Rgb([red(x) as u16, 0x8080, 0])
+        }));
+        let narrow = DynamicImage::ImageRgb8(wide.to_rgb8());
+        let settings = OutputSettings {
+            output_px_size: 3,
+            dither_mode: DitherMode::None,
+            ..OutputSettings::default()
+        };
+
+        let mut narrow_differs = false;
+        for chunk in 0..16 {
+            let exact = (chunk * 4..chunk * 4 + 4).map(red).sum::<u32>() as f64 / 4.0 / 257.0;
+            let [wide_red, _, _, _] = source_chunk_colour(&wide, settings, chunk, 0).unwrap().0;
+            let [narrow_red, _, _, _] = source_chunk_colour(&narrow, settings, chunk, 0).unwrap().0;
+            //only rounded down once, right at the end
+            assert_eq!(f64::from(wide_red), exact.floor(), "chunk {chunk}");
+            narrow_differs |= f64::from(narrow_red) != exact.floor();
+        }
+        //bringing each pixel down to 8 bits first loses some of it
+        assert!(narrow_differs);
+
+        //with every red to pick from, the output is just the chunk averages
+        let reds: Vec<_> = (0..=255).map(|r| Rgba([r, 0x80, 0, 255])).collect();
+        let unscaled = OutputSettings {
+            scale_output_to_original: false,
+            ..settings
+        };
+        let from_wide = dither(&wide, &reds, unscaled).unwrap();
+        assert_eq!(
+            from_wide.get_pixel(3, 1),
+            source_chunk_colour(&wide, settings, 3, 1).unwrap()
+        );
+        assert_ne!(from_wide, dither(&narrow, &reds, unscaled).unwrap());
+
+        //8-bit inputs come out just the same as before
+        let eight = gradient(16, 16);
+        let [r, g, b, _] = source_chunk_colour(&eight, settings, 1, 2).unwrap().0;
+        let average = |channel: usize| {
+            let sum: u32 = (4..8)
+                .flat_map(|x| (8..12).map(move |y| (x, y)))
+                .map(|(x, y)| u32::from(eight.get_pixel(x, y).0[channel]))
+                .sum();
+            (sum / 16) as u8
+        };
+        assert_eq!([r, g, b], [average(0), average(1), average(2)]);
+    }
+
+    #[test]
+    fn source_chunks_are_only_found_for_settings_that_would_dither() {
+        let image = DynamicImage::ImageRgba8(blocks(32, 32, (4, 4), (0, 0)));
+        let legacy = OutputSettings {
+            output_px_size: 3,
+            dither_mode: DitherMode::Legacy,
+            dithering_scale: 2,
+            ..OutputSettings::default()
+        };
+        //4px chunks, each dithered into 2x2
+        assert_eq!(
+            source_chunk_colour(&image, legacy, 3, 5),
+            source_chunk_colour(&image, legacy, 2, 4)
+        );
+        assert_eq!(
+            source_chunk_colour(&image, legacy, 2, 4),
+            //15 pixels of the block's colour, and its black corner
+            Some(Rgba([34, 99, 120, 255]))
+        );
+        //past the last chunk
+        assert_eq!(source_chunk_colour(&image, legacy, 16, 0), None);
+
+        for invalid in [
+            OutputSettings {
+                output_px_size: 0,
+                ..legacy
+            },
+            OutputSettings {
+                output_px_size: MAX_OUTPUT_PX_SIZE + 1,
+                ..legacy
+            },
+            OutputSettings {
+                dithering_scale: 0,
+                ..legacy
+            },
+        ] {
+            assert_eq!(
+                source_chunk_colour(&image, invalid, 0, 0),
+                None,
+                "{invalid:?}"
+            );
+        }
+        //chunks that fit the width but not the height
+        let wide = OutputSettings {
+            output_px_size: 5,
+            ..legacy
+        };
+        assert_eq!(
+            source_chunk_colour(&DynamicImage::new_rgb8(32, 8), wide, 0, 0),
+            None
+        );
+        assert_eq!(
+            source_chunk_colour(&DynamicImage::new_rgb8(0, 0), legacy, 0, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn heatmaps_match_each_pixels_source_chunk() {
+        let image = DynamicImage::ImageRgba8(blocks(40, 24, (4, 4), (1, 0)));
+        let palette = [RED, GREEN, BLUE, Rgba([255, 255, 255, 255])];
+        for (dither_mode, dithering_scale) in [(DitherMode::None, 1), (DitherMode::Legacy, 2)] {
+            let settings = OutputSettings {
+                output_px_size: 3,
+                dither_mode,
+                dithering_scale,
+                scale_output_to_original: false,
+                ..OutputSettings::default()
+            };
+            let output = dither(&image, palette, settings).unwrap();
+            //bigger than the output, so that some pixels don't have a chunk
+            let mut padded = DynamicImage::new_rgba8(output.width() + 3, output.height() + 2);
+            image::imageops::replace(&mut padded, &output, 0, 0);
+
+            let (heatmap, max_distance) =
+                difference_heatmap(&image, &padded, settings, DistanceAlgorithm::Euclidean);
+            let distances: Vec<_> = padded
+                .pixels()
+                .map(|(x, y, px)| {
+                    source_chunk_colour(&image, settings, x, y).map_or(0, |source| {
+                        DistanceAlgorithm::Euclidean.distance(px, source)
+                    })
+                })
+                .collect();
+            assert_eq!(
+                max_distance,
+                distances.iter().copied().max().unwrap().max(1)
+            );
+            for ((x, y, px), distance) in heatmap.pixels().zip(distances) {
+                assert_eq!(
+                    px,
+                    heatmap_colour(distance as f32 / max_distance as f32),
+                    "{x}, {y} with {settings:?}"
+                );
             }
+        }
 
-            for px_x in (output_settings.dithering_scale * chunk_x)
-                ..(output_settings.dithering_scale * (chunk_x + 1))
-            {
-                for px_y in (output_settings.dithering_scale * chunk_y)
-                    ..(output_settings.dithering_scale * (chunk_y + 1))
-                {
-                    let mut is_even_px = px_y % 2 == 0;
-                    if px_x % 2 == 0 {
-                        is_even_px = !is_even_px;
-                    }
-                    is_even_px &= output_settings.dithering_scale > 1;
+        //settings that wouldn't dither have nothing to compare against
+        let invalid = OutputSettings {
+            output_px_size: 0,
+            ..OutputSettings::default()
+        };
+        let (heatmap, max_distance) =
+            difference_heatmap(&image, &image, invalid, DistanceAlgorithm::Euclidean);
+        assert_eq!(max_distance, 1);
+        assert!(heatmap.pixels().all(|(_, _, px)| px == heatmap_colour(0.0)));
+    }
 
-                    output.put_pixel(px_x, px_y, if is_even_px { first } else { second });
-                }
+    #[test]
+    fn grey_palettes_match_the_generic_path() {
+        //a grey for each 8x8 block, with a darker pixel in each so that no chunk has a tie
+        let grey = DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, y| {
+            let level = ((x / 8 * 29 + y / 8 * 71) % 256) as u8;
+            if x % 8 == 0 && y % 8 == 0 {
+                image::Luma([level / 2])
+            } else {
+                image::Luma([level])
+            }
+        }));
+        let stop = CancellationToken::new();
+        for dist_algo in [DistanceAlgorithm::Luminance, DistanceAlgorithm::Value] {
+            for settings in [
+                PaletteSettings {
+                    chunks_per_dimension: 8,
+                    closeness_threshold: 0,
+                    ..PaletteSettings::default()
+                },
+                PaletteSettings {
+                    chunks_per_dimension: 8,
+                    closeness_threshold: 20,
+                    exclude_colors: vec![Rgba([100, 100, 100, 255])],
+                    exclude_threshold: 10,
+                    extra_colors: vec![Rgba([255, 0, 0, 255])],
+                },
+            ] {
+                let (fast, fast_stats) =
+                    get_palette_with_stats(&grey, settings.clone(), dist_algo, &NoProgress, &stop)
+                        .unwrap();
+                let (generic, generic_stats) =
+                    palette_and_stats_from_source(&grey, settings, dist_algo, &NoProgress, &stop)
+                        .unwrap();
+                assert_eq!(fast, generic, "{dist_algo:?}");
+                assert!(fast.len() > 2);
+                assert_eq!(fast_stats.chunks_processed, generic_stats.chunks_processed);
+                assert!(fast
+                    .iter()
+                    .filter(|colour| **colour != Rgba([255, 0, 0, 255]))
+                    .all(|Rgba([r, g, b, _])| r == g && g == b));
             }
+        }
+    }
 
-            chunks_progress_bar += 1;
-            let _ = progress_sender.send((chunks_progress_bar, total_chunks));
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    #[test]
+    fn palettes_work_like_slices() {
+        let palette = Palette::from_colours([BLUE, RED, GREEN]);
+        assert_eq!(palette.len(), 3);
+        assert!(!palette.is_empty());
+        assert_eq!(palette[1], RED);
+        assert_eq!(
+            palette.iter().copied().collect::<Vec<_>>(),
+            [BLUE, RED, GREEN]
+        );
+        assert_eq!((&palette).into_iter().count(), 3);
+        assert!(palette.contains(&GREEN));
+
+        //anything that took a slice before still takes a palette
+        fn takes_a_slice(colours: &[Rgba<u8>]) -> usize {
+            colours.len()
         }
+        assert_eq!(takes_a_slice(&palette), 3);
+        assert_eq!(takes_a_slice(palette.as_ref()), 3);
+        assert_eq!(Vec::from(palette.clone()), [BLUE, RED, GREEN]);
+        assert_eq!(palette.clone().into_iter().next(), Some(BLUE));
+        assert_eq!(Palette::from(vec![BLUE, RED, GREEN]), palette);
+        assert!(Palette::default().is_empty());
     }
 
-    pixel_perfect_scale(output_settings, &output)
-}
+    #[test]
+    fn palettes_sort_by_hue_or_a_custom_key() {
+        let mut palette = Palette::from_colours([BLUE, GREEN, RED]);
+        palette.sort(PaletteSortOrder::Hue);
+        assert_eq!(*palette, [RED, GREEN, BLUE]);
 
-pub fn pixel_perfect_scale(output_settings: OutputSettings, from: &DynamicImage) -> DynamicImage {
-    if !output_settings.scale_output_to_original {
-        return from.clone();
+        let by_blue = PaletteSortOrder::CustomKey(|colour| u64::from(u8::MAX - colour.0[2]));
+        assert_eq!(*palette.clone().sorted(by_blue), [BLUE, RED, GREEN]);
+        //red and green have the same key, so stay in the order they were in
+        let reversed = Palette::from_colours([GREEN, RED, BLUE]).sorted(by_blue);
+        assert_eq!(*reversed, [BLUE, GREEN, RED]);
     }
 
-    let scaling_factor =
-        (1 << (output_settings.output_px_size - 1)) / output_settings.dithering_scale;
+    #[test]
+    fn nearest_colours_are_found() {
+        let palette = Palette::from_colours([RED, GREEN, BLUE]);
+        let algo = DistanceAlgorithm::Euclidean;
+        let reddish = Rgba([200, 20, 0, 255]);
+        assert_eq!(palette.nearest(RED, algo), Some((0, 0)));
+        let (index, distance) = palette.nearest(reddish, algo).unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(distance, algo.distance(RED, reddish));
 
-    let (final_w, final_h) = (
-        from.width() * scaling_factor,
-        from.height() * scaling_factor,
-    );
-    let mut final_img = DynamicImage::new(final_w, final_h, ColorType::Rgb8);
+        let (first, first_distance, second) = palette.nearest_two(reddish, algo).unwrap();
+        assert_eq!((first, first_distance), (0, algo.distance(RED, reddish)));
+        assert_eq!(second, Some((1, algo.distance(GREEN, reddish))));
 
-    for x in 0..from.width() {
-        for y in 0..from.height() {
-            let px = from.get_pixel(x, y);
+        let single = Palette::from_colours([RED]);
+        assert_eq!(single.nearest_two(reddish, algo).unwrap().2, None);
+        assert_eq!(Palette::default().nearest(reddish, algo), None);
+        assert_eq!(Palette::default().nearest_two(reddish, algo), None);
+    }
 
-            for px_x in (scaling_factor * x)..(scaling_factor * (x + 1)) {
-                for px_y in (scaling_factor * y)..(scaling_factor * (y + 1)) {
-                    final_img.put_pixel(px_x, px_y, px);
+    //what `nearest_two` should come out with, by sorting every colour by its distance and then its index
+    fn nearest_two_by_brute_force(
+        palette: &[Rgba<u8>],
+        target: Rgba<u8>,
+        algo: DistanceAlgorithm,
+    ) -> (usize, u32, Option<(usize, u32)>) {
+        let mut by_distance: Vec<_> = palette
+            .iter()
+            .enumerate()
+            .map(|(i, colour)| (algo.distance(*colour, target), i))
+            .collect();
+        by_distance.sort_unstable();
+        let (first_distance, first) = by_distance[0];
+        let second = by_distance.get(1).map(|&(dist, i)| (i, dist));
+        (first, first_distance, second)
+    }
+
+    #[test]
+    fn single_colour_palettes_have_no_second() {
+        for &algo in ALL_ALGOS {
+            for target in [RED, BLUE, Rgba([1, 2, 3, 0])] {
+                let (first, first_distance, second) = nearest_two(&[GREEN], target, algo);
+                assert_eq!(first, 0);
+                assert_eq!(first_distance, algo.distance(GREEN, target));
+                assert_eq!(second, None, "{algo:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn exact_matches_are_no_distance_away() {
+        let palette = [RED, GREEN, BLUE, Rgba([10, 20, 30, 255])];
+        for &algo in ALL_ALGOS {
+            for (i, &colour) in palette.iter().enumerate() {
+                let (first, first_distance, second) = nearest_two(&palette, colour, algo);
+                assert_eq!(first_distance, 0, "{algo:?}");
+                //value and luminance see different colours as the same, so an earlier one can win
+                assert!(first <= i, "{algo:?}");
+                assert_eq!(algo.distance(palette[first], colour), 0);
+                let (second, second_distance) = second.unwrap();
+                assert_ne!(second, first);
+                assert_eq!(second_distance, algo.distance(palette[second], colour));
+            }
+        }
+        for algo in [
+            DistanceAlgorithm::Euclidean,
+            DistanceAlgorithm::HSVEuclidean,
+            DistanceAlgorithm::Manhattan,
+        ] {
+            for (i, &colour) in palette.iter().enumerate() {
+                assert_eq!(nearest_two(&palette, colour, algo).0, i, "{algo:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn ties_go_to_the_earliest_colour() {
+        let algo = DistanceAlgorithm::Euclidean;
+        let grey = Rgba([100, 100, 100, 255]);
+        let lighter = Rgba([110, 100, 100, 255]);
+        let darker = Rgba([90, 100, 100, 255]);
+        let far = Rgba([0, 0, 0, 255]);
+        let dist = algo.distance(lighter, grey);
+        assert_eq!(dist, algo.distance(darker, grey));
+
+        //a tie for first puts the later colour second
+        assert_eq!(
+            nearest_two(&[lighter, darker, far], grey, algo),
+            (0, dist, Some((1, dist)))
+        );
+        assert_eq!(
+            nearest_two(&[far, darker, lighter], grey, algo),
+            (1, dist, Some((2, dist)))
+        );
+        //a tie for second goes to the earlier one too
+        assert_eq!(
+            nearest_two(&[grey, far, lighter, darker], grey, algo),
+            (0, 0, Some((2, dist)))
+        );
+        //as does a palette that's the same colour over and over
+        assert_eq!(
+            nearest_two(&[far; 4], grey, algo),
+            (
+                0,
+                algo.distance(far, grey),
+                Some((1, algo.distance(far, grey)))
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic = "the palette shouldn't be empty"]
+    fn empty_palettes_panic() {
+        nearest_two(&[], RED, DistanceAlgorithm::Euclidean);
+    }
+
+    #[test]
+    fn merged_palettes_skip_close_colours() {
+        let algo = DistanceAlgorithm::Euclidean;
+        let nearly_red = Rgba([250, 5, 0, 255]);
+        let first = Palette::from_colours([RED, GREEN]);
+        let second = Palette::from_colours([nearly_red, GREEN, BLUE]);
+        assert_eq!(
+            *first.clone().merge(second.clone(), 20, algo),
+            [RED, GREEN, BLUE]
+        );
+        //only exact repeats go without a threshold
+        assert_eq!(
+            *first.merge(second, 0, algo),
+            [RED, GREEN, nearly_red, BLUE]
+        );
+    }
+
+    #[test]
+    fn palettes_save_and_load() {
+        let palette = Palette::from_colours([RED, GREEN, BLUE]);
+        for extension in ["gpl", "hex", "act"] {
+            let path = std::env::temp_dir().join(format!(
+                "pxls-test-{}-palette.{extension}",
+                std::process::id()
+            ));
+            palette.save(&path).unwrap();
+            assert_eq!(Palette::load(&path).unwrap(), palette, "{extension}");
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(palette.save(Path::new("palette.unknown")).is_err());
+    }
+
+    #[test]
+    fn palettes_are_displayed_as_hex() {
+        let palette = Palette::from_colours([RED, BLUE]);
+        assert_eq!(palette.to_string(), "#FF0000, #0000FF");
+        //sorted, so the order doesn't matter
+        assert_eq!(
+            format!("{palette:?}"),
+            format!("{:?}", Palette::from_colours([BLUE, RED]))
+        );
+    }
+
+    #[test]
+    fn fractions_only_dither_past_the_boundary() {
+        let half = DitheringMode::Fraction(0.5);
+        //exactly on it
+        assert!(!half.should_dither(1, 2, 0));
+        assert!(half.should_dither(3, 5, 0));
+        assert!(!half.should_dither(2, 5, 0));
+        assert!(half.should_dither(0, 0, 0));
+
+        assert!(!DitheringMode::Fraction(0.0).should_dither(5, 5, 0));
+        assert!(DitheringMode::Fraction(1.0).should_dither(1, 100, 0));
+        //an exact match for the first colour
+        assert!(!DitheringMode::Fraction(1.0).should_dither(0, 100, 0));
+    }
+
+    #[test]
+    fn no_ratio_always_dithers() {
+        let none = DitheringMode::Ratio(0);
+        assert!(none.should_dither(0, u32::MAX, 0));
+        assert!(none.should_dither(5, 5, 0));
+        //higher ratios dither less
+        assert!(DitheringMode::Ratio(1).should_dither(10, 20, 10));
+        assert!(!DitheringMode::Ratio(2).should_dither(10, 20, 10));
+    }
+
+    #[test]
+    fn candidates_are_one_per_chunk_column_by_column() {
+        let image = blocks(8, 4, (2, 2), (0, 0));
+        let source = ViewSource(&image);
+        let palette = [RED, GREEN, BLUE];
+        let candidates = compute_dither_candidates(
+            &source,
+            &palette,
+            2,
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+        );
+        let chunks: Vec<_> = candidates
+            .iter()
+            .map(|candidate| (candidate.chunk_x, candidate.chunk_y))
+            .collect();
+        assert_eq!(
+            chunks,
+            [
+                (0, 0),
+                (0, 1),
+                (1, 0),
+                (1, 1),
+                (2, 0),
+                (2, 1),
+                (3, 0),
+                (3, 1)
+            ]
+        );
+        assert!(candidates
+            .iter()
+            .all(|candidate| palette.contains(&candidate.first)));
+
+        //none for anything that dithering would turn down
+        let no_ratio = OutputSettings {
+            dithering_mode: DitheringMode::Ratio(0),
+            ..OutputSettings::default()
+        };
+        for (palette, output_settings) in [
+            (&palette[..], no_ratio),
+            (&[][..], OutputSettings::default()),
+        ] {
+            assert!(compute_dither_candidates(
+                &source,
+                palette,
+                2,
+                DistanceAlgorithm::Euclidean,
+                output_settings,
+            )
+            .is_empty());
+        }
+    }
+
+    #[test]
+    fn canonical_is_idempotent() {
+        for settings in output_settings_grid() {
+            assert_eq!(settings.canonical().canonical(), settings.canonical());
+        }
+    }
+
+    #[test]
+    fn canonical_ignores_fields_that_dont_change_the_output() {
+        let legacy = OutputSettings {
+            dithering_scale: 1,
+            ..OutputSettings::default()
+        };
+        let other_mode = OutputSettings {
+            dithering_mode: DitheringMode::Fraction(0.25),
+            ..legacy
+        };
+        assert_ne!(legacy, other_mode);
+        assert_eq!(legacy.canonical(), other_mode.canonical());
+
+        let bayer = OutputSettings {
+            dither_mode: DitherMode::Bayer {
+                strength: DitherMode::DEFAULT_STRENGTH,
+            },
+            ..OutputSettings::default()
+        };
+        let other_scale = OutputSettings {
+            dithering_scale: 3,
+            dithering_mode: DitheringMode::Ratio(9),
+            ..bayer
+        };
+        assert_ne!(bayer, other_scale);
+        assert_eq!(bayer.canonical(), other_scale.canonical());
+
+        //with a checkerboard, the mode matters
+        let checkerboard = OutputSettings::default();
+        let other_ratio = OutputSettings {
+            dithering_mode: DitheringMode::Ratio(9),
+            ..checkerboard
+        };
+        assert_ne!(checkerboard.canonical(), other_ratio.canonical());
+    }
+
+    #[test]
+    fn canonical_equality_matches_the_old_eq() {
+        let grid = output_settings_grid();
+        for &a in &grid {
+            for &b in &grid {
+                assert_eq!(
+                    a.canonical() == b.canonical(),
+                    old_output_settings_eq(a, b),
+                    "{a:?} and {b:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn palette_stats_count_every_chunk_and_pixel() {
+        let image = gradient(64, 48);
+        let settings = PaletteSettings {
+            chunks_per_dimension: 8,
+            ..PaletteSettings::default()
+        };
+        let (palette, stats) = get_palette_with_stats(
+            &image,
+            settings,
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        assert_eq!(stats.chunks_processed, 64);
+        assert_eq!(stats.pixels_sampled, 64 * 48);
+        assert_eq!(stats.palette_size, palette.len());
+        assert!(stats.duration > Duration::ZERO);
+        let cache_hit_rate = stats.cache_hit_rate.unwrap();
+        assert!((0.0..=1.0).contains(&cache_hit_rate));
+    }
+
+    #[test]
+    fn dither_stats_count_every_chunk_and_pixel() {
+        let image = gradient(64, 48);
+        let palette = [Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])];
+        let (output, stats) = dither_original_with_stats(
+            &image,
+            palette,
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        //the default virtual pixels are 16px, so that's 4 by 3 chunks
+        assert_eq!(stats.chunks_processed, 12);
+        assert_eq!(stats.pixels_sampled, 64 * 48);
+        assert_eq!(stats.palette_size, 2);
+        assert!(stats.duration > Duration::ZERO);
+        assert_eq!(stats.cache_hit_rate, None);
+        assert_eq!(output.dimensions(), (64, 48));
+    }
+
+    #[cfg(feature = "serde")]
+    fn round_trip<T: Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn settings_round_trip() {
+        let palette_settings = PaletteSettings {
+            chunks_per_dimension: 12,
+            closeness_threshold: 300,
+            exclude_colors: vec![Rgba([1, 2, 3, 255])],
+            exclude_threshold: 4,
+            extra_colors: vec![Rgba([5, 6, 7, 255]), Rgba([8, 9, 10, 255])],
+        };
+        assert_eq!(round_trip(&palette_settings), palette_settings);
+
+        for &dither_mode in ALL_DITHER_MODES {
+            for dithering_mode in [DitheringMode::Ratio(3), DitheringMode::Fraction(0.75)] {
+                let output_settings = OutputSettings {
+                    dither_mode,
+                    dithering_mode,
+                    post_sharpen: 0.5,
+                    ..OutputSettings::default()
+                };
+                assert_eq!(round_trip(&output_settings), output_settings);
+            }
+        }
+
+        for &algorithm in ALL_ALGOS {
+            assert_eq!(round_trip(&algorithm), algorithm);
+        }
+
+        let palette = Palette::from_colours([Rgba([255, 0, 0, 255]), Rgba([0, 0, 0, 128])]);
+        assert_eq!(round_trip(&palette), palette);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn older_settings_get_defaults_for_newer_fields() {
+        //from before `extra_colors` and `post_sharpen` were added
+        let palette_settings: PaletteSettings = serde_json::from_str(
+            r#"{"chunks_per_dimension": 20, "closeness_threshold": 60, "exclude_colors": [], "exclude_threshold": 5}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            palette_settings,
+            PaletteSettings {
+                chunks_per_dimension: 20,
+                closeness_threshold: 60,
+                exclude_colors: vec![],
+                exclude_threshold: 5,
+                extra_colors: vec![],
+            }
+        );
+
+        let output_settings: OutputSettings = serde_json::from_str(
+            r#"{"output_px_size": 3, "dither_mode": "None", "dithering_mode": {"Ratio": 2}, "dithering_scale": 1, "scale_output_to_original": false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            output_settings,
+            OutputSettings {
+                output_px_size: 3,
+                dither_mode: DitherMode::None,
+                dithering_mode: DitheringMode::Ratio(2),
+                dithering_scale: 1,
+                scale_output_to_original: false,
+                post_sharpen: 0.0,
+            }
+        );
+    }
+
+    //any size at all without having to allocate it, with pixels made up from where they are
+    struct Generated(u32, u32);
+
+    impl GenericImageView for Generated {
+        type Pixel = Rgba<u8>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            (self.0, self.1)
+        }
+
+        fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+            Rgba([x as u8, y as u8, (x ^ y) as u8, 255])
+        }
+    }
+
+    impl PixelSource for Generated {}
+
+    //small enough to get through quickly, but with the edge cases turning up often
+    fn dimension() -> impl Strategy<Value = u32> {
+        prop_oneof![Just(0), Just(1), 2..48_u32]
+    }
+
+    fn output_settings_strategy() -> impl Strategy<Value = OutputSettings> {
+        (
+            //big virtual pixels scale tiny images up to hundreds of megapixels, which is fine but slow
+            prop_oneof![
+                0..=6_u32,
+                Just(MAX_OUTPUT_PX_SIZE),
+                Just(MAX_OUTPUT_PX_SIZE + 1),
+                Just(u32::MAX)
+            ],
+            prop::sample::select(ALL_DITHER_MODES),
+            0..=8_u32,
+            0..=4_u32,
+            any::<bool>(),
+        )
+            .prop_map(
+                |(
+                    output_px_size,
+                    dither_mode,
+                    ratio,
+                    dithering_scale,
+                    scale_output_to_original,
+                )| {
+                    OutputSettings {
+                        output_px_size,
+                        dither_mode,
+                        dithering_mode: DitheringMode::Ratio(ratio),
+                        dithering_scale,
+                        scale_output_to_original,
+                        post_sharpen: 0.0,
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn palettes_of_any_size_dont_panic(
+            width in dimension(),
+            height in dimension(),
+            chunks_per_dimension in 0..=64_u32,
+            closeness_threshold in 0..=800_u32,
+            algorithm in prop::sample::select(ALL_ALGOS),
+        ) {
+            let settings = PaletteSettings {
+                chunks_per_dimension,
+                closeness_threshold,
+                ..PaletteSettings::default()
+            };
+            let result = get_palette_from_source(
+                &Generated(width, height),
+                settings.clone(),
+                algorithm,
+                &NoProgress,
+                &CancellationToken::new(),
+            );
+            let invalid = settings.validated(algorithm).is_err();
+            match result {
+                Err(PxlsError::InvalidSettings(_)) => prop_assert!(invalid),
+                Err(PxlsError::ZeroDimension) => {
+                    prop_assert!(!invalid && (width == 0 || height == 0));
                 }
+                Ok(palette) => prop_assert!(!invalid && !palette.is_empty()),
+                Err(e) => prop_assert!(false, "unexpected error: {}", e),
             }
         }
+
+        #[test]
+        fn dithers_of_any_size_dont_panic(
+            width in dimension(),
+            height in dimension(),
+            output_settings in output_settings_strategy(),
+            algorithm in prop::sample::select(ALL_ALGOS),
+        ) {
+            let result = dither_source_with_palette(
+                &Generated(width, height),
+                [Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])],
+                algorithm,
+                output_settings,
+                &NoProgress,
+                &CancellationToken::new(),
+            );
+            if let Ok(output) = result {
+                prop_assert!(width > 0 && height > 0);
+                prop_assert_eq!(
+                    output.dimensions(),
+                    predicted_output_size((width, height), output_settings)
+                );
+            }
+        }
+
+        //few enough values per channel that ties turn up all the time
+        #[test]
+        fn nearest_two_matches_a_brute_force_search(
+            palette in prop::collection::vec(
+                prop::array::uniform4(prop::sample::select(&[0_u8, 1, 127, 128, 255][..])),
+                1..16,
+            ),
+            target in prop::array::uniform4(any::<u8>()),
+            algo in prop::sample::select(ALL_ALGOS),
+        ) {
+            let palette: Vec<_> = palette.into_iter().map(Rgba).collect();
+            let target = Rgba(target);
+            prop_assert_eq!(
+                nearest_two(&palette, target, algo),
+                nearest_two_by_brute_force(&palette, target, algo)
+            );
+        }
+
+        #[test]
+        fn scaling_any_size_doesnt_panic(
+            width in dimension(),
+            height in dimension(),
+            output_settings in output_settings_strategy(),
+        ) {
+            let _ = pixel_perfect_scale(output_settings, &DynamicImage::new_rgb8(width, height));
+        }
     }
 
-    final_img
+    #[test]
+    fn huge_empty_images_are_errors() {
+        for (width, height) in [(u32::MAX, 0), (0, u32::MAX)] {
+            assert_eq!(
+                get_palette_from_source(
+                    &Generated(width, height),
+                    PaletteSettings::default(),
+                    DistanceAlgorithm::Euclidean,
+                    &NoProgress,
+                    &CancellationToken::new(),
+                ),
+                Err(PxlsError::ZeroDimension)
+            );
+            assert_eq!(
+                dither_source_with_palette(
+                    &Generated(width, height),
+                    [Rgba([0, 0, 0, 255])],
+                    DistanceAlgorithm::Euclidean,
+                    OutputSettings::default(),
+                    &NoProgress,
+                    &CancellationToken::new(),
+                ),
+                Err(PxlsError::ZeroDimension)
+            );
+        }
+    }
+
+    #[test]
+    fn huge_thin_images_are_too_small_to_dither() {
+        //u32::MAX's nearest factor to the default 16px virtual pixels is 17, which doesn't fit in 1px
+        assert_eq!(
+            dither_source_with_palette(
+                &Generated(u32::MAX, 1),
+                [Rgba([0, 0, 0, 255])],
+                DistanceAlgorithm::Euclidean,
+                OutputSettings::default(),
+                &NoProgress,
+                &CancellationToken::new(),
+            ),
+            Err(PxlsError::ImageTooSmall { needed: 17, got: 1 })
+        );
+    }
+
+    #[test]
+    fn scaling_an_empty_image_too_wide_is_an_error() {
+        //no pixels, but 16 times 2^28 doesn't fit in a u32
+        let settings = OutputSettings {
+            output_px_size: 29,
+            dither_mode: DitherMode::None,
+            ..OutputSettings::default()
+        };
+        assert!(matches!(
+            pixel_perfect_scale(settings, &DynamicImage::new_rgb8(16, 0)),
+            Err(PxlsError::Scale(ScaleError::TooManyPixels { .. }))
+        ));
+    }
+
+    #[test]
+    fn scaling_an_empty_image_makes_an_empty_image() {
+        let scaled =
+            pixel_perfect_scale(OutputSettings::default(), &DynamicImage::new_rgb8(0, 0)).unwrap();
+        assert_eq!(scaled.dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn zero_chunks_is_invalid() {
+        let settings = PaletteSettings {
+            chunks_per_dimension: 0,
+            ..PaletteSettings::default()
+        };
+        assert_eq!(
+            settings.validated(DistanceAlgorithm::Euclidean),
+            Err(ValidationError::NoChunks)
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    mod spans {
+        use super::*;
+        use std::{collections::BTreeMap, sync::Mutex};
+        use tracing::{
+            field::{Field, Visit},
+            span::{Attributes, Id, Record},
+            Event, Subscriber,
+        };
+        use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+        type Fields = BTreeMap<&'static str, String>;
+
+        struct FieldsVisitor<'a>(&'a mut Fields);
+
+        impl Visit for FieldsVisitor<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.insert(field.name(), format!("{value:?}"));
+            }
+        }
+
+        //every span with its fields once it's closed, and every event
+        #[derive(Clone, Default)]
+        struct Captured {
+            spans: Arc<Mutex<Vec<(&'static str, Fields)>>>,
+            events: Arc<Mutex<Vec<Fields>>>,
+        }
+
+        impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Captured {
+            fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+                let mut fields = Fields::new();
+                attrs.record(&mut FieldsVisitor(&mut fields));
+                ctx.span(id).unwrap().extensions_mut().insert(fields);
+            }
+
+            fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+                let span = ctx.span(id).unwrap();
+                let mut extensions = span.extensions_mut();
+                values.record(&mut FieldsVisitor(extensions.get_mut::<Fields>().unwrap()));
+            }
+
+            fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+                let mut fields = Fields::new();
+                event.record(&mut FieldsVisitor(&mut fields));
+                self.events.lock().unwrap().push(fields);
+            }
+
+            fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+                let span = ctx.span(&id).unwrap();
+                let fields = span.extensions_mut().remove::<Fields>().unwrap();
+                self.spans.lock().unwrap().push((span.name(), fields));
+            }
+        }
+
+        fn fields(pairs: &[(&'static str, &str)]) -> Fields {
+            pairs
+                .iter()
+                .map(|(name, value)| (*name, (*value).to_string()))
+                .collect()
+        }
+
+        #[test]
+        fn the_pipeline_is_traced() {
+            let captured = Captured::default();
+            let image = gradient(64, 32);
+            let output_settings = OutputSettings {
+                output_px_size: 4,
+                dither_mode: DitherMode::Bayer { strength: 50 },
+                ..OutputSettings::default()
+            };
+            //the closest size that fits, rather than the one asked for
+            let px_size = dither_chunk_size((64, 32), output_settings).unwrap();
+            let (grid_width, grid_height) = (64 / px_size, 32 / px_size);
+            let (palette, output) = tracing::subscriber::with_default(
+                tracing_subscriber::registry().with(captured.clone()),
+                || {
+                    let stop = CancellationToken::new();
+                    let palette = get_palette(
+                        &image,
+                        PaletteSettings {
+                            chunks_per_dimension: 8,
+                            ..palette_settings(20)
+                        },
+                        DistanceAlgorithm::Manhattan,
+                        &NoProgress,
+                        &stop,
+                    )
+                    .unwrap();
+                    let output = dither_original_with_palette(
+                        &image,
+                        &palette,
+                        DistanceAlgorithm::Manhattan,
+                        output_settings,
+                        &NoProgress,
+                        &stop,
+                    )
+                    .unwrap();
+                    pixel_perfect_scale(output_settings, &output).unwrap();
+                    (palette, output)
+                },
+            );
+
+            let spans = captured.spans.lock().unwrap();
+            let span = |name| {
+                spans
+                    .iter()
+                    .find(|(span_name, _)| *span_name == name)
+                    .unwrap_or_else(|| panic!("no {name} span in {spans:?}"))
+                    .1
+                    .clone()
+            };
+            assert_eq!(
+                span("get_palette"),
+                fields(&[
+                    ("width", "64"),
+                    ("height", "32"),
+                    ("chunks_per_dimension", "8"),
+                    ("algorithm", "Manhattan"),
+                    ("chunks", "64"),
+                    ("palette_len", &palette.len().to_string()),
+                ])
+            );
+            assert_eq!(
+                span("dither_original_with_palette"),
+                fields(&[
+                    ("width", "64"),
+                    ("height", "32"),
+                    ("palette_len", &palette.len().to_string()),
+                    ("algorithm", "Manhattan"),
+                    ("dither_mode", "Bayer { strength: 50 }"),
+                    ("chunks", &(grid_width * grid_height).to_string()),
+                ])
+            );
+            assert_eq!(
+                span("pixel_perfect_scale"),
+                fields(&[
+                    ("width", &output.width().to_string()),
+                    ("height", &output.height().to_string()),
+                    ("scale_output_to_original", "true"),
+                ])
+            );
+        }
+
+        #[test]
+        fn progress_is_traced_every_tenth() {
+            let captured = Captured::default();
+            tracing::subscriber::with_default(
+                tracing_subscriber::registry().with(captured.clone()),
+                || {
+                    for done in 1..=95 {
+                        NoProgress.tick(progress::Stage::Dithering, done, 95);
+                    }
+                    //nothing to be a tenth of
+                    NoProgress.tick(progress::Stage::Palette, 0, 0);
+                },
+            );
+
+            let events = captured.events.lock().unwrap();
+            let done: Vec<_> = events
+                .iter()
+                .map(|event| {
+                    assert_eq!(event["message"], "progress");
+                    assert_eq!(event["stage"], "Dithering");
+                    assert_eq!(event["total"], "95");
+                    event["done"].parse::<u32>().unwrap()
+                })
+                .collect();
+            assert_eq!(done, [10, 19, 29, 38, 48, 57, 67, 76, 86, 95]);
+        }
+    }
 }
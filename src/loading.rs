@@ -0,0 +1,118 @@
+use anyhow::{anyhow, bail, Context};
+use image::{
+    DynamicImage, GrayAlphaImage, GrayImage, ImageFormat, ImageReader, RgbImage, RgbaImage,
+};
+use std::{
+    fmt::{Display, Formatter},
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+//how an image that wouldn't decode normally got loaded anyway
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    //any rows that couldn't be read are left blank
+    PartialPng { rows_read: u32, height: u32 },
+    LenientJpeg,
+}
+
+impl Display for Recovery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PartialPng { rows_read, height } if rows_read == height => {
+                write!(f, "the PNG was read ignoring its checksums")
+            }
+            Self::PartialPng { rows_read, height } => {
+                write!(
+                    f,
+                    "only {rows_read} of the PNG's {height} rows could be read"
+                )
+            }
+            Self::LenientJpeg => write!(f, "the JPEG was read with a more lenient decoder"),
+        }
+    }
+}
+
+//for truncated or slightly corrupt files, eg. from a partial download
+pub fn load_image_best_effort(path: &Path) -> anyhow::Result<(DynamicImage, Option<Recovery>)> {
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    let format = reader.format();
+    let original = match reader.decode() {
+        Ok(image) => return Ok((image, None)),
+        Err(e) => e,
+    };
+
+    let (attempted, fallback) = match format {
+        Some(ImageFormat::Png) => ("reading it as a truncated PNG", load_partial_png(path)),
+        Some(ImageFormat::Jpeg) => (
+            "reading it with a more lenient JPEG decoder",
+            load_lenient_jpeg(path).map(|image| (image, Recovery::LenientJpeg)),
+        ),
+        _ => return Err(original.into()),
+    };
+
+    fallback
+        .map(|(image, recovery)| (image, Some(recovery)))
+        .map_err(|fallback| {
+            anyhow::Error::new(original).context(format!("{attempted} also failed: {fallback}"))
+        })
+}
+
+fn load_partial_png(path: &Path) -> anyhow::Result<(DynamicImage, Recovery)> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    decoder.ignore_checksums(true);
+    decoder.set_ignore_iccp_chunk(true);
+
+    let mut reader = decoder.read_info()?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    let mut buffer = vec![0; reader.output_buffer_size()];
+
+    //interlaced rows come in several passes, so a partial one doesn't make a sensible image
+    let rows_read = if reader.info().interlaced {
+        reader.next_frame(&mut buffer)?;
+        height
+    } else {
+        let line_size = reader.output_line_size(width);
+        let mut rows_read = 0;
+        while let Ok(Some(row)) = reader.next_row() {
+            let start = rows_read as usize * line_size;
+            buffer[start..start + line_size].copy_from_slice(row.data());
+            rows_read += 1;
+        }
+        rows_read
+    };
+    if rows_read == 0 {
+        bail!("no rows could be read");
+    }
+
+    let image = match reader.output_color_type().0 {
+        png::ColorType::Grayscale => GrayImage::from_raw(width, height, buffer).map(Into::into),
+        png::ColorType::GrayscaleAlpha => {
+            GrayAlphaImage::from_raw(width, height, buffer).map(Into::into)
+        }
+        png::ColorType::Rgb => RgbImage::from_raw(width, height, buffer).map(Into::into),
+        png::ColorType::Rgba => RgbaImage::from_raw(width, height, buffer).map(Into::into),
+        png::ColorType::Indexed => None,
+    }
+    .ok_or_else(|| anyhow!("unexpected pixel layout after decoding"))?;
+
+    Ok((image, Recovery::PartialPng { rows_read, height }))
+}
+
+fn load_lenient_jpeg(path: &Path) -> anyhow::Result<DynamicImage> {
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(File::open(path)?));
+    let pixels = decoder.decode()?;
+    let info = decoder.info().context("no image info after decoding")?;
+    let (width, height) = (u32::from(info.width), u32::from(info.height));
+
+    let image = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => GrayImage::from_raw(width, height, pixels).map(Into::into),
+        jpeg_decoder::PixelFormat::RGB24 => {
+            RgbImage::from_raw(width, height, pixels).map(Into::into)
+        }
+        other => bail!("{other:?} JPEGs aren't supported"),
+    };
+    image.ok_or_else(|| anyhow!("decoded data didn't match the image size"))
+}
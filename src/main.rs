@@ -6,10 +6,15 @@
     clippy::cast_precision_loss
 )]
 
-use crate::{cli::cli_main, gui::gui_main};
+use crate::{
+    cli::{cli_main, list_algorithms},
+    gui::gui_main,
+};
 use std::env::args;
 
 mod cli;
+#[cfg(feature = "custom-palette-sort")]
+mod custom_sort;
 mod gui;
 
 fn main() {
@@ -21,8 +26,11 @@ fn main() {
         if args.len() == 1 {
             let first = args[0].to_lowercase();
             if ["--help", "-help", "-h", "--h", "help", "h", "?", "-?"].contains(&first.as_str()) {
-                eprintln!("usage: pxls [input_file] [chunks_per_dimension] [closeness_threshold] [distance_algo] [output_file] [output_virtual_pixel_size] [dithering_factor] [dithering_scale]\nor usage: pxls ask");
+                eprintln!("usage: pxls [input_file] [chunks_per_dimension] [closeness_threshold] [distance_algo] [output_file] [output_virtual_pixel_size] [dithering_factor] [dithering_scale] (--brightness n) (--contrast n) (--saturation n) (--exclude-color #RRGGBB)... (--exclude-threshold n) (--dithering-fraction f) (--dither-mode mode) (--diffusion-direction direction) (--algorithm-index n) (--sharpen f) (--export-inkscape-svg path) (--superpixels n) (--compactness f) (--histogram-peaks bin_width) (--palette-method name) (--analyze) (--rotate degrees) (--flip direction) (-v|-vv)\nor usage: pxls ask\nor usage: pxls list-algorithms");
                 std::process::exit(1);
+            } else if first == "list-algorithms" {
+                list_algorithms();
+                return;
             } else if ["a", "-a", "--a", "ask", "-ask", "--ask"].contains(&first.as_str()) {
                 should_ask = true;
             }
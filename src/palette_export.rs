@@ -0,0 +1,71 @@
+use crate::pixel_operations::rgb_to_hex;
+use image::Rgba;
+use std::fmt::Write;
+//`std`'s clock panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+const SWATCH_SIZE: usize = 20;
+const SWATCH_GAP: usize = 2;
+
+//one swatch per colour in a row, each with an id that inkscape shows as the colour's name
+pub fn palette_to_inkscape_svg(palette: &[Rgba<u8>], names: Option<&[&str]>) -> String {
+    let width = (palette.len() * (SWATCH_SIZE + SWATCH_GAP)).saturating_sub(SWATCH_GAP);
+
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{SWATCH_SIZE}\" viewBox=\"0 0 {width} {SWATCH_SIZE}\">\n  <title>Palette made by pxls {} on {}</title>\n",
+        env!("CARGO_PKG_VERSION"),
+        today()
+    );
+
+    for (i, colour) in palette.iter().copied().enumerate() {
+        //names run out before the colours do if there aren't enough of them
+        let id = names
+            .and_then(|names| names.get(i))
+            .map_or_else(|| format!("color-{i}"), |name| escape_attribute(name));
+
+        let _ = writeln!(
+            svg,
+            "  <rect id=\"{id}\" fill=\"{}\" width=\"{SWATCH_SIZE}\" height=\"{SWATCH_SIZE}\" x=\"{}\" y=\"0\"/>",
+            rgb_to_hex(colour),
+            i * (SWATCH_SIZE + SWATCH_GAP)
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+//as YYYY-MM-DD in UTC, using https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86400) as i64;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,346 @@
+use crate::pixel_operations::rgb_from_hex;
+use image::Rgba;
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    path::Path,
+};
+
+const GPL_HEADER: &str = "GIMP Palette";
+//256 rgb triples, then the number of colours used and the transparent index as big-endian u16s
+const ACT_COLOURS_LEN: usize = 256 * 3;
+const ACT_LEN: usize = ACT_COLOURS_LEN + 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PaletteFormat {
+    Gpl,
+    Hex,
+    Act,
+}
+
+impl Display for PaletteFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gpl => write!(f, "GIMP"),
+            Self::Hex => write!(f, "hex"),
+            Self::Act => write!(f, "ACT"),
+        }
+    }
+}
+
+impl PaletteFormat {
+    //`None` for anything that doesn't say which format it is, like `.txt`
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "gpl" => Some(Self::Gpl),
+            "hex" => Some(Self::Hex),
+            "act" => Some(Self::Act),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, bytes: &[u8]) -> Result<Vec<Rgba<u8>>, PaletteIoError> {
+        let invalid = |reason: String| PaletteIoError::Invalid {
+            format: self,
+            reason,
+        };
+
+        let colours = match self {
+            Self::Gpl => {
+                let text = std::str::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+                let mut lines = text.lines();
+                if lines.next().map(str::trim) != Some(GPL_HEADER) {
+                    return Err(invalid(format!("doesn't start with `{GPL_HEADER}`")));
+                }
+
+                let mut colours = vec![];
+                for line in lines.map(str::trim) {
+                    if line.is_empty()
+                        || line.starts_with('#')
+                        || line.starts_with("Name:")
+                        || line.starts_with("Columns:")
+                    {
+                        continue;
+                    }
+
+                    //anything after the three channels is the colour's name
+                    let channels: Option<Vec<u8>> = line
+                        .split_whitespace()
+                        .take(3)
+                        .map(|channel| channel.parse().ok())
+                        .collect();
+                    match channels.as_deref() {
+                        Some(&[r, g, b]) => colours.push(Rgba([r, g, b, u8::MAX])),
+                        _ => return Err(invalid(format!("`{line}` isn't a colour"))),
+                    }
+                }
+                colours
+            }
+            Self::Hex => {
+                let text = std::str::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+                text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(|line| {
+                        rgb_from_hex(line)
+                            .ok_or_else(|| invalid(format!("`{line}` isn't a colour")))
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            Self::Act => {
+                if bytes.len() != ACT_LEN && bytes.len() != ACT_COLOURS_LEN {
+                    return Err(invalid(format!(
+                        "should be {ACT_COLOURS_LEN} or {ACT_LEN} bytes, but was {}",
+                        bytes.len()
+                    )));
+                }
+
+                //older files don't have the count, and always have all 256
+                let count = bytes
+                    .get(ACT_COLOURS_LEN..ACT_COLOURS_LEN + 2)
+                    .map_or(256, |count| {
+                        usize::from(u16::from_be_bytes([count[0], count[1]]))
+                    })
+                    .clamp(1, 256);
+                bytes[..ACT_COLOURS_LEN]
+                    .chunks_exact(3)
+                    .take(count)
+                    .map(|rgb| Rgba([rgb[0], rgb[1], rgb[2], u8::MAX]))
+                    .collect()
+            }
+        };
+
+        if colours.is_empty() {
+            return Err(invalid("there aren't any colours".to_string()));
+        }
+        Ok(colours)
+    }
+
+    //alpha gets dropped, as none of the formats have anywhere to put it
+    pub fn write(self, colours: &[Rgba<u8>]) -> Result<Vec<u8>, PaletteIoError> {
+        let bytes = match self {
+            Self::Gpl => {
+                let mut text = format!("{GPL_HEADER}\nName: pxls\n#\n");
+                for Rgba([r, g, b, _]) in colours {
+                    text.push_str(&format!("{r:>3} {g:>3} {b:>3}\n"));
+                }
+                text.into_bytes()
+            }
+            Self::Hex => colours
+                .iter()
+                .map(|Rgba([r, g, b, _])| format!("{r:02x}{g:02x}{b:02x}\n"))
+                .collect::<String>()
+                .into_bytes(),
+            Self::Act => {
+                if colours.len() > 256 {
+                    return Err(PaletteIoError::Invalid {
+                        format: self,
+                        reason: format!("can only hold 256 colours, not {}", colours.len()),
+                    });
+                }
+
+                let mut bytes = vec![0; ACT_LEN];
+                for (rgb, Rgba([r, g, b, _])) in bytes.chunks_exact_mut(3).zip(colours) {
+                    rgb.copy_from_slice(&[*r, *g, *b]);
+                }
+                bytes[ACT_COLOURS_LEN..ACT_COLOURS_LEN + 2]
+                    .copy_from_slice(&(colours.len() as u16).to_be_bytes());
+                //no transparent colour
+                bytes[ACT_COLOURS_LEN + 2..].copy_from_slice(&u16::MAX.to_be_bytes());
+                bytes
+            }
+        };
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteIoError {
+    Io(std::io::Error),
+    UnknownFormat,
+    Invalid {
+        format: PaletteFormat,
+        reason: String,
+    },
+}
+
+impl Display for PaletteIoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read the palette: {e}"),
+            Self::UnknownFormat => write!(f, "couldn't work out what format the palette is in"),
+            Self::Invalid { format, reason } => write!(f, "invalid {format} palette: {reason}"),
+        }
+    }
+}
+
+impl Error for PaletteIoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PaletteIoError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+//works out the format from the contents, for when the extension doesn't say
+pub fn sniff_palette_format(bytes: &[u8]) -> Option<PaletteFormat> {
+    if bytes.starts_with(format!("{GPL_HEADER}\n").as_bytes())
+        || bytes.starts_with(format!("{GPL_HEADER}\r\n").as_bytes())
+    {
+        return Some(PaletteFormat::Gpl);
+    }
+    if bytes.len() == ACT_LEN {
+        return Some(PaletteFormat::Act);
+    }
+
+    let text = std::str::from_utf8(bytes).ok()?;
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    let is_hex = |line: &str| line.len() == 6 && line.bytes().all(|b| b.is_ascii_hexdigit());
+    if lines.clone().next().is_some() && lines.all(is_hex) {
+        return Some(PaletteFormat::Hex);
+    }
+
+    None
+}
+
+//the extension is trusted first, but renamed files still load if their contents give them away
+pub fn load_palette(path: &Path) -> Result<Vec<Rgba<u8>>, PaletteIoError> {
+    let bytes = std::fs::read(path)?;
+
+    let from_extension = PaletteFormat::from_extension(path);
+    let sniffed = sniff_palette_format(&bytes).filter(|format| Some(*format) != from_extension);
+
+    let mut error = PaletteIoError::UnknownFormat;
+    for format in from_extension.into_iter().chain(sniffed) {
+        match format.parse(&bytes) {
+            Ok(colours) => return Ok(colours),
+            //the extension's error is the more useful one if both fail
+            Err(e) => {
+                if matches!(error, PaletteIoError::UnknownFormat) {
+                    error = e;
+                }
+            }
+        }
+    }
+    Err(error)
+}
+
+//unlike loading, the extension has to say what format to write
+pub fn save_palette(path: &Path, colours: &[Rgba<u8>]) -> Result<(), PaletteIoError> {
+    let format = PaletteFormat::from_extension(path).ok_or(PaletteIoError::UnknownFormat)?;
+    std::fs::write(path, format.write(colours)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOURS: [Rgba<u8>; 3] = [
+        Rgba([255, 0, 0, 255]),
+        Rgba([0, 128, 0, 255]),
+        Rgba([18, 52, 86, 255]),
+    ];
+
+    fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("pxls-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn gpl_is_sniffed_from_its_header() {
+        assert_eq!(
+            sniff_palette_format(b"GIMP Palette\nName: x\n255 0 0\n"),
+            Some(PaletteFormat::Gpl)
+        );
+        assert_eq!(
+            sniff_palette_format(b"GIMP Palette\r\n255 0 0\r\n"),
+            Some(PaletteFormat::Gpl)
+        );
+        //has to be the whole first line
+        assert_eq!(sniff_palette_format(b"GIMP Palettes\n255 0 0\n"), None);
+        assert_eq!(sniff_palette_format(b"255 0 0\nGIMP Palette\n"), None);
+    }
+
+    #[test]
+    fn hex_is_sniffed_from_every_line() {
+        assert_eq!(
+            sniff_palette_format(b"ff0000\n00FF00\n\n  123abc \n"),
+            Some(PaletteFormat::Hex)
+        );
+        assert_eq!(sniff_palette_format(b"ff0000\n00ff0\n"), None);
+        assert_eq!(sniff_palette_format(b"ff0000\n#00ff00\n"), None);
+        assert_eq!(sniff_palette_format(b"ff0000\nzz0000\n"), None);
+        assert_eq!(sniff_palette_format(b"\n\n"), None);
+        assert_eq!(sniff_palette_format(b""), None);
+    }
+
+    #[test]
+    fn act_is_sniffed_from_its_length() {
+        assert_eq!(
+            sniff_palette_format(&[0; ACT_LEN]),
+            Some(PaletteFormat::Act)
+        );
+        assert_eq!(sniff_palette_format(&[0; ACT_LEN - 1]), None);
+        assert_eq!(sniff_palette_format(&[0; ACT_LEN + 1]), None);
+    }
+
+    #[test]
+    fn written_palettes_are_sniffed_and_parsed_back() {
+        for format in [PaletteFormat::Gpl, PaletteFormat::Hex, PaletteFormat::Act] {
+            let bytes = format.write(&COLOURS).unwrap();
+            assert_eq!(sniff_palette_format(&bytes), Some(format));
+            assert_eq!(format.parse(&bytes).unwrap(), COLOURS, "{format}");
+        }
+    }
+
+    #[test]
+    fn renamed_files_still_load() {
+        for (name, format) in [
+            ("gpl.txt", PaletteFormat::Gpl),
+            ("hex.pal", PaletteFormat::Hex),
+            ("act", PaletteFormat::Act),
+            //the extension's wrong, so it falls back to the contents
+            ("really-gpl.hex", PaletteFormat::Gpl),
+        ] {
+            let path = temp_file(name, &format.write(&COLOURS).unwrap());
+            assert_eq!(load_palette(&path).unwrap(), COLOURS, "{name}");
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn unrecognisable_files_are_an_error() {
+        let path = temp_file("unknown.txt", b"not a palette");
+        assert!(matches!(
+            load_palette(&path),
+            Err(PaletteIoError::UnknownFormat)
+        ));
+        std::fs::remove_file(path).unwrap();
+
+        //the extension's error wins if the contents don't say otherwise
+        let path = temp_file("broken.gpl", b"GIMP Palette\nnot a colour\n");
+        assert!(matches!(
+            load_palette(&path),
+            Err(PaletteIoError::Invalid {
+                format: PaletteFormat::Gpl,
+                ..
+            })
+        ));
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            load_palette(Path::new("/definitely/not/a/palette.gpl")),
+            Err(PaletteIoError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    cancellation::CancellationToken,
+    dither_original_with_stats,
+    progress::{MaxUpdates, ProgressReporter, Stage, DEFAULT_MAX_UPDATES},
+    quantizer::{BuiltInQuantizer, Quantizer},
+    DistanceAlgorithm, OutputSettings, Palette, PaletteAlgorithm, PaletteSettings,
+    PaletteSortOrder, PxlsError, Stats,
+};
+use image::DynamicImage;
+use std::{cell::RefCell, time::Duration};
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Timings {
+    //`None` if the palette was given rather than generated
+    pub palette: Option<Duration>,
+    pub dithering: Duration,
+}
+
+type ProgressCallback<'a> = Box<dyn FnMut(Stage, u32, u32) + 'a>;
+
+pub struct RenderResult {
+    pub palette: Palette,
+    pub output: DynamicImage,
+    pub timings: Timings,
+    //`None` if the palette was given rather than generated
+    pub palette_stats: Option<Stats>,
+    pub dithering_stats: Stats,
+}
+
+/// Goes from an image to its output in one go, with the defaults for anything that isn't set.
+///
+/// # Examples
+///
+/// ```
+/// use image::{DynamicImage, RgbImage};
+/// use pxls::{pipeline::Pxls, PaletteSettings};
+///
+/// let image = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+///     image::Rgb([(x * 4) as u8, (y * 4) as u8, 128])
+/// }));
+/// let mut reports = 0;
+/// let result = Pxls::new(&image)
+///     .palette_settings(PaletteSettings {
+///         closeness_threshold: 15,
+///         ..PaletteSettings::default()
+///     })
+///     .on_progress(|_stage, _done, _total| reports += 1)
+///     .run()?;
+/// assert!(!result.palette.is_empty());
+/// assert!(reports > 0);
+/// # Ok::<(), pxls::PxlsError>(())
+/// ```
+pub struct Pxls<'a> {
+    image: &'a DynamicImage,
+    palette_settings: PaletteSettings,
+    palette_algorithm: PaletteAlgorithm,
+    //takes over from `palette_algorithm` if set
+    quantizer: Option<Box<dyn Quantizer + 'a>>,
+    max_colours: Option<usize>,
+    output_settings: OutputSettings,
+    algorithm: DistanceAlgorithm,
+    sort_order: Option<PaletteSortOrder>,
+    //skips generating one if set
+    palette: Option<Palette>,
+    on_progress: Option<ProgressCallback<'a>>,
+    max_progress_updates: u32,
+    stop: CancellationToken,
+}
+
+impl<'a> Pxls<'a> {
+    pub fn new(image: &'a DynamicImage) -> Self {
+        Self {
+            image,
+            palette_settings: PaletteSettings::default(),
+            palette_algorithm: PaletteAlgorithm::default(),
+            quantizer: None,
+            max_colours: None,
+            output_settings: OutputSettings::default(),
+            algorithm: DistanceAlgorithm::Euclidean,
+            sort_order: None,
+            palette: None,
+            on_progress: None,
+            max_progress_updates: DEFAULT_MAX_UPDATES,
+            stop: CancellationToken::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn palette_settings(mut self, palette_settings: PaletteSettings) -> Self {
+        self.palette_settings = palette_settings;
+        self
+    }
+
+    #[must_use]
+    pub fn palette_algorithm(mut self, palette_algorithm: PaletteAlgorithm) -> Self {
+        self.palette_algorithm = palette_algorithm;
+        self.quantizer = None;
+        self
+    }
+
+    //for generating the palette some other way than the built-in `PaletteAlgorithm`s, in which case the palette
+    //settings are only used for anything that the quantizer was made with
+    #[must_use]
+    pub fn quantizer(mut self, quantizer: Box<dyn Quantizer + 'a>) -> Self {
+        self.quantizer = Some(quantizer);
+        self
+    }
+
+    #[must_use]
+    pub const fn max_colours(mut self, max_colours: usize) -> Self {
+        self.max_colours = Some(max_colours);
+        self
+    }
+
+    #[must_use]
+    pub const fn output_settings(mut self, output_settings: OutputSettings) -> Self {
+        self.output_settings = output_settings;
+        self
+    }
+
+    #[must_use]
+    pub const fn algorithm(mut self, algorithm: DistanceAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    //applied to generated palettes, but given ones are left in the order they came in
+    #[must_use]
+    pub const fn sort_palette(mut self, sort_order: PaletteSortOrder) -> Self {
+        self.sort_order = Some(sort_order);
+        self
+    }
+
+    #[must_use]
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    //called with how far through each stage it is, as it goes
+    #[must_use]
+    pub fn on_progress(mut self, on_progress: impl FnMut(Stage, u32, u32) + 'a) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    //how many times each stage calls `on_progress` at most, on top of the last call once it's done
+    #[must_use]
+    pub const fn max_progress_updates(mut self, max_progress_updates: u32) -> Self {
+        self.max_progress_updates = max_progress_updates;
+        self
+    }
+
+    //cancelling it, or a parent of it, from another thread stops the run with `PxlsError::Cancelled`
+    #[must_use]
+    pub fn cancel_token(mut self, stop: impl Into<CancellationToken>) -> Self {
+        self.stop = stop.into();
+        self
+    }
+
+    pub fn run_palette_only(mut self) -> Result<Palette, PxlsError> {
+        self.generate_palette().map(|(palette, _)| palette)
+    }
+
+    pub fn run(mut self) -> Result<RenderResult, PxlsError> {
+        let (palette, palette_stats) = self.generate_palette()?;
+
+        let (output, dithering_stats) = dither_original_with_stats(
+            self.image,
+            &palette,
+            self.algorithm,
+            self.output_settings,
+            &MaxUpdates(
+                ForwardProgress::to(&mut self.on_progress),
+                self.max_progress_updates,
+            ),
+            &self.stop,
+        )?;
+
+        Ok(RenderResult {
+            palette,
+            output,
+            timings: Timings {
+                palette: palette_stats.map(|stats| stats.duration),
+                dithering: dithering_stats.duration,
+            },
+            palette_stats,
+            dithering_stats,
+        })
+    }
+
+    fn generate_palette(&mut self) -> Result<(Palette, Option<Stats>), PxlsError> {
+        if let Some(palette) = self.palette.take() {
+            return Ok((palette, None));
+        }
+
+        let built_in;
+        let quantizer: &dyn Quantizer = match &self.quantizer {
+            Some(quantizer) => quantizer.as_ref(),
+            None => {
+                built_in = BuiltInQuantizer {
+                    algorithm: self.palette_algorithm,
+                    settings: self.palette_settings.clone(),
+                    dist_algo: self.algorithm,
+                };
+                &built_in
+            }
+        };
+        let (palette, stats) = quantizer.palette_with_stats(
+            self.image,
+            self.max_colours,
+            &MaxUpdates(
+                ForwardProgress::to(&mut self.on_progress),
+                self.max_progress_updates,
+            ),
+            &self.stop,
+        )?;
+        let palette = match self.sort_order {
+            Some(sort_order) => palette.sorted(sort_order),
+            None => palette,
+        };
+
+        Ok((palette, Some(stats)))
+    }
+}
+
+//the callback is `FnMut`, but reporters only get `&self`
+struct ForwardProgress<'c, 'a>(Option<RefCell<&'c mut ProgressCallback<'a>>>);
+
+impl<'c, 'a> ForwardProgress<'c, 'a> {
+    fn to(on_progress: &'c mut Option<ProgressCallback<'a>>) -> Self {
+        Self(on_progress.as_mut().map(RefCell::new))
+    }
+}
+
+impl ProgressReporter for ForwardProgress<'_, '_> {
+    fn report(&self, stage: Stage, done: u32, total: u32) {
+        if let Some(on_progress) = &self.0 {
+            (on_progress.borrow_mut())(stage, done, total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, 100, 255])
+        }))
+    }
+
+    #[test]
+    fn given_palettes_skip_generating_one() {
+        let image = gradient();
+        let palette = Palette::from(vec![Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])]);
+        let mut stages = vec![];
+        let result = Pxls::new(&image)
+            .palette(palette.clone())
+            //would be put in order if it had been generated
+            .sort_palette(PaletteSortOrder::Hue)
+            .on_progress(|stage, _, _| stages.push(stage))
+            .run()
+            .unwrap();
+
+        assert_eq!(result.palette, palette);
+        assert!(result.palette_stats.is_none());
+        assert!(result.timings.palette.is_none());
+        assert!(result
+            .output
+            .pixels()
+            .all(|(_, _, px)| palette.contains(&px)));
+        assert!(!stages.is_empty());
+        assert!(stages.iter().all(|stage| *stage == Stage::Dithering));
+    }
+
+    #[test]
+    fn palette_only_runs_dont_dither() {
+        let image = gradient();
+        let mut stages = vec![];
+        let palette = Pxls::new(&image)
+            .on_progress(|stage, _, _| stages.push(stage))
+            .run_palette_only()
+            .unwrap();
+        assert!(!palette.is_empty());
+        assert!(stages.iter().all(|stage| *stage == Stage::Palette));
+    }
+
+    #[test]
+    fn progress_is_limited_to_the_max_updates() {
+        let image = gradient();
+        let mut reports = vec![];
+        Pxls::new(&image)
+            .palette_settings(PaletteSettings {
+                chunks_per_dimension: 32,
+                ..PaletteSettings::default()
+            })
+            .max_progress_updates(4)
+            .on_progress(|stage, done, total| reports.push((stage, done, total)))
+            .run()
+            .unwrap();
+
+        for stage in [Stage::Palette, Stage::Dithering] {
+            let reports: Vec<_> = reports.iter().filter(|(s, _, _)| *s == stage).collect();
+            //the updates, and then the last one on top
+            assert!((1..=5).contains(&reports.len()), "{stage:?}: {reports:?}");
+            let &&(_, done, total) = reports.last().unwrap();
+            assert_eq!(done, total);
+        }
+    }
+
+    #[test]
+    fn every_chunk_is_reported_when_asked_for() {
+        let image = gradient();
+        let mut palette_reports = 0;
+        Pxls::new(&image)
+            .palette_settings(PaletteSettings {
+                chunks_per_dimension: 8,
+                ..PaletteSettings::default()
+            })
+            .max_progress_updates(u32::MAX)
+            .on_progress(|stage, _, _| palette_reports += u32::from(stage == Stage::Palette))
+            .run_palette_only()
+            .unwrap();
+        assert_eq!(palette_reports, 8 * 8);
+    }
+
+    #[test]
+    fn cancelled_runs_stop() {
+        let image = gradient();
+        let stop = CancellationToken::new();
+        stop.cancel();
+        assert!(matches!(
+            Pxls::new(&image).cancel_token(stop).run(),
+            Err(PxlsError::Cancelled)
+        ));
+    }
+}
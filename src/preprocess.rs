@@ -0,0 +1,107 @@
+use crate::pixel_operations::luminance;
+use image::{DynamicImage, Rgba};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+//all of these go from -100 to 100, with 0 meaning no change
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Adjustments {
+    pub brightness: i32,
+    pub contrast: i32,
+    pub saturation: i32,
+}
+
+impl Adjustments {
+    pub const fn is_identity(self) -> bool {
+        self.brightness == 0 && self.contrast == 0 && self.saturation == 0
+    }
+}
+
+//clockwise, applied before anything else
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Flip {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+pub fn apply_rotation(image: DynamicImage, rotation: Rotation) -> DynamicImage {
+    match rotation {
+        Rotation::None => image,
+        Rotation::Cw90 => image.rotate90(),
+        Rotation::Cw180 => image.rotate180(),
+        Rotation::Cw270 => image.rotate270(),
+    }
+}
+
+pub fn apply_flip(image: DynamicImage, flip: Flip) -> DynamicImage {
+    match flip {
+        Flip::None => image,
+        Flip::Horizontal => image.fliph(),
+        Flip::Vertical => image.flipv(),
+        //the same as turning it upside down
+        Flip::Both => image.rotate180(),
+    }
+}
+
+pub fn adjust(input: &DynamicImage, adjustments: Adjustments) -> DynamicImage {
+    if adjustments.is_identity() {
+        return input.clone();
+    }
+
+    let mut output = if adjustments.brightness == 0 {
+        input.clone()
+    } else {
+        input.brighten(adjustments.brightness * i32::from(u8::MAX) / 100)
+    };
+
+    if adjustments.contrast != 0 {
+        output = output.adjust_contrast(adjustments.contrast as f32);
+    }
+
+    if adjustments.saturation != 0 {
+        output = saturate(&output, adjustments.saturation);
+    }
+
+    output
+}
+
+fn saturate(input: &DynamicImage, saturation: i32) -> DynamicImage {
+    let factor = 1.0 + (saturation as f32 / 100.0);
+
+    let mut rgba = input.to_rgba8();
+    for px in rgba.pixels_mut() {
+        let Rgba([r, g, b, a]) = *px;
+        //luminance is squared, so sqrt it to get back to a normal channel value
+        let grey = (luminance(*px) as f32).sqrt();
+
+        let saturate_channel = |channel: u8| {
+            (channel as f32 - grey)
+                .mul_add(factor, grey)
+                .clamp(0.0, 255.0) as u8
+        };
+
+        *px = Rgba([
+            saturate_channel(r),
+            saturate_channel(g),
+            saturate_channel(b),
+            a,
+        ]);
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
@@ -0,0 +1,351 @@
+use std::sync::mpsc::Sender;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Palette,
+    Dithering,
+}
+
+//reporting every chunk of a big image swamps whoever's listening, so by default a stage only reports this often
+pub const DEFAULT_MAX_UPDATES: u32 = 200;
+
+//told how far through its current stage the work is, as it goes
+pub trait ProgressReporter {
+    fn report(&self, stage: Stage, done: u32, total: u32);
+
+    //how many times a stage reports at most. the last report always gets through on top of these
+    fn max_updates(&self) -> u32 {
+        DEFAULT_MAX_UPDATES
+    }
+
+    //called by the stages as each bit of work gets done, and only passes every so often on to `report`
+    fn tick(&self, stage: Stage, done: u32, total: u32) {
+        //an event each time it gets another 10% of the way through
+        #[cfg(feature = "tracing")]
+        if let Some(total) = std::num::NonZeroU64::new(u64::from(total)) {
+            let tenths = |done: u32| u64::from(done) * 10 / total;
+            if tenths(done) > tenths(done.saturating_sub(1)) {
+                tracing::debug!(?stage, done, total, "progress");
+            }
+        }
+
+        let step = total.div_ceil(self.max_updates().max(1)).max(1);
+        if done == total || done.is_multiple_of(step) {
+            self.report(stage, done, total);
+        }
+    }
+}
+
+//for a different number of updates than the default, eg. `MaxUpdates(sender, u32::MAX)` to hear about every chunk
+#[derive(Copy, Clone, Debug)]
+pub struct MaxUpdates<R>(pub R, pub u32);
+
+impl<R: ProgressReporter> ProgressReporter for MaxUpdates<R> {
+    fn report(&self, stage: Stage, done: u32, total: u32) {
+        self.0.report(stage, done, total);
+    }
+
+    fn max_updates(&self) -> u32 {
+        self.1
+    }
+}
+
+//for when nobody's watching
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    #[inline]
+    fn report(&self, _stage: Stage, _done: u32, _total: u32) {}
+}
+
+//the stage gets dropped, as whoever's on the other end started the work so already knows it
+impl ProgressReporter for Sender<(u32, u32)> {
+    fn report(&self, _stage: Stage, done: u32, total: u32) {
+        //the receiver going away just means nobody wants to know any more
+        let _ = self.send((done, total));
+    }
+}
+
+//so that an `Option<&Sender<_>>` can be passed without giving the sender up
+impl ProgressReporter for &Sender<(u32, u32)> {
+    fn report(&self, stage: Stage, done: u32, total: u32) {
+        (**self).report(stage, done, total);
+    }
+}
+
+impl<F: Fn(Stage, u32, u32)> ProgressReporter for F {
+    fn report(&self, stage: Stage, done: u32, total: u32) {
+        self(stage, done, total);
+    }
+}
+
+impl<R: ProgressReporter> ProgressReporter for Option<R> {
+    fn report(&self, stage: Stage, done: u32, total: u32) {
+        if let Some(reporter) = self {
+            reporter.report(stage, done, total);
+        }
+    }
+
+    fn max_updates(&self) -> u32 {
+        self.as_ref()
+            .map_or(DEFAULT_MAX_UPDATES, ProgressReporter::max_updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cancellation::CancellationToken, dither_original_with_palette, dither_source_with_palette,
+        get_palette, get_palette_from_source, DistanceAlgorithm, DitherMode,
+        ErrorDiffusionDirection, OutputSettings, PaletteSettings, PixelSource, PxlsError,
+    };
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+    use std::{cell::RefCell, hint::black_box, sync::mpsc::channel, time::Instant};
+
+    //cancels as soon as anything past the first few rows gets read, which is part way between two reports
+    struct CancelsPastRow<'a>(&'a CancellationToken, u32);
+
+    impl GenericImageView for CancelsPastRow<'_> {
+        type Pixel = Rgba<u8>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            (64, 64)
+        }
+
+        fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+            if y >= self.1 {
+                self.0.cancel();
+            }
+            Rgba([x as u8 * 4, y as u8 * 4, 0, 255])
+        }
+    }
+
+    impl PixelSource for CancelsPastRow<'_> {}
+
+    //every report but the last should have been a tick, and the last should say where it stopped
+    fn assert_stopped_between_ticks(reports: &[(u32, u32)], step: u32) {
+        let (&(stopped_at, total), ticks) = reports.split_last().unwrap();
+        assert!(
+            ticks.iter().all(|&(done, _)| done % step == 0),
+            "{reports:?}"
+        );
+        assert!(stopped_at % step != 0 && stopped_at < total, "{reports:?}");
+        assert!(
+            ticks.iter().all(|&(done, _)| done < stopped_at),
+            "{reports:?}"
+        );
+    }
+
+    fn ticks(reporter: &impl ProgressReporter, total: u32) {
+        for done in 1..=total {
+            reporter.tick(Stage::Palette, done, total);
+        }
+    }
+
+    #[test]
+    fn each_stage_is_tagged() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba([x as u8 * 8, y as u8 * 8, 0, 255])
+        }));
+        let stages = RefCell::new(vec![]);
+        let record = |stage, _, _| stages.borrow_mut().push(stage);
+        let stop = CancellationToken::new();
+
+        let palette = get_palette(
+            &image,
+            PaletteSettings::default(),
+            DistanceAlgorithm::Euclidean,
+            &record,
+            &stop,
+        )
+        .unwrap();
+        assert!(!stages.borrow().is_empty());
+        assert!(stages.borrow().iter().all(|stage| *stage == Stage::Palette));
+
+        stages.borrow_mut().clear();
+        dither_original_with_palette(
+            &image,
+            &palette,
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+            &record,
+            &stop,
+        )
+        .unwrap();
+        assert!(!stages.borrow().is_empty());
+        assert!(stages
+            .borrow()
+            .iter()
+            .all(|stage| *stage == Stage::Dithering));
+    }
+
+    #[test]
+    fn ticks_only_report_every_so_often() {
+        let reports = RefCell::new(vec![]);
+        let record = |_, done, _| reports.borrow_mut().push(done);
+        ticks(&MaxUpdates(&record, 4), 10);
+        //every 3rd, and then the last one
+        assert_eq!(*reports.borrow(), [3, 6, 9, 10]);
+
+        reports.borrow_mut().clear();
+        ticks(&MaxUpdates(&record, u32::MAX), 5);
+        assert_eq!(*reports.borrow(), [1, 2, 3, 4, 5]);
+
+        reports.borrow_mut().clear();
+        ticks(&record, 1_000);
+        assert_eq!(reports.borrow().len(), DEFAULT_MAX_UPDATES as usize);
+        assert_eq!(reports.borrow().last(), Some(&1_000));
+
+        //no updates along the way, but the last one still gets through
+        reports.borrow_mut().clear();
+        ticks(&MaxUpdates(&record, 0), 3);
+        assert_eq!(*reports.borrow(), [3]);
+    }
+
+    #[test]
+    fn senders_and_options_pass_reports_on() {
+        let (tx, rx) = channel();
+        ticks(&MaxUpdates(&tx, u32::MAX), 3);
+        ticks(&Some(&tx), 2);
+        ticks(&None::<&Sender<_>>, 2);
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [(1, 3), (2, 3), (3, 3), (1, 2), (2, 2)]
+        );
+        assert_eq!(Some(MaxUpdates(NoProgress, 7)).max_updates(), 7);
+        assert_eq!(None::<NoProgress>.max_updates(), DEFAULT_MAX_UPDATES);
+
+        //nobody listening any more isn't an error
+        drop(rx);
+        ticks(&tx, 3);
+    }
+
+    #[test]
+    fn lots_of_chunks_still_only_send_so_many_messages() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(300, 300, |x, y| {
+            Rgba([x as u8, y as u8, 0, 255])
+        }));
+        let stop = CancellationToken::new();
+        let (tx, rx) = channel();
+
+        let palette = get_palette(
+            &image,
+            PaletteSettings {
+                chunks_per_dimension: 300,
+                ..PaletteSettings::default()
+            },
+            DistanceAlgorithm::Euclidean,
+            &tx,
+            &stop,
+        )
+        .unwrap();
+        let messages: Vec<_> = rx.try_iter().collect();
+        assert!(messages.len() <= DEFAULT_MAX_UPDATES as usize + 1);
+        assert_eq!(messages.last(), Some(&(300 * 300, 300 * 300)));
+
+        //both of the chunk loops, one chunk per pixel
+        for dither_mode in [
+            DitherMode::None,
+            DitherMode::FloydSteinberg {
+                direction: ErrorDiffusionDirection::default(),
+            },
+        ] {
+            dither_original_with_palette(
+                &image,
+                &palette,
+                DistanceAlgorithm::Euclidean,
+                OutputSettings {
+                    output_px_size: 1,
+                    dither_mode,
+                    ..OutputSettings::default()
+                },
+                &tx,
+                &stop,
+            )
+            .unwrap();
+            let messages: Vec<_> = rx.try_iter().collect();
+            assert!(messages.len() <= DEFAULT_MAX_UPDATES as usize + 1);
+            assert_eq!(messages.last(), Some(&(300 * 300, 300 * 300)));
+        }
+    }
+
+    #[test]
+    fn cancelled_stages_still_say_where_they_got_to() {
+        let stop = CancellationToken::new();
+        let (tx, rx) = channel();
+        let reporter = MaxUpdates(&tx, 4);
+
+        let result = get_palette_from_source(
+            &CancelsPastRow(&stop, 24),
+            PaletteSettings {
+                chunks_per_dimension: 32,
+                ..PaletteSettings::default()
+            },
+            DistanceAlgorithm::Euclidean,
+            &reporter,
+            &stop,
+        );
+        assert!(matches!(result, Err(PxlsError::Cancelled)));
+        assert_stopped_between_ticks(&rx.try_iter().collect::<Vec<_>>(), 32 * 32 / 4);
+
+        for dither_mode in [
+            DitherMode::Legacy,
+            DitherMode::FloydSteinberg {
+                direction: ErrorDiffusionDirection::default(),
+            },
+        ] {
+            let stop = CancellationToken::new();
+            let result = dither_source_with_palette(
+                &CancelsPastRow(&stop, 24),
+                [Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])],
+                DistanceAlgorithm::Euclidean,
+                OutputSettings {
+                    output_px_size: 2,
+                    dither_mode,
+                    ..OutputSettings::default()
+                },
+                &reporter,
+                &stop,
+            );
+            assert!(matches!(result, Err(PxlsError::Cancelled)));
+            assert_stopped_between_ticks(&rx.try_iter().collect::<Vec<_>>(), 32 * 32 / 4);
+        }
+
+        //even stopping before anything's done gets a message through
+        let stop = CancellationToken::new();
+        stop.cancel();
+        let result = get_palette(
+            &DynamicImage::new_rgba8(64, 64),
+            PaletteSettings::default(),
+            DistanceAlgorithm::Euclidean,
+            &tx,
+            &stop,
+        );
+        assert!(matches!(result, Err(PxlsError::Cancelled)));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>().len(), 1);
+    }
+
+    #[test]
+    fn no_progress_costs_nothing() {
+        assert_eq!(std::mem::size_of::<NoProgress>(), 0);
+
+        const TOTAL: u32 = 100_000;
+        let time = |reporter: &dyn Fn()| {
+            (0..3)
+                .map(|_| {
+                    let started = Instant::now();
+                    reporter();
+                    started.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let nothing = time(&|| ticks(black_box(&MaxUpdates(NoProgress, u32::MAX)), TOTAL));
+        let (tx, rx) = channel();
+        let sending = time(&|| ticks(black_box(&MaxUpdates(&tx, u32::MAX)), TOTAL));
+        assert_eq!(rx.try_iter().count(), 3 * TOTAL as usize);
+        assert!(nothing < sending, "{nothing:?} vs {sending:?}");
+    }
+}
@@ -0,0 +1,361 @@
+use crate::{
+    cancellation::CancellationToken,
+    progress::{ProgressReporter, Stage},
+    DistanceAlgorithm, Instant, Palette, PaletteAlgorithm, PaletteSettings, PxlsError, Stats,
+    DEFAULT_HISTOGRAM_QUANTIZATION, DEFAULT_SUPERPIXEL_COMPACTNESS, DEFAULT_SUPERPIXEL_TARGET,
+};
+use image::DynamicImage;
+use std::sync::{Mutex, PoisonError};
+
+//anything that can come up with a palette for an image. the built-in `PaletteAlgorithm`s are all `BuiltInQuantizer`s,
+//and anything else that implements it can go into `Pxls::quantizer` or be added with `register_quantizer`
+pub trait Quantizer {
+    //what it's picked by on the command line, so lowercase without spaces
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    //`max_colours` is a limit on how big the palette can be, if there is one
+    fn palette(
+        &self,
+        image: &DynamicImage,
+        max_colours: Option<usize>,
+        progress: &dyn ProgressReporter,
+        stop: &CancellationToken,
+    ) -> Result<Palette, PxlsError>;
+
+    //only the duration and palette size unless it's overridden
+    fn palette_with_stats(
+        &self,
+        image: &DynamicImage,
+        max_colours: Option<usize>,
+        progress: &dyn ProgressReporter,
+        stop: &CancellationToken,
+    ) -> Result<(Palette, Stats), PxlsError> {
+        let started = Instant::now();
+        let palette = self.palette(image, max_colours, progress, stop)?;
+        let stats = Stats {
+            duration: started.elapsed(),
+            palette_size: palette.len(),
+            ..Stats::default()
+        };
+        Ok((palette, stats))
+    }
+}
+
+//the palette functions all want a sized reporter
+struct DynProgress<'a>(&'a dyn ProgressReporter);
+
+impl ProgressReporter for DynProgress<'_> {
+    fn report(&self, stage: Stage, done: u32, total: u32) {
+        self.0.report(stage, done, total);
+    }
+
+    fn max_updates(&self) -> u32 {
+        self.0.max_updates()
+    }
+}
+
+//one of the `PaletteAlgorithm`s, along with everything it needs to make a palette
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuiltInQuantizer {
+    pub algorithm: PaletteAlgorithm,
+    pub settings: PaletteSettings,
+    pub dist_algo: DistanceAlgorithm,
+}
+
+impl Quantizer for BuiltInQuantizer {
+    fn name(&self) -> &'static str {
+        match self.algorithm {
+            PaletteAlgorithm::Chunks => "chunks",
+            PaletteAlgorithm::Superpixel { .. } => "superpixels",
+            PaletteAlgorithm::HistogramPeaks { .. } => "histogram-peaks",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.algorithm {
+            PaletteAlgorithm::Chunks => "the most common colour of each of a grid of chunks",
+            PaletteAlgorithm::Superpixel { .. } => {
+                "the average colour of each of a set of superpixels"
+            }
+            PaletteAlgorithm::HistogramPeaks { .. } => {
+                "the most common colours in a histogram of the image"
+            }
+        }
+    }
+
+    fn palette(
+        &self,
+        image: &DynamicImage,
+        max_colours: Option<usize>,
+        progress: &dyn ProgressReporter,
+        stop: &CancellationToken,
+    ) -> Result<Palette, PxlsError> {
+        self.palette_with_stats(image, max_colours, progress, stop)
+            .map(|(palette, _)| palette)
+    }
+
+    //none of them have a way to aim for a number of colours, so any past the limit get dropped, keeping the ones that
+    //were found first
+    fn palette_with_stats(
+        &self,
+        image: &DynamicImage,
+        max_colours: Option<usize>,
+        progress: &dyn ProgressReporter,
+        stop: &CancellationToken,
+    ) -> Result<(Palette, Stats), PxlsError> {
+        let (palette, stats) = self.algorithm.get_palette_with_stats(
+            image,
+            self.settings.clone(),
+            self.dist_algo,
+            &DynProgress(progress),
+            stop,
+        )?;
+        let Some(max_colours) = max_colours.filter(|max| *max < palette.len()) else {
+            return Ok((palette, stats));
+        };
+        let palette: Palette = palette.into_iter().take(max_colours).collect();
+        let stats = Stats {
+            palette_size: palette.len(),
+            ..stats
+        };
+        Ok((palette, stats))
+    }
+}
+
+//the name of what `Pxls` uses if it isn't given anything else
+pub const DEFAULT_QUANTIZER: &str = "chunks";
+
+//makes a quantizer that goes by the same settings as the rest of the pipeline, for quantizers that have a use for them
+pub type MakeQuantizer = fn(&PaletteSettings, DistanceAlgorithm) -> Box<dyn Quantizer>;
+
+static REGISTERED: Mutex<Vec<MakeQuantizer>> = Mutex::new(vec![]);
+
+//adds a quantizer to what `available_quantizers` and `find_quantizer` go through, after all of the built-in ones
+pub fn register_quantizer(make: MakeQuantizer) {
+    REGISTERED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(make);
+}
+
+fn built_in_algorithms() -> [PaletteAlgorithm; 3] {
+    [
+        PaletteAlgorithm::Chunks,
+        PaletteAlgorithm::Superpixel {
+            target: DEFAULT_SUPERPIXEL_TARGET,
+            compactness: DEFAULT_SUPERPIXEL_COMPACTNESS,
+        },
+        PaletteAlgorithm::HistogramPeaks {
+            quantization: DEFAULT_HISTOGRAM_QUANTIZATION,
+        },
+    ]
+}
+
+//every quantizer that's been built in, on their default settings for anything that isn't in `settings`, followed by
+//every one that's been registered
+pub fn available_quantizers(
+    settings: &PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+) -> Vec<Box<dyn Quantizer>> {
+    let built_in = built_in_algorithms().into_iter().map(|algorithm| {
+        Box::new(BuiltInQuantizer {
+            algorithm,
+            settings: settings.clone(),
+            dist_algo,
+        }) as Box<dyn Quantizer>
+    });
+    let registered = REGISTERED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    built_in
+        .chain(registered.into_iter().map(|make| make(settings, dist_algo)))
+        .collect()
+}
+
+//just the names, for listing them out
+pub fn quantizer_names() -> Vec<&'static str> {
+    available_quantizers(&PaletteSettings::default(), DistanceAlgorithm::Euclidean)
+        .iter()
+        .map(|quantizer| quantizer.name())
+        .collect()
+}
+
+pub fn find_quantizer(
+    name: &str,
+    settings: &PaletteSettings,
+    dist_algo: DistanceAlgorithm,
+) -> Option<Box<dyn Quantizer>> {
+    available_quantizers(settings, dist_algo)
+        .into_iter()
+        .find(|quantizer| quantizer.name().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pipeline::Pxls, progress::NoProgress};
+    use image::{Rgba, RgbaImage};
+
+    //always gives back the same colours
+    struct Mock(Vec<Rgba<u8>>);
+
+    impl Quantizer for Mock {
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn description(&self) -> &'static str {
+            "the exclude colours, or black if there aren't any"
+        }
+
+        fn palette(
+            &self,
+            _: &DynamicImage,
+            max_colours: Option<usize>,
+            _: &dyn ProgressReporter,
+            stop: &CancellationToken,
+        ) -> Result<Palette, PxlsError> {
+            if stop.is_cancelled() {
+                return Err(PxlsError::Cancelled);
+            }
+            let colours = self.0.iter().copied();
+            Ok(colours.take(max_colours.unwrap_or(usize::MAX)).collect())
+        }
+    }
+
+    fn make_mock(settings: &PaletteSettings, _: DistanceAlgorithm) -> Box<dyn Quantizer> {
+        if settings.exclude_colors.is_empty() {
+            Box::new(Mock(vec![Rgba([0, 0, 0, 255])]))
+        } else {
+            Box::new(Mock(settings.exclude_colors.clone()))
+        }
+    }
+
+    //a different colour in each 4x4 block, with one black pixel in each so that no chunk has a tie for its most
+    //common colour
+    fn blocks() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            if x % 4 == 0 && y % 4 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([(x / 4 * 60) as u8, (y / 4 * 60) as u8, 200, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn registered_quantizers_can_be_found() {
+        register_quantizer(make_mock);
+        assert!(quantizer_names().contains(&"mock"));
+
+        let colours = vec![Rgba([1, 2, 3, 255]), Rgba([4, 5, 6, 255])];
+        let settings = PaletteSettings {
+            exclude_colors: colours.clone(),
+            ..PaletteSettings::default()
+        };
+        let mock = find_quantizer("MOCK", &settings, DistanceAlgorithm::Euclidean).unwrap();
+        let (palette, stats) = mock
+            .palette_with_stats(&blocks(), None, &NoProgress, &CancellationToken::new())
+            .unwrap();
+        assert_eq!(Vec::from(palette), colours);
+        assert_eq!(stats.palette_size, 2);
+        let palette = mock
+            .palette(&blocks(), Some(1), &NoProgress, &CancellationToken::new())
+            .unwrap();
+        assert_eq!(Vec::from(palette), colours[..1]);
+    }
+
+    #[test]
+    fn pipelines_use_the_quantizer_they_are_given() {
+        let colours = vec![Rgba([1, 2, 3, 255]), Rgba([4, 5, 6, 255])];
+        let image = blocks();
+        let palette = Pxls::new(&image)
+            .quantizer(Box::new(Mock(colours.clone())))
+            .run_palette_only()
+            .unwrap();
+        assert_eq!(Vec::from(palette), colours);
+        let palette = Pxls::new(&image)
+            .quantizer(Box::new(Mock(colours.clone())))
+            .max_colours(1)
+            .run_palette_only()
+            .unwrap();
+        assert_eq!(Vec::from(palette), colours[..1]);
+    }
+
+    #[test]
+    fn built_in_quantizers_come_first() {
+        let names = quantizer_names();
+        assert_eq!(names[..3], ["chunks", "superpixels", "histogram-peaks"]);
+        assert!(find_quantizer(
+            "nothing",
+            &PaletteSettings::default(),
+            DistanceAlgorithm::Euclidean
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn built_in_quantizers_match_their_algorithms() {
+        let settings = PaletteSettings {
+            chunks_per_dimension: 4,
+            ..PaletteSettings::default()
+        };
+        for algorithm in built_in_algorithms() {
+            let quantizer = BuiltInQuantizer {
+                algorithm,
+                settings: settings.clone(),
+                dist_algo: DistanceAlgorithm::Euclidean,
+            };
+            let stop = CancellationToken::new();
+            let palette = quantizer
+                .palette(&blocks(), None, &NoProgress, &stop)
+                .unwrap();
+            let expected = algorithm
+                .get_palette(
+                    &blocks(),
+                    settings.clone(),
+                    DistanceAlgorithm::Euclidean,
+                    &NoProgress,
+                    &stop,
+                )
+                .unwrap();
+            //colours that come up just as often can be in either order
+            assert_eq!(
+                palette.content_hash(),
+                expected.content_hash(),
+                "{}",
+                quantizer.name()
+            );
+        }
+    }
+
+    #[test]
+    fn built_in_quantizers_keep_to_the_limit() {
+        let quantizer = BuiltInQuantizer {
+            algorithm: PaletteAlgorithm::Chunks,
+            settings: PaletteSettings {
+                chunks_per_dimension: 4,
+                closeness_threshold: 0,
+                ..PaletteSettings::default()
+            },
+            dist_algo: DistanceAlgorithm::Euclidean,
+        };
+        let stop = CancellationToken::new();
+        let (everything, _) = quantizer
+            .palette_with_stats(&blocks(), None, &NoProgress, &stop)
+            .unwrap();
+        assert!(everything.len() > 2);
+        let (limited, stats) = quantizer
+            .palette_with_stats(&blocks(), Some(2), &NoProgress, &stop)
+            .unwrap();
+        assert_eq!(*limited, (*everything)[..2]);
+        assert_eq!(stats.palette_size, 2);
+        let (roomy, _) = quantizer
+            .palette_with_stats(&blocks(), Some(usize::MAX), &NoProgress, &stop)
+            .unwrap();
+        assert_eq!(roomy, everything);
+    }
+}
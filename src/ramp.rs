@@ -0,0 +1,243 @@
+use image::Rgba;
+use std::fmt::{Display, Formatter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RampColorSpace {
+    Rgb,
+    Lab,
+    Oklab,
+    //the hue goes the short way round the wheel
+    HsvShort,
+    //the hue goes the long way round the wheel
+    HsvLong,
+}
+
+impl RampColorSpace {
+    pub const fn to_str(self) -> &'static str {
+        match self {
+            Self::Rgb => "RGB",
+            Self::Lab => "CIELAB",
+            Self::Oklab => "OKLab",
+            Self::HsvShort => "HSV (short hue)",
+            Self::HsvLong => "HSV (long hue)",
+        }
+    }
+}
+
+impl Display for RampColorSpace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+pub const ALL_RAMP_COLOR_SPACES: [RampColorSpace; 5] = [
+    RampColorSpace::Rgb,
+    RampColorSpace::Lab,
+    RampColorSpace::Oklab,
+    RampColorSpace::HsvShort,
+    RampColorSpace::HsvLong,
+];
+
+//includes both ends, so `steps` of 1 just gives back `start`
+pub fn generate_color_ramp(
+    start: Rgba<u8>,
+    end: Rgba<u8>,
+    steps: usize,
+    color_space: RampColorSpace,
+) -> Vec<Rgba<u8>> {
+    match steps {
+        0 => return vec![],
+        1 => return vec![start],
+        _ => {}
+    }
+
+    let (from, to) = match color_space {
+        RampColorSpace::Rgb => (to_unit(start), to_unit(end)),
+        RampColorSpace::Lab => (rgb_to_lab(start), rgb_to_lab(end)),
+        RampColorSpace::Oklab => (rgb_to_oklab(start), rgb_to_oklab(end)),
+        RampColorSpace::HsvShort | RampColorSpace::HsvLong => {
+            let from = rgb_to_hsv(start);
+            let mut to = rgb_to_hsv(end);
+
+            //shift the end hue so that lerping goes the way round we want
+            let mut delta = to[0] - from[0];
+            if delta > 180.0 {
+                delta -= 360.0;
+            } else if delta < -180.0 {
+                delta += 360.0;
+            }
+            if color_space == RampColorSpace::HsvLong && delta != 0.0 {
+                delta -= 360.0_f32.copysign(delta);
+            }
+            to[0] = from[0] + delta;
+
+            (from, to)
+        }
+    };
+
+    (0..steps)
+        .map(|i| {
+            let t = i as f32 / (steps - 1) as f32;
+            let [a, b, c] =
+                [0, 1, 2].map(|channel| (to[channel] - from[channel]).mul_add(t, from[channel]));
+            let alpha = (f32::from(end[3]) - f32::from(start[3])).mul_add(t, f32::from(start[3]));
+
+            let Rgba([r, g, b, _]) = match color_space {
+                RampColorSpace::Rgb => from_unit([a, b, c]),
+                RampColorSpace::Lab => lab_to_rgb([a, b, c]),
+                RampColorSpace::Oklab => oklab_to_rgb([a, b, c]),
+                RampColorSpace::HsvShort | RampColorSpace::HsvLong => hsv_to_rgb([a, b, c]),
+            };
+            Rgba([r, g, b, alpha.round() as u8])
+        })
+        .collect()
+}
+
+fn to_unit(Rgba([r, g, b, _]): Rgba<u8>) -> [f32; 3] {
+    [r, g, b].map(|c| f32::from(c) / 255.0)
+}
+
+fn from_unit(rgb: [f32; 3]) -> Rgba<u8> {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    Rgba([r, g, b, u8::MAX])
+}
+
+// https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055_f32.mul_add(c.powf(1.0 / 2.4), -0.055)
+    }
+}
+
+// https://en.wikipedia.org/wiki/CIELAB_color_space, using a D65 white point
+const D65: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+const LAB_EPSILON: f32 = 216.0 / 24389.0;
+const LAB_KAPPA: f32 = 24389.0 / 27.0;
+
+fn rgb_to_lab(colour: Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = to_unit(colour).map(srgb_to_linear);
+    let xyz = [
+        0.180_437_5_f32.mul_add(b, 0.412_456_4_f32.mul_add(r, 0.357_576_1 * g)) / D65[0],
+        0.072_175_f32.mul_add(b, 0.212_672_9_f32.mul_add(r, 0.715_152_2 * g)) / D65[1],
+        0.950_304_1_f32.mul_add(b, 0.019_333_9_f32.mul_add(r, 0.119_192 * g)) / D65[2],
+    ];
+    let [fx, fy, fz] = xyz.map(|t| {
+        if t > LAB_EPSILON {
+            t.cbrt()
+        } else {
+            LAB_KAPPA.mul_add(t, 16.0) / 116.0
+        }
+    });
+
+    [
+        116.0f32.mul_add(fy, -16.0),
+        500.0 * (fx - fy),
+        200.0 * (fy - fz),
+    ]
+}
+
+fn lab_to_rgb([l, a, b]: [f32; 3]) -> Rgba<u8> {
+    let fy = (l + 16.0) / 116.0;
+    let fx = a / 500.0 + fy;
+    let fz = fy - b / 200.0;
+    let [x, y, z] = [fx, fy, fz].map(|f| {
+        let cubed = f.powi(3);
+        if cubed > LAB_EPSILON {
+            cubed
+        } else {
+            116.0f32.mul_add(f, -16.0) / LAB_KAPPA
+        }
+    });
+    let [x, y, z] = [x * D65[0], y * D65[1], z * D65[2]];
+
+    from_unit(
+        [
+            (-0.498_531_4_f32).mul_add(z, 3.240_454_2_f32.mul_add(x, -1.537_138_5 * y)),
+            0.041_556_f32.mul_add(z, (-0.969_266_f32).mul_add(x, 1.876_010_8 * y)),
+            1.057_225_2_f32.mul_add(z, 0.055_643_4_f32.mul_add(x, -0.204_025_9 * y)),
+        ]
+        .map(linear_to_srgb),
+    )
+}
+
+// https://bottosson.github.io/posts/oklab/
+fn rgb_to_oklab(colour: Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = to_unit(colour).map(srgb_to_linear);
+    let [l, m, s] = [
+        0.051_457_565_f32.mul_add(b, 0.412_221_46_f32.mul_add(r, 0.536_332_55 * g)),
+        0.107_406_58_f32.mul_add(b, 0.211_903_5_f32.mul_add(r, 0.680_699_5 * g)),
+        0.629_978_7_f32.mul_add(b, 0.088_302_46_f32.mul_add(r, 0.281_718_85 * g)),
+    ]
+    .map(f32::cbrt);
+
+    [
+        (-0.004_072_047_f32).mul_add(s, 0.210_454_26_f32.mul_add(l, 0.793_617_8 * m)),
+        0.450_593_7_f32.mul_add(s, 1.977_998_5_f32.mul_add(l, -2.428_592_2 * m)),
+        (-0.808_675_77_f32).mul_add(s, 0.025_904_037_f32.mul_add(l, 0.782_771_77 * m)),
+    ]
+}
+
+fn oklab_to_rgb([l, a, b]: [f32; 3]) -> Rgba<u8> {
+    let [l, m, s] = [
+        0.215_803_76_f32.mul_add(b, 0.396_337_78_f32.mul_add(a, l)),
+        (-0.063_854_17_f32).mul_add(b, (-0.105_561_346_f32).mul_add(a, l)),
+        (-1.291_485_5_f32).mul_add(b, (-0.089_484_18_f32).mul_add(a, l)),
+    ]
+    .map(|c| c.powi(3));
+
+    from_unit(
+        [
+            0.230_759_05_f32.mul_add(s, 4.076_741_7_f32.mul_add(l, -3.307_711_6 * m)),
+            (-0.341_319_4_f32).mul_add(s, (-1.268_438_f32).mul_add(l, 2.609_757_4 * m)),
+            1.707_614_7_f32.mul_add(s, (-0.004_196_086_3_f32).mul_add(l, -0.703_418_6 * m)),
+        ]
+        .map(linear_to_srgb),
+    )
+}
+
+//hue in degrees, saturation and value from 0 to 1 - the one in `pixel_operations` rounds too much for interpolating
+fn rgb_to_hsv(colour: Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = to_unit(colour);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    [hue, saturation, max]
+}
+
+fn hsv_to_rgb([hue, saturation, value]: [f32; 3]) -> Rgba<u8> {
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let min = value - chroma;
+
+    from_unit([r + min, g + min, b + min])
+}
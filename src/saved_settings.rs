@@ -0,0 +1,257 @@
+//the settings that get saved with sessions and autosaves. every blob is tagged with the version it was saved as, so
+//that once the settings change shape, older blobs get migrated forward rather than failing to load or loading wrong.
+//
+//to change the shape, add a `SettingsV2`, point `CurrentSettings` and `CURRENT_SETTINGS_VERSION` at it, and add an arm
+//to `migrate` that loads the old version and converts it
+use crate::{preprocess::Adjustments, DistanceAlgorithm, OutputSettings, PaletteSettings};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+pub const CURRENT_SETTINGS_VERSION: u32 = 2;
+
+//blobs saved before there were versions don't have a tag, but they're all this shape
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SettingsV1 {
+    pub palette_settings: PaletteSettings,
+    pub output_settings: OutputSettings,
+    pub distance_algorithm: DistanceAlgorithm,
+    pub adjustments: Adjustments,
+}
+
+//from before the palette could be made by anything other than chunks
+pub const V1_PALETTE_METHOD: &str = "chunks";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SettingsV2 {
+    pub palette_settings: PaletteSettings,
+    //the name of the `Quantizer` that made the palette
+    pub palette_method: String,
+    pub output_settings: OutputSettings,
+    pub distance_algorithm: DistanceAlgorithm,
+    pub adjustments: Adjustments,
+}
+
+impl From<SettingsV1> for SettingsV2 {
+    fn from(
+        SettingsV1 {
+            palette_settings,
+            output_settings,
+            distance_algorithm,
+            adjustments,
+        }: SettingsV1,
+    ) -> Self {
+        Self {
+            palette_settings,
+            palette_method: V1_PALETTE_METHOD.to_string(),
+            output_settings,
+            distance_algorithm,
+            adjustments,
+        }
+    }
+}
+
+pub type CurrentSettings = SettingsV2;
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Json(serde_json::Error),
+    NotAnObject,
+    InvalidVersion,
+    UnknownVersion(u64),
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid settings: {e}"),
+            Self::NotAnObject => write!(f, "settings need to be an object"),
+            Self::InvalidVersion => write!(f, "the settings' version needs to be a whole number"),
+            Self::UnknownVersion(version) => write!(
+                f,
+                "settings are from version {version}, but only up to version {CURRENT_SETTINGS_VERSION} can be loaded"
+            ),
+        }
+    }
+}
+
+impl Error for SettingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for SettingsError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+pub fn load_settings(json: &str) -> Result<CurrentSettings, SettingsError> {
+    migrate(serde_json::from_str(json)?)
+}
+
+//any version in, the current version out. anything else in the object (eg. the rest of the entry it's in) is ignored
+pub fn migrate(blob: Value) -> Result<CurrentSettings, SettingsError> {
+    let version = match blob
+        .as_object()
+        .ok_or(SettingsError::NotAnObject)?
+        .get("version")
+    {
+        None => 1,
+        Some(version) => version.as_u64().ok_or(SettingsError::InvalidVersion)?,
+    };
+
+    match version {
+        1 => Ok(serde_json::from_value::<SettingsV1>(blob)?.into()),
+        2 => Ok(serde_json::from_value::<SettingsV2>(blob)?),
+        version => Err(SettingsError::UnknownVersion(version)),
+    }
+}
+
+#[derive(Serialize)]
+struct Tagged<'a> {
+    version: u32,
+    #[serde(flatten)]
+    settings: &'a CurrentSettings,
+}
+
+pub fn save_settings(settings: &CurrentSettings) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&Tagged {
+        version: CURRENT_SETTINGS_VERSION,
+        settings,
+    })
+}
+
+//for settings inside something bigger, so that they get tagged and migrated too, eg.
+//  #[serde(flatten, with = "pxls::saved_settings::versioned")]
+//  settings: CurrentSettings,
+pub mod versioned {
+    use super::{migrate, CurrentSettings, Tagged, CURRENT_SETTINGS_VERSION};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(
+        settings: &CurrentSettings,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Tagged {
+            version: CURRENT_SETTINGS_VERSION,
+            settings,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<CurrentSettings, D::Error> {
+        migrate(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DitheringMode;
+    use image::Rgba;
+
+    const UNVERSIONED: &str = include_str!("../tests/fixtures/settings/unversioned.json");
+    const V1: &str = include_str!("../tests/fixtures/settings/v1.json");
+    const V2: &str = include_str!("../tests/fixtures/settings/v2.json");
+
+    fn assert_common(settings: &CurrentSettings) {
+        assert_eq!(settings.palette_settings.chunks_per_dimension, 40);
+        assert_eq!(settings.palette_settings.closeness_threshold, 120);
+        assert_eq!(
+            settings.palette_settings.exclude_colors,
+            [Rgba([255, 0, 255, 255])]
+        );
+        assert_eq!(settings.palette_settings.exclude_threshold, 12);
+        assert_eq!(settings.output_settings.output_px_size, 4);
+        assert_eq!(
+            settings.output_settings.dithering_mode,
+            DitheringMode::Ratio(3)
+        );
+        assert_eq!(settings.output_settings.dithering_scale, 2);
+        assert_eq!(settings.distance_algorithm, DistanceAlgorithm::Manhattan);
+        assert_eq!(
+            settings.adjustments,
+            Adjustments {
+                brightness: 10,
+                contrast: -5,
+                saturation: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn unversioned_blobs_migrate_forward() {
+        let settings = load_settings(UNVERSIONED).unwrap();
+        assert_common(&settings);
+        //saved before these existed, so they get their defaults
+        assert!(settings.palette_settings.extra_colors.is_empty());
+        assert_eq!(
+            settings.output_settings.dither_mode,
+            OutputSettings::default().dither_mode
+        );
+        assert_eq!(settings.output_settings.post_sharpen, 0.0);
+        assert_eq!(settings.palette_method, V1_PALETTE_METHOD);
+    }
+
+    #[test]
+    fn v1_blobs_load() {
+        let settings = load_settings(V1).unwrap();
+        assert_common(&settings);
+        assert_eq!(
+            settings.palette_settings.extra_colors,
+            [Rgba([0, 0, 0, 255])]
+        );
+        assert_eq!(settings.output_settings.post_sharpen, 0.25);
+        assert_eq!(settings.palette_method, V1_PALETTE_METHOD);
+    }
+
+    #[test]
+    fn v2_blobs_load() {
+        let settings = load_settings(V2).unwrap();
+        assert_common(&settings);
+        assert_eq!(settings.palette_method, "superpixels");
+    }
+
+    #[test]
+    fn saving_tags_the_current_version() {
+        let settings = load_settings(V2).unwrap();
+        let saved = save_settings(&settings).unwrap();
+        let blob: Value = serde_json::from_str(&saved).unwrap();
+        assert_eq!(blob["version"], CURRENT_SETTINGS_VERSION);
+        assert_eq!(load_settings(&saved).unwrap(), settings);
+    }
+
+    #[test]
+    fn future_versions_are_rejected() {
+        let mut blob: Value = serde_json::from_str(V2).unwrap();
+        blob["version"] = (CURRENT_SETTINGS_VERSION + 1).into();
+        assert!(matches!(
+            migrate(blob),
+            Err(SettingsError::UnknownVersion(version)) if version == u64::from(CURRENT_SETTINGS_VERSION) + 1
+        ));
+    }
+
+    #[test]
+    fn malformed_blobs_are_rejected() {
+        assert!(matches!(
+            load_settings("[1, 2]"),
+            Err(SettingsError::NotAnObject)
+        ));
+        assert!(matches!(
+            load_settings(r#"{"version": "one"}"#),
+            Err(SettingsError::InvalidVersion)
+        ));
+        assert!(matches!(load_settings("{"), Err(SettingsError::Json(_))));
+    }
+}
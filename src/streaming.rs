@@ -0,0 +1,779 @@
+use crate::{
+    cancellation::CancellationToken,
+    check_not_empty, dedup_palette, dither_chunk_size, dither_chunks_into, get_closest_factor,
+    get_palette_from_source, output_scale_factor,
+    progress::{NoProgress, ProgressReporter, Stage},
+    resize_keeping_buffer, DistanceAlgorithm, OutputSettings, Palette, PaletteSettings,
+    PixelSource, PxlsError,
+};
+use image::{GenericImageView, Rgba, RgbaImage};
+#[cfg(feature = "png")]
+use std::io::Write;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{Display, Formatter},
+    ops::Deref,
+};
+
+//works out the same sort of palette as `get_palette`, but from pixels as they arrive rather than a whole image.
+//each chunk gets its colour as soon as it's full, so a row-by-row stream picks chunks along each row of chunks
+//rather than down each column, which can mean a slightly different palette.
+pub struct StreamingPaletteBuilder {
+    settings: PaletteSettings,
+    algo: DistanceAlgorithm,
+    current_palette: Vec<Rgba<u8>>,
+    //counts for each chunk that has had some but not all of its pixels
+    chunk_buffers: HashMap<(u32, u32), ChunkBuffer>,
+    chunks_per_dimension: u32,
+    chunk_size: (u32, u32),
+}
+
+#[derive(Default)]
+struct ChunkBuffer {
+    counts: HashMap<Rgba<u8>, u32>,
+    pixels_seen: u32,
+}
+
+impl StreamingPaletteBuilder {
+    pub fn new(
+        width: u32,
+        height: u32,
+        settings: PaletteSettings,
+        algo: DistanceAlgorithm,
+    ) -> Self {
+        let chunks_per_dimension =
+            get_closest_factor(settings.chunks_per_dimension, width.min(height));
+
+        Self {
+            settings,
+            algo,
+            current_palette: vec![],
+            chunk_buffers: HashMap::new(),
+            chunks_per_dimension,
+            chunk_size: (width / chunks_per_dimension, height / chunks_per_dimension),
+        }
+    }
+
+    //every pixel should only be fed in once
+    pub fn feed_pixel(&mut self, x: u32, y: u32, pixel: Rgba<u8>) {
+        let (chunk_width, chunk_height) = self.chunk_size;
+        let chunk_position = (x / chunk_width, y / chunk_height);
+        //same as `get_palette`, the pixels past the last whole chunk get ignored
+        if chunk_position.0 >= self.chunks_per_dimension
+            || chunk_position.1 >= self.chunks_per_dimension
+        {
+            return;
+        }
+
+        let buffer = self.chunk_buffers.entry(chunk_position).or_default();
+        *buffer.counts.entry(pixel).or_default() += 1;
+        buffer.pixels_seen += 1;
+
+        if buffer.pixels_seen == chunk_width * chunk_height {
+            if let Some(buffer) = self.chunk_buffers.remove(&chunk_position) {
+                self.process_chunk(buffer.counts);
+            }
+        }
+    }
+
+    fn process_chunk(&mut self, counts: HashMap<Rgba<u8>, u32>) {
+        let closeness_threshold = self
+            .algo
+            .standardise_closeness_threshold(self.settings.closeness_threshold);
+        let exclude_threshold = self
+            .algo
+            .standardise_closeness_threshold(self.settings.exclude_threshold);
+
+        let most_common = counts
+            .into_iter()
+            .filter(|(px, _)| {
+                !self
+                    .settings
+                    .exclude_colors
+                    .iter()
+                    .any(|excluded| self.algo.distance(*px, *excluded) < exclude_threshold)
+                    && !self
+                        .current_palette
+                        .iter()
+                        .any(|so_far| self.algo.distance(*px, *so_far) < closeness_threshold)
+            })
+            .max_by_key(|(_, count)| *count);
+        if let Some((most_common, _)) = most_common {
+            self.current_palette.push(most_common);
+        }
+    }
+
+    //any chunks that never got all of their pixels are still used, in case the stream got cut short
+    pub fn finish(mut self) -> Palette {
+        let mut unfinished: Vec<_> = std::mem::take(&mut self.chunk_buffers)
+            .into_iter()
+            .collect();
+        unfinished.sort_unstable_by_key(|((chunk_x, chunk_y), _)| (*chunk_y, *chunk_x));
+        for (_, buffer) in unfinished {
+            self.process_chunk(buffer.counts);
+        }
+
+        let mut palette = self.current_palette;
+        palette.extend(self.settings.extra_colors);
+        dedup_palette(palette).into()
+    }
+}
+
+//every `step`th pixel of the image both ways, read as they're needed rather than copied out
+struct Subsampled<'a, I> {
+    image: &'a I,
+    step: u32,
+}
+
+impl<I: PixelSource> GenericImageView for Subsampled<'_, I> {
+    type Pixel = Rgba<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.image.dimensions();
+        (width.div_ceil(self.step), height.div_ceil(self.step))
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+        self.image.get_pixel(x * self.step, y * self.step)
+    }
+}
+
+impl<I: PixelSource> PixelSource for Subsampled<'_, I> {
+    fn get_pixel_wide(&self, x: u32, y: u32) -> [u16; 3] {
+        self.image.get_pixel_wide(x * self.step, y * self.step)
+    }
+}
+
+//`get_palette_from_source`, but only looking at enough evenly spread pixels to make about `max_pixels`, for images
+//too big to go through every pixel of
+pub fn get_palette_subsampled(
+    image: &impl PixelSource,
+    max_pixels: u64,
+    settings: PaletteSettings,
+    algo: DistanceAlgorithm,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<Palette, PxlsError> {
+    let pixels = u64::from(image.width()) * u64::from(image.height());
+    let step = (pixels as f64 / max_pixels.max(1) as f64)
+        .sqrt()
+        .ceil()
+        .max(1.0) as u32;
+    get_palette_from_source(&Subsampled { image, step }, settings, algo, progress, stop)
+}
+
+//where `dither_tiled` sends each tile of the output as soon as it's done
+pub trait TileSink {
+    type Error;
+
+    //tiles come left to right and then top to bottom, with `x` and `y` being where they go in the whole output
+    fn write_tile(&mut self, x: u32, y: u32, tile: &RgbaImage) -> Result<(), Self::Error>;
+
+    //called after the last tile
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<E, F: FnMut(u32, u32, &RgbaImage) -> Result<(), E>> TileSink for F {
+    type Error = E;
+
+    fn write_tile(&mut self, x: u32, y: u32, tile: &RgbaImage) -> Result<(), E> {
+        self(x, y, tile)
+    }
+}
+
+#[derive(Debug)]
+pub enum TiledError<E> {
+    Pxls(PxlsError),
+    Sink(E),
+}
+
+impl<E: Display> Display for TiledError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pxls(e) => write!(f, "{e}"),
+            Self::Sink(e) => write!(f, "couldn't write out a tile: {e}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for TiledError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Pxls(e) => e.source(),
+            Self::Sink(e) => Some(e),
+        }
+    }
+}
+
+impl<E> From<PxlsError> for TiledError<E> {
+    fn from(e: PxlsError) -> Self {
+        Self::Pxls(e)
+    }
+}
+
+//dithers `image` a tile at a time, so that only one tile of the input and the output is ever in memory at once, eg.
+//for scans too big to load. the palette can come from `get_palette_subsampled`.
+//`tile_size` is in input pixels, and gets rounded down to a whole number of chunks. each tile is dithered on its own,
+//so anything that looks at the chunks around it (error diffusion and sharpening) or at where it is (the ordered and
+//noise patterns) starts again in each tile, and can leave seams along the edges of the tiles
+#[allow(clippy::too_many_arguments)]
+pub fn dither_tiled<S: TileSink>(
+    image: &impl PixelSource,
+    tile_size: u32,
+    palette: impl AsRef<[Rgba<u8>]>,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    sink: &mut S,
+    progress: &impl ProgressReporter,
+    stop: &CancellationToken,
+) -> Result<(), TiledError<S::Error>> {
+    let mut tiles = TiledDither::new(
+        image,
+        tile_size,
+        palette,
+        distance_algorithm,
+        output_settings,
+    )?;
+    while tiles.step(sink, stop)? {
+        progress.tick(Stage::Dithering, tiles.tiles_done(), tiles.total_tiles());
+    }
+
+    sink.finish().map_err(TiledError::Sink)
+}
+
+//`dither_tiled` one tile at a time, for when something else has to happen in between, eg. drawing frames in the
+//browser where there's no other thread to dither on. `image` can be anything that gives a `PixelSource`, eg. a
+//reference or an `Arc`
+pub struct TiledDither<I, P> {
+    image: I,
+    palette: P,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    chunk_size: u32,
+    tile_size: u32,
+    output_per_chunk: u32,
+    //the pixels past the last whole chunk get left out, the same as when it's all done at once
+    width: u32,
+    height: u32,
+    tiles_across: u32,
+    tiles_done: u32,
+    tile: RgbaImage,
+    output: RgbaImage,
+}
+
+impl<I, P> TiledDither<I, P>
+where
+    I: Deref,
+    I::Target: PixelSource + Sized,
+    P: AsRef<[Rgba<u8>]>,
+{
+    pub fn new(
+        image: I,
+        tile_size: u32,
+        palette: P,
+        distance_algorithm: DistanceAlgorithm,
+        output_settings: OutputSettings,
+    ) -> Result<Self, PxlsError> {
+        let output_settings = output_settings.validated()?;
+        if palette.as_ref().is_empty() {
+            return Err(PxlsError::EmptyPalette);
+        }
+        check_not_empty(&*image)?;
+        //has to be the same for every tile, so gets worked out from the whole image
+        let chunk_size = dither_chunk_size(image.dimensions(), output_settings)?;
+        let tile_size = (tile_size / chunk_size).max(1) * chunk_size;
+        let (width, height) = (
+            image.width() / chunk_size * chunk_size,
+            image.height() / chunk_size * chunk_size,
+        );
+
+        Ok(Self {
+            image,
+            palette,
+            distance_algorithm,
+            output_settings,
+            chunk_size,
+            tile_size,
+            output_per_chunk: output_settings.effective_dithering_scale()
+                * output_scale_factor(output_settings),
+            width,
+            height,
+            tiles_across: width.div_ceil(tile_size),
+            tiles_done: 0,
+            tile: RgbaImage::new(0, 0),
+            output: RgbaImage::new(0, 0),
+        })
+    }
+
+    pub const fn total_tiles(&self) -> u32 {
+        self.tiles_across * self.height.div_ceil(self.tile_size)
+    }
+
+    pub const fn tiles_done(&self) -> u32 {
+        self.tiles_done
+    }
+
+    //dithers the next tile and sends it to `sink`, or gives back false if they've all been done.
+    //`sink` only gets finished by `dither_tiled`
+    pub fn step<S: TileSink>(
+        &mut self,
+        sink: &mut S,
+        stop: &CancellationToken,
+    ) -> Result<bool, TiledError<S::Error>> {
+        if self.tiles_done == self.total_tiles() {
+            return Ok(false);
+        }
+
+        let (x, y) = (
+            self.tiles_done % self.tiles_across * self.tile_size,
+            self.tiles_done / self.tiles_across * self.tile_size,
+        );
+        resize_keeping_buffer(
+            &mut self.tile,
+            self.tile_size.min(self.width - x),
+            self.tile_size.min(self.height - y),
+        );
+        for (px_x, px_y, px) in self.tile.enumerate_pixels_mut() {
+            *px = self.image.get_pixel(x + px_x, y + px_y);
+        }
+
+        dither_chunks_into(
+            &self.tile,
+            self.palette.as_ref(),
+            self.distance_algorithm,
+            self.output_settings,
+            self.chunk_size,
+            &mut self.output,
+            &NoProgress,
+            stop,
+        )?;
+        sink.write_tile(
+            x / self.chunk_size * self.output_per_chunk,
+            y / self.chunk_size * self.output_per_chunk,
+            &self.output,
+        )
+        .map_err(TiledError::Sink)?;
+
+        self.tiles_done += 1;
+        Ok(true)
+    }
+}
+
+//writes the tiles from `dither_tiled` out to a PNG as they come in, only ever holding on to one row of tiles
+#[cfg(feature = "png")]
+pub struct PngTileSink<W: Write + 'static> {
+    //only `None` once it's finished
+    writer: Option<png::StreamWriter<'static, W>>,
+    width: u32,
+    //the RGB of the row of tiles being filled in, and where it goes in the output
+    band: Vec<u8>,
+    band_y: u32,
+}
+
+#[cfg(feature = "png")]
+impl<W: Write + 'static> PngTileSink<W> {
+    //the size of the whole output, ie. `predicted_output_size`
+    pub fn new(writer: W, width: u32, height: u32) -> Result<Self, png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        Ok(Self {
+            writer: Some(encoder.write_header()?.into_stream_writer()?),
+            width,
+            band: vec![],
+            band_y: 0,
+        })
+    }
+
+    fn write_band(&mut self) -> Result<(), png::EncodingError> {
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(&self.band)?;
+        }
+        self.band.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "png")]
+impl<W: Write + 'static> TileSink for PngTileSink<W> {
+    type Error = png::EncodingError;
+
+    fn write_tile(&mut self, x: u32, y: u32, tile: &RgbaImage) -> Result<(), Self::Error> {
+        if y != self.band_y {
+            self.write_band()?;
+            self.band_y = y;
+        }
+
+        let row_len = self.width as usize * 3;
+        self.band.resize(row_len * tile.height() as usize, 0);
+        for (row, pixels) in self.band.chunks_exact_mut(row_len).zip(tile.rows()) {
+            for (rgb, px) in row[x as usize * 3..].chunks_exact_mut(3).zip(pixels) {
+                rgb.copy_from_slice(&px.0[..3]);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        self.write_band()?;
+        match self.writer.take() {
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dither_source_with_palette, get_palette, DitherMode, ViewSource};
+    use image::{codecs::jpeg::JpegDecoder, ColorType, DynamicImage, ImageDecoder};
+    use std::io::Cursor;
+
+    //4x4 flat blocks of 16px, so that every block lines up with whole jpeg blocks and comes back as one colour
+    const BLOCKS_JPEG: &[u8] = include_bytes!("../tests/fixtures/blocks.jpg");
+
+    const PALETTE: [Rgba<u8>; 3] = [
+        Rgba([0, 0, 0, 255]),
+        Rgba([255, 255, 255, 255]),
+        Rgba([200, 40, 40, 255]),
+    ];
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 2) as u8, (y * 3) as u8, (x ^ y) as u8, 255])
+        })
+    }
+
+    //puts the tiles back together, checking that they cover the output exactly once
+    fn stitched(
+        image: &RgbaImage,
+        tile_size: u32,
+        output_settings: OutputSettings,
+    ) -> (RgbaImage, Vec<(u32, u32)>) {
+        let (width, height) = crate::predicted_output_size(image.dimensions(), output_settings);
+        let mut output = RgbaImage::new(width, height);
+        let mut written = vec![0_u8; (width * height) as usize];
+        let mut positions = vec![];
+        dither_tiled(
+            &ViewSource(image),
+            tile_size,
+            PALETTE,
+            DistanceAlgorithm::Euclidean,
+            output_settings,
+            &mut |x, y, tile: &RgbaImage| {
+                positions.push((x, y));
+                for (px_x, px_y, px) in tile.enumerate_pixels() {
+                    output.put_pixel(x + px_x, y + px_y, *px);
+                    written[((y + px_y) * width + x + px_x) as usize] += 1;
+                }
+                Ok::<_, std::convert::Infallible>(())
+            },
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert!(written.iter().all(|times| *times == 1));
+        (output, positions)
+    }
+
+    #[test]
+    fn tiles_stitch_back_into_the_whole_dither() {
+        let image = gradient(96, 64);
+        //the modes that only look at each chunk on its own have no seams
+        for dither_mode in [DitherMode::None, DitherMode::Legacy] {
+            for scale_output_to_original in [false, true] {
+                let settings = OutputSettings {
+                    output_px_size: 3,
+                    dither_mode,
+                    scale_output_to_original,
+                    ..OutputSettings::default()
+                };
+                let whole = dither_source_with_palette(
+                    &ViewSource(&image),
+                    PALETTE,
+                    DistanceAlgorithm::Euclidean,
+                    settings,
+                    &NoProgress,
+                    &CancellationToken::new(),
+                )
+                .unwrap()
+                .to_rgba8();
+                //a tile size that isn't a whole number of chunks gets rounded down to one
+                for tile_size in [4, 13, 32, 1000] {
+                    let (tiled, _) = stitched(&image, tile_size, settings);
+                    assert_eq!(tiled, whole, "{settings:?} in {tile_size}px tiles");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_come_across_then_down() {
+        let settings = OutputSettings {
+            output_px_size: 3,
+            dither_mode: DitherMode::None,
+            scale_output_to_original: false,
+            ..OutputSettings::default()
+        };
+        //4px chunks, so 12px tiles are three chunks each, and the last column and row are cut short
+        let image = gradient(40, 28);
+        let (_, positions) = stitched(&image, 12, settings);
+        assert_eq!(
+            positions,
+            [
+                (0, 0),
+                (3, 0),
+                (6, 0),
+                (9, 0),
+                (0, 3),
+                (3, 3),
+                (6, 3),
+                (9, 3),
+                (0, 6),
+                (3, 6),
+                (6, 6),
+                (9, 6)
+            ]
+        );
+
+        let mut tiles =
+            TiledDither::new(&image, 12, PALETTE, DistanceAlgorithm::Euclidean, settings).unwrap();
+        assert_eq!(tiles.total_tiles(), 12);
+        let mut sink = |_, _, _: &RgbaImage| Ok::<_, std::convert::Infallible>(());
+        let stop = CancellationToken::new();
+        while tiles.step(&mut sink, &stop).unwrap() {}
+        assert_eq!(tiles.tiles_done(), 12);
+        assert!(!tiles.step(&mut sink, &stop).unwrap());
+    }
+
+    #[test]
+    fn tiling_errors_are_passed_on() {
+        let image = gradient(32, 32);
+        let settings = OutputSettings {
+            output_px_size: 2,
+            ..OutputSettings::default()
+        };
+        let tiled = |sink: &mut dyn FnMut(u32, u32, &RgbaImage) -> Result<(), &'static str>,
+                     stop: &CancellationToken| {
+            let mut sink = sink;
+            dither_tiled(
+                &ViewSource(&image),
+                8,
+                PALETTE,
+                DistanceAlgorithm::Euclidean,
+                settings,
+                &mut sink,
+                &NoProgress,
+                stop,
+            )
+        };
+
+        let mut written = 0;
+        let result = tiled(
+            &mut |_, _, _| {
+                written += 1;
+                if written == 3 {
+                    Err("full")
+                } else {
+                    Ok(())
+                }
+            },
+            &CancellationToken::new(),
+        );
+        assert!(matches!(result, Err(TiledError::Sink("full"))));
+        assert_eq!(written, 3);
+
+        let stop = CancellationToken::new();
+        stop.cancel();
+        let result = tiled(&mut |_, _, _| Ok(()), &stop);
+        assert!(matches!(
+            result,
+            Err(TiledError::Pxls(PxlsError::Cancelled))
+        ));
+
+        assert!(matches!(
+            TiledDither::new(&image, 8, [], DistanceAlgorithm::Euclidean, settings),
+            Err(PxlsError::EmptyPalette)
+        ));
+    }
+
+    //the sink has to own its writer, so this lets the test see what got written
+    #[cfg(feature = "png")]
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    #[cfg(feature = "png")]
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_tiles_are_written_in_bands() {
+        let image = gradient(96, 64);
+        let settings = OutputSettings {
+            output_px_size: 3,
+            dither_mode: DitherMode::Legacy,
+            ..OutputSettings::default()
+        };
+        let (width, height) = crate::predicted_output_size(image.dimensions(), settings);
+        let png = SharedBuffer::default();
+        let mut sink = PngTileSink::new(png.clone(), width, height).unwrap();
+        dither_tiled(
+            &ViewSource(&image),
+            16,
+            PALETTE,
+            DistanceAlgorithm::Euclidean,
+            settings,
+            &mut sink,
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        drop(sink);
+
+        let (stitched, _) = stitched(&image, 16, settings);
+        let decoded = image::load_from_memory(&png.0.borrow()).unwrap();
+        assert_eq!(decoded.color(), ColorType::Rgb8);
+        assert_eq!(decoded.to_rgba8(), stitched);
+    }
+
+    //counts how many pixels get read
+    struct Counted<'a>(&'a RgbaImage, std::cell::Cell<u64>);
+
+    impl GenericImageView for Counted<'_> {
+        type Pixel = Rgba<u8>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            self.0.dimensions()
+        }
+
+        fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+            self.1.set(self.1.get() + 1);
+            *self.0.get_pixel(x, y)
+        }
+    }
+
+    impl PixelSource for Counted<'_> {}
+
+    #[test]
+    fn subsampled_palettes_only_look_at_so_many_pixels() {
+        //flat 20px blocks, so that any pixel of a chunk gives the same colour
+        let image = RgbaImage::from_fn(200, 200, |x, y| {
+            Rgba([(x / 20 * 25) as u8, (y / 20 * 25) as u8, 100, 255])
+        });
+        let settings = PaletteSettings {
+            chunks_per_dimension: 10,
+            closeness_threshold: 0,
+            ..PaletteSettings::default()
+        };
+        let stop = CancellationToken::new();
+        let subsampled = |max_pixels| {
+            let counted = Counted(&image, std::cell::Cell::new(0));
+            let palette = get_palette_subsampled(
+                &counted,
+                max_pixels,
+                settings.clone(),
+                DistanceAlgorithm::Euclidean,
+                &NoProgress,
+                &stop,
+            )
+            .unwrap();
+            (palette, counted.1.get())
+        };
+        let whole = get_palette(
+            &DynamicImage::ImageRgba8(image.clone()),
+            settings.clone(),
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(whole.len(), 100);
+
+        assert_eq!(subsampled(u64::MAX), (whole.clone(), 200 * 200));
+        assert_eq!(subsampled(200 * 200), (whole.clone(), 200 * 200));
+        //every 4th pixel both ways
+        assert_eq!(subsampled(50 * 50), (whole.clone(), 50 * 50));
+        //not a square number, so it rounds the step up to look at fewer
+        let (_, read) = subsampled(1000);
+        assert_eq!(read, 29 * 29);
+        //asking for nothing still looks at one pixel
+        let (palette, read) = subsampled(0);
+        assert_eq!((palette.len(), read), (1, 1));
+    }
+
+    #[test]
+    fn streamed_jpegs_match_a_full_decode() {
+        let decoder = JpegDecoder::new(Cursor::new(BLOCKS_JPEG)).unwrap();
+        let (width, height) = decoder.dimensions();
+        assert_eq!(decoder.color_type(), ColorType::Rgb8);
+        let mut scanlines = vec![0; usize::try_from(decoder.total_bytes()).unwrap()];
+        decoder.read_image(&mut scanlines).unwrap();
+
+        let settings = PaletteSettings {
+            chunks_per_dimension: 4,
+            ..PaletteSettings::default()
+        };
+        //a row at a time, as the scanlines come out of the decoder
+        let mut builder = StreamingPaletteBuilder::new(
+            width,
+            height,
+            settings.clone(),
+            DistanceAlgorithm::Euclidean,
+        );
+        for (y, row) in (0..).zip(scanlines.chunks_exact(width as usize * 3)) {
+            for (x, pixel) in (0..).zip(row.chunks_exact(3)) {
+                builder.feed_pixel(x, y, Rgba([pixel[0], pixel[1], pixel[2], u8::MAX]));
+            }
+        }
+        let streamed = builder.finish();
+
+        let full = image::load_from_memory(BLOCKS_JPEG).unwrap();
+        assert_eq!(full.as_bytes(), scanlines);
+        let palette = get_palette(
+            &full,
+            settings,
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(streamed.len(), 16);
+        //the chunks get finished in a different order
+        assert_eq!(streamed.content_hash(), palette.content_hash());
+    }
+
+    #[test]
+    fn streams_that_get_cut_short_still_use_what_they_had() {
+        let full = image::load_from_memory(BLOCKS_JPEG).unwrap();
+        let full = DynamicImage::ImageRgb8(full.to_rgb8());
+        let mut builder = StreamingPaletteBuilder::new(
+            64,
+            64,
+            PaletteSettings {
+                chunks_per_dimension: 4,
+                ..PaletteSettings::default()
+            },
+            DistanceAlgorithm::Euclidean,
+        );
+        //only the first row of chunks, and half of the second
+        for y in 0..24 {
+            for x in 0..64 {
+                builder.feed_pixel(x, y, full.get_pixel(x, y));
+            }
+        }
+        assert_eq!(builder.finish().len(), 8);
+    }
+}
@@ -0,0 +1,78 @@
+//opening and saving files. native dialogs block until they're closed, so they get a thread each, and in the browser
+//they're async and get run alongside the gui. either way, whatever comes of them gets sent back to be picked up on
+//a later frame
+use std::sync::mpsc::{channel, Receiver};
+
+pub struct PickedFile {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_file() -> Receiver<Result<PickedFile, String>> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        if let Some(path) = rfd::FileDialog::new().pick_file() {
+            let picked = std::fs::read(&path)
+                .map(|bytes| PickedFile {
+                    name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    bytes,
+                })
+                .map_err(|e| format!("Couldn't read {}: {e}", path.display()));
+            let _ = sender.send(picked);
+        }
+    });
+    receiver
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn pick_file() -> Receiver<Result<PickedFile, String>> {
+    let (sender, receiver) = channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Some(file) = rfd::AsyncFileDialog::new().pick_file().await {
+            let _ = sender.send(Ok(PickedFile {
+                name: file.file_name(),
+                bytes: file.read().await,
+            }));
+        }
+    });
+    receiver
+}
+
+//sends back whether it worked, or nothing if it was cancelled
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: String, bytes: Vec<u8>) -> Receiver<Result<(), String>> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        if let Some(path) = rfd::FileDialog::new().set_file_name(name).save_file() {
+            let _ = sender.send(
+                std::fs::write(&path, bytes)
+                    .map_err(|e| format!("Couldn't write to {}: {e}", path.display())),
+            );
+        }
+    });
+    receiver
+}
+
+//the browser decides where it goes, usually the downloads folder
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: String, bytes: Vec<u8>) -> Receiver<Result<(), String>> {
+    let (sender, receiver) = channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Some(file) = rfd::AsyncFileDialog::new()
+            .set_file_name(name)
+            .save_file()
+            .await
+        {
+            let _ = sender.send(
+                file.write(&bytes)
+                    .await
+                    .map_err(|e| format!("Couldn't save: {e}")),
+            );
+        }
+    });
+    receiver
+}
@@ -0,0 +1,348 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+#![allow(
+    clippy::cast_lossless,
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss
+)]
+
+//a smaller version of the gui that also works in the browser, where there are no threads, no clipboard and no file
+//system. build it with eg. `trunk serve --no-default-features --features gui-web`, which uses `index.html`
+
+use crate::{
+    files::{pick_file, save_file, PickedFile},
+    render::{RenderTask, Rendered},
+    tasks::{FrameRunner, Runner, TaskHandle},
+};
+use egui::{ColorImage, Context, Grid, ProgressBar, Slider, TextureHandle, TextureOptions, Widget};
+use image::{ImageFormat, RgbaImage};
+use pxls::{DistanceAlgorithm, OutputSettings, PaletteSettings, PxlsError, ALL_ALGOS};
+use std::{
+    io::Cursor,
+    path::Path,
+    sync::{mpsc::Receiver, Arc},
+};
+
+mod files;
+mod render;
+mod tasks;
+
+const APP_NAME: &str = "Pxls";
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use eframe::wasm_bindgen::JsCast;
+
+    wasm_bindgen_futures::spawn_local(async {
+        let canvas = eframe::web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("pxls_canvas"))
+            .and_then(|canvas| canvas.dyn_into::<eframe::web_sys::HtmlCanvasElement>().ok())
+            .expect("the page needs a <canvas id=\"pxls_canvas\">");
+
+        if let Err(e) = eframe::WebRunner::new()
+            .start(
+                canvas,
+                eframe::WebOptions::default(),
+                Box::new(|_| Ok(Box::new(WebApp::new(FrameRunner::default())))),
+            )
+            .await
+        {
+            eframe::web_sys::console::error_1(&e);
+        }
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    fn run(runner: impl Runner + 'static) -> eframe::Result {
+        eframe::run_native(
+            APP_NAME,
+            eframe::NativeOptions::default(),
+            Box::new(|_| Ok(Box::new(WebApp::new(runner)))),
+        )
+    }
+
+    //`--frames` does all the work on the gui thread like in the browser, to try that out without building for wasm
+    let result = if std::env::args().skip(1).any(|arg| arg == "--frames") {
+        run(FrameRunner::default())
+    } else {
+        run(tasks::ThreadRunner)
+    };
+    if let Err(e) = result {
+        eprintln!("Error running eframe: {e:?}");
+    }
+}
+
+struct Input {
+    name: String,
+    image: Arc<RgbaImage>,
+    texture: TextureHandle,
+}
+
+struct Output {
+    rendered: Rendered,
+    texture: TextureHandle,
+}
+
+struct WebApp<R> {
+    runner: R,
+    palette_settings: PaletteSettings,
+    distance_algorithm: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    input: Option<Input>,
+    output: Option<Output>,
+    //dropping a render stops it, so starting a new one stops the old one
+    render: Option<TaskHandle<Result<Rendered, PxlsError>>>,
+    picking: Option<Receiver<Result<PickedFile, String>>>,
+    saving: Option<Receiver<Result<(), String>>>,
+    error: Option<String>,
+}
+
+impl<R: Runner> WebApp<R> {
+    fn new(runner: R) -> Self {
+        Self {
+            runner,
+            palette_settings: PaletteSettings::default(),
+            distance_algorithm: DistanceAlgorithm::Euclidean,
+            output_settings: OutputSettings::default(),
+            input: None,
+            output: None,
+            render: None,
+            picking: None,
+            saving: None,
+            error: None,
+        }
+    }
+
+    fn texture(ctx: &Context, name: &str, image: &RgbaImage) -> TextureHandle {
+        ctx.load_texture(
+            name,
+            ColorImage::from_rgba_unmultiplied(
+                [image.width() as usize, image.height() as usize],
+                image.as_raw(),
+            ),
+            TextureOptions::NEAREST,
+        )
+    }
+
+    fn start_render(&mut self) {
+        let Some(input) = &self.input else {
+            return;
+        };
+        match RenderTask::new(
+            input.image.clone(),
+            self.palette_settings.clone(),
+            self.distance_algorithm,
+            self.output_settings,
+        ) {
+            Ok(task) => {
+                self.render = Some(self.runner.spawn(task));
+                self.error = None;
+            }
+            Err(e) => {
+                self.render = None;
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn open(&mut self, ctx: &Context, picked: PickedFile) {
+        match image::load_from_memory(&picked.bytes) {
+            Ok(image) => {
+                let image = image.to_rgba8();
+                self.input = Some(Input {
+                    texture: Self::texture(ctx, "input", &image),
+                    name: picked.name,
+                    image: Arc::new(image),
+                });
+                self.output = None;
+                self.start_render();
+            }
+            Err(e) => self.error = Some(format!("Couldn't open {}: {e}", picked.name)),
+        }
+    }
+
+    fn save(&mut self) {
+        let (Some(input), Some(output)) = (&self.input, &self.output) else {
+            return;
+        };
+        let mut png = Cursor::new(vec![]);
+        if let Err(e) = output.rendered.output.write_to(&mut png, ImageFormat::Png) {
+            self.error = Some(format!("Couldn't make a PNG: {e}"));
+            return;
+        }
+
+        let stem = Path::new(&input.name)
+            .file_stem()
+            .map_or_else(|| "output".into(), |stem| stem.to_string_lossy());
+        self.saving = Some(save_file(format!("{stem}-pxls.png"), png.into_inner()));
+    }
+
+    //picks up whatever's been sent back since the last frame
+    fn poll(&mut self, ctx: &Context) {
+        self.runner.run_frame();
+
+        if let Some(picked) = self.picking.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.picking = None;
+            match picked {
+                Ok(picked) => self.open(ctx, picked),
+                Err(e) => self.error = Some(e),
+            }
+        }
+        if let Some(Err(e)) = self.saving.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.saving = None;
+            self.error = Some(e);
+        }
+        if let Some(result) = self.render.as_mut().and_then(TaskHandle::poll) {
+            self.render = None;
+            match result {
+                Ok(rendered) => {
+                    self.output = Some(Output {
+                        texture: Self::texture(ctx, "output", &rendered.output),
+                        rendered,
+                    });
+                }
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+
+        //nothing else would wake the gui up to check on them
+        if self.render.is_some() || self.picking.is_some() || self.saving.is_some() {
+            ctx.request_repaint();
+        }
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        let before = (
+            self.palette_settings.clone(),
+            self.distance_algorithm,
+            self.output_settings,
+        );
+
+        Grid::new("settings").show(ui, |ui| {
+            ui.label("Distance Algorithm: ");
+            let current = self.distance_algorithm;
+            egui::ComboBox::from_id_salt("distance_algorithm")
+                .selected_text(current.to_str())
+                .show_ui(ui, |ui| {
+                    for possibility in ALL_ALGOS {
+                        ui.selectable_value(
+                            &mut self.distance_algorithm,
+                            *possibility,
+                            possibility.to_str(),
+                        );
+                    }
+                });
+            if current != self.distance_algorithm {
+                //keep the threshold meaning roughly the same thing in the new units
+                self.palette_settings.closeness_threshold =
+                    self.distance_algorithm.closeness_threshold_from_normalised(
+                        current.normalised_closeness_threshold(
+                            self.palette_settings.closeness_threshold,
+                        ),
+                    );
+            }
+            ui.end_row();
+
+            ui.label("Chunks per Dimension: ");
+            ui.add(
+                Slider::new(&mut self.palette_settings.chunks_per_dimension, 1..=10_000)
+                    .logarithmic(true),
+            );
+            ui.end_row();
+
+            ui.label("Closeness Threshold: ");
+            ui.add(
+                Slider::new(
+                    &mut self.palette_settings.closeness_threshold,
+                    self.distance_algorithm.closeness_threshold_range(),
+                )
+                .logarithmic(true),
+            );
+            ui.end_row();
+
+            ui.label("Virtual Pixel Size: ");
+            let min = self.output_settings.effective_dithering_scale().ilog2() + 1;
+            ui.add(Slider::new(
+                &mut self.output_settings.output_px_size,
+                min..=10,
+            ));
+            ui.end_row();
+        });
+
+        if before
+            != (
+                self.palette_settings.clone(),
+                self.distance_algorithm,
+                self.output_settings,
+            )
+        {
+            self.start_render();
+        }
+    }
+}
+
+impl<R: Runner> eframe::App for WebApp<R> {
+    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+        self.poll(ctx);
+
+        egui::SidePanel::left("controls").show(ctx, |ui| {
+            ui.heading(APP_NAME);
+            ui.horizontal(|ui| {
+                if ui.button("Open…").clicked() && self.picking.is_none() {
+                    self.picking = Some(pick_file());
+                }
+                if ui
+                    .add_enabled(self.output.is_some(), egui::Button::new("Save…"))
+                    .clicked()
+                {
+                    self.save();
+                }
+            });
+            ui.separator();
+
+            self.settings_ui(ui);
+
+            if let Some(render) = &self.render {
+                ProgressBar::new(render.progress())
+                    .animate(true)
+                    .show_percentage()
+                    .ui(ui);
+            }
+            if let Some(output) = &self.output {
+                ui.label(format!("{} colours", output.rendered.palette.len()));
+                ui.horizontal_wrapped(|ui| {
+                    for colour in &output.rendered.palette {
+                        let [r, g, b, a] = colour.0;
+                        let (rect, _) =
+                            ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+                        ui.painter().rect_filled(
+                            rect,
+                            2.0,
+                            egui::Color32::from_rgba_unmultiplied(r, g, b, a),
+                        );
+                    }
+                });
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let texture = self
+                .output
+                .as_ref()
+                .map(|output| &output.texture)
+                .or_else(|| self.input.as_ref().map(|input| &input.texture));
+            match texture {
+                Some(texture) => {
+                    ui.add(egui::Image::new(texture).shrink_to_fit());
+                }
+                None => {
+                    ui.centered_and_justified(|ui| ui.label("Open an image to get started"));
+                }
+            }
+        });
+    }
+}
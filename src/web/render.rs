@@ -0,0 +1,281 @@
+use crate::tasks::{Step, Task};
+use image::{imageops, RgbaImage};
+use pxls::{
+    cancellation::CancellationToken,
+    predicted_output_size,
+    streaming::{StreamingPaletteBuilder, TiledDither, TiledError},
+    DistanceAlgorithm, OutputSettings, Palette, PaletteSettings, PxlsError,
+};
+use std::{convert::Infallible, sync::Arc};
+
+//roughly how many input pixels get looked at in each step, so no step takes much longer than a frame
+const PIXELS_PER_STEP: u32 = 1 << 16;
+const TILE_SIZE: u32 = 256;
+
+pub struct Rendered {
+    pub palette: Palette,
+    pub output: RgbaImage,
+}
+
+enum Stage {
+    Palette {
+        //`None` once it's been finished
+        builder: Option<StreamingPaletteBuilder>,
+        next_column: u32,
+    },
+    Dithering {
+        palette: Palette,
+        tiles: TiledDither<Arc<RgbaImage>, Palette>,
+        output: RgbaImage,
+    },
+}
+
+//the same as `get_palette` and then `dither_original_with_palette`, but split up into steps. the palette is fed in
+//a column of pixels at a time, so the chunks fill up in the same order that `get_palette` goes through them, and the
+//dithering is done in tiles
+pub struct RenderTask {
+    input: Arc<RgbaImage>,
+    dist_algo: DistanceAlgorithm,
+    output_settings: OutputSettings,
+    stage: Stage,
+}
+
+impl RenderTask {
+    pub fn new(
+        input: Arc<RgbaImage>,
+        palette_settings: PaletteSettings,
+        dist_algo: DistanceAlgorithm,
+        output_settings: OutputSettings,
+    ) -> Result<Self, PxlsError> {
+        let palette_settings = palette_settings.validated(dist_algo)?;
+        let output_settings = output_settings.validated()?;
+        if input.width() == 0 || input.height() == 0 {
+            return Err(PxlsError::ZeroDimension);
+        }
+
+        Ok(Self {
+            stage: Stage::Palette {
+                builder: Some(StreamingPaletteBuilder::new(
+                    input.width(),
+                    input.height(),
+                    palette_settings,
+                    dist_algo,
+                )),
+                next_column: 0,
+            },
+            input,
+            dist_algo,
+            output_settings,
+        })
+    }
+
+    //the first half of the progress is the palette, and the second half is the dithering
+    fn step_palette(&mut self) -> Result<Step<Rendered>, PxlsError> {
+        let Stage::Palette {
+            builder,
+            next_column,
+        } = &mut self.stage
+        else {
+            unreachable!("only called while working out the palette");
+        };
+        let Some(builder_ref) = builder else {
+            unreachable!("the builder is only taken when moving on to dithering");
+        };
+
+        let (width, height) = self.input.dimensions();
+        let columns = (PIXELS_PER_STEP / height).max(1).min(width - *next_column);
+        for x in *next_column..(*next_column + columns) {
+            for y in 0..height {
+                builder_ref.feed_pixel(x, y, *self.input.get_pixel(x, y));
+            }
+        }
+        *next_column += columns;
+        if *next_column < width {
+            return Ok(Step::Progress(*next_column as f32 / width as f32 / 2.0));
+        }
+
+        let palette = builder
+            .take()
+            .map(StreamingPaletteBuilder::finish)
+            .unwrap_or_default();
+        if palette.is_empty() {
+            return Err(PxlsError::EmptyPalette);
+        }
+        let tiles = TiledDither::new(
+            self.input.clone(),
+            TILE_SIZE,
+            palette.clone(),
+            self.dist_algo,
+            self.output_settings,
+        )?;
+        let (output_width, output_height) =
+            predicted_output_size(self.input.dimensions(), self.output_settings);
+        self.stage = Stage::Dithering {
+            palette,
+            tiles,
+            output: RgbaImage::new(output_width, output_height),
+        };
+        Ok(Step::Progress(0.5))
+    }
+
+    fn step_dithering(&mut self) -> Result<Step<Rendered>, PxlsError> {
+        let Stage::Dithering {
+            palette,
+            tiles,
+            output,
+        } = &mut self.stage
+        else {
+            unreachable!("only called while dithering");
+        };
+
+        let mut sink = |x: u32, y: u32, tile: &RgbaImage| {
+            imageops::replace(output, tile, i64::from(x), i64::from(y));
+            Ok::<_, Infallible>(())
+        };
+        //the rendered image is only ever used by the gui, so there's nothing to stop it early
+        if tiles
+            .step(&mut sink, &CancellationToken::new())
+            .map_err(|e| match e {
+                TiledError::Pxls(e) => e,
+                TiledError::Sink(never) => match never {},
+            })?
+        {
+            return Ok(Step::Progress(
+                0.5 + tiles.tiles_done() as f32 / tiles.total_tiles() as f32 / 2.0,
+            ));
+        }
+
+        Ok(Step::Done(Rendered {
+            palette: std::mem::take(palette),
+            output: std::mem::take(output),
+        }))
+    }
+}
+
+impl Task for RenderTask {
+    type Output = Result<Rendered, PxlsError>;
+
+    fn step(&mut self) -> Step<Self::Output> {
+        let step = match self.stage {
+            Stage::Palette { .. } => self.step_palette(),
+            Stage::Dithering { .. } => self.step_dithering(),
+        };
+        match step {
+            Ok(Step::Progress(progress)) => Step::Progress(progress),
+            Ok(Step::Done(rendered)) => Step::Done(Ok(rendered)),
+            Err(e) => Step::Done(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgba};
+    use pxls::{dither_original_with_palette, get_palette, progress::NoProgress};
+
+    //a different colour in each 8x8 block, with one pixel in each that's a different colour, so that no chunk has a
+    //tie for its most common colour
+    fn blocks(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            if x % 8 == 0 && y % 8 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([(x / 8 * 40) as u8, (y / 8 * 40) as u8, 200, 255])
+            }
+        })
+    }
+
+    fn run(mut task: RenderTask) -> (Result<Rendered, PxlsError>, Vec<f32>) {
+        let mut progress = vec![];
+        loop {
+            match task.step() {
+                Step::Progress(p) => progress.push(p),
+                Step::Done(rendered) => return (rendered, progress),
+            }
+        }
+    }
+
+    #[test]
+    fn steps_make_the_same_as_doing_it_all_at_once() {
+        let input = blocks(48, 40);
+        let (palette_settings, output_settings) = (
+            PaletteSettings {
+                chunks_per_dimension: 5,
+                ..PaletteSettings::default()
+            },
+            OutputSettings::default(),
+        );
+        let (rendered, progress) = run(RenderTask::new(
+            Arc::new(input.clone()),
+            palette_settings.clone(),
+            DistanceAlgorithm::Euclidean,
+            output_settings,
+        )
+        .unwrap());
+        let rendered = rendered.unwrap();
+
+        let input = DynamicImage::ImageRgba8(input);
+        let stop = CancellationToken::new();
+        let palette = get_palette(
+            &input,
+            palette_settings,
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(rendered.palette, palette);
+        let output = dither_original_with_palette(
+            &input,
+            &palette,
+            DistanceAlgorithm::Euclidean,
+            output_settings,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+        assert_eq!(rendered.output, output.to_rgba8());
+
+        assert!(!progress.is_empty());
+        assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(progress.iter().all(|p| (0.0..=1.0).contains(p)));
+    }
+
+    #[test]
+    fn big_images_take_more_than_one_step_for_the_palette() {
+        let (width, height) = (PIXELS_PER_STEP / 32, 128);
+        let (_, progress) = run(RenderTask::new(
+            Arc::new(blocks(width, height)),
+            PaletteSettings::default(),
+            DistanceAlgorithm::Euclidean,
+            OutputSettings::default(),
+        )
+        .unwrap());
+        assert!(progress.iter().filter(|p| **p < 0.5).count() > 1);
+    }
+
+    #[test]
+    fn bad_inputs_are_rejected_up_front() {
+        let settings = |chunks_per_dimension| PaletteSettings {
+            chunks_per_dimension,
+            ..PaletteSettings::default()
+        };
+        for (input, palette_settings, output_px_size) in [
+            (RgbaImage::new(0, 4), settings(2), 1),
+            (blocks(16, 16), settings(0), 1),
+            (blocks(16, 16), settings(2), 0),
+        ] {
+            assert!(RenderTask::new(
+                Arc::new(input),
+                palette_settings,
+                DistanceAlgorithm::Euclidean,
+                OutputSettings {
+                    output_px_size,
+                    ..OutputSettings::default()
+                },
+            )
+            .is_err());
+        }
+    }
+}
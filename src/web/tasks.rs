@@ -0,0 +1,216 @@
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    time::Duration,
+};
+//`std`'s clock panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+pub enum Step<T> {
+    //how far through it is, from 0 to 1
+    Progress(f32),
+    Done(T),
+}
+
+//work that gets done a step at a time. on native it can all happen on another thread, but the browser doesn't have
+//threads, so there it gets a few steps every frame instead
+pub trait Task: Send + 'static {
+    type Output: Send + 'static;
+
+    fn step(&mut self) -> Step<Self::Output>;
+}
+
+pub trait Runner {
+    fn spawn<T: Task>(&mut self, task: T) -> TaskHandle<T::Output>;
+
+    //has to be called every frame, for runners that do their work on the gui thread
+    fn run_frame(&mut self) {}
+}
+
+//dropping it stops the task after whichever step it's on
+pub struct TaskHandle<T> {
+    steps: Receiver<Step<T>>,
+    progress: f32,
+}
+
+impl<T> TaskHandle<T> {
+    fn new() -> (Sender<Step<T>>, Self) {
+        let (sender, steps) = channel();
+        (
+            sender,
+            Self {
+                steps,
+                progress: 0.0,
+            },
+        )
+    }
+
+    //the output, once it's done
+    pub fn poll(&mut self) -> Option<T> {
+        while let Ok(step) = self.steps.try_recv() {
+            match step {
+                Step::Progress(progress) => self.progress = progress,
+                Step::Done(output) => {
+                    self.progress = 1.0;
+                    return Some(output);
+                }
+            }
+        }
+        None
+    }
+
+    pub const fn progress(&self) -> f32 {
+        self.progress
+    }
+}
+
+//steps the task and sends back how it went, giving back whether it should keep going
+fn step_and_send<T: Task>(task: &mut T, sender: &Sender<Step<T::Output>>) -> bool {
+    let step = task.step();
+    let done = matches!(step, Step::Done(_));
+    sender.send(step).is_ok() && !done
+}
+
+//every task gets its own thread
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ThreadRunner;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Runner for ThreadRunner {
+    fn spawn<T: Task>(&mut self, mut task: T) -> TaskHandle<T::Output> {
+        let (sender, handle) = TaskHandle::new();
+        std::thread::spawn(move || while step_and_send(&mut task, &sender) {});
+        handle
+    }
+}
+
+//runs tasks on the gui thread, taking turns until `budget` is used up each frame so the gui can still draw the
+//progress in between
+pub struct FrameRunner {
+    budget: Duration,
+    tasks: Vec<Box<dyn FnMut() -> bool>>,
+}
+
+impl FrameRunner {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            tasks: vec![],
+        }
+    }
+}
+
+impl Default for FrameRunner {
+    //leaves most of a 60fps frame for drawing
+    fn default() -> Self {
+        Self::new(Duration::from_millis(10))
+    }
+}
+
+impl Runner for FrameRunner {
+    fn spawn<T: Task>(&mut self, mut task: T) -> TaskHandle<T::Output> {
+        let (sender, handle) = TaskHandle::new();
+        self.tasks
+            .push(Box::new(move || step_and_send(&mut task, &sender)));
+        handle
+    }
+
+    //every task gets at least one step, even if that goes over the budget
+    fn run_frame(&mut self) {
+        let start = Instant::now();
+        let mut first_round = true;
+        while !self.tasks.is_empty() && (first_round || start.elapsed() < self.budget) {
+            self.tasks.retain_mut(|task| task());
+            first_round = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //counts up to `to`, one at a time
+    struct Count {
+        so_far: u32,
+        to: u32,
+    }
+
+    impl Task for Count {
+        type Output = u32;
+
+        fn step(&mut self) -> Step<u32> {
+            if self.so_far == self.to {
+                return Step::Done(self.so_far);
+            }
+            self.so_far += 1;
+            Step::Progress(self.so_far as f32 / self.to as f32)
+        }
+    }
+
+    const fn count(to: u32) -> Count {
+        Count { so_far: 0, to }
+    }
+
+    #[test]
+    fn thread_runner_finishes_tasks() {
+        let mut handle = ThreadRunner.spawn(count(100));
+        let output = loop {
+            if let Some(output) = handle.poll() {
+                break output;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(output, 100);
+        assert!((handle.progress() - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn frame_runner_only_works_during_frames() {
+        let mut runner = FrameRunner::new(Duration::ZERO);
+        let mut handle = runner.spawn(count(3));
+        assert!(handle.poll().is_none());
+        assert!(handle.progress().abs() < f32::EPSILON);
+
+        //with no budget, each frame is one step
+        for expected in [1.0 / 3.0, 2.0 / 3.0, 1.0] {
+            runner.run_frame();
+            assert!(handle.poll().is_none());
+            assert!((handle.progress() - expected).abs() < f32::EPSILON);
+        }
+        runner.run_frame();
+        assert_eq!(handle.poll(), Some(3));
+        assert!(runner.tasks.is_empty());
+    }
+
+    #[test]
+    fn frame_runner_shares_frames() {
+        let mut runner = FrameRunner::new(Duration::ZERO);
+        let mut short = runner.spawn(count(1));
+        let mut long = runner.spawn(count(4));
+        runner.run_frame();
+        runner.run_frame();
+        assert_eq!(short.poll(), Some(1));
+        assert!(long.poll().is_none());
+        assert!((long.progress() - 0.5).abs() < f32::EPSILON);
+        assert_eq!(runner.tasks.len(), 1);
+    }
+
+    #[test]
+    fn frame_runner_uses_its_budget() {
+        let mut runner = FrameRunner::new(Duration::MAX);
+        let mut handle = runner.spawn(count(1000));
+        runner.run_frame();
+        assert_eq!(handle.poll(), Some(1000));
+    }
+
+    #[test]
+    fn dropped_handles_stop_their_tasks() {
+        let mut runner = FrameRunner::new(Duration::MAX);
+        drop(runner.spawn(count(u32::MAX)));
+        runner.run_frame();
+        assert!(runner.tasks.is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+//runs the `pxls` binary's CLI and checks that it saves just what the library's pipeline makes with the same
+//settings, so that the CLI can't drift off onto a copy of its own. the GUI worker's side of this is in its own tests,
+//as the binary's modules can't be reached from here
+#![cfg(all(feature = "gui", feature = "cli"))]
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use pxls::{pipeline::Pxls, DistanceAlgorithm, DitheringMode, OutputSettings, PaletteSettings};
+use std::{path::PathBuf, process::Command};
+
+//flat 16px blocks, each with a gradient along its bottom row so that there's something to dither. the gradients are
+//never the most common colour in a chunk, so the palette doesn't depend on how ties are broken
+fn blocks() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+        let (block_x, block_y) = ((x / 16) as u8, (y / 16) as u8);
+        let flat = Rgba([block_x * 60, block_y * 60, 200 - block_x * 30, 255]);
+        if y % 16 == 15 {
+            Rgba([flat[0] + (x % 16) as u8 * 3, flat[1], flat[2], 255])
+        } else {
+            flat
+        }
+    }))
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+#[test]
+fn the_cli_saves_what_the_pipeline_makes() {
+    let image = blocks();
+    let input = temp_path("cli_pipeline_input.png");
+    let output = temp_path("cli_pipeline_output.png");
+    image.save(&input).unwrap();
+    let _ = std::fs::remove_file(&output);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_pxls"))
+        .arg(&input)
+        .args(["4", "0", "euclidean"])
+        .arg(&output)
+        .args(["3", "4", "2"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let saved = image::open(&output).unwrap();
+
+    let expected = Pxls::new(&image)
+        .palette_settings(PaletteSettings {
+            chunks_per_dimension: 4,
+            closeness_threshold: 0,
+            ..PaletteSettings::default()
+        })
+        .output_settings(OutputSettings {
+            output_px_size: 3,
+            dithering_mode: DitheringMode::Ratio(4),
+            dithering_scale: 2,
+            scale_output_to_original: true,
+            ..OutputSettings::default()
+        })
+        .algorithm(DistanceAlgorithm::Euclidean)
+        .run()
+        .unwrap()
+        .output;
+
+    assert_eq!(saved.to_rgba8(), expected.to_rgba8());
+}
@@ -0,0 +1,97 @@
+//goes down the branches that nothing else was getting to, so that `make coverage` notices if they stop being
+//reachable. only uses the library without features, like `lib_only`
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use pxls::{
+    cancellation::CancellationToken, get_closest_factor, get_palette, get_palette_with_stats,
+    pixel_perfect_scale, progress::NoProgress, DistanceAlgorithm, OutputSettings, PaletteSettings,
+    PxlsError,
+};
+
+//one colour, with a slightly different colour in one corner of each 4x4 chunk
+fn nearly_flat(size: u32) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(size, size, |x, y| {
+        if x % 4 == 0 && y % 4 == 0 {
+            Rgba([104, 100, 100, 255])
+        } else {
+            Rgba([100, 100, 100, 255])
+        }
+    }))
+}
+
+fn settings(chunks_per_dimension: u32) -> PaletteSettings {
+    PaletteSettings {
+        chunks_per_dimension,
+        ..PaletteSettings::default()
+    }
+}
+
+#[test]
+fn stopping_before_the_first_chunk() {
+    let stop = CancellationToken::new();
+    stop.cancel();
+    assert!(matches!(
+        get_palette(
+            &nearly_flat(16),
+            settings(4),
+            DistanceAlgorithm::Euclidean,
+            &NoProgress,
+            &stop,
+        ),
+        Err(PxlsError::Cancelled)
+    ));
+}
+
+//every chunk after the first only has colours that are too close to the first one, so they don't add anything
+#[test]
+fn chunks_with_nothing_far_enough_away() {
+    let (palette, stats) = get_palette_with_stats(
+        &nearly_flat(16),
+        settings(4),
+        DistanceAlgorithm::Euclidean,
+        &NoProgress,
+        &CancellationToken::new(),
+    )
+    .unwrap();
+    assert_eq!(palette.len(), 1);
+    assert_eq!(palette[0], Rgba([100, 100, 100, 255]));
+    assert_eq!(stats.chunks_processed, 16);
+}
+
+//nothing gets added after the first chunk, so the cache never gets cleared again and almost every pixel is a hit
+#[test]
+fn cache_hits() {
+    let (_, stats) = get_palette_with_stats(
+        &nearly_flat(16),
+        settings(4),
+        DistanceAlgorithm::Euclidean,
+        &NoProgress,
+        &CancellationToken::new(),
+    )
+    .unwrap();
+    assert_eq!(stats.pixels_sampled, 16 * 16);
+    //both colours miss in the first chunk, and again in the second after the cache gets cleared for the new colour
+    assert_eq!(stats.cache_hit_rate, Some(252.0 / 256.0));
+}
+
+#[test]
+fn not_scaling_back_to_the_original() {
+    let output = nearly_flat(8);
+    let settings = OutputSettings {
+        scale_output_to_original: false,
+        //would be rejected if it got as far as being checked
+        output_px_size: 0,
+        ..OutputSettings::default()
+    };
+    let scaled = pixel_perfect_scale(settings, &output).unwrap();
+    assert_eq!(scaled.dimensions(), (8, 8));
+    assert_eq!(scaled, output);
+}
+
+//`number` is the closest factor whenever the target is at least as big, and for primes it's that or 1
+#[test]
+fn closest_factor_falls_back_to_the_number() {
+    assert_eq!(get_closest_factor(100, 7), 7);
+    assert_eq!(get_closest_factor(7, 7), 7);
+    assert_eq!(get_closest_factor(12, 13), 13);
+    assert_eq!(get_closest_factor(5, 13), 1);
+}
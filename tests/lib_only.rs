@@ -0,0 +1,53 @@
+//only uses what the library has without any features, so that `cargo test --no-default-features` checks that the
+//library works on its own
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use pxls::{
+    cancellation::CancellationToken, dither_original_with_palette, get_palette,
+    pixel_perfect_scale_by, progress::NoProgress, DistanceAlgorithm, OutputSettings,
+    PaletteSettings,
+};
+
+fn stripes() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, _| {
+        if x < 32 {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([0, 0, 255, 255])
+        }
+    }))
+}
+
+#[test]
+fn palette_dither_and_scale() {
+    let image = stripes();
+    let stop = CancellationToken::new();
+
+    let palette = get_palette(
+        &image,
+        PaletteSettings::default(),
+        DistanceAlgorithm::Euclidean,
+        &NoProgress,
+        &stop,
+    )
+    .unwrap();
+    assert_eq!(palette.len(), 2);
+    assert!(palette.contains(&Rgba([255, 0, 0, 255])));
+    assert!(palette.contains(&Rgba([0, 0, 255, 255])));
+
+    let output = dither_original_with_palette(
+        &image,
+        &palette,
+        DistanceAlgorithm::Euclidean,
+        OutputSettings::default(),
+        &NoProgress,
+        &stop,
+    )
+    .unwrap();
+    //scaled back up to the size of the input
+    assert_eq!(output.dimensions(), image.dimensions());
+    assert_eq!(output.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+    assert_eq!(output.get_pixel(63, 63), Rgba([0, 0, 255, 255]));
+
+    let doubled = pixel_perfect_scale_by(&output, 2).unwrap();
+    assert_eq!(doubled.dimensions(), (128, 128));
+}
@@ -0,0 +1,119 @@
+//dithers an image far too big to hold without ever making it, and checks that only about a tile's worth of memory
+//gets used. on its own so that the allocator only counts this test
+#![cfg(feature = "png")]
+
+use image::{GenericImageView, Rgba};
+use pxls::{
+    cancellation::CancellationToken,
+    predicted_output_size,
+    progress::NoProgress,
+    streaming::{dither_tiled, get_palette_subsampled, PngTileSink},
+    DistanceAlgorithm, DitherMode, OutputSettings, PaletteSettings, PixelSource,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(now, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+//how much more than was already allocated `f` needs at most
+fn peak_allocation(f: impl FnOnce()) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    f();
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+//any size at all, with pixels made up from where they are
+struct Generated(u32, u32);
+
+impl GenericImageView for Generated {
+    type Pixel = Rgba<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.0, self.1)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+        Rgba([(x / 64) as u8, (y / 64) as u8, ((x ^ y) / 256) as u8, 255])
+    }
+}
+
+impl PixelSource for Generated {}
+
+#[test]
+fn tiled_dithering_only_holds_a_tile_at_a_time() {
+    const SIZE: u32 = 4096;
+    const TILE_SIZE: u32 = 256;
+    //the whole input would be 64MB and the whole output 4MB, where one tile of the input is 256KB
+    const BUDGET: usize = 4 << 20;
+
+    let image = Generated(SIZE, SIZE);
+    let stop = CancellationToken::new();
+    let settings = OutputSettings {
+        output_px_size: 3,
+        dither_mode: DitherMode::None,
+        scale_output_to_original: false,
+        ..OutputSettings::default()
+    };
+    let (width, height) = predicted_output_size((SIZE, SIZE), settings);
+    assert_eq!((width, height), (SIZE / 4, SIZE / 4));
+
+    let mut palette = None;
+    let palette_peak = peak_allocation(|| {
+        palette = Some(
+            get_palette_subsampled(
+                &image,
+                1 << 16,
+                PaletteSettings::default(),
+                DistanceAlgorithm::Euclidean,
+                &NoProgress,
+                &stop,
+            )
+            .unwrap(),
+        );
+    });
+    assert!(
+        palette_peak < BUDGET,
+        "{palette_peak} bytes for the palette"
+    );
+    let palette = palette.unwrap();
+    assert!(!palette.is_empty());
+
+    let dither_peak = peak_allocation(|| {
+        let mut sink = PngTileSink::new(io::sink(), width, height).unwrap();
+        dither_tiled(
+            &image,
+            TILE_SIZE,
+            &palette,
+            DistanceAlgorithm::Euclidean,
+            settings,
+            &mut sink,
+            &NoProgress,
+            &stop,
+        )
+        .unwrap();
+    });
+    assert!(dither_peak < BUDGET, "{dither_peak} bytes for the dither");
+}